@@ -0,0 +1,35 @@
+// Benchmarks of whole-series operations on a keyset of the size of the district data (400 districts x 6 age groups) over 1000 days.
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+
+use covid::{Counters, FGauge, NaiveDate};
+
+fn counters() -> Counters<(u16, u8)> {
+	let start = NaiveDate::from_ymd_opt(2020, 1, 1).unwrap();
+	let mut ts = Counters::new(start, start + chrono::Duration::days(1000));
+	for district in 0..400u16 {
+		for ag in 0..6u8 {
+			for (i, v) in ts.get_or_create((district, ag)).iter_mut().enumerate() {
+				*v = (i as u64 * 7 + district as u64) % 113;
+			}
+		}
+	}
+	ts
+}
+
+fn convert(c: &mut Criterion) {
+	let ts = counters();
+	// cumsum as the reference of a single pass over all values
+	c.bench_function("cumsum", |b| {
+		b.iter_batched(|| ts.clone(), |mut ts| ts.cumsum(), BatchSize::LargeInput)
+	});
+	c.bench_function("u64 to f64", |b| {
+		b.iter_batched(
+			|| ts.clone(),
+			|ts| -> FGauge<(u16, u8)> { ts.into() },
+			BatchSize::LargeInput,
+		)
+	});
+}
+
+criterion_group!(benches, convert);
+criterion_main!(benches);
@@ -0,0 +1,53 @@
+// Pushes a simple line list (CSV with the columns date,region,count) into the `custom` measurement, with the daily count and a seven day sum per region.
+//
+// Usage: cargo run --example custom_measurement -- linelist.csv
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use smartstring::alias::String as SmartString;
+
+use covid::{
+	global_start_date, naive_today, Aggregation, FieldDescriptor, MovingSum, NaiveDate, TimeSeries,
+	ViewTimeSeries,
+};
+
+type Field = FieldDescriptor<Arc<dyn ViewTimeSeries<SmartString>>>;
+
+#[derive(Debug, Clone, Deserialize)]
+struct LineListRecord {
+	date: NaiveDate,
+	region: SmartString,
+	count: u64,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+	let argv: Vec<String> = std::env::args().collect();
+	let start = global_start_date();
+	let end = naive_today();
+
+	let mut cases = TimeSeries::<SmartString, u64>::new(start, end);
+	let mut r = csv::Reader::from_reader(covid::env_fetcher().open(&argv[1])?);
+	for row in r.deserialize() {
+		let rec: LineListRecord = row?;
+		let index = match cases.date_index(rec.date) {
+			Some(v) => v,
+			None => continue,
+		};
+		cases.get_or_create(rec.region)[index] += rec.count;
+	}
+
+	let cases = Arc::new(cases);
+	let fields: Vec<Field> = vec![
+		Field::new(cases.clone(), "cases_d1").with_aggregation(Aggregation::Sum),
+		Field::new(Arc::new(MovingSum::new(cases.clone(), 7)), "cases_d7")
+			.with_aggregation(Aggregation::Last),
+	];
+
+	let keyset = covid::KeySet::new(&["region"], cases.keys(), |k, out| {
+		out.push(k.clone());
+	})?;
+
+	covid::write_measurement(&covid::env_client(), "custom", &keyset, &fields, start..end)?;
+	Ok(())
+}
@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use super::context::AgeGroup;
+use super::error::{Error, Result};
+
+// A partition of all ages into bands, given by their lower bounds: the first band starts at zero, each band ends before the next one starts and the last one is open-ended.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "Vec<u16>")]
+pub struct AgeBands {
+	lows: Vec<u16>,
+}
+
+impl AgeBands {
+	pub fn new(lows: Vec<u16>) -> Result<Self> {
+		if lows.first() != Some(&0) {
+			return Err(Error::Schema(format!(
+				"age bands must start at 0, got {:?}",
+				lows
+			)));
+		}
+		if !lows.windows(2).all(|w| w[0] < w[1]) {
+			return Err(Error::Schema(format!(
+				"age bands must be strictly increasing, got {:?}",
+				lows
+			)));
+		}
+		Ok(Self { lows })
+	}
+
+	pub fn bands(&self) -> Vec<AgeGroup> {
+		(0..self.lows.len()).map(|i| self.band(i)).collect()
+	}
+
+	fn band(&self, i: usize) -> AgeGroup {
+		AgeGroup {
+			low: self.lows[i],
+			high: self.lows.get(i + 1).map(|next| next - 1),
+		}
+	}
+
+	fn index_of_age(&self, age: u16) -> usize {
+		// the first band starts at zero, so there is always one
+		self.lows.iter().rposition(|low| *low <= age).unwrap()
+	}
+
+	pub fn band_of_age(&self, age: u16) -> AgeGroup {
+		self.band(self.index_of_age(age))
+	}
+
+	// The band which contains all ages of `ag`; None if it spans several bands.
+	pub fn band_of(&self, ag: &AgeGroup) -> Option<AgeGroup> {
+		let i = self.index_of_age(ag.low);
+		let band = self.band(i);
+		match (ag.high, band.high) {
+			(_, None) => Some(band),
+			(Some(high), Some(band_high)) if high <= band_high => Some(band),
+			_ => None,
+		}
+	}
+
+	// Each band of `from` with the band of these which contains it, None for those which span several.
+	pub fn conversion_from(&self, from: &AgeBands) -> Vec<(AgeGroup, Option<AgeGroup>)> {
+		from.bands()
+			.into_iter()
+			.map(|ag| (ag, self.band_of(&ag)))
+			.collect()
+	}
+
+	// The closure to rekey the age groups of a dataset with, by band_of.
+	pub fn into_rekey(self) -> impl Fn(&AgeGroup) -> Option<AgeGroup> {
+		move |ag| self.band_of(ag)
+	}
+
+	// Fails unless each band of `scheme` is within one of these, i.e. unless the data in that scheme can be rekeyed into these bands without splitting a band.
+	pub fn check_compatible(&self, scheme: AgeScheme) -> Result<()> {
+		for (from, to) in self.conversion_from(&scheme.bands()) {
+			if to.is_none() {
+				return Err(Error::Schema(format!(
+					"age band {} of the {} data spans several of the bands {}",
+					from,
+					scheme.name(),
+					self
+				)));
+			}
+		}
+		Ok(())
+	}
+}
+
+impl TryFrom<Vec<u16>> for AgeBands {
+	type Error = Error;
+
+	fn try_from(lows: Vec<u16>) -> Result<Self> {
+		Self::new(lows)
+	}
+}
+
+impl fmt::Display for AgeBands {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		for (i, band) in self.bands().iter().enumerate() {
+			if i > 0 {
+				f.write_str(",")?;
+			}
+			write!(f, "{}", band)?;
+		}
+		Ok(())
+	}
+}
+
+// The age bands of the source datasets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AgeScheme {
+	// the RKI case data and the population of the demographics measurement
+	Cases,
+	// the RKI vaccination data, which starts at five years, and the population of the vaccination measurement
+	Vaccination,
+	// the RKI hospitalization data: the bands of the cases and their total (A00+), which band_of drops
+	Hospitalization,
+}
+
+impl AgeScheme {
+	pub fn all() -> [AgeScheme; 3] {
+		[Self::Cases, Self::Vaccination, Self::Hospitalization]
+	}
+
+	pub fn name(&self) -> &'static str {
+		match self {
+			Self::Cases => "cases",
+			Self::Vaccination => "vacc",
+			Self::Hospitalization => "hosp",
+		}
+	}
+
+	pub fn bands(&self) -> AgeBands {
+		let lows: &[u16] = match self {
+			Self::Cases | Self::Hospitalization => &[0, 5, 15, 35, 60, 80],
+			Self::Vaccination => &[0, 5, 12, 18, 60],
+		};
+		AgeBands {
+			lows: lows.to_vec(),
+		}
+	}
+}
+
+impl FromStr for AgeScheme {
+	type Err = Error;
+
+	fn from_str(s: &str) -> Result<Self> {
+		Self::all()
+			.iter()
+			.find(|scheme| scheme.name() == s)
+			.copied()
+			.ok_or_else(|| Error::Schema(format!("unknown age scheme {:?}", s)))
+	}
+}
+
+// Maps age groups (e.g. the single ages of destatis) onto the bands of `scheme`, for rekeying a dataset; age groups which span several bands map to None.
+pub fn age_rekey(scheme: AgeScheme) -> impl Fn(&AgeGroup) -> Option<AgeGroup> {
+	scheme.bands().into_rekey()
+}
+
+// Custom age bands of the measurements keyed by age, by built-in measurement name, e.g. {"data_v2_demo": [0, 15, 60]}. Measurements without an entry keep the bands of their source data.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct AgeBandConfig {
+	bands: HashMap<String, AgeBands>,
+}
+
+impl AgeBandConfig {
+	pub fn bands(&self, measurement: &str) -> Option<&AgeBands> {
+		self.bands.get(measurement)
+	}
+
+	// Checks the configured measurements against `supported`, the measurements which can be rebanded with the scheme of their source data each.
+	pub fn validate(&self, supported: &[(&str, AgeScheme)]) -> Result<()> {
+		for (measurement, bands) in self.bands.iter() {
+			let scheme = match supported.iter().find(|(name, _)| name == measurement) {
+				Some((_, scheme)) => *scheme,
+				None => {
+					return Err(Error::Schema(format!(
+						"custom age bands are not supported for {}",
+						measurement
+					)))
+				}
+			};
+			bands
+				.check_compatible(scheme)
+				.map_err(|e| Error::Schema(format!("{}: {}", measurement, e)))?;
+		}
+		Ok(())
+	}
+
+	// The closure to rekey the age groups of `measurement` with; it keeps them as they are if there are no bands configured for it.
+	pub fn rekey(&self, measurement: &str) -> impl Fn(&AgeGroup) -> Option<AgeGroup> + '_ {
+		let bands = self.bands(measurement);
+		move |ag| match bands {
+			Some(bands) => bands.band_of(ag),
+			None => Some(*ag),
+		}
+	}
+}
+
+pub fn load_age_band_config<R: io::Read>(r: R) -> Result<AgeBandConfig> {
+	serde_json::from_reader(r).map_err(|e| Error::Schema(e.to_string()))
+}
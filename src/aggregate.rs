@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use std::io;
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use smartstring::alias::String as SmartString;
+
+use super::context::{AgeGroup, DistrictId, StateId};
+use super::error::{Error, Result};
+use super::rki::{DistrictInfo, FullCaseKey, StateInfo};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyDimension {
+	State,
+	District,
+	Age,
+	Sex,
+}
+
+impl KeyDimension {
+	pub fn tag_name(&self) -> &'static str {
+		match self {
+			Self::State => "state",
+			Self::District => "district",
+			Self::Age => "age",
+			Self::Sex => "sex",
+		}
+	}
+}
+
+// Key of an aggregate: the tag values, in the order of `group_by`.
+pub type AggregateKey = Vec<SmartString>;
+
+// Declarative description of a user-defined measurement over the case data: the full key (state, district, age, sex) is reduced to the dimensions in `group_by` and all series which end up with the same key are summed up.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AggregateSpec {
+	pub name: String,
+	pub group_by: Vec<KeyDimension>,
+	// lower bounds of the age bins to re-bin into, e.g. [0, 15, 60]; the last bin is open-ended. An age group is counted into the bin its lower bound falls into.
+	#[serde(default)]
+	pub age_bins: Option<Vec<u16>>,
+}
+
+impl AggregateSpec {
+	pub fn tag_names(&self) -> Vec<&'static str> {
+		self.group_by.iter().map(|dim| dim.tag_name()).collect()
+	}
+
+	fn validate(&self) -> Result<()> {
+		let invalid = |msg: String| Err(Error::Schema(msg));
+		if self.name.is_empty() {
+			return invalid("aggregate without name".into());
+		}
+		for (i, dim) in self.group_by.iter().enumerate() {
+			if self.group_by[..i].contains(dim) {
+				return invalid(format!(
+					"aggregate {}: {} is listed twice in group_by",
+					self.name,
+					dim.tag_name()
+				));
+			}
+		}
+		if let Some(bins) = self.age_bins.as_ref() {
+			if bins.is_empty() || !bins.windows(2).all(|w| w[0] < w[1]) {
+				return invalid(format!(
+					"aggregate {}: age_bins must be non-empty and strictly increasing",
+					self.name
+				));
+			}
+		}
+		Ok(())
+	}
+
+	fn bin_age(&self, ag: AgeGroup) -> Option<AgeGroup> {
+		let bins = match self.age_bins.as_ref() {
+			Some(v) => v,
+			None => return Some(ag),
+		};
+		// ages below the first bin are not covered
+		let i = bins.iter().rposition(|low| *low <= ag.low)?;
+		Some(AgeGroup {
+			low: bins[i],
+			high: bins.get(i + 1).map(|next| next - 1),
+		})
+	}
+
+	// Maps a full case key to the key of this aggregate; None drops the series.
+	pub fn rekey(
+		&self,
+		key: &FullCaseKey,
+		states: &HashMap<StateId, Arc<StateInfo>>,
+		districts: &HashMap<DistrictId, Arc<DistrictInfo>>,
+	) -> Option<AggregateKey> {
+		let (state_id, district_id, ag, sex) = key;
+		let mut result = Vec::with_capacity(self.group_by.len());
+		for dim in self.group_by.iter() {
+			result.push(match dim {
+				KeyDimension::State => states.get(state_id)?.name.as_str().into(),
+				KeyDimension::District => match districts.get(district_id) {
+					Some(i) => i.name.as_str().into(),
+					None => panic!("failed to find district {} in data", district_id),
+				},
+				KeyDimension::Age => match **ag {
+					Some(ag) => self.bin_age(ag)?.to_string().into(),
+					None => ag.to_string().into(),
+				},
+				KeyDimension::Sex => sex.to_string().into(),
+			});
+		}
+		Some(result)
+	}
+}
+
+// Loads a JSON list of aggregate specs, e.g. `[{"name": "cases_by_sex", "group_by": ["state", "sex"]}]`.
+pub fn load_aggregate_specs<R: io::Read>(r: R) -> Result<Vec<AggregateSpec>> {
+	let specs: Vec<AggregateSpec> =
+		serde_json::from_reader(r).map_err(|e| Error::Schema(e.to_string()))?;
+	for spec in specs.iter() {
+		spec.validate()?;
+	}
+	Ok(specs)
+}
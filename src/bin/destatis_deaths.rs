@@ -4,10 +4,18 @@ use std::path::Path;
 
 use csv;
 
+use smartstring::alias::String as SmartString;
+
+use chrono::{TimeZone, Utc};
+
+use log::info;
+
 use covid::{
 	DestatisDeathCurrent, DestatisDeathHistoric, ProgressSink, RawDestatisDeathByMonthRow,
 };
 
+static DEATHS_MEASUREMENT: &str = "destatis_deaths_v1";
+
 static FIRST_YEAR: i32 = 2020;
 static LAST_YEAR: Option<i32> = None;
 
@@ -38,14 +46,35 @@ impl RawMonthlyData {
 		Ok(())
 	}
 
+	fn pandemics(&self) -> Vec<DestatisDeathCurrent> {
+		self.pandemic_samples
+			.iter()
+			.map(|((year, month), v)| {
+				let samples = &self.pre_pandemic_samples[(month - 1) as usize][..];
+				let (z_score, percentile) = if samples.is_empty() {
+					(None, None)
+				} else {
+					let historic = DestatisDeathHistoric::from_sorted_slice(*month, samples);
+					(
+						historic.z_score(*v),
+						Some(covid::percentile_rank(samples, *v)),
+					)
+				};
+				DestatisDeathCurrent {
+					year: *year,
+					month: *month,
+					death_incidence_per_inhabitant: *v,
+					z_score,
+					percentile,
+				}
+			})
+			.collect()
+	}
+
 	fn write_pandemics<W: io::Write>(&self, w: W) -> io::Result<()> {
 		let mut w = csv::Writer::from_writer(w);
-		for ((year, month), v) in self.pandemic_samples.iter() {
-			w.serialize(DestatisDeathCurrent {
-				year: *year,
-				month: *month,
-				death_incidence_per_inhabitant: *v,
-			})?;
+		for rec in self.pandemics() {
+			w.serialize(rec)?;
 		}
 		w.flush()?;
 		Ok(())
@@ -84,7 +113,7 @@ fn load_data<P: AsRef<Path>, S: ProgressSink + ?Sized>(
 	datafile: P,
 	out: &mut RawMonthlyData,
 ) -> io::Result<()> {
-	let r = covid::magic_open(datafile)?;
+	let r = covid::env_fetcher().open(datafile)?;
 	let mut r = csv::Reader::from_reader(r);
 	let mut pm = covid::CountMeter::new(s);
 	let mut n = 0;
@@ -100,23 +129,91 @@ fn load_data<P: AsRef<Path>, S: ProgressSink + ?Sized>(
 	Ok(())
 }
 
+// one point per month, at its first day
+fn stream_pandemics<S: ProgressSink + ?Sized>(
+	s: &mut S,
+	records: &[DestatisDeathCurrent],
+	client: &covid::influxdb::Client,
+	options: &covid::StreamOptions,
+) -> io::Result<()> {
+	if !options.is_enabled(DEATHS_MEASUREMENT) {
+		return Ok(());
+	}
+	let measurement = options.measurement_name(DEATHS_MEASUREMENT);
+	let pm = covid::CountMeter::new(s);
+	let mut readouts = Vec::with_capacity(records.len());
+	for rec in records.iter() {
+		let mut fields: Vec<SmartString> = vec!["death_incidence_per_inhabitant".into()];
+		let mut fieldv: Vec<covid::influxdb::readout::FieldValue> =
+			vec![rec.death_incidence_per_inhabitant.into()];
+		// a field without value is left out rather than written as NaN
+		if let Some(z_score) = rec.z_score {
+			fields.push("death_z_score".into());
+			fieldv.push(z_score.into());
+		}
+		if let Some(percentile) = rec.percentile {
+			fields.push("death_percentile".into());
+			fieldv.push(percentile.into());
+		}
+		readouts.push(covid::influxdb::Readout {
+			ts: Utc
+				.with_ymd_and_hms(rec.year, rec.month, 1, 0, 0, 0)
+				.unwrap(),
+			measurement: measurement.into(),
+			precision: covid::influxdb::Precision::Seconds,
+			fields,
+			tags: Vec::new(),
+			samples: vec![covid::influxdb::Sample {
+				fieldv,
+				tagv: Vec::new(),
+			}],
+		});
+	}
+	if !readouts.is_empty() {
+		client.post(
+			&options.database,
+			options.retention_policy.as_deref(),
+			None,
+			readouts[0].precision,
+			&readouts[..],
+		)?;
+	}
+	pm.finish(readouts.len());
+	Ok(())
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+	covid::init_logging()?;
 	let argv: Vec<String> = std::env::args().collect();
 	let datafile = &argv[1];
 	let out_pre_pandemic = &argv[2];
 	let out_pandemic = &argv[3];
 	let mut data = RawMonthlyData::new();
-	println!("loading destatis data ...");
+	info!("loading destatis data ...");
 	load_data(&mut *covid::default_output(), datafile, &mut data)?;
-	println!("writing pre-pandemic summary ...");
+	info!("writing pre-pandemic summary ...");
 	{
 		let w = std::fs::File::create(out_pre_pandemic)?;
 		data.write_pre_pandemics(w)?;
 	}
-	println!("writing pandemic monthly data ...");
+	info!("writing pandemic monthly data ...");
 	{
 		let w = std::fs::File::create(out_pandemic)?;
 		data.write_pandemics(w)?;
 	}
+	// optional, the pandemic months with their z-score and percentile as a measurement
+	let to_influx = match std::env::var("COVID_DESTATIS_DEATHS_INFLUX") {
+		Ok(v) => v.parse::<u32>()? != 0,
+		Err(_) => false,
+	};
+	if to_influx {
+		info!("streaming pandemic monthly data to influxdb ...");
+		stream_pandemics(
+			&mut *covid::default_output(),
+			&data.pandemics()[..],
+			&covid::env_client(),
+			&covid::env_stream_options(),
+		)?;
+	}
 	Ok(())
 }
@@ -1,95 +1,59 @@
 use std::io;
 
-use smartstring::alias::String as SmartString;
-
-use chrono::{Datelike, NaiveDate, TimeZone, Utc};
-
-use serde::Deserialize;
-
 use csv;
 
-use covid::{CountMeter, ProgressSink};
-
-static EVENTS_MEASUREMENT: &'static str = "events_v1";
+use log::info;
 
-#[derive(Debug, Clone, Deserialize)]
-struct HolidayRecord {
-	state: SmartString,
-	holiday: SmartString,
-	start: NaiveDate,
-	end: NaiveDate,
-}
-
-fn stream_holidays<R: io::Read, S: ProgressSink + ?Sized>(
-	s: &mut S,
-	mut r: csv::Reader<R>,
-	client: &covid::influxdb::Client,
-) -> io::Result<()> {
-	let tags: Vec<SmartString> = vec!["state".into(), "is_holiday".into(), "holiday_kind".into()];
-	let fields: Vec<SmartString> = vec!["text".into(), "end".into()];
+use covid::{Event, HolidayRecord};
 
-	let mut pm = CountMeter::new(s);
-	let mut n = 0;
-	let mut readout_buf = Vec::with_capacity(16);
-	for (i, row) in r.deserialize().enumerate() {
-		let rec: HolidayRecord = row?;
-		let start = Utc
-			.ymd(rec.start.year(), rec.start.month(), rec.start.day())
-			.and_hms(0, 0, 0);
-		let end = Utc
-			.ymd(rec.end.year(), rec.end.month(), rec.end.day())
-			.and_hms(0, 0, 0);
-		if (end - start).num_days() < 3 {
-			continue;
-		}
-		readout_buf.push(covid::influxdb::Readout {
-			ts: start,
-			measurement: EVENTS_MEASUREMENT.into(),
-			precision: covid::influxdb::Precision::Seconds,
-			fields: fields.clone(),
-			tags: tags.clone(),
-			samples: vec![covid::influxdb::Sample {
-				fieldv: vec![
-					format!("{}\n\n<sup>{}</sup>", rec.holiday, rec.state).into(),
-					format!("{}000", end.timestamp()).into(),
-				],
-				tagv: vec![rec.state, "true".into(), rec.holiday],
-			}],
-		});
-		if readout_buf.len() == readout_buf.capacity() {
-			client.post(
-				"covid",
-				None,
-				None,
-				readout_buf[0].precision,
-				&readout_buf[..],
-			)?;
-			readout_buf.clear();
-			pm.update(i + 1);
-		}
-		n = i + 1;
-	}
-	if readout_buf.len() > 0 {
-		client.post(
-			"covid",
-			None,
-			None,
-			readout_buf[0].precision,
-			&readout_buf[..],
-		)?;
+fn fetch_holidays(fetcher: &covid::Fetcher, year: i32) -> io::Result<Vec<HolidayRecord>> {
+	let mut result = Vec::new();
+	for (state_code, _) in covid::STATE_CODES.iter() {
+		result.extend(covid::fetch_school_holidays(fetcher, state_code, year)?);
+		result.extend(covid::fetch_public_holidays(fetcher, state_code, year)?);
 	}
-	pm.finish(n);
-	Ok(())
+	Ok(result)
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-	let argv: Vec<String> = std::env::args().collect();
+	covid::init_logging()?;
+	let mut argv: Vec<String> = std::env::args().collect();
+	// --annotations PATH writes all events into a Grafana annotation JSON file instead of InfluxDB
+	let annotations = match argv.iter().position(|arg| arg == "--annotations") {
+		Some(i) if i + 1 < argv.len() => {
+			let path = argv.remove(i + 1);
+			argv.remove(i);
+			Some(path)
+		}
+		Some(_) => return Err("--annotations requires a value".into()),
+		None => None,
+	};
 	let client = covid::env_client();
+	let options = covid::env_stream_options();
+	let fetcher = covid::env_fetcher();
+	let mut collected = Vec::new();
 	for name in argv[1..].iter() {
-		println!("streaming {} to influxdb ...", name);
-		let r = covid::magic_open(name)?;
-		let r = csv::Reader::from_reader(r);
-		stream_holidays(&mut *covid::default_output(), r, &client)?;
+		// bare years are fetched from the holiday APIs, everything else is a curated CSV file
+		let records = if let Ok(year) = name.parse::<i32>() {
+			info!("fetching holidays for {} ...", year);
+			fetch_holidays(&fetcher, year)?
+		} else {
+			info!("reading {} ...", name);
+			let r = fetcher.open(name)?;
+			let mut r = csv::Reader::from_reader(r);
+			r.deserialize().collect::<Result<Vec<HolidayRecord>, _>>()?
+		};
+		let events = records.into_iter().map(Event::from);
+		if annotations.is_some() {
+			collected.extend(events);
+			continue;
+		}
+		info!("streaming {} to influxdb ...", name);
+		covid::stream_events(&mut *covid::default_output(), events, &client, &options)?;
+	}
+	if let Some(path) = annotations {
+		info!("writing {} annotations to {} ...", collected.len(), path);
+		covid::write_annotations(std::fs::File::create(path)?, &collected)?;
 	}
 	Ok(())
 }
@@ -0,0 +1,43 @@
+use log::info;
+
+use covid::Event;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+	covid::init_logging()?;
+	let mut argv: Vec<String> = std::env::args().collect();
+	// --annotations PATH writes all events into a Grafana annotation JSON file instead of InfluxDB
+	let annotations = match argv.iter().position(|arg| arg == "--annotations") {
+		Some(i) if i + 1 < argv.len() => {
+			let path = argv.remove(i + 1);
+			argv.remove(i);
+			Some(path)
+		}
+		Some(_) => return Err("--annotations requires a value".into()),
+		None => None,
+	};
+	let mut events = Vec::new();
+	for name in argv[1..].iter() {
+		info!("reading {} ...", name);
+		events.extend(
+			covid::load_interventions(name)?
+				.into_iter()
+				.map(Event::from),
+		);
+	}
+	match annotations {
+		Some(path) => {
+			info!("writing {} annotations to {} ...", events.len(), path);
+			covid::write_annotations(std::fs::File::create(path)?, &events)?;
+		}
+		None => {
+			info!("streaming {} interventions to influxdb ...", events.len());
+			covid::stream_events(
+				&mut *covid::default_output(),
+				events.into_iter(),
+				&covid::env_client(),
+				&covid::env_stream_options(),
+			)?;
+		}
+	}
+	Ok(())
+}
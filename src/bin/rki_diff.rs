@@ -1,13 +1,19 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs;
 use std::fs::File;
 use std::io;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
 
 use chrono::NaiveDate;
 
-use covid::timeseries;
+use log::{info, warn};
+
 use covid::{
-	global_start_date, naive_today, CountMeter, Counters, DiffRecord, DistrictId, InfectionRecord,
-	MaybeAgeGroup, ProgressSink, ReportFlag, Sex, StepMeter, ViewTimeSeries,
+	diff_partition_of, dump_publication_date, global_start_date, load_sha256sums, naive_today,
+	sha256_file, CountMeter, Counters, CsvRecords, DiffRecord, DiffSchema, DiffStore, DistrictId,
+	InfectionRecord, InfectionSchema, MaybeAgeGroup, ProgressSink, ReportFlag, Sex, StepMeter,
 };
 
 type PartialCaseKey = (DistrictId, MaybeAgeGroup, Sex);
@@ -21,12 +27,11 @@ struct PartialDiffData {
 	pub late_cases: Counters<PartialCaseKey>,
 	pub deaths_by_pub: Counters<PartialCaseKey>,
 	pub recovered_by_pub: Counters<PartialCaseKey>,
-	pub cases_by_rep_buf: Counters<PartialCaseKey>,
 	pub cases_by_rep_d7: Counters<PartialCaseKey>,
 	pub cases_retracted: Counters<PartialCaseKey>,
 }
 
-fn saturating_add_u64_i32(reg: &mut u64, v: i32) {
+fn saturating_add_u64_i64(reg: &mut u64, v: i64) {
 	if v < 0 {
 		let v = (-v) as u64;
 		*reg = reg.saturating_sub(v);
@@ -35,70 +40,97 @@ fn saturating_add_u64_i32(reg: &mut u64, v: i32) {
 	}
 }
 
-impl PartialDiffData {
-	fn new(start: NaiveDate, end: NaiveDate) -> Self {
+// the sum of the changes by key and day index
+type Changes = HashMap<(PartialCaseKey, usize), i64>;
+
+fn add_change(changes: &mut Changes, k: PartialCaseKey, index: usize, v: i64) {
+	if v != 0 {
+		*changes.entry((k, index)).or_insert(0) += v;
+	}
+}
+
+// The contribution of one case file, published for `date`, to the diff data. It does not depend on the other files or the existing records, so that the files can be parsed in parallel and applied in order afterwards. Within one file, all changes to a key and day have the same sign (new reports add to the date, retractions subtract from the day before), so that summing them up first gives the same result as applying the records one by one with saturation.
+struct FileDiff {
+	date: NaiveDate,
+	start: NaiveDate,
+	len: usize,
+	// the keys with any case, death or recovery, which are created in all series
+	keys: HashSet<PartialCaseKey>,
+	cases: Changes,
+	cases_delayed: Changes,
+	case_delay_total: Changes,
+	late_cases: Changes,
+	deaths: Changes,
+	recovered: Changes,
+	cases_retracted: Changes,
+	// the cases by report date within the seven days up to `date`, for each key with any reported cases in the file
+	cases_rep_d7: HashMap<PartialCaseKey, i64>,
+}
+
+impl FileDiff {
+	fn new(date: NaiveDate, start: NaiveDate, end: NaiveDate) -> Self {
 		Self {
-			cases_by_pub: Counters::new(start, end),
-			cases_delayed: Counters::new(start, end),
-			case_delay_total: Counters::new(start, end),
-			late_cases: Counters::new(start, end),
-			deaths_by_pub: Counters::new(start, end),
-			recovered_by_pub: Counters::new(start, end),
-			cases_by_rep_buf: Counters::new(start, end),
-			cases_by_rep_d7: Counters::new(start, end),
-			cases_retracted: Counters::new(start, end),
+			date,
+			start,
+			len: (end - start).num_days() as usize,
+			keys: HashSet::new(),
+			cases: HashMap::new(),
+			cases_delayed: HashMap::new(),
+			case_delay_total: HashMap::new(),
+			late_cases: HashMap::new(),
+			deaths: HashMap::new(),
+			recovered: HashMap::new(),
+			cases_retracted: HashMap::new(),
+			cases_rep_d7: HashMap::new(),
 		}
 	}
 
-	fn submit(&mut self, date: NaiveDate, rec: &InfectionRecord) {
-		let index = self
-			.cases_by_pub
-			.date_index(date)
-			.expect("date out of range");
+	fn date_index(&self, date: NaiveDate) -> usize {
+		let days = (date - self.start).num_days();
+		assert!(days >= 0 && (days as usize) < self.len, "date out of range");
+		days as usize
+	}
+
+	fn submit(&mut self, rec: &InfectionRecord) {
+		let index = self.date_index(self.date);
+		let case_count = rec.case_count as i64;
+		let death_count = rec.death_count as i64;
+		let recovered_count = rec.recovered_count as i64;
 
 		let (case_index, case_diff, cases_retracted) = match rec.case {
-			ReportFlag::NewlyReported => (index, rec.case_count, 0),
+			ReportFlag::NewlyReported => (index, case_count, 0),
 			// Note: the data is negative in the source already.
-			ReportFlag::Retracted => (index - 1, rec.case_count, -rec.case_count),
+			ReportFlag::Retracted => (index - 1, case_count, -case_count),
 			_ => (0, 0, 0),
 		};
 		let (rep_case_index, rep_case_diff) = match rec.case {
-			ReportFlag::NewlyReported | ReportFlag::Consistent => (
-				self.cases_by_rep_buf
-					.date_index(rec.report_date)
-					.expect("date out of range"),
-				rec.case_count,
-			),
+			ReportFlag::NewlyReported | ReportFlag::Consistent => {
+				(self.date_index(rec.report_date), case_count)
+			}
 			_ => (0, 0),
 		};
 		let (death_index, death_diff) = match rec.death {
-			ReportFlag::NewlyReported => (index, rec.death_count),
+			ReportFlag::NewlyReported => (index, death_count),
 			// Note: the data is negative in the source already.
-			ReportFlag::Retracted => (index - 1, rec.death_count),
+			ReportFlag::Retracted => (index - 1, death_count),
 			_ => (0, 0),
 		};
 		let (recovered_index, recovered_diff) = match rec.recovered {
-			ReportFlag::NewlyReported => (index, rec.recovered_count),
+			ReportFlag::NewlyReported => (index, recovered_count),
 			// Note: the data is negative in the source already.
-			ReportFlag::Retracted => (index - 1, rec.recovered_count),
+			ReportFlag::Retracted => (index - 1, recovered_count),
 			_ => (0, 0),
 		};
 
 		let k = (rec.district_id, rec.age_group, rec.sex);
 		if rep_case_diff != 0 {
 			// we don't want to instantiate the key if there's nothing going on
-			saturating_add_u64_i32(
-				&mut self.cases_by_rep_buf.get_or_create(k)[rep_case_index],
-				rep_case_diff,
-			);
-		}
-		if cases_retracted != 0 {
-			// we don't want to instantiate the key if there's nothing going on
-			saturating_add_u64_i32(
-				&mut self.cases_retracted.get_or_create(k)[case_index],
-				cases_retracted,
-			);
+			let d7 = self.cases_rep_d7.entry(k).or_insert(0);
+			if rep_case_index <= index && rep_case_index + 7 > index {
+				*d7 += rep_case_diff;
+			}
 		}
+		add_change(&mut self.cases_retracted, k, case_index, cases_retracted);
 
 		if case_diff == 0 && death_diff == 0 && recovered_diff == 0 {
 			return;
@@ -106,53 +138,132 @@ impl PartialDiffData {
 
 		let (case_delay, case_delay_count, late_case_count) = match rec.case {
 			ReportFlag::NewlyReported => {
-				let delay = (date - rec.report_date).num_days();
+				let delay = (self.date - rec.report_date).num_days();
 				assert!(delay >= 0);
 				// we only want to include cases which take part in the pandemic situation, because that's what's relevant. if someone found a case from three months ago in some file, we don't really care... or do we?!
 				if delay > DELAY_CUTOFF {
-					(0, 0, rec.case_count)
+					(0, 0, case_count)
 				} else {
-					(delay as i32, rec.case_count, 0)
+					(delay, case_count, 0)
 				}
 			}
 			_ => (0, 0, 0),
 		};
 
-		saturating_add_u64_i32(
-			&mut self.cases_by_pub.get_or_create(k)[case_index],
-			case_diff,
-		);
-		saturating_add_u64_i32(
-			&mut self.cases_delayed.get_or_create(k)[case_index],
-			case_delay_count,
-		);
-		saturating_add_u64_i32(
-			&mut self.case_delay_total.get_or_create(k)[case_index],
+		self.keys.insert(k);
+		add_change(&mut self.cases, k, case_index, case_diff);
+		add_change(&mut self.cases_delayed, k, case_index, case_delay_count);
+		add_change(
+			&mut self.case_delay_total,
+			k,
+			case_index,
 			case_delay * case_delay_count,
 		);
-		saturating_add_u64_i32(
-			&mut self.late_cases.get_or_create(k)[case_index],
-			late_case_count,
-		);
-		saturating_add_u64_i32(
-			&mut self.deaths_by_pub.get_or_create(k)[death_index],
-			death_diff,
-		);
-		saturating_add_u64_i32(
-			&mut self.recovered_by_pub.get_or_create(k)[recovered_index],
-			recovered_diff,
-		);
+		add_change(&mut self.late_cases, k, case_index, late_case_count);
+		add_change(&mut self.deaths, k, death_index, death_diff);
+		add_change(&mut self.recovered, k, recovered_index, recovered_diff);
+	}
+}
+
+impl PartialDiffData {
+	fn new(start: NaiveDate, end: NaiveDate) -> Self {
+		Self {
+			cases_by_pub: Counters::new(start, end),
+			cases_delayed: Counters::new(start, end),
+			case_delay_total: Counters::new(start, end),
+			late_cases: Counters::new(start, end),
+			deaths_by_pub: Counters::new(start, end),
+			recovered_by_pub: Counters::new(start, end),
+			cases_by_rep_d7: Counters::new(start, end),
+			cases_retracted: Counters::new(start, end),
+		}
+	}
+
+	fn apply(&mut self, diff: FileDiff) {
+		let mut series = [
+			(&mut self.cases_by_pub, diff.cases),
+			(&mut self.cases_delayed, diff.cases_delayed),
+			(&mut self.case_delay_total, diff.case_delay_total),
+			(&mut self.late_cases, diff.late_cases),
+			(&mut self.deaths_by_pub, diff.deaths),
+			(&mut self.recovered_by_pub, diff.recovered),
+		];
+		for (counters, changes) in series.iter_mut() {
+			for k in diff.keys.iter() {
+				counters.get_or_create(*k);
+			}
+			for ((k, index), v) in changes.drain() {
+				saturating_add_u64_i64(&mut counters.get_or_create(k)[index], v);
+			}
+		}
+		for ((k, index), v) in diff.cases_retracted {
+			saturating_add_u64_i64(&mut self.cases_retracted.get_or_create(k)[index], v);
+		}
+		// this carries over the d7 of the cases by report date as of this file into cases_by_rep_d7
+		let index = self
+			.cases_by_rep_d7
+			.date_index(diff.date)
+			.expect("date out of range");
+		for (k, d7) in diff.cases_rep_d7 {
+			self.cases_by_rep_d7.get_or_create(k)[index] = d7.max(0) as u64;
+		}
+	}
+
+	fn counters(&self) -> [&Counters<PartialCaseKey>; 8] {
+		[
+			&self.cases_by_pub,
+			&self.cases_delayed,
+			&self.case_delay_total,
+			&self.late_cases,
+			&self.deaths_by_pub,
+			&self.recovered_by_pub,
+			&self.cases_by_rep_d7,
+			&self.cases_retracted,
+		]
+	}
+
+	// Drops the keys which are zero on all days in all series, which write no records anyway, and returns how many were dropped.
+	fn prune_zero_keys(&mut self) -> usize {
+		let mut live = HashSet::new();
+		for counters in self.counters().iter() {
+			let zero: HashSet<&PartialCaseKey> = counters.zero_keys().collect();
+			live.extend(counters.keys().filter(|k| !zero.contains(k)).copied());
+		}
+		let n = self.cases_by_pub.key_count();
+		for counters in [
+			&mut self.cases_by_pub,
+			&mut self.cases_delayed,
+			&mut self.case_delay_total,
+			&mut self.late_cases,
+			&mut self.deaths_by_pub,
+			&mut self.recovered_by_pub,
+			&mut self.cases_by_rep_d7,
+			&mut self.cases_retracted,
+		]
+		.iter_mut()
+		{
+			counters.retain_keys(|k| live.contains(k));
+		}
+		n - self.cases_by_pub.key_count()
+	}
+
+	fn memory_usage(&self) -> usize {
+		self.counters().iter().map(|c| c.memory_usage()).sum()
 	}
 
-	fn write_all<W: io::Write, S: ProgressSink + ?Sized>(
+	// The records of the publication dates from `from` up to `to` (exclusive).
+	fn each_record<S: ProgressSink + ?Sized, F: FnMut(DiffRecord) -> io::Result<()>>(
 		&self,
 		s: &mut S,
-		w: &mut W,
+		from: NaiveDate,
+		to: NaiveDate,
+		mut f: F,
 	) -> io::Result<()> {
 		let start = self.cases_by_pub.start();
-		let len = self.cases_by_pub.len();
-		let mut pm = StepMeter::new(s, len);
-		for i in 0..len {
+		let first = (from - start).num_days().max(0) as usize;
+		let len = ((to - start).num_days().max(0) as usize).min(self.cases_by_pub.len());
+		let mut pm = StepMeter::new(s, len.saturating_sub(first));
+		for i in first..len {
 			let date = start + chrono::Duration::days(i as i64);
 			for k in self.cases_by_pub.keys() {
 				let cases = self.cases_by_pub.get_value(k, i).unwrap_or(0);
@@ -165,12 +276,13 @@ impl PartialDiffData {
 				let cases_retracted = self.cases_retracted.get_value(k, i).unwrap_or(0);
 				if cases == 0
 					&& deaths == 0 && recovered == 0
-					&& cases_rep_d7 == 0 && cases_retracted == 0
+					&& cases_rep_d7 == 0
+					&& cases_retracted == 0
 				{
 					continue;
 				}
 				let (district_id, age_group, sex) = *k;
-				DiffRecord {
+				f(DiffRecord {
 					date,
 					district_id,
 					age_group,
@@ -183,11 +295,10 @@ impl PartialDiffData {
 					recovered,
 					cases_rep_d7,
 					cases_retracted,
-				}
-				.write(w)?;
+				})?;
 			}
-			if i % 30 == 29 {
-				pm.update(i + 1);
+			if (i - first) % 30 == 29 {
+				pm.update(i - first + 1);
 			}
 		}
 		pm.finish();
@@ -197,14 +308,15 @@ impl PartialDiffData {
 
 fn load_existing<R: io::Read, S: ProgressSink + ?Sized>(
 	s: &mut S,
-	r: &mut R,
+	r: &mut CsvRecords<R>,
 	d: &mut PartialDiffData,
-) -> io::Result<()> {
-	let mut r = csv::Reader::from_reader(r);
+) -> io::Result<Option<NaiveDate>> {
 	let mut pm = CountMeter::new(s);
 	let mut n = 0;
+	let mut last = None;
 	for (i, row) in r.deserialize().enumerate() {
 		let rec: DiffRecord = row?;
+		last = last.max(Some(rec.date));
 		let index = d
 			.cases_by_pub
 			.date_index(rec.date)
@@ -224,92 +336,522 @@ fn load_existing<R: io::Read, S: ProgressSink + ?Sized>(
 		n = i + 1;
 	}
 	pm.finish(n);
-	Ok(())
+	Ok(last)
 }
 
+// Returns the latest date of the existing records.
 fn try_load_existing<P: AsRef<Path>, S: ProgressSink + ?Sized>(
 	s: &mut S,
 	path: P,
 	d: &mut PartialDiffData,
-) -> io::Result<()> {
+) -> io::Result<Option<NaiveDate>> {
 	// not using magic open as a safeguard: the output will always be uncompressed and refusing compressed input protects against accidentally overwriting a source file
-	let mut r = match File::open(path) {
+	let f = match File::open(&path) {
 		Ok(f) => f,
 		// ignore missing files here
-		Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+		Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
 		Err(other) => return Err(other),
 	};
+	let mut r = CsvRecords::new(
+		path.as_ref().display().to_string(),
+		csv::Reader::from_reader(f),
+	)?;
+	let schema = DiffSchema::detect(r.headers())?;
+	info!("existing records: {} diff file", schema);
 	load_existing(s, &mut r, d)
 }
 
-fn merge_new<P: AsRef<Path>, S: ProgressSink + ?Sized>(
-	s: &mut S,
+fn diff_file<P: AsRef<Path>>(
 	path: P,
 	date: NaiveDate,
-	d: &mut PartialDiffData,
-) -> io::Result<()> {
-	let r = covid::magic_open(path)?;
-	let mut r = csv::Reader::from_reader(r);
-	let mut pm = CountMeter::new(s);
+	start: NaiveDate,
+	end: NaiveDate,
+) -> io::Result<FileDiff> {
+	let mut r = CsvRecords::open(path)?;
+	let schema = InfectionSchema::detect(r.headers())?;
+	let mut d = FileDiff::new(date, start, end);
 	let mut n = 0;
-	// the trick here is that we re-calculate the entire thing on each merge of new data and then carry over the d7 into the cases_by_rep_d7 timeseries
-	d.cases_by_rep_buf.clear();
-	for (i, row) in r.deserialize().enumerate() {
+	for row in r.deserialize() {
 		let rec: InfectionRecord = row?;
-		d.submit(date, &rec);
-		if i % 500000 == 499999 {
-			pm.update(i + 1);
+		d.submit(&rec);
+		n += 1;
+	}
+	info!(
+		"{}: {} records ({} case file) -> {}",
+		r.file(),
+		n,
+		schema,
+		date
+	);
+	Ok(d)
+}
+
+// Parses the case files of `merges` on up to `threads` threads. The results are in the order of `merges`.
+fn diff_files(
+	merges: &[(String, NaiveDate)],
+	start: NaiveDate,
+	end: NaiveDate,
+	threads: usize,
+) -> io::Result<Vec<FileDiff>> {
+	let next = AtomicUsize::new(0);
+	let results: Vec<Mutex<Option<io::Result<FileDiff>>>> =
+		merges.iter().map(|_| Mutex::new(None)).collect();
+	std::thread::scope(|scope| {
+		for _ in 0..threads.min(merges.len()) {
+			scope.spawn(|| loop {
+				let i = next.fetch_add(1, Ordering::Relaxed);
+				let (path, date) = match merges.get(i) {
+					Some(v) => v,
+					None => break,
+				};
+				let diff = diff_file(path, *date, start, end);
+				*results[i].lock().unwrap() = Some(diff);
+			});
 		}
-		n = i + 1;
+	});
+	results
+		.into_iter()
+		.map(|r| r.into_inner().unwrap().expect("case file not parsed"))
+		.collect()
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+	let mut path = path.as_os_str().to_os_string();
+	path.push(suffix);
+	PathBuf::from(path)
+}
+
+// Keeps the current file as FILE.1, and up to `backups` older ones as FILE.2 and so on. The current file stays in place, so that there is no moment without it.
+fn rotate_backups(path: &Path, backups: usize) -> io::Result<()> {
+	if backups == 0 || !path.exists() {
+		return Ok(());
 	}
-	// and now, we use the cases_by_rep_buf data to form a _d7 which we then write out for *this* date.
-	{
-		d.cases_by_rep_buf.cumsum();
-		let index = d
-			.cases_by_rep_d7
-			.date_index(date)
-			.expect("date out of range");
-		let d7 = timeseries::Diff::padded(&d.cases_by_rep_buf, 7, 0.);
-		for k in d.cases_by_rep_buf.keys() {
-			d.cases_by_rep_d7.get_or_create(*k)[index] = d7.getf(k, date).expect("no data") as u64;
+	for i in (1..backups).rev() {
+		let older = with_suffix(path, &format!(".{}", i));
+		if older.exists() {
+			fs::rename(older, with_suffix(path, &format!(".{}", i + 1)))?;
 		}
 	}
-	pm.finish(n);
+	let backup = with_suffix(path, ".1");
+	match fs::remove_file(&backup) {
+		Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+		_ => (),
+	}
+	// a hard link is free, but not every file system has them
+	if fs::hard_link(path, &backup).is_err() {
+		fs::copy(path, &backup)?;
+	}
 	Ok(())
 }
 
+// Writes the records to FILE.tmp, checks that all of them can be read back and only then replaces the file (atomically, by rename), after rotating the backups. If anything fails, the existing file is left alone.
 fn writeback<P: AsRef<Path>, S: ProgressSink + ?Sized>(
 	s: &mut S,
 	path: P,
 	d: &PartialDiffData,
+	backups: usize,
 ) -> io::Result<()> {
-	let mut f = File::create(path)?;
-	DiffRecord::write_header(&mut f)?;
-	d.write_all(s, &mut f)?;
+	let path = path.as_ref();
+	let tmp = with_suffix(path, ".tmp");
+	let mut rows = 0;
+	{
+		let mut w = DiffRecord::writer(File::create(&tmp)?)?;
+		let start = d.cases_by_pub.start();
+		let end = start + chrono::Duration::days(d.cases_by_pub.len() as i64);
+		d.each_record(s, start, end, |rec| {
+			rows += 1;
+			Ok(w.serialize(rec)?)
+		})?;
+		w.flush()?;
+		w.into_inner()
+			.map_err(|e| io::Error::new(e.error().kind(), e.to_string()))?
+			.sync_all()?;
+	}
+	let mut r = csv::Reader::from_path(&tmp)?;
+	let mut written = 0;
+	for rec in r.records() {
+		rec?;
+		written += 1;
+	}
+	if written != rows {
+		return Err(io::Error::new(
+			io::ErrorKind::InvalidData,
+			format!(
+				"{}: {} records written, but {} read back; {} is left as it was",
+				tmp.display(),
+				rows,
+				written,
+				path.display()
+			),
+		));
+	}
+	rotate_backups(path, backups)?;
+	fs::rename(&tmp, path)?;
+	info!("{}: {} records", path.display(), rows);
 	Ok(())
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-	let argv: Vec<String> = std::env::args().collect();
-	let datafile = &argv[1];
+// Loads the partitions of `months` from the store, if they exist. Returns the latest date of the records in the store.
+fn load_partitions<S: ProgressSink + ?Sized>(
+	s: &mut S,
+	store: &DiffStore,
+	months: &BTreeSet<NaiveDate>,
+	d: &mut PartialDiffData,
+) -> io::Result<Option<NaiveDate>> {
+	let mut last = None;
+	for month in months.iter() {
+		if let Some(partition) = store.partition(*month) {
+			info!(
+				"loading {} ({} records) ...",
+				partition.file, partition.rows
+			);
+			last = last.max(load_existing(s, &mut store.read_partition(partition)?, d)?);
+		}
+	}
+	// the latest records are in the last partition, which is not loaded if the merges are (wrongly) before it
+	if let Some(partition) = store
+		.partitions()
+		.last()
+		.filter(|p| !months.contains(&p.month))
+	{
+		let mut r = store.read_partition(partition)?;
+		for row in r.deserialize() {
+			let rec: DiffRecord = row?;
+			last = last.max(Some(rec.date));
+		}
+	}
+	Ok(last)
+}
 
+// Rewrites the partitions of `months`, and only those.
+fn writeback_partitions<S: ProgressSink + ?Sized>(
+	s: &mut S,
+	store: &mut DiffStore,
+	months: &BTreeSet<NaiveDate>,
+	d: &PartialDiffData,
+) -> io::Result<()> {
+	for month in months.iter() {
+		let next = diff_partition_of(*month + chrono::Duration::days(31));
+		let mut records = Vec::new();
+		d.each_record(s, *month, next, |rec| {
+			records.push(rec);
+			Ok(())
+		})?;
+		store.write_partition(*month, records.iter())?;
+	}
+	Ok(())
+}
+
+// The case files to merge with the dates they refer to, from `FILE`, `--date PUBLISHED FILE` or `FILE PUBLISHED` arguments. Without an explicit publication date, it is taken from the file name (see dump_publication_date). The dates must be strictly increasing.
+fn parse_merges(args: &[String]) -> Result<Vec<(String, NaiveDate)>, String> {
+	let parse_date = |s: &str| {
+		s.parse::<NaiveDate>()
+			.map_err(|e| format!("invalid date {:?}: {}", s, e))
+	};
+	let mut merges: Vec<(String, NaiveDate)> = Vec::new();
+	let mut args = args.iter().peekable();
+	while let Some(arg) = args.next() {
+		let (file, published) = if arg == "--date" {
+			let date = parse_date(args.next().ok_or("--date needs a date and a file")?)?;
+			(args.next().ok_or("--date needs a date and a file")?, date)
+		} else if let Some(date) = args.peek().and_then(|next| next.parse::<NaiveDate>().ok()) {
+			args.next();
+			(arg, date)
+		} else {
+			let date = dump_publication_date(arg).ok_or_else(|| {
+				format!(
+					"cannot tell the publication date of {} from its name, use --date",
+					arg
+				)
+			})?;
+			(arg, date)
+		};
+		if let Some(inferred) = dump_publication_date(file).filter(|d| *d != published) {
+			warn!(
+				"{}: merging as published on {}, although its name says {}",
+				file, published, inferred
+			);
+		}
+		// subtract one because the publication refers to the day before
+		let date = published - chrono::Duration::days(1);
+		if let Some((prev_file, prev_date)) = merges.last() {
+			if *prev_date >= date {
+				return Err(format!(
+					"the case files must be in the order of their publication: {} (for {}) after {} (for {})",
+					file, date, prev_file, prev_date
+				));
+			}
+		}
+		merges.push((file.clone(), date));
+	}
+	Ok(merges)
+}
+
+struct MergeOptions {
+	// the number of previous versions of a diff file to keep
+	backups: usize,
+	threads: usize,
+}
+
+impl MergeOptions {
+	fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+		Ok(Self {
+			backups: match std::env::var("COVID_DIFF_BACKUPS") {
+				Ok(v) => v.parse::<usize>()?,
+				Err(_) => 1,
+			},
+			threads: match std::env::var("COVID_DIFF_THREADS") {
+				Ok(v) => v.parse::<usize>()?.max(1),
+				Err(_) => std::thread::available_parallelism()
+					.map(|n| n.get())
+					.unwrap_or(1),
+			},
+		})
+	}
+}
+
+// Merges the case files into the diff data at `datafile`. Case files which are not after the existing records are an error, or, with `resume`, skipped as merged by an earlier run.
+fn merge_into(
+	datafile: &str,
+	mut merges: Vec<(String, NaiveDate)>,
+	opts: &MergeOptions,
+	resume: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
 	let start = global_start_date();
 	let end = naive_today();
 	let mut counters = PartialDiffData::new(start, end);
 
-	println!("loading existing records ...");
-	try_load_existing(&mut *covid::default_output(), datafile, &mut counters)?;
+	// with a diff store, only the months which the merges write to are loaded and rewritten: those of the dates and, for the retractions, of the days before
+	let mut store = if DiffStore::is_store(datafile) {
+		Some(DiffStore::open(datafile)?)
+	} else {
+		None
+	};
+	let months: BTreeSet<NaiveDate> = merges
+		.iter()
+		.flat_map(|(_, date)| {
+			vec![
+				diff_partition_of(*date),
+				diff_partition_of(*date - chrono::Duration::days(1)),
+			]
+		})
+		.collect();
 
-	for pair in argv[2..].chunks(2) {
-		let newfile = &pair[0];
-		// subtract one because the publication refers to the day before
-		let date = pair[1].parse::<NaiveDate>()? - chrono::Duration::days(1);
-		println!("merging new records ({} -> {}) ...", newfile, date);
-		merge_new(&mut *covid::default_output(), newfile, date, &mut counters)?;
+	info!("loading existing records ...");
+	let last = match store.as_ref() {
+		Some(store) => {
+			load_partitions(&mut *covid::default_output(), store, &months, &mut counters)?
+		}
+		None => try_load_existing(&mut *covid::default_output(), datafile, &mut counters)?,
+	};
+	if let (true, Some(last)) = (resume, last) {
+		let n = merges.len();
+		merges.retain(|(_, date)| *date > last);
+		if merges.len() < n {
+			info!(
+				"skipping {} case files up to {}, merged before",
+				n - merges.len(),
+				last
+			);
+		}
+		if merges.is_empty() {
+			return Ok(());
+		}
+	}
+	// merging a case file twice would count its changes twice
+	if let (Some(last), Some((file, date))) = (last, merges.first()) {
+		if *date <= last {
+			return Err(format!(
+				"{} (for {}) is not after the existing records, which go up to {}",
+				file, date, last
+			)
+			.into());
+		}
 	}
 
-	println!("rewriting records ...");
-	writeback(&mut *covid::default_output(), datafile, &counters)?;
+	info!(
+		"merging {} case files on up to {} threads ...",
+		merges.len(),
+		opts.threads
+	);
+	// applied in the order of the arguments, so that the result does not depend on the number of threads
+	for diff in diff_files(&merges, start, end, opts.threads)? {
+		counters.apply(diff);
+	}
+	let pruned = counters.prune_zero_keys();
+	info!(
+		"{} keys ({} without any values dropped), {:.1} MiB",
+		counters.cases_by_pub.key_count(),
+		pruned,
+		counters.memory_usage() as f64 / (1024. * 1024.)
+	);
 
+	info!("rewriting records ...");
+	match store.as_mut() {
+		Some(store) => {
+			writeback_partitions(&mut *covid::default_output(), store, &months, &counters)?
+		}
+		None => writeback(
+			&mut *covid::default_output(),
+			datafile,
+			&counters,
+			opts.backups,
+		)?,
+	}
 	Ok(())
 }
+
+static SHA256SUMS: &str = "SHA256SUMS";
+
+// Merges the dumps published from `from` up to `to` in `dir` (by the dates in their names) into the diff data. Dumps missing from the directory are downloaded into it from `url_template`, with {date} replaced by the publication date. If the directory has a SHA256SUMS file, each dump must be in it and match. The dumps are merged in batches of COVID_BACKFILL_BATCH (default 30), each written back before the next one, so that an interrupted backfill continues with the first batch which is not through when run again.
+fn backfill(
+	datafile: &str,
+	dir: &Path,
+	from: NaiveDate,
+	to: NaiveDate,
+	url_template: Option<&str>,
+	opts: &MergeOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+	let mut dumps: BTreeMap<NaiveDate, PathBuf> = BTreeMap::new();
+	for entry in fs::read_dir(dir)? {
+		let path = entry?.path();
+		let name = path.file_name().and_then(|v| v.to_str()).unwrap_or("");
+		if name == SHA256SUMS || name.ends_with(".part") || !path.is_file() {
+			continue;
+		}
+		let date = match dump_publication_date(&path) {
+			Some(date) if date >= from && date <= to => date,
+			_ => continue,
+		};
+		if let Some(other) = dumps.insert(date, path.clone()) {
+			return Err(format!(
+				"two dumps published on {}: {} and {}",
+				date,
+				other.display(),
+				path.display()
+			)
+			.into());
+		}
+	}
+
+	if let Some(template) = url_template {
+		let fetcher = covid::env_fetcher();
+		for date in from.iter_days().take_while(|date| *date <= to) {
+			if dumps.contains_key(&date) {
+				continue;
+			}
+			let url = template.replace("{date}", &date.to_string());
+			let name = url
+				.split(['?', '#'])
+				.next()
+				.and_then(|v| v.rsplit('/').next())
+				.unwrap_or("");
+			// the date must be in the name, so that the dump is found again on the next run
+			let name = if dump_publication_date(name) == Some(date) {
+				name.to_string()
+			} else {
+				format!("{}_{}", date, name)
+			};
+			let path = dir.join(name);
+			if fetcher.download(&url, &path)? {
+				info!("downloaded {}", path.display());
+				dumps.insert(date, path);
+			} else {
+				warn!("no dump published on {} ({} not found)", date, url);
+			}
+		}
+	}
+
+	match File::open(dir.join(SHA256SUMS)) {
+		Ok(f) => {
+			let checksums = load_sha256sums(f)?;
+			for path in dumps.values() {
+				let name = path.file_name().and_then(|v| v.to_str()).unwrap_or("");
+				let expected = checksums
+					.get(name)
+					.ok_or_else(|| format!("{}: not in {}", path.display(), SHA256SUMS))?;
+				let actual = sha256_file(path)?;
+				if actual != *expected {
+					return Err(format!(
+						"{}: checksum mismatch (expected {}, got {})",
+						path.display(),
+						expected,
+						actual
+					)
+					.into());
+				}
+			}
+			info!("{} dumps match {}", dumps.len(), SHA256SUMS);
+		}
+		Err(e) if e.kind() == io::ErrorKind::NotFound => {
+			warn!(
+				"no {} in {}, the dumps are not verified",
+				SHA256SUMS,
+				dir.display()
+			);
+		}
+		Err(e) => return Err(e.into()),
+	}
+
+	// a gap attributes the changes of several days to the dump after it
+	for (prev, next) in dumps.keys().zip(dumps.keys().skip(1)) {
+		if *next - *prev > chrono::Duration::days(1) {
+			warn!("no dumps published between {} and {}", prev, next);
+		}
+	}
+
+	let batch = match std::env::var("COVID_BACKFILL_BATCH") {
+		Ok(v) => v.parse::<usize>()?.max(1),
+		Err(_) => 30,
+	};
+	// subtract one because the publication refers to the day before
+	let merges: Vec<(String, NaiveDate)> = dumps
+		.iter()
+		.map(|(date, path)| {
+			(
+				path.display().to_string(),
+				*date - chrono::Duration::days(1),
+			)
+		})
+		.collect();
+	info!(
+		"backfilling {} dumps from {} to {} in batches of {}",
+		merges.len(),
+		from,
+		to,
+		batch
+	);
+	for chunk in merges.chunks(batch) {
+		merge_into(datafile, chunk.to_vec(), opts, true)?;
+	}
+	Ok(())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+	covid::init_logging()?;
+	let argv: Vec<String> = std::env::args().collect();
+	if argv[1] == "--migrate" {
+		// rki_diff --migrate DIFF.csv STORE: converts a diff file into a (new) diff store
+		let n = DiffStore::create(&argv[3])?.migrate(&argv[2])?;
+		info!("migrated {} records into {}", n, argv[3]);
+		return Ok(());
+	}
+	let opts = MergeOptions::from_env()?;
+	if argv[1] == "--backfill" {
+		// rki_diff --backfill DATAFILE DUMPDIR FROM TO [URL_TEMPLATE]
+		if argv.len() < 6 {
+			return Err(
+				"usage: rki_diff --backfill DATAFILE DUMPDIR FROM TO [URL_TEMPLATE]".into(),
+			);
+		}
+		return backfill(
+			&argv[2],
+			Path::new(&argv[3]),
+			argv[4].parse()?,
+			argv[5].parse()?,
+			argv.get(6).map(|v| v.as_str()),
+			&opts,
+		);
+	}
+	merge_into(&argv[1], parse_merges(&argv[2..])?, &opts, false)
+}
@@ -0,0 +1,9 @@
+// Runs the to_influx pipeline on the same inputs, but writes one wide CSV file per measurement into the directory given with `--out` (default: the current directory).
+use covid::pipeline::{run, take_flag};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+	covid::init_logging()?;
+	let mut argv: Vec<String> = std::env::args().collect();
+	let dir = take_flag(&mut argv, "--out")?.unwrap_or_else(|| ".".into());
+	Ok(run(&argv, covid::Output::Csv(covid::CsvSink::new(dir)?))?)
+}
@@ -0,0 +1,77 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::NaiveDate;
+
+// High-water marks of stream_dynamic: per measurement, the last day for which all lines have been submitted successfully. The file has one `measurement<TAB>YYYY-MM-DD` line per measurement and is rewritten (via a temporary file and rename) on every update, so that it stays intact if the process dies.
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+	path: PathBuf,
+	done: BTreeMap<String, NaiveDate>,
+}
+
+impl Checkpoint {
+	// A missing file is treated as empty.
+	pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+		let path = path.as_ref().to_path_buf();
+		let mut done = BTreeMap::new();
+		let f = match fs::File::open(&path) {
+			Ok(f) => f,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self { path, done }),
+			Err(e) => return Err(e),
+		};
+		for line in io::BufReader::new(f).lines() {
+			let line = line?;
+			if line.is_empty() {
+				continue;
+			}
+			let (measurement, date) = line.split_once('\t').ok_or_else(|| {
+				io::Error::new(
+					io::ErrorKind::InvalidData,
+					format!("malformed checkpoint line: {:?}", line),
+				)
+			})?;
+			let date = date
+				.parse::<NaiveDate>()
+				.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+			done.insert(measurement.into(), date);
+		}
+		Ok(Self { path, done })
+	}
+
+	pub fn get(&self, measurement: &str) -> Option<NaiveDate> {
+		self.done.get(measurement).copied()
+	}
+
+	// Records (or with None: forgets) the high-water mark of a measurement and writes the file.
+	pub fn set(&mut self, measurement: &str, date: Option<NaiveDate>) -> io::Result<()> {
+		match date {
+			Some(date) => {
+				self.done.insert(measurement.into(), date);
+			}
+			None => {
+				if self.done.remove(measurement).is_none() {
+					return Ok(());
+				}
+			}
+		}
+		self.save()
+	}
+
+	fn save(&self) -> io::Result<()> {
+		let mut tmp = self.path.clone().into_os_string();
+		tmp.push(".tmp");
+		let tmp = PathBuf::from(tmp);
+		{
+			let mut f = io::BufWriter::new(fs::File::create(&tmp)?);
+			for (measurement, date) in self.done.iter() {
+				writeln!(f, "{}\t{}", measurement, date.format("%Y-%m-%d"))?;
+			}
+			f.into_inner()?.sync_all()?;
+		}
+		fs::rename(tmp, &self.path)
+	}
+}
@@ -3,18 +3,22 @@ use std::num::ParseIntError;
 use std::ops::{Deref, DerefMut};
 use std::str::FromStr;
 
-use serde::{de, Deserialize, Deserializer};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
 pub type DistrictId = u32;
 pub type StateId = u32;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Sex {
 	#[serde(rename = "M")]
 	Male,
 	#[serde(rename = "W")]
 	Female,
-	#[serde(rename = "unbekannt")]
+	// all of the others, if the sex dimension is collapsed on load
+	#[serde(rename = "gesamt")]
+	All,
+	// anything else the RKI may publish (e.g. "divers") is counted as unknown, instead of failing the load; serde requires this to be the last variant
+	#[serde(rename = "unbekannt", other)]
 	Unknown,
 }
 
@@ -24,6 +28,7 @@ impl fmt::Display for Sex {
 			Self::Male => f.write_str("M"),
 			Self::Female => f.write_str("W"),
 			Self::Unknown => f.write_str("unbekannt"),
+			Self::All => f.write_str("gesamt"),
 		}
 	}
 }
@@ -196,6 +201,25 @@ impl<'de> Deserialize<'de> for AgeGroup {
 	}
 }
 
+// as the strings they are parsed from, for the snapshots
+impl Serialize for AgeGroup {
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: Serializer,
+	{
+		serializer.collect_str(self)
+	}
+}
+
+impl Serialize for MaybeAgeGroup {
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: Serializer,
+	{
+		serializer.collect_str(self)
+	}
+}
+
 impl<'de> Deserialize<'de> for MaybeAgeGroup {
 	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
 	where
@@ -0,0 +1,148 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+
+use log::{debug, warn};
+
+use super::error::{Error, Result};
+
+// Typed records from a CSV file. Errors carry the file name, the line and, where csv can tell, the name of the offending column.
+pub struct CsvRecords<R> {
+	reader: csv::Reader<R>,
+	file: String,
+	headers: csv::StringRecord,
+	// number of skipped rows per reason, see deserialize_skipping
+	skipped: BTreeMap<String, usize>,
+}
+
+impl CsvRecords<Box<dyn io::Read>> {
+	// Opens the file (or URL) through env_fetcher, with the default CSV dialect.
+	pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+		Self::open_with(path, &csv::ReaderBuilder::new())
+	}
+
+	pub fn open_with<P: AsRef<Path>>(path: P, builder: &csv::ReaderBuilder) -> Result<Self> {
+		let file = path.as_ref().display().to_string();
+		let r = super::env_fetcher().open(path)?;
+		Self::new(file, builder.from_reader(r))
+	}
+}
+
+impl<R: io::Read> CsvRecords<R> {
+	// `file` only names the source in errors.
+	pub fn new<S: Into<String>>(file: S, mut reader: csv::Reader<R>) -> Result<Self> {
+		let file = file.into();
+		let headers = match reader.headers() {
+			Ok(v) => v.clone(),
+			Err(e) => return Err(with_context(&file, None, e)),
+		};
+		Ok(Self {
+			reader,
+			file,
+			headers,
+			skipped: BTreeMap::new(),
+		})
+	}
+
+	pub fn headers(&self) -> &csv::StringRecord {
+		&self.headers
+	}
+
+	pub fn file(&self) -> &str {
+		&self.file
+	}
+
+	// All records; the first error ends the load.
+	pub fn deserialize<'r, T: DeserializeOwned + 'r>(
+		&'r mut self,
+	) -> impl Iterator<Item = Result<T>> + 'r {
+		let file = &self.file;
+		let headers = &self.headers;
+		self.reader
+			.deserialize()
+			.map(move |row| row.map_err(|e| with_context(file, Some(headers), e)))
+	}
+
+	// Like deserialize, but rows which cannot be read as a record are skipped and counted per reason instead. Errors of the underlying reader still end the load.
+	pub fn deserialize_skipping<'r, T: DeserializeOwned + 'r>(
+		&'r mut self,
+	) -> impl Iterator<Item = Result<T>> + 'r {
+		let file = &self.file;
+		let headers = &self.headers;
+		let skipped = &mut self.skipped;
+		self.reader.deserialize().filter_map(move |row| {
+			let err = match row {
+				Ok(v) => return Some(Ok(v)),
+				Err(e) => e,
+			};
+			match skip_reason(headers, &err) {
+				Some(reason) => {
+					debug!("skipping row: {}", with_context(file, Some(headers), err));
+					*skipped.entry(reason).or_insert(0) += 1;
+					None
+				}
+				None => Some(Err(with_context(file, Some(headers), err))),
+			}
+		})
+	}
+
+	pub fn nskipped(&self) -> usize {
+		self.skipped.values().sum()
+	}
+
+	// The counts of skipped rows per reason as a warning, in the style of SubmitLog::log_summary.
+	pub fn log_summary(&self) {
+		if self.skipped.is_empty() {
+			return;
+		}
+		let reasons: Vec<String> = self
+			.skipped
+			.iter()
+			.map(|(reason, n)| format!("{}: {}", reason, n))
+			.collect();
+		warn!(
+			"... skipped {} unreadable rows of {} ({})",
+			self.nskipped(),
+			self.file,
+			reasons.join(", ")
+		);
+	}
+}
+
+fn column_name(headers: &csv::StringRecord, err: &csv::Error) -> Option<String> {
+	let index = match err.kind() {
+		csv::ErrorKind::Deserialize { err, .. } => err.field()?,
+		_ => return None,
+	};
+	Some(match headers.get(index as usize) {
+		Some(name) => name.into(),
+		None => format!("#{}", index),
+	})
+}
+
+// Why a row cannot be read as a record, or None if the error is not specific to the row.
+fn skip_reason(headers: &csv::StringRecord, err: &csv::Error) -> Option<String> {
+	match err.kind() {
+		csv::ErrorKind::Deserialize { err: de, .. } => Some(match column_name(headers, err) {
+			Some(column) => format!("{}: {}", column, de.kind()),
+			None => de.kind().to_string(),
+		}),
+		csv::ErrorKind::UnequalLengths { .. } => Some("wrong number of fields".into()),
+		csv::ErrorKind::Utf8 { .. } => Some("invalid UTF-8".into()),
+		_ => None,
+	}
+}
+
+fn with_context(file: &str, headers: Option<&csv::StringRecord>, err: csv::Error) -> Error {
+	if err.is_io_error() {
+		return err.into();
+	}
+	Error::Csv {
+		file: Some(file.into()),
+		line: err.position().map(|pos| pos.line()),
+		column: headers.and_then(|headers| column_name(headers, &err)),
+		source: err,
+	}
+}
@@ -0,0 +1,167 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use chrono::NaiveDate;
+
+use super::influxdb;
+use super::progress::{ProgressSink, StepMeter};
+use super::runlog::PhaseStats;
+use super::timeseries::{TimeSeriesKey, ViewTimeSeries};
+use super::{FieldDescriptor, KeySet, Result, StreamOptions};
+
+fn field_value(v: &influxdb::readout::FieldValue) -> String {
+	match v {
+		influxdb::readout::FieldValue::Numeric(v) => v.to_string(),
+		influxdb::readout::FieldValue::Integer(v) => v.to_string(),
+		influxdb::readout::FieldValue::Bool(v) => v.to_string(),
+		influxdb::readout::FieldValue::String(v) => v.to_string(),
+	}
+}
+
+// Writes keyed daily series as wide CSV files into a directory, one file per measurement (`<measurement>.csv`) with the columns date, tags... and fields...; there is one row per key and date and absent values are left empty. Existing files are overwritten.
+pub struct CsvSink {
+	dir: PathBuf,
+}
+
+impl CsvSink {
+	pub fn new<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+		let dir = dir.as_ref().to_path_buf();
+		fs::create_dir_all(&dir)?;
+		Ok(Self { dir })
+	}
+
+	fn create(&self, measurement: &str) -> io::Result<csv::Writer<fs::File>> {
+		// measurement names are plain identifiers in practice, but they must not escape the output directory
+		let name: String = measurement
+			.chars()
+			.map(|ch| match ch {
+				'/' | '\\' => '_',
+				ch => ch,
+			})
+			.collect();
+		Ok(csv::Writer::from_path(
+			self.dir.join(format!("{}.csv", name)),
+		)?)
+	}
+
+	#[allow(clippy::too_many_arguments)]
+	pub fn stream_dynamic<K: TimeSeriesKey, S: ProgressSink + ?Sized>(
+		&mut self,
+		progress: &mut S,
+		measurement: &str,
+		start: NaiveDate,
+		ndays: usize,
+		keyset: &KeySet<K>,
+		fields: &[FieldDescriptor<Arc<dyn ViewTimeSeries<K>>>],
+		options: &StreamOptions,
+	) -> Result<PhaseStats> {
+		let mut stats = PhaseStats::default();
+		if !options.is_enabled(measurement) {
+			return Ok(stats);
+		}
+		let measurement = options.measurement_name(measurement);
+		let mut w = self.create(measurement)?;
+		{
+			let mut header = vec!["date"];
+			header.extend(keyset.tag_names().iter().map(|name| name.as_str()));
+			header.extend(fields.iter().map(|desc| desc.name()));
+			w.write_record(&header)?;
+		}
+		let filter = options.sample_filter;
+		let dates: Vec<String> = start
+			.iter_days()
+			.take(ndays)
+			.map(|date| date.format("%Y-%m-%d").to_string())
+			.collect();
+
+		let mut pm = StepMeter::new(progress, keyset.len());
+		// values[field index * ndays + day]
+		let mut values = vec![f64::NAN; fields.len() * ndays];
+		let mut fieldv: Vec<String> = Vec::with_capacity(fields.len());
+		let mut previous: Vec<String> = Vec::new();
+		for (j, entry) in keyset.iter().enumerate() {
+			for (l, desc) in fields.iter().enumerate() {
+				desc.get_range(
+					entry.key,
+					start,
+					ndays,
+					&mut values[l * ndays..(l + 1) * ndays],
+				);
+			}
+			previous.clear();
+			for (i, date) in dates.iter().enumerate() {
+				fieldv.clear();
+				let mut any_present = false;
+				let mut all_default = true;
+				for l in 0..fields.len() {
+					let v = values[l * ndays + i];
+					if v.is_nan() {
+						fieldv.push(String::new());
+					} else {
+						any_present = true;
+						if v != 0. {
+							all_default = false;
+						}
+						fieldv.push(v.to_string());
+					}
+				}
+				if !any_present || (filter.skip_default && all_default) {
+					continue;
+				}
+				if filter.skip_unchanged {
+					if previous == fieldv {
+						continue;
+					}
+					previous.clear();
+					previous.extend_from_slice(&fieldv);
+				}
+				w.write_field(date)?;
+				for tagv in entry.tag_values.iter() {
+					w.write_field(tagv.as_str())?;
+				}
+				w.write_record(&fieldv)?;
+				stats.rows += 1;
+			}
+			pm.update(j + 1);
+		}
+		pm.finish();
+		w.flush()?;
+		Ok(stats)
+	}
+
+	// Appends an arbitrary readout (e.g. the run statistics) to `<measurement>.csv`, with the columns time (RFC 3339), tags... and fields.... The header is only written if the file is new.
+	pub fn write_readout(&mut self, readout: &influxdb::Readout) -> Result<()> {
+		let path = self.dir.join(format!("{}.csv", readout.measurement));
+		let is_new = !path.exists();
+		let f = fs::OpenOptions::new()
+			.create(true)
+			.append(true)
+			.open(path)?;
+		let mut w = csv::Writer::from_writer(f);
+		if is_new {
+			w.write_field("time")?;
+			w.write_record(
+				readout
+					.tags
+					.iter()
+					.chain(readout.fields.iter())
+					.map(|x| x.as_str()),
+			)?;
+		}
+		let ts = readout.ts.to_rfc3339();
+		for sample in readout.samples.iter() {
+			w.write_field(&ts)?;
+			w.write_record(
+				sample
+					.tagv
+					.iter()
+					.map(|x| x.to_string())
+					.chain(sample.fieldv.iter().map(field_value)),
+			)?;
+		}
+		w.flush()?;
+		Ok(())
+	}
+}
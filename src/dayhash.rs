@@ -0,0 +1,101 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::hash::Hasher;
+use std::io;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::NaiveDate;
+
+use fnv::FnvHasher;
+
+// Hash of the line protocol written for one day of a measurement. It does not depend on the order of the lines, as keysets are often built from hash maps. FNV is used as it is stable across builds, unlike the std hasher.
+pub fn day_hash(lines: &[u8]) -> u64 {
+	lines
+		.split_inclusive(|ch| *ch == b'\n')
+		.fold(0u64, |acc, line| {
+			let mut h = FnvHasher::default();
+			h.write(line);
+			acc.wrapping_add(h.finish())
+		})
+}
+
+// State of the incremental mode of stream_dynamic: per measurement and day, the hash of the lines submitted in an earlier run. The file has one `measurement<TAB>YYYY-MM-DD<TAB>hash` line per day and is replaced (via a temporary file and rename) on save.
+#[derive(Debug, Clone)]
+pub struct DayHashes {
+	path: PathBuf,
+	hashes: HashMap<String, BTreeMap<NaiveDate, u64>>,
+}
+
+impl DayHashes {
+	// A missing file is treated as empty.
+	pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+		let path = path.as_ref().to_path_buf();
+		let mut hashes: HashMap<String, BTreeMap<NaiveDate, u64>> = HashMap::new();
+		let f = match fs::File::open(&path) {
+			Ok(f) => f,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self { path, hashes }),
+			Err(e) => return Err(e),
+		};
+		for line in io::BufReader::new(f).lines() {
+			let line = line?;
+			if line.is_empty() {
+				continue;
+			}
+			let malformed = || {
+				io::Error::new(
+					io::ErrorKind::InvalidData,
+					format!("malformed day hash line: {:?}", line),
+				)
+			};
+			let mut parts = line.split('\t');
+			let (measurement, date, hash) = match (parts.next(), parts.next(), parts.next()) {
+				(Some(measurement), Some(date), Some(hash)) => (measurement, date, hash),
+				_ => return Err(malformed()),
+			};
+			let date = date.parse::<NaiveDate>().map_err(|_| malformed())?;
+			let hash = u64::from_str_radix(hash, 16).map_err(|_| malformed())?;
+			hashes
+				.entry(measurement.into())
+				.or_default()
+				.insert(date, hash);
+		}
+		Ok(Self { path, hashes })
+	}
+
+	pub fn get(&self, measurement: &str, date: NaiveDate) -> Option<u64> {
+		self.hashes.get(measurement)?.get(&date).copied()
+	}
+
+	// Merges the hashes of the days written for a measurement; days not included keep their previous hash.
+	pub fn update<I: IntoIterator<Item = (NaiveDate, u64)>>(&mut self, measurement: &str, days: I) {
+		self.hashes
+			.entry(measurement.into())
+			.or_default()
+			.extend(days);
+	}
+
+	pub fn save(&self) -> io::Result<()> {
+		let mut tmp = self.path.clone().into_os_string();
+		tmp.push(".tmp");
+		let tmp = PathBuf::from(tmp);
+		{
+			let mut f = io::BufWriter::new(fs::File::create(&tmp)?);
+			let mut measurements: Vec<_> = self.hashes.keys().collect();
+			measurements.sort();
+			for measurement in measurements {
+				for (date, hash) in self.hashes[measurement].iter() {
+					writeln!(
+						f,
+						"{}\t{}\t{:016x}",
+						measurement,
+						date.format("%Y-%m-%d"),
+						hash
+					)?;
+				}
+			}
+			f.into_inner()?.sync_all()?;
+		}
+		fs::rename(tmp, &self.path)
+	}
+}
@@ -1,3 +1,5 @@
+use chrono::{NaiveDate, Weekday};
+
 use serde::{de, Deserialize, Deserializer, Serialize};
 
 use super::context::{AgeGroup, Sex, StateId};
@@ -49,6 +51,65 @@ where
 	Ok(low_s.parse::<u32>().map_err(de::Error::custom)?)
 }
 
+fn destatis_week<'de, D>(deserializer: D) -> Result<u32, D::Error>
+where
+	D: Deserializer<'de>,
+{
+	let s = String::deserialize(deserializer)?;
+	if !s.starts_with("KW") {
+		return Err(de::Error::custom("destatis week must start with KW"));
+	}
+	s[2..].parse::<u32>().map_err(de::Error::custom)
+}
+
+// "DG" is Germany as a whole, the states use their two-digit code
+fn destatis_state<'de, D>(deserializer: D) -> Result<StateId, D::Error>
+where
+	D: Deserializer<'de>,
+{
+	let s = String::deserialize(deserializer)?;
+	if s == "DG" {
+		return Ok(0);
+	}
+	s.parse::<StateId>().map_err(de::Error::custom)
+}
+
+// age bands of the death statistics, e.g. "ALT030B35" (30 to under 35) or "ALT095UM" (95 and over); the empty code is the sum over all ages, which is returned as 0+ like in the hospitalization data
+fn destatis_age_band<'de, D>(deserializer: D) -> Result<AgeGroup, D::Error>
+where
+	D: Deserializer<'de>,
+{
+	let s = String::deserialize(deserializer)?;
+	if s.is_empty() || s == "INSGESAMT" {
+		return Ok(AgeGroup { low: 0, high: None });
+	}
+	if !s.starts_with("ALT") || s.len() < 6 {
+		return Err(de::Error::custom("destatis age must start with ALT"));
+	}
+	let low = s[3..6].parse::<u16>().map_err(de::Error::custom)?;
+	let rest = &s[6..];
+	if rest == "UM" {
+		return Ok(AgeGroup { low, high: None });
+	}
+	if rest.is_empty() {
+		return Ok(AgeGroup {
+			low,
+			high: Some(low),
+		});
+	}
+	if !rest.starts_with('B') {
+		return Err(de::Error::custom("unrecognized destatis age band"));
+	}
+	let end = rest[1..].parse::<u16>().map_err(de::Error::custom)?;
+	if end <= low {
+		return Err(de::Error::custom("empty destatis age band"));
+	}
+	Ok(AgeGroup {
+		low,
+		high: Some(end - 1),
+	})
+}
+
 fn destatis_maybe_f64<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
 where
 	D: Deserializer<'de>,
@@ -60,8 +121,29 @@ where
 	Ok(Some(s.parse::<f64>().map_err(de::Error::custom)?))
 }
 
+// reference date of a population count: "31.12.2020" in the GENESIS export, ISO dates and bare years (end of the year) are accepted, too
+fn destatis_stichtag<'de, D>(deserializer: D) -> Result<Option<NaiveDate>, D::Error>
+where
+	D: Deserializer<'de>,
+{
+	let s = String::deserialize(deserializer)?;
+	if let Ok(date) = NaiveDate::parse_from_str(&s, "%d.%m.%Y") {
+		return Ok(Some(date));
+	}
+	if let Ok(date) = s.parse::<NaiveDate>() {
+		return Ok(Some(date));
+	}
+	let year = s.parse::<i32>().map_err(de::Error::custom)?;
+	NaiveDate::from_ymd_opt(year, 12, 31)
+		.map(Some)
+		.ok_or_else(|| de::Error::custom("year out of range"))
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct RawDestatisRow {
+	// older exports lack the column, see RawPopulationData::ref_date
+	#[serde(rename = "Zeit", default, deserialize_with = "destatis_stichtag")]
+	pub date: Option<NaiveDate>,
 	#[serde(rename = "1_Auspraegung_Code")]
 	pub state_id: StateId,
 	#[serde(rename = "2_Auspraegung_Code", deserialize_with = "destatis_sex")]
@@ -85,6 +167,51 @@ pub struct RawDestatisDeathByMonthRow {
 	pub death_incidence_per_1k: Option<f64>,
 }
 
+// deaths per calendar week ("Sterbefälle nach Kalenderwochen"), by state and age band
+#[derive(Debug, Clone, Deserialize)]
+pub struct RawDestatisDeathByWeekRow {
+	#[serde(rename = "Zeit")]
+	pub year: i32,
+	#[serde(rename = "1_Auspraegung_Code", deserialize_with = "destatis_state")]
+	pub state_id: StateId,
+	#[serde(rename = "2_Auspraegung_Code", deserialize_with = "destatis_week")]
+	pub week: u32,
+	#[serde(rename = "3_Auspraegung_Code", deserialize_with = "destatis_age_band")]
+	pub age_group: AgeGroup,
+	#[serde(
+		rename = "BEV016__Sterbefaelle__Anzahl",
+		deserialize_with = "destatis_maybe_f64"
+	)]
+	pub deaths: Option<f64>,
+}
+
+impl RawDestatisDeathByWeekRow {
+	// the sunday which ends the (ISO) calendar week
+	pub fn week_end(&self) -> Option<NaiveDate> {
+		NaiveDate::from_isoywd_opt(self.year, self.week, Weekday::Sun)
+	}
+}
+
+// Median of an ascendingly sorted, non-empty slice.
+pub fn sorted_median(sl: &[f64]) -> f64 {
+	assert!(!sl.is_empty());
+	let n = sl.len();
+	if n.is_multiple_of(2) {
+		(sl[n / 2 - 1] + sl[n / 2]) / 2.
+	} else {
+		// if odd, this will select the center element, as / will implicitly round down and the index is zero-based
+		sl[n / 2]
+	}
+}
+
+// Position of v in an ascendingly sorted, non-empty slice, in percent: the share of values below v, with equal values counting half.
+pub fn percentile_rank(sl: &[f64], v: f64) -> f64 {
+	assert!(!sl.is_empty());
+	let below = sl.iter().filter(|x| **x < v).count();
+	let equal = sl.iter().filter(|x| **x == v).count();
+	(below as f64 + equal as f64 / 2.) / (sl.len() as f64) * 100.
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DestatisDeathHistoric {
 	// types chosen for conversion-less compat with chrono::Datelike
@@ -94,6 +221,8 @@ pub struct DestatisDeathHistoric {
 	pub median: f64,
 	pub max: f64,
 	pub sum: f64,
+	// sample standard deviation, zero for a single year
+	pub stddev: f64,
 }
 
 impl DestatisDeathHistoric {
@@ -110,14 +239,12 @@ impl DestatisDeathHistoric {
 			sum += v;
 		}
 		let mean = sum / (sl.len() as f64);
-		let median = if sl.len() % 2 == 0 {
-			// neither of these can panic, because we assert that there is at least one element in the slice at the beginning
-			let v1 = sl[sl.len() / 2];
-			let v2 = sl[sl.len() / 2 + 1];
-			(v1 + v2) / 2.
+		let median = sorted_median(sl);
+		let stddev = if sl.len() > 1 {
+			let sqdev: f64 = sl.iter().map(|v| (v - mean) * (v - mean)).sum();
+			(sqdev / (sl.len() - 1) as f64).sqrt()
 		} else {
-			// if odd, this will select the center element, as / will implicitly round down and the index is zero-based
-			sl[sl.len() / 2]
+			0.
 		};
 		Self {
 			month,
@@ -126,6 +253,16 @@ impl DestatisDeathHistoric {
 			median,
 			max: sl[sl.len() - 1],
 			sum,
+			stddev,
+		}
+	}
+
+	// Distance of v from the mean in standard deviations; None if the samples do not vary.
+	pub fn z_score(&self, v: f64) -> Option<f64> {
+		if self.stddev > 0. {
+			Some((v - self.mean) / self.stddev)
+		} else {
+			None
 		}
 	}
 }
@@ -136,4 +273,7 @@ pub struct DestatisDeathCurrent {
 	pub year: i32,
 	pub month: u32,
 	pub death_incidence_per_inhabitant: f64,
+	// relative to the pre-pandemic years of the same month, see DestatisDeathHistoric::z_score and percentile_rank; empty if there are no such years
+	pub z_score: Option<f64>,
+	pub percentile: Option<f64>,
 }
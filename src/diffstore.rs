@@ -0,0 +1,235 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{Datelike, NaiveDate};
+
+use log::info;
+
+use super::csvin::CsvRecords;
+use super::error::{Error, Result};
+use super::ioutil::magic_open;
+use super::rki::{DiffRecord, DiffSchema};
+
+static INDEX_FILE: &str = "index";
+static INDEX_HEADER: &str = "covid-diff\t2";
+
+// The first day of the month of `date`, which names the partition it is stored in.
+pub fn diff_partition_of(date: NaiveDate) -> NaiveDate {
+	NaiveDate::from_ymd_opt(date.year(), date.month(), 1).unwrap()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffPartition {
+	pub month: NaiveDate,
+	// relative to the directory of the store
+	pub file: String,
+	pub rows: usize,
+}
+
+// The second version of the diff file of rki_diff: a directory with one zstd-compressed CSV (in the columns of DiffRecord) per month of publication dates, and an index of the partitions. The index has a `covid-diff<TAB>2` header and one `YYYY-MM-01<TAB>file<TAB>rows` line per partition. Partitions and the index are replaced via a temporary file and rename, so that a merge only rewrites the months it touches and leaves the store intact if the process dies.
+#[derive(Debug, Clone)]
+pub struct DiffStore {
+	dir: PathBuf,
+	partitions: BTreeMap<NaiveDate, DiffPartition>,
+}
+
+impl DiffStore {
+	// Diff files of the first version are plain files, stores are directories.
+	pub fn is_store<P: AsRef<Path>>(path: P) -> bool {
+		path.as_ref().is_dir()
+	}
+
+	// A directory without index is an empty store.
+	pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self> {
+		let dir = dir.as_ref().to_path_buf();
+		let mut partitions = BTreeMap::new();
+		let f = match fs::File::open(dir.join(INDEX_FILE)) {
+			Ok(f) => f,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self { dir, partitions }),
+			Err(e) => return Err(e.into()),
+		};
+		let mut lines = io::BufReader::new(f).lines();
+		match lines.next().transpose()? {
+			Some(header) if header == INDEX_HEADER => (),
+			other => {
+				return Err(Error::Schema(format!(
+					"{}: not a diff store index (header {:?})",
+					dir.display(),
+					other
+				)))
+			}
+		}
+		for line in lines {
+			let line = line?;
+			if line.is_empty() {
+				continue;
+			}
+			let partition = parse_index_line(&line).ok_or_else(|| {
+				Error::Schema(format!(
+					"{}: malformed index line: {:?}",
+					dir.display(),
+					line
+				))
+			})?;
+			partitions.insert(partition.month, partition);
+		}
+		Ok(Self { dir, partitions })
+	}
+
+	pub fn create<P: AsRef<Path>>(dir: P) -> Result<Self> {
+		fs::create_dir_all(dir.as_ref())?;
+		Self::open(dir)
+	}
+
+	// in the order of the months
+	pub fn partitions(&self) -> impl Iterator<Item = &DiffPartition> {
+		self.partitions.values()
+	}
+
+	pub fn partition(&self, month: NaiveDate) -> Option<&DiffPartition> {
+		self.partitions.get(&month)
+	}
+
+	pub fn rows(&self) -> usize {
+		self.partitions.values().map(|p| p.rows).sum()
+	}
+
+	pub fn partition_path(&self, partition: &DiffPartition) -> PathBuf {
+		self.dir.join(&partition.file)
+	}
+
+	pub fn read_partition(
+		&self,
+		partition: &DiffPartition,
+	) -> Result<CsvRecords<Box<dyn io::Read>>> {
+		let path = self.partition_path(partition);
+		let r = CsvRecords::new(
+			path.display().to_string(),
+			csv::Reader::from_reader(magic_open(&path)?),
+		)?;
+		DiffSchema::detect(r.headers())?;
+		Ok(r)
+	}
+
+	// Replaces the partition of `month` with `records`, which must all have their date in that month.
+	pub fn write_partition<'r, I: IntoIterator<Item = &'r DiffRecord>>(
+		&mut self,
+		month: NaiveDate,
+		records: I,
+	) -> Result<()> {
+		let month = diff_partition_of(month);
+		let file = format!("{}.csv.zst", month.format("%Y-%m"));
+		let path = self.dir.join(&file);
+		let tmp = self.dir.join(format!("{}.tmp", file));
+		let mut rows = 0;
+		{
+			let encoder = zstd::stream::write::Encoder::new(fs::File::create(&tmp)?, 0)?;
+			let mut w = DiffRecord::writer(encoder)?;
+			for rec in records {
+				if diff_partition_of(rec.date) != month {
+					drop(w);
+					fs::remove_file(&tmp)?;
+					return Err(Error::Schema(format!(
+						"record of {} does not belong into the partition of {}",
+						rec.date,
+						month.format("%Y-%m")
+					)));
+				}
+				w.serialize(rec)?;
+				rows += 1;
+			}
+			w.flush()?;
+			w.into_inner()
+				.map_err(|e| io::Error::new(e.error().kind(), e.to_string()))?
+				.finish()?
+				.sync_all()?;
+		}
+		// the partition is only replaced once it reads back in full
+		let mut r = csv::Reader::from_reader(magic_open(&tmp)?);
+		let mut written = 0;
+		for rec in r.records() {
+			rec?;
+			written += 1;
+		}
+		if written != rows {
+			return Err(Error::Schema(format!(
+				"{}: {} records written, but {} read back",
+				tmp.display(),
+				rows,
+				written
+			)));
+		}
+		fs::rename(tmp, path)?;
+		self.partitions
+			.insert(month, DiffPartition { month, file, rows });
+		self.save_index()?;
+		Ok(())
+	}
+
+	fn save_index(&self) -> io::Result<()> {
+		let path = self.dir.join(INDEX_FILE);
+		let tmp = self.dir.join(format!("{}.tmp", INDEX_FILE));
+		{
+			let mut f = io::BufWriter::new(fs::File::create(&tmp)?);
+			writeln!(f, "{}", INDEX_HEADER)?;
+			for p in self.partitions.values() {
+				writeln!(f, "{}\t{}\t{}", p.month, p.file, p.rows)?;
+			}
+			f.flush()?;
+		}
+		fs::rename(tmp, path)
+	}
+
+	// Splits a diff file of the first version into partitions. It must be sorted by date, as rki_diff writes it. Returns the number of records.
+	pub fn migrate<P: AsRef<Path>>(&mut self, v1: P) -> Result<usize> {
+		let path = v1.as_ref();
+		let mut r = CsvRecords::new(
+			path.display().to_string(),
+			csv::Reader::from_reader(fs::File::open(path)?),
+		)?;
+		let schema = DiffSchema::detect(r.headers())?;
+		info!("migrating {} ({} diff file)", r.file(), schema);
+		let mut month = None;
+		let mut buf = Vec::new();
+		let mut n = 0;
+		for row in r.deserialize() {
+			let rec: DiffRecord = row?;
+			let rec_month = diff_partition_of(rec.date);
+			if month != Some(rec_month) {
+				if let Some(month) = month {
+					if rec_month < month {
+						return Err(Error::Schema(format!(
+							"{}: not sorted by date ({} after {})",
+							path.display(),
+							rec.date,
+							month.format("%Y-%m")
+						)));
+					}
+					self.write_partition(month, buf.iter())?;
+					buf.clear();
+				}
+				month = Some(rec_month);
+			}
+			buf.push(rec);
+			n += 1;
+		}
+		if let Some(month) = month {
+			self.write_partition(month, buf.iter())?;
+		}
+		Ok(n)
+	}
+}
+
+fn parse_index_line(line: &str) -> Option<DiffPartition> {
+	let mut parts = line.split('\t');
+	let month = parts.next()?.parse::<NaiveDate>().ok()?;
+	let file = parts.next()?.to_string();
+	let rows = parts.next()?.parse().ok()?;
+	if parts.next().is_some() || month != diff_partition_of(month) {
+		return None;
+	}
+	Some(DiffPartition { month, file, rows })
+}
@@ -1,4 +1,4 @@
-use serde::Deserialize;
+use serde::{de, Deserialize, Deserializer};
 
 use chrono::naive::NaiveDate;
 
@@ -29,25 +29,54 @@ pub struct ICULoadRecord {
 	pub beds_free_adult_only: u32,
 }
 
-/* fn divi_date_compat<'de, D>(deserializer: D) -> Result<NaiveDate, D::Error>
-	where D: Deserializer<'de>
+// One row of the DIVI register at hospital site granularity: one row per site and day. The numbers are those of the site alone; the district file has the sums over all sites of a district.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ICUSiteLoadRecord {
+	pub date: NaiveDate,
+	#[serde(rename = "bundesland")]
+	pub state_id: StateId,
+	#[serde(rename = "gemeindeschluessel")]
+	pub district_id: DistrictId,
+	#[serde(rename = "standort_id")]
+	pub site_id: String,
+	#[serde(rename = "anzahl_meldebereiche")]
+	pub num_regions: u32,
+	#[serde(rename = "faelle_covid_aktuell")]
+	pub current_covid_cases: u32,
+	#[serde(rename = "faelle_covid_aktuell_invasiv_beatmet")]
+	pub current_covid_cases_invasive_ventilation: u32,
+	#[serde(rename = "betten_frei")]
+	pub beds_free: u32,
+	#[serde(rename = "betten_belegt")]
+	pub beds_in_use: u32,
+	#[serde(rename = "betten_belegt_nur_erwachsen")]
+	pub beds_in_use_adult_only: u32,
+	#[serde(rename = "betten_frei_nur_erwachsen")]
+	pub beds_free_adult_only: u32,
+}
+
+// The DIVI files use either plain ISO dates or timestamps of the form 2020/04/24 12:15:00; the time is dropped.
+fn divi_date_compat<'de, D>(deserializer: D) -> Result<NaiveDate, D::Error>
+where
+	D: Deserializer<'de>,
 {
-	let mut s = String::deserialize(deserializer)?;
-	s.split(".").collect();
-	if s.len() == 10 {
-		// plain ISO date
-		s.parse::<NaiveDate>().map_err(de::Error::custom)
-	} else if s.len() == 19 {
-		// full pseudo-ISO date
-		s.truncate(10);
-		let s = s.replace("/", "-");
-		s.parse::<NaiveDate>().map_err(de::Error::custom)
-	} else {
-		Err(de::Error::custom("invalid length for date, must be eiter 10 or 19 bytes"))
+	let s = String::deserialize(deserializer)?;
+	match s.len() {
+		10 => s.parse::<NaiveDate>().map_err(de::Error::custom),
+		19 => s
+			.get(..10)
+			.ok_or_else(|| de::Error::custom("invalid timestamp"))?
+			.replace('/', "-")
+			.parse::<NaiveDate>()
+			.map_err(de::Error::custom),
+		_ => Err(de::Error::custom(
+			"invalid length for date, must be either 10 or 19 bytes",
+		)),
 	}
-} */
+}
 
-/* #[derive(Debug, Clone, Deserialize)]
+// National number of ICU reporting areas which report a limitation of their operation, by reason. An area may report several reasons.
+#[derive(Debug, Clone, Deserialize)]
 pub struct ICUUnavailableReasonRecord {
 	#[serde(deserialize_with = "divi_date_compat")]
 	pub date: NaiveDate,
@@ -61,4 +90,4 @@ pub struct ICUUnavailableReasonRecord {
 	pub missing_material: u32,
 	#[serde(rename = "einschraenkung_beatmungsgeraet")]
 	pub missing_ventilator: u32,
-} */
+}
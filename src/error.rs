@@ -0,0 +1,87 @@
+use std::io;
+
+use chrono::NaiveDate;
+
+use thiserror::Error;
+
+use super::influxdb;
+use super::submit::SubmitError;
+
+// The error type of the library: loading, cooking and streaming all report through it, so that callers can tell bad input from a failing sink.
+#[derive(Debug, Error)]
+pub enum Error {
+	#[error(transparent)]
+	Io(#[from] io::Error),
+	// the line is that of the offending record, if csv knows it; file and column are filled in by CsvRecords
+	#[error("{}{source}", csv_context(.file, .line, .column))]
+	Csv {
+		file: Option<String>,
+		line: Option<u64>,
+		column: Option<String>,
+		source: csv::Error,
+	},
+	// input which is readable, but not what is expected of it, e.g. an invalid aggregate spec or a missing field
+	#[error("{0}")]
+	Schema(String),
+	// a row which the submit policy does not allow to skip
+	#[error("row {row}: {source}")]
+	Rejected { row: usize, source: SubmitError },
+	#[error("invalid date range: {start} to {end}")]
+	DateRange { start: NaiveDate, end: NaiveDate },
+	#[error(transparent)]
+	Sink(influxdb::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+fn csv_context(file: &Option<String>, line: &Option<u64>, column: &Option<String>) -> String {
+	let mut parts = Vec::new();
+	if let Some(file) = file {
+		parts.push(file.clone());
+	}
+	if let Some(line) = line {
+		parts.push(format!("line {}", line));
+	}
+	if let Some(column) = column {
+		parts.push(format!("column {}", column));
+	}
+	if parts.is_empty() {
+		return String::new();
+	}
+	format!("{}: ", parts.join(", "))
+}
+
+impl From<csv::Error> for Error {
+	fn from(err: csv::Error) -> Self {
+		if err.is_io_error() {
+			match err.into_kind() {
+				csv::ErrorKind::Io(e) => return Self::Io(e),
+				_ => unreachable!(),
+			}
+		}
+		Self::Csv {
+			file: None,
+			line: err.position().map(|pos| pos.line()),
+			column: None,
+			source: err,
+		}
+	}
+}
+
+impl From<influxdb::Error> for Error {
+	fn from(err: influxdb::Error) -> Self {
+		match err {
+			influxdb::Error::Io(e) => Self::Io(e),
+			other => Self::Sink(other),
+		}
+	}
+}
+
+impl From<Error> for io::Error {
+	fn from(err: Error) -> Self {
+		match err {
+			Error::Io(e) => e,
+			other => Self::new(io::ErrorKind::InvalidData, other),
+		}
+	}
+}
@@ -0,0 +1,184 @@
+use std::io;
+use std::path::Path;
+
+use smartstring::alias::String as SmartString;
+
+use serde::{Deserialize, Serialize};
+
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+
+use super::csvin::CsvRecords;
+use super::error::Result;
+use super::holidays::HolidayRecord;
+use super::influxdb;
+use super::progress::{CountMeter, ProgressSink};
+use super::StreamOptions;
+
+pub static EVENTS_MEASUREMENT: &str = "events_v1";
+
+// A time range to be shown as an annotation, e.g. a holiday or an intervention.
+#[derive(Debug, Clone)]
+pub struct Event {
+	pub state: SmartString,
+	// the name of the holiday or the measure
+	pub name: SmartString,
+	// "holiday" or "intervention"
+	pub category: SmartString,
+	// for holidays school or public, for interventions "npi"
+	pub kind: SmartString,
+	pub start: NaiveDate,
+	pub end: NaiveDate,
+}
+
+impl Event {
+	// start and end of the event, at midnight
+	pub fn range(&self) -> (DateTime<Utc>, DateTime<Utc>) {
+		let start = Utc.from_utc_datetime(&self.start.and_hms_opt(0, 0, 0).unwrap());
+		let end = Utc.from_utc_datetime(&self.end.and_hms_opt(0, 0, 0).unwrap());
+		(start, end)
+	}
+
+	pub fn text(&self) -> String {
+		format!("{}\n\n<sup>{}</sup>", self.name, self.state)
+	}
+}
+
+impl From<HolidayRecord> for Event {
+	fn from(other: HolidayRecord) -> Self {
+		Self {
+			state: other.state,
+			name: other.holiday,
+			category: "holiday".into(),
+			kind: other.kind.to_string().into(),
+			start: other.start,
+			end: other.end,
+		}
+	}
+}
+
+// A non-pharmaceutical intervention (lockdown, mask mandate, 2G/3G rule, ...) in a state, from the curated intervention timeline.
+#[derive(Debug, Clone, Deserialize)]
+pub struct InterventionRecord {
+	pub state: SmartString,
+	pub measure: SmartString,
+	pub start: NaiveDate,
+	// empty while the measure is in force
+	pub end: Option<NaiveDate>,
+}
+
+impl From<InterventionRecord> for Event {
+	fn from(other: InterventionRecord) -> Self {
+		Self {
+			state: other.state,
+			name: other.measure,
+			category: "intervention".into(),
+			kind: "npi".into(),
+			start: other.start,
+			end: other.end.unwrap_or_else(super::naive_today),
+		}
+	}
+}
+
+pub fn load_interventions<P: AsRef<Path>>(path: P) -> Result<Vec<InterventionRecord>> {
+	let mut r = CsvRecords::open(path)?;
+	r.deserialize().collect()
+}
+
+// an entry of the Grafana annotation API, see https://grafana.com/docs/grafana/latest/developers/http_api/annotations/
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GrafanaAnnotation {
+	// milliseconds since the epoch
+	time: i64,
+	time_end: i64,
+	is_region: bool,
+	tags: Vec<SmartString>,
+	text: String,
+}
+
+// Writes the events as a JSON list for the Grafana annotation import, tagged with category, kind and state.
+pub fn write_annotations<W: io::Write>(w: W, events: &[Event]) -> Result<()> {
+	let annotations: Vec<_> = events
+		.iter()
+		.map(|ev| {
+			let (start, end) = ev.range();
+			GrafanaAnnotation {
+				time: start.timestamp_millis(),
+				time_end: end.timestamp_millis(),
+				is_region: end > start,
+				tags: vec![ev.category.clone(), ev.kind.clone(), ev.state.clone()],
+				text: ev.text(),
+			}
+		})
+		.collect();
+	serde_json::to_writer_pretty(w, &annotations).map_err(io::Error::from)?;
+	Ok(())
+}
+
+// Writes the events into events_v1, one point per event at its start.
+pub fn stream_events<I: Iterator<Item = Event>, S: ProgressSink + ?Sized>(
+	s: &mut S,
+	events: I,
+	client: &influxdb::Client,
+	options: &StreamOptions,
+) -> Result<()> {
+	if !options.is_enabled(EVENTS_MEASUREMENT) {
+		return Ok(());
+	}
+	let measurement = options.measurement_name(EVENTS_MEASUREMENT);
+	let retention_policy = options.retention_policy.as_deref();
+	// holiday_kind is the name of the event, it predates the interventions
+	let tags: Vec<SmartString> = vec![
+		"state".into(),
+		"is_holiday".into(),
+		"holiday_kind".into(),
+		"kind".into(),
+	];
+	let fields: Vec<SmartString> = vec!["text".into(), "end".into()];
+
+	let mut pm = CountMeter::new(s);
+	let mut n = 0;
+	let mut readout_buf = Vec::with_capacity(16);
+	for (i, ev) in events.enumerate() {
+		let (start, end) = ev.range();
+		let is_holiday = if ev.category == "holiday" {
+			"true"
+		} else {
+			"false"
+		};
+		readout_buf.push(influxdb::Readout {
+			ts: start,
+			measurement: measurement.into(),
+			precision: influxdb::Precision::Seconds,
+			fields: fields.clone(),
+			tags: tags.clone(),
+			samples: vec![influxdb::Sample {
+				fieldv: vec![ev.text().into(), format!("{}000", end.timestamp()).into()],
+				tagv: vec![ev.state, is_holiday.into(), ev.name, ev.kind],
+			}],
+		});
+		if readout_buf.len() == readout_buf.capacity() {
+			client.post(
+				&options.database,
+				retention_policy,
+				None,
+				readout_buf[0].precision,
+				&readout_buf[..],
+			)?;
+			readout_buf.clear();
+			pm.update(i + 1);
+		}
+		n = i + 1;
+	}
+	if !readout_buf.is_empty() {
+		client.post(
+			&options.database,
+			retention_policy,
+			None,
+			readout_buf[0].precision,
+			&readout_buf[..],
+		)?;
+	}
+	pm.finish(n);
+	Ok(())
+}
@@ -0,0 +1,138 @@
+use std::borrow::Cow;
+use std::str::FromStr;
+
+use log::error;
+
+use super::error::{Error, Result};
+use super::{env_client_with_url, CsvSink, Output, ParquetSink, SqliteSink, StreamOptions};
+
+// What a FanOut does when one of its targets fails: FailFast aborts the run, like a single output would; LogAndContinue logs the error and carries on with the other targets, e.g. for a staging server which may be down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SinkErrorPolicy {
+	#[default]
+	FailFast,
+	LogAndContinue,
+}
+
+impl FromStr for SinkErrorPolicy {
+	type Err = String;
+
+	fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+		match s.trim() {
+			"fail" => Ok(Self::FailFast),
+			"continue" => Ok(Self::LogAndContinue),
+			other => Err(format!(
+				"invalid error policy {:?}, expected fail or continue",
+				other
+			)),
+		}
+	}
+}
+
+pub struct FanOutTarget {
+	pub name: String,
+	pub output: Output,
+	pub policy: SinkErrorPolicy,
+	// number of measurements (and run statistics) which failed with LogAndContinue
+	failures: usize,
+}
+
+impl FanOutTarget {
+	pub fn failures(&self) -> usize {
+		self.failures
+	}
+}
+
+// Writes the same run into several outputs, e.g. two InfluxDB servers and a local line protocol file. The targets are written one after the other, in the order they were added. The checkpoint and the incremental state (see StreamOptions) describe what the first target has, so the others are always written in full.
+#[derive(Default)]
+pub struct FanOut {
+	targets: Vec<FanOutTarget>,
+}
+
+impl FanOut {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn with_target<S: Into<String>>(
+		mut self,
+		name: S,
+		output: Output,
+		policy: SinkErrorPolicy,
+	) -> Self {
+		self.targets.push(FanOutTarget {
+			name: name.into(),
+			output,
+			policy,
+			failures: 0,
+		});
+		self
+	}
+
+	// Adds a target described as KIND=TARGET[,POLICY], with KIND one of influx (the URL of the server, with the credentials of the environment), lp (a line protocol file), sqlite (a SQLite file), csv or parquet (a directory), and POLICY fail (the default) or continue.
+	pub fn with_spec(self, spec: &str) -> Result<Self> {
+		let (target, policy) = match spec.rsplit_once(',') {
+			Some((target, policy)) if policy == "fail" || policy == "continue" => {
+				(target, policy.parse().map_err(Error::Schema)?)
+			}
+			_ => (spec, SinkErrorPolicy::default()),
+		};
+		let (kind, path) = target
+			.split_once('=')
+			.ok_or_else(|| Error::Schema(format!("expected kind=target, got {:?}", spec)))?;
+		let output = match kind {
+			"influx" => Output::Influx(env_client_with_url(path.into())),
+			"lp" => Output::line_protocol_file(path)?,
+			"sqlite" => Output::Sqlite(SqliteSink::open(path)?),
+			"csv" => Output::Csv(CsvSink::new(path)?),
+			"parquet" => Output::Parquet(ParquetSink::new(path)?),
+			other => {
+				return Err(Error::Schema(format!(
+					"unknown output kind {:?}, expected influx, lp, sqlite, csv or parquet",
+					other
+				)))
+			}
+		};
+		Ok(self.with_target(target, output, policy))
+	}
+
+	pub fn targets(&self) -> &[FanOutTarget] {
+		&self.targets
+	}
+
+	// Calls `f` for each target, with the options for that target, and returns the result of the first one which succeeded.
+	pub(crate) fn each<T, F: FnMut(&mut Output, &StreamOptions) -> Result<T>>(
+		&mut self,
+		what: &str,
+		options: &StreamOptions,
+		mut f: F,
+	) -> Result<Option<T>> {
+		let mut result = None;
+		for (i, target) in self.targets.iter_mut().enumerate() {
+			let options = if i == 0 {
+				Cow::Borrowed(options)
+			} else {
+				let mut options = options.clone();
+				options.checkpoint = None;
+				options.incremental_state = None;
+				Cow::Owned(options)
+			};
+			match f(&mut target.output, &options) {
+				Ok(v) => {
+					if result.is_none() {
+						result = Some(v);
+					}
+				}
+				Err(e) if target.policy == SinkErrorPolicy::LogAndContinue => {
+					error!(
+						"{}: writing {} failed, continuing with the other outputs: {}",
+						target.name, what, e
+					);
+					target.failures += 1;
+				}
+				Err(e) => return Err(e),
+			}
+		}
+		Ok(result)
+	}
+}
@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use log::{debug, trace};
+
+use sha2::{Digest, Sha256};
+
+use super::ioutil::{magic_open, magic_wrap};
+
+// the longest tail of the URL path kept in a cache file name; with the hash and the .meta or .part suffix, the names stay well within NAME_MAX (255 bytes)
+static MAX_BASENAME_CHARS: usize = 64;
+
+pub fn is_url(s: &str) -> bool {
+	s.starts_with("http://") || s.starts_with("https://")
+}
+
+fn url_name_hint(url: &str) -> &str {
+	// strip query and fragment so that the extension of the path is visible to the compression detection
+	match url.find(['?', '#']) {
+		Some(i) => &url[..i],
+		None => url,
+	}
+}
+
+// The hash of the full URL, which keeps URLs apart which only differ in their query or in characters not allowed in file names, and the sanitized last part of the path, which keeps the name readable and the extension visible.
+fn cache_file_name(url: &str) -> String {
+	let hash: String = Sha256::digest(url.as_bytes())
+		.iter()
+		.map(|b| format!("{:02x}", b))
+		.collect();
+	let base = url_name_hint(url).rsplit('/').next().unwrap_or("");
+	let skip = base.chars().count().saturating_sub(MAX_BASENAME_CHARS);
+	let mut result = hash;
+	result.push('-');
+	for ch in base.chars().skip(skip) {
+		if ch.is_ascii_alphanumeric() || ch == '.' || ch == '-' {
+			result.push(ch);
+		} else {
+			result.push('_');
+		}
+	}
+	result
+}
+
+fn into_io_error(e: reqwest::Error) -> io::Error {
+	io::Error::other(e)
+}
+
+// Writes the body to `path`. It is downloaded into a temporary file first, so that an interrupted download does not leave a truncated file behind which would then be considered valid.
+fn save_response(resp: &mut reqwest::blocking::Response, url: &str, path: &Path) -> io::Result<()> {
+	let mut tmp_path = path.as_os_str().to_os_string();
+	tmp_path.push(".part");
+	let tmp_path = PathBuf::from(tmp_path);
+	{
+		let mut f = fs::File::create(&tmp_path)?;
+		let n = resp.copy_to(&mut f).map_err(into_io_error)?;
+		f.sync_all()?;
+		trace!("downloaded {} bytes from {}", n, url);
+	}
+	fs::rename(&tmp_path, path)
+}
+
+// The lowercase hex SHA-256 of the file, as sha256sum prints it.
+pub fn sha256_file<P: AsRef<Path>>(path: P) -> io::Result<String> {
+	let mut hasher = Sha256::new();
+	io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+	Ok(hasher
+		.finalize()
+		.iter()
+		.map(|b| format!("{:02x}", b))
+		.collect())
+}
+
+// The checksums of a SHA256SUMS file, in the output format of sha256sum (`HASH  NAME`, or `HASH *NAME` for binary mode), by file name.
+pub fn load_sha256sums<R: io::Read>(r: R) -> io::Result<HashMap<String, String>> {
+	let mut result = HashMap::new();
+	for line in BufReader::new(r).lines() {
+		let line = line?;
+		if line.trim().is_empty() {
+			continue;
+		}
+		let (hash, name) = line
+			.split_once(' ')
+			.filter(|(hash, _)| hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()))
+			.ok_or_else(|| {
+				io::Error::new(
+					io::ErrorKind::InvalidData,
+					format!("malformed checksum line: {:?}", line),
+				)
+			})?;
+		let name = name.trim_start_matches(' ').trim_start_matches('*');
+		result.insert(name.to_string(), hash.to_ascii_lowercase());
+	}
+	Ok(result)
+}
+
+#[derive(Debug, Clone, Default)]
+struct CacheMeta {
+	etag: Option<String>,
+	last_modified: Option<String>,
+}
+
+impl CacheMeta {
+	fn read<R: io::Read>(r: R) -> io::Result<Self> {
+		let mut result = Self::default();
+		for line in BufReader::new(r).lines() {
+			let line = line?;
+			match line.split_once(": ") {
+				Some(("etag", v)) => result.etag = Some(v.into()),
+				Some(("last-modified", v)) => result.last_modified = Some(v.into()),
+				_ => (),
+			}
+		}
+		Ok(result)
+	}
+
+	fn write<W: io::Write>(&self, w: &mut W) -> io::Result<()> {
+		if let Some(etag) = self.etag.as_ref() {
+			writeln!(w, "etag: {}", etag)?;
+		}
+		if let Some(last_modified) = self.last_modified.as_ref() {
+			writeln!(w, "last-modified: {}", last_modified)?;
+		}
+		Ok(())
+	}
+
+	fn from_headers(headers: &reqwest::header::HeaderMap) -> Self {
+		let get = |name| {
+			headers
+				.get(name)
+				.and_then(|v| v.to_str().ok())
+				.map(|v| v.to_string())
+		};
+		Self {
+			etag: get(reqwest::header::ETAG),
+			last_modified: get(reqwest::header::LAST_MODIFIED),
+		}
+	}
+}
+
+// Opens local paths or http(s) URLs. If a cache directory is configured, URLs are downloaded into it and revalidated using ETag/Last-Modified on subsequent opens; otherwise they are streamed.
+pub struct Fetcher {
+	client: reqwest::blocking::Client,
+	cache_dir: Option<PathBuf>,
+}
+
+impl Fetcher {
+	pub fn new(cache_dir: Option<PathBuf>) -> Self {
+		Self {
+			client: reqwest::blocking::Client::builder()
+				// the datasets are large and the default timeout of 30s is too short for them
+				.timeout(None)
+				.build()
+				.expect("failed to construct HTTP client"),
+			cache_dir,
+		}
+	}
+
+	pub fn open<P: AsRef<Path>>(&self, source: P) -> io::Result<Box<dyn Read>> {
+		let source = source.as_ref();
+		let url = match source.to_str() {
+			Some(s) if is_url(s) => s,
+			_ => return magic_open(source),
+		};
+		match self.cache_dir.as_ref() {
+			Some(cache_dir) => {
+				let path = self.fetch_cached(cache_dir, url)?;
+				// the cached file is stored exactly as it was served, so the name hint of the URL is needed for the compression detection
+				magic_wrap(fs::File::open(path)?, url_name_hint(url))
+			}
+			None => {
+				debug!("streaming {}", url);
+				let resp = self
+					.client
+					.get(url)
+					.send()
+					.and_then(|r| r.error_for_status())
+					.map_err(into_io_error)?;
+				magic_wrap(resp, url_name_hint(url))
+			}
+		}
+	}
+
+	// The local file which `open` reads `source` from: the path itself, or for URLs the copy in the cache, which is downloaded or revalidated first. None for URLs without a cache, which are streamed.
+	pub fn local_copy<P: AsRef<Path>>(&self, source: P) -> io::Result<Option<PathBuf>> {
+		let source = source.as_ref();
+		let url = match source.to_str() {
+			Some(s) if is_url(s) => s,
+			_ => return Ok(Some(source.to_path_buf())),
+		};
+		match self.cache_dir.as_ref() {
+			Some(cache_dir) => Ok(Some(self.fetch_cached(cache_dir, url)?)),
+			None => Ok(None),
+		}
+	}
+
+	// The ETag and Last-Modified of `url`, from a HEAD request. None if the server sends neither.
+	pub fn validators(&self, url: &str) -> io::Result<Option<String>> {
+		let resp = self
+			.client
+			.head(url)
+			.send()
+			.and_then(|r| r.error_for_status())
+			.map_err(into_io_error)?;
+		let meta = CacheMeta::from_headers(resp.headers());
+		if meta.etag.is_none() && meta.last_modified.is_none() {
+			return Ok(None);
+		}
+		let mut result = Vec::new();
+		meta.write(&mut result)?;
+		Ok(Some(String::from_utf8_lossy(&result).into_owned()))
+	}
+
+	// Downloads `url` to `path`, bypassing the cache. Returns false if the server does not have it (404).
+	pub fn download<P: AsRef<Path>>(&self, url: &str, path: P) -> io::Result<bool> {
+		debug!("downloading {} to {}", url, path.as_ref().display());
+		let resp = self.client.get(url).send().map_err(into_io_error)?;
+		if resp.status() == reqwest::StatusCode::NOT_FOUND {
+			return Ok(false);
+		}
+		let mut resp = resp.error_for_status().map_err(into_io_error)?;
+		save_response(&mut resp, url, path.as_ref())?;
+		Ok(true)
+	}
+
+	fn fetch_cached(&self, cache_dir: &Path, url: &str) -> io::Result<PathBuf> {
+		fs::create_dir_all(cache_dir)?;
+		let name = cache_file_name(url);
+		let data_path = cache_dir.join(&name);
+		let meta_path = cache_dir.join(format!("{}.meta", name));
+
+		let meta = if data_path.exists() {
+			match fs::File::open(&meta_path) {
+				Ok(f) => Some(CacheMeta::read(f)?),
+				Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+				Err(e) => return Err(e),
+			}
+		} else {
+			None
+		};
+
+		let req = self.client.get(url);
+		let req = match meta.as_ref() {
+			Some(meta) => {
+				let req = match meta.etag.as_ref() {
+					Some(v) => req.header(reqwest::header::IF_NONE_MATCH, v),
+					None => req,
+				};
+				match meta.last_modified.as_ref() {
+					Some(v) => req.header(reqwest::header::IF_MODIFIED_SINCE, v),
+					None => req,
+				}
+			}
+			None => req,
+		};
+
+		let resp = req.send().map_err(into_io_error)?;
+		if resp.status() == reqwest::StatusCode::NOT_MODIFIED {
+			debug!("{} not modified, using cached copy", url);
+			return Ok(data_path);
+		}
+		let mut resp = resp.error_for_status().map_err(into_io_error)?;
+
+		debug!("downloading {} into cache", url);
+		let new_meta = CacheMeta::from_headers(resp.headers());
+		save_response(&mut resp, url, &data_path)?;
+		let mut f = fs::File::create(&meta_path)?;
+		new_meta.write(&mut f)?;
+		Ok(data_path)
+	}
+}
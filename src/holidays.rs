@@ -0,0 +1,154 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+
+use smartstring::alias::String as SmartString;
+
+use serde::{de, Deserialize, Deserializer, Serialize};
+
+use chrono::naive::NaiveDate;
+
+use super::fetch::Fetcher;
+
+// ISO 3166-2:DE subdivision codes, as used by the holiday APIs, mapped to the state names used throughout the RKI data
+pub static STATE_CODES: &[(&str, &str)] = &[
+	("BW", "Baden-Württemberg"),
+	("BY", "Bayern"),
+	("BE", "Berlin"),
+	("BB", "Brandenburg"),
+	("HB", "Bremen"),
+	("HH", "Hamburg"),
+	("HE", "Hessen"),
+	("MV", "Mecklenburg-Vorpommern"),
+	("NI", "Niedersachsen"),
+	("NW", "Nordrhein-Westfalen"),
+	("RP", "Rheinland-Pfalz"),
+	("SL", "Saarland"),
+	("SN", "Sachsen"),
+	("ST", "Sachsen-Anhalt"),
+	("SH", "Schleswig-Holstein"),
+	("TH", "Thüringen"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HolidayKind {
+	// the curated files predate the kind column and only list school holidays
+	#[default]
+	School,
+	Public,
+}
+
+impl fmt::Display for HolidayKind {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(match self {
+			Self::School => "school",
+			Self::Public => "public",
+		})
+	}
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HolidayRecord {
+	pub state: SmartString,
+	pub holiday: SmartString,
+	pub start: NaiveDate,
+	pub end: NaiveDate,
+	#[serde(default)]
+	pub kind: HolidayKind,
+}
+
+fn iso_datetime_date<'de, D>(deserializer: D) -> Result<NaiveDate, D::Error>
+where
+	D: Deserializer<'de>,
+{
+	let s = String::deserialize(deserializer)?;
+	if s.len() < 10 {
+		return Err(de::Error::custom("date too short"));
+	}
+	s[..10].parse::<NaiveDate>().map_err(de::Error::custom)
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct SchoolHolidayRecord {
+	#[serde(deserialize_with = "iso_datetime_date")]
+	start: NaiveDate,
+	#[serde(deserialize_with = "iso_datetime_date")]
+	end: NaiveDate,
+	name: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct PublicHolidayRecord {
+	#[serde(rename = "datum")]
+	date: NaiveDate,
+}
+
+fn capitalize(s: &str) -> SmartString {
+	let mut chars = s.chars();
+	let mut result = SmartString::new();
+	if let Some(ch) = chars.next() {
+		result.extend(ch.to_uppercase());
+	}
+	result.push_str(chars.as_str());
+	result
+}
+
+fn state_name(state_code: &str) -> io::Result<&'static str> {
+	match STATE_CODES.iter().find(|(code, _)| *code == state_code) {
+		Some((_, name)) => Ok(name),
+		None => Err(io::Error::new(
+			io::ErrorKind::InvalidInput,
+			format!("unknown state code: {}", state_code),
+		)),
+	}
+}
+
+pub fn fetch_school_holidays(
+	fetcher: &Fetcher,
+	state_code: &str,
+	year: i32,
+) -> io::Result<Vec<HolidayRecord>> {
+	let state = state_name(state_code)?;
+	let r = fetcher.open(format!(
+		"https://ferien-api.de/api/v1/holidays/{}/{}",
+		state_code, year
+	))?;
+	let raw: Vec<SchoolHolidayRecord> = serde_json::from_reader(r)?;
+	Ok(raw
+		.into_iter()
+		.map(|rec| HolidayRecord {
+			state: state.into(),
+			// the API returns the names in lowercase, the curated data always used the capitalized form
+			holiday: capitalize(&rec.name),
+			start: rec.start,
+			end: rec.end,
+			kind: HolidayKind::School,
+		})
+		.collect())
+}
+
+pub fn fetch_public_holidays(
+	fetcher: &Fetcher,
+	state_code: &str,
+	year: i32,
+) -> io::Result<Vec<HolidayRecord>> {
+	let state = state_name(state_code)?;
+	let r = fetcher.open(format!(
+		"https://feiertage-api.de/api/?jahr={}&nur_land={}",
+		year, state_code
+	))?;
+	let raw: BTreeMap<String, PublicHolidayRecord> = serde_json::from_reader(r)?;
+	let mut result: Vec<_> = raw
+		.into_iter()
+		.map(|(name, rec)| HolidayRecord {
+			state: state.into(),
+			holiday: name.into(),
+			start: rec.date,
+			end: rec.date,
+			kind: HolidayKind::Public,
+		})
+		.collect();
+	result.sort_by_key(|rec| rec.start);
+	Ok(result)
+}
@@ -1,17 +1,25 @@
 use std::fmt;
 use std::io;
+use std::io::Write;
 
 use log::trace;
 
+use chrono::{DateTime, TimeZone, Utc};
+
 use base64;
-use bytes::{BufMut, BytesMut};
+use bytes::{BufMut, Bytes, BytesMut};
+use flate2;
 use reqwest;
 
 use serde::{Deserialize, Serialize};
 
 pub mod readout;
+pub mod transport;
 
-pub use readout::{Precision, Readout, Sample};
+pub use readout::{Precision, Readout, Sample, SampleDedup, SampleFilter};
+pub use transport::{
+	FileTransport, HttpRequest, HttpResponse, Method, ReqwestTransport, Transport,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
@@ -22,20 +30,20 @@ pub enum Auth {
 }
 
 impl Auth {
-	pub fn apply(
-		&self,
-		req: reqwest::blocking::RequestBuilder,
-	) -> reqwest::blocking::RequestBuilder {
+	pub fn apply(&self, req: &mut HttpRequest) {
 		match self {
-			Self::None => req,
-			Self::HTTP { username, password } => req.header(
+			Self::None => (),
+			Self::HTTP { username, password } => req.add_header(
 				"Authorization",
 				format!(
 					"Basic {}",
 					base64::encode(format!("{}:{}", username, password,))
 				),
 			),
-			Self::Query { username, password } => req.query(&[("u", username), ("p", password)]),
+			Self::Query { username, password } => {
+				req.add_query("u", username.as_str());
+				req.add_query("p", password.as_str());
+			}
 		}
 	}
 }
@@ -47,6 +55,11 @@ pub enum Error {
 	DataError,
 	DatabaseNotFound,
 	UnexpectedSuccessStatus,
+	// any other error status
+	Status(u16),
+	// the error InfluxDB reported for a query
+	Query(String),
+	Io(io::Error),
 }
 
 impl fmt::Display for Error {
@@ -57,6 +70,9 @@ impl fmt::Display for Error {
 			Self::DataError => write!(f, "malformed data"),
 			Self::DatabaseNotFound => write!(f, "database not found"),
 			Self::UnexpectedSuccessStatus => write!(f, "unexpected success status"),
+			Self::Status(status) => write!(f, "HTTP status {}", status),
+			Self::Query(msg) => write!(f, "query failed: {}", msg),
+			Self::Io(e) => fmt::Display::fmt(e, f),
 		}
 	}
 }
@@ -69,28 +85,222 @@ impl From<reqwest::Error> for Error {
 
 impl From<Error> for io::Error {
 	fn from(err: Error) -> Self {
-		Self::new(io::ErrorKind::Other, err)
+		match err {
+			Error::Io(e) => e,
+			other => Self::other(other),
+		}
 	}
 }
 
 impl std::error::Error for Error {}
 
+#[derive(Debug, Deserialize)]
+struct QueryResponse {
+	#[serde(default)]
+	results: Vec<StatementResult>,
+	#[serde(default)]
+	error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StatementResult {
+	#[serde(default)]
+	series: Vec<Series>,
+	#[serde(default)]
+	error: Option<String>,
+}
+
+// A series of a query result, with the rows in the order of `columns`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Series {
+	pub name: String,
+	#[serde(default)]
+	pub columns: Vec<String>,
+	#[serde(default)]
+	pub values: Vec<Vec<serde_json::Value>>,
+}
+
+// as a double quoted identifier for InfluxQL
+fn quote_ident(name: &str) -> String {
+	format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
 pub struct Client {
-	client: reqwest::blocking::Client,
-	write_url: String,
+	transport: Box<dyn Transport>,
+	api_url: String,
 	auth: Auth,
+	// bodies of at least this many bytes are sent gzip-compressed
+	gzip_threshold: Option<usize>,
+}
+
+// The result of a write by the status code of its response.
+fn write_status(status: u16) -> Result<(), Error> {
+	match status {
+		204 => Ok(()),
+		401 | 403 => Err(Error::PermissionError),
+		400 | 413 => Err(Error::DataError),
+		404 => Err(Error::DatabaseNotFound),
+		_ if status < 400 => Err(Error::UnexpectedSuccessStatus),
+		_ => Err(Error::Status(status)),
+	}
 }
 
 impl Client {
 	pub fn new(api_url: String, auth: Auth) -> Self {
 		Self {
-			client: reqwest::blocking::Client::new(),
-			write_url: format!("{}/write", api_url),
+			transport: Box::new(ReqwestTransport::new()),
+			api_url,
 			auth,
+			gzip_threshold: None,
+		}
+	}
+
+	pub fn with_gzip(mut self, threshold: Option<usize>) -> Self {
+		self.gzip_threshold = threshold;
+		self
+	}
+
+	// e.g. a mock which records the requests, for tests
+	pub fn with_transport(mut self, transport: Box<dyn Transport>) -> Self {
+		self.transport = transport;
+		self
+	}
+
+	pub fn api_url(&self) -> &str {
+		&self.api_url
+	}
+
+	// Checks that the server is reachable and answers like InfluxDB.
+	pub fn ping(&self) -> Result<(), Error> {
+		let mut req = HttpRequest::new(Method::Get, format!("{}/ping", self.api_url));
+		self.auth.apply(&mut req);
+		match self.transport.send(req)?.status {
+			204 => Ok(()),
+			401 | 403 => Err(Error::PermissionError),
+			status if status < 400 => Err(Error::UnexpectedSuccessStatus),
+			status => Err(Error::Status(status)),
+		}
+	}
+
+	// Runs a single InfluxQL statement via /query and returns its result. Statements which modify the server have to be POSTed.
+	fn statement(
+		&self,
+		method: Method,
+		database: Option<&str>,
+		epoch: Option<Precision>,
+		q: &str,
+	) -> Result<StatementResult, Error> {
+		let mut req = HttpRequest::new(method, format!("{}/query", self.api_url));
+		self.auth.apply(&mut req);
+		if let Some(database) = database {
+			req.add_query("db", database);
+		}
+		if let Some(epoch) = epoch {
+			req.add_query("epoch", epoch.value());
+		}
+		req.add_query("q", q);
+		let resp = self.transport.send(req)?;
+		match resp.status {
+			// 400 carries the error of the query in the body
+			200 | 400 => (),
+			401 | 403 => return Err(Error::PermissionError),
+			status => return Err(Error::Status(status)),
+		}
+		let resp: QueryResponse = serde_json::from_slice(&resp.body)
+			.map_err(|e| Error::Query(format!("invalid response: {}", e)))?;
+		if let Some(msg) = resp.error {
+			return Err(Error::Query(msg));
+		}
+		let result = resp
+			.results
+			.into_iter()
+			.next()
+			.ok_or_else(|| Error::Query("no result".into()))?;
+		match result.error {
+			Some(msg) => Err(Error::Query(msg)),
+			None => Ok(result),
+		}
+	}
+
+	// Creates the database unless it exists. Users without admin privileges may not create databases, but may still write into an existing one; for them, this only checks that the database is visible.
+	pub fn ensure_database(&self, name: &str) -> Result<(), Error> {
+		match self.statement(
+			Method::Post,
+			None,
+			None,
+			&format!("CREATE DATABASE {}", quote_ident(name)),
+		) {
+			Err(Error::PermissionError) => (),
+			other => return other.map(|_| ()),
+		}
+		let result = self.statement(Method::Post, None, None, "SHOW DATABASES")?;
+		let exists = result
+			.series
+			.iter()
+			.flat_map(|series| series.values.iter())
+			.any(|row| row.first().and_then(|v| v.as_str()) == Some(name));
+		if exists {
+			Ok(())
+		} else {
+			Err(Error::DatabaseNotFound)
+		}
+	}
+
+	// Runs a read-only InfluxQL statement (GET /query) against `database`. Timestamps in the result are integers in units of `epoch`.
+	pub fn query(&self, database: &str, epoch: Precision, q: &str) -> Result<Vec<Series>, Error> {
+		Ok(self
+			.statement(Method::Get, Some(database), Some(epoch), q)?
+			.series)
+	}
+
+	// The timestamp of the latest point of a measurement, or None if it has no points.
+	pub fn last_timestamp(
+		&self,
+		database: &str,
+		retention_policy: Option<&str>,
+		measurement: &str,
+	) -> Result<Option<DateTime<Utc>>, Error> {
+		let from = match retention_policy {
+			Some(policy) => format!("{}.{}", quote_ident(policy), quote_ident(measurement)),
+			None => quote_ident(measurement),
+		};
+		let series = self.query(
+			database,
+			Precision::Seconds,
+			&format!("SELECT * FROM {} ORDER BY time DESC LIMIT 1", from),
+		)?;
+		let time = match series.first().and_then(|series| series.values.first()) {
+			Some(row) => row.first(),
+			None => return Ok(None),
+		};
+		match time
+			.and_then(|v| v.as_i64())
+			.and_then(|ts| Utc.timestamp_opt(ts, 0).single())
+		{
+			Some(ts) => Ok(Some(ts)),
+			None => Err(Error::Query(format!("invalid timestamp: {:?}", time))),
 		}
 	}
 
-	pub fn post_raw<T: Into<reqwest::blocking::Body>>(
+	fn encode_body(&self, body: Bytes) -> (Bytes, bool) {
+		let threshold = match self.gzip_threshold {
+			Some(v) => v,
+			None => return (body, false),
+		};
+		if body.len() < threshold {
+			return (body, false);
+		}
+		let mut enc = flate2::write::GzEncoder::new(
+			Vec::with_capacity(body.len() / 4),
+			flate2::Compression::fast(),
+		);
+		enc.write_all(&body).expect("write to Vec failed");
+		let compressed = enc.finish().expect("write to Vec failed");
+		trace!("compressed write body to {} bytes", compressed.len());
+		(compressed.into(), true)
+	}
+
+	pub fn post_raw<T: Into<Bytes>>(
 		&self,
 		database: &str,
 		retention_policy: Option<&str>,
@@ -98,31 +308,19 @@ impl Client {
 		precision: Precision,
 		body: T,
 	) -> Result<(), Error> {
-		let req = self.client.post(self.write_url.clone());
-		let req = auth.unwrap_or_else(|| &self.auth).apply(req);
-		let req = req.query(&[("db", database), ("precision", precision.value())]);
-		let req = match retention_policy {
-			Some(policy) => req.query(&[("rp", policy)]),
-			None => req,
-		};
-		let req = req.body(body);
-		let resp = req.send()?;
-		match resp.error_for_status_ref() {
-			Ok(resp) => match resp.status() {
-				reqwest::StatusCode::NO_CONTENT => Ok(()),
-				_ => Err(Error::UnexpectedSuccessStatus),
-			},
-			Err(e) => match e.status().unwrap() {
-				reqwest::StatusCode::FORBIDDEN | reqwest::StatusCode::UNAUTHORIZED => {
-					Err(Error::PermissionError)
-				}
-				reqwest::StatusCode::BAD_REQUEST | reqwest::StatusCode::PAYLOAD_TOO_LARGE => {
-					Err(Error::DataError)
-				}
-				reqwest::StatusCode::NOT_FOUND => Err(Error::DatabaseNotFound),
-				_ => Err(Error::Request(e)),
-			},
+		let mut req = HttpRequest::new(Method::Post, format!("{}/write", self.api_url));
+		auth.unwrap_or(&self.auth).apply(&mut req);
+		req.add_query("db", database);
+		req.add_query("precision", precision.value());
+		if let Some(policy) = retention_policy {
+			req.add_query("rp", policy);
+		}
+		let (body, compressed) = self.encode_body(body.into());
+		if compressed {
+			req.add_header("Content-Encoding", "gzip");
 		}
+		req.body = body;
+		write_status(self.transport.send(req)?.status)
 	}
 
 	pub fn post(
@@ -1,4 +1,7 @@
+use std::collections::HashMap;
+use std::fmt;
 use std::io;
+use std::str::FromStr;
 
 use smartstring::alias::String as SmartString;
 
@@ -61,6 +64,15 @@ impl From<&str> for FieldValue {
 }
 
 impl FieldValue {
+	pub fn is_default(&self) -> bool {
+		match self {
+			Self::Numeric(v) => *v == 0.,
+			Self::Integer(v) => *v == 0,
+			Self::Bool(v) => !*v,
+			Self::String(v) => v.is_empty(),
+		}
+	}
+
 	fn write_into<W: io::Write>(&self, w: &mut W) -> io::Result<()> {
 		match self {
 			Self::Numeric(v) => write!(w, "{:?}", v),
@@ -154,27 +166,125 @@ pub struct Readout {
 	pub samples: Vec<Sample>,
 }
 
+// Controls which samples are omitted when writing. Both are off by default, because omitted samples may leave stale data in the database when re-importing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SampleFilter {
+	// skip samples where all fields have their default value (zero, false or empty)
+	pub skip_default: bool,
+	// skip samples where all fields are equal to the previously written sample of the same series
+	pub skip_unchanged: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ParseSampleFilterError(String);
+
+impl fmt::Display for ParseSampleFilterError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "unknown sample filter: {:?}", self.0)
+	}
+}
+
+impl std::error::Error for ParseSampleFilterError {}
+
+impl FromStr for SampleFilter {
+	type Err = ParseSampleFilterError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let mut result = Self::default();
+		for part in s.split(',').map(|v| v.trim()) {
+			match part {
+				"" | "none" => (),
+				"default" | "zero" => result.skip_default = true,
+				"unchanged" => result.skip_unchanged = true,
+				other => return Err(ParseSampleFilterError(other.into())),
+			}
+		}
+		Ok(result)
+	}
+}
+
+impl SampleFilter {
+	pub fn is_noop(&self) -> bool {
+		!self.skip_default && !self.skip_unchanged
+	}
+}
+
+pub struct SampleDedup {
+	filter: SampleFilter,
+	previous: HashMap<(SmartString, Vec<SmartString>), Vec<FieldValue>>,
+}
+
+impl SampleDedup {
+	pub fn new(filter: SampleFilter) -> Self {
+		Self {
+			filter,
+			previous: HashMap::new(),
+		}
+	}
+
+	pub fn accept(&mut self, measurement: &str, sample: &Sample) -> bool {
+		if self.filter.skip_default && sample.fieldv.iter().all(|v| v.is_default()) {
+			return false;
+		}
+		if !self.filter.skip_unchanged {
+			return true;
+		}
+		let k = (SmartString::from(measurement), sample.tagv.clone());
+		match self.previous.get_mut(&k) {
+			Some(prev) if *prev == sample.fieldv => false,
+			Some(prev) => {
+				prev.clone_from(&sample.fieldv);
+				true
+			}
+			None => {
+				self.previous.insert(k, sample.fieldv.clone());
+				true
+			}
+		}
+	}
+}
+
 impl Readout {
+	fn write_sample<W: io::Write>(&self, dest: &mut W, sample: &Sample) -> io::Result<()> {
+		write_measurement(dest, &self.measurement)?;
+		for (k, v) in self.tags.iter().zip(sample.tagv.iter()) {
+			dest.write(b",")?;
+			write_name(dest, k)?;
+			dest.write(b"=")?;
+			write_name(dest, v)?;
+		}
+		let mut first = true;
+		for (k, v) in self.fields.iter().zip(sample.fieldv.iter()) {
+			dest.write(if first { b" " } else { b"," })?;
+			write_name(dest, k)?;
+			dest.write(b"=")?;
+			v.write_into(dest)?;
+			first = false;
+		}
+		dest.write_all(&b" "[..])?;
+		self.precision.encode_timestamp(dest, &self.ts)?;
+		dest.write_all(&b"\n"[..])?;
+		Ok(())
+	}
+
 	pub fn write<W: io::Write>(&self, dest: &mut W) -> io::Result<()> {
 		for sample in self.samples.iter() {
-			write_measurement(dest, &self.measurement)?;
-			for (k, v) in self.tags.iter().zip(sample.tagv.iter()) {
-				dest.write(b",")?;
-				write_name(dest, k)?;
-				dest.write(b"=")?;
-				write_name(dest, v)?;
-			}
-			let mut first = true;
-			for (k, v) in self.fields.iter().zip(sample.fieldv.iter()) {
-				dest.write(if first { b" " } else { b"," })?;
-				write_name(dest, k)?;
-				dest.write(b"=")?;
-				v.write_into(dest)?;
-				first = false;
+			self.write_sample(dest, sample)?;
+		}
+		Ok(())
+	}
+
+	// Readouts must be passed in chronological order for the unchanged check to be meaningful.
+	pub fn write_filtered<W: io::Write>(
+		&self,
+		dest: &mut W,
+		dedup: &mut SampleDedup,
+	) -> io::Result<()> {
+		for sample in self.samples.iter() {
+			if !dedup.accept(&self.measurement, sample) {
+				continue;
 			}
-			dest.write_all(&b" "[..])?;
-			self.precision.encode_timestamp(dest, &self.ts)?;
-			dest.write_all(&b"\n"[..])?;
+			self.write_sample(dest, sample)?;
 		}
 		Ok(())
 	}
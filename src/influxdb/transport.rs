@@ -0,0 +1,146 @@
+use std::fs;
+use std::io;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use bytes::Bytes;
+
+use reqwest;
+
+use super::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+	Get,
+	Post,
+}
+
+// A request to the InfluxDB HTTP API as the Client hands it to its Transport. The query parameters are not encoded yet, so that they can be checked as they are.
+#[derive(Debug, Clone)]
+pub struct HttpRequest {
+	pub method: Method,
+	// without the query string
+	pub url: String,
+	pub query: Vec<(String, String)>,
+	pub headers: Vec<(String, String)>,
+	pub body: Bytes,
+}
+
+impl HttpRequest {
+	pub fn new<S: Into<String>>(method: Method, url: S) -> Self {
+		Self {
+			method,
+			url: url.into(),
+			query: Vec::new(),
+			headers: Vec::new(),
+			body: Bytes::new(),
+		}
+	}
+
+	pub fn add_query<K: Into<String>, V: Into<String>>(&mut self, name: K, value: V) {
+		self.query.push((name.into(), value.into()));
+	}
+
+	pub fn add_header<K: Into<String>, V: Into<String>>(&mut self, name: K, value: V) {
+		self.headers.push((name.into(), value.into()));
+	}
+
+	pub fn param(&self, name: &str) -> Option<&str> {
+		self.query
+			.iter()
+			.find(|(k, _)| k == name)
+			.map(|(_, v)| v.as_str())
+	}
+
+	// header names are case-insensitive
+	pub fn header(&self, name: &str) -> Option<&str> {
+		self.headers
+			.iter()
+			.find(|(k, _)| k.eq_ignore_ascii_case(name))
+			.map(|(_, v)| v.as_str())
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+	pub status: u16,
+	pub body: Bytes,
+}
+
+// Sends the requests of a Client. The Client interprets the status codes; a transport only fails for requests which did not get a response at all.
+pub trait Transport: Send + Sync {
+	fn send(&self, req: HttpRequest) -> Result<HttpResponse, Error>;
+}
+
+// The transport of Client::new.
+pub struct ReqwestTransport {
+	client: reqwest::blocking::Client,
+}
+
+impl ReqwestTransport {
+	pub fn new() -> Self {
+		Self {
+			client: reqwest::blocking::Client::new(),
+		}
+	}
+}
+
+impl Default for ReqwestTransport {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Transport for ReqwestTransport {
+	fn send(&self, req: HttpRequest) -> Result<HttpResponse, Error> {
+		let builder = match req.method {
+			Method::Get => self.client.get(req.url),
+			Method::Post => self.client.post(req.url),
+		};
+		let mut builder = builder.query(&req.query);
+		for (name, value) in req.headers.iter() {
+			builder = builder.header(name, value);
+		}
+		let resp = builder.body(req.body).send()?;
+		Ok(HttpResponse {
+			status: resp.status().as_u16(),
+			body: resp.bytes()?,
+		})
+	}
+}
+
+// Appends the line protocol of all writes to a local file instead of sending it anywhere, e.g. to keep a copy of a run next to the servers. Everything else is answered with 204 No Content, like a ping; there is nothing to query.
+pub struct FileTransport {
+	file: Mutex<fs::File>,
+}
+
+impl FileTransport {
+	// Truncates the file if it exists.
+	pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+		Ok(Self {
+			file: Mutex::new(fs::File::create(path)?),
+		})
+	}
+}
+
+impl Transport for FileTransport {
+	fn send(&self, req: HttpRequest) -> Result<HttpResponse, Error> {
+		if req.method == Method::Post && req.url.ends_with("/write") {
+			let mut file = self.file.lock().unwrap();
+			if req.header("Content-Encoding") == Some("gzip") {
+				let mut body = Vec::new();
+				flate2::read::GzDecoder::new(&req.body[..])
+					.read_to_end(&mut body)
+					.map_err(Error::Io)?;
+				file.write_all(&body).map_err(Error::Io)?;
+			} else {
+				file.write_all(&req.body).map_err(Error::Io)?;
+			}
+		}
+		Ok(HttpResponse {
+			status: 204,
+			body: Bytes::new(),
+		})
+	}
+}
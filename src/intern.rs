@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::hash::Hash;
+
+// Dense id of a key within a KeyInterner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct KeyId(pub u32);
+
+impl KeyId {
+	#[inline(always)]
+	pub fn index(self) -> usize {
+		self.0 as usize
+	}
+}
+
+// Assigns dense ids to the keys of a key space, in the order in which they are first seen. A rich key (state, district, age group, sex, ...) is hashed once when it is interned; anything keyed by the ids can use plain vectors instead.
+#[derive(Debug, Clone)]
+pub struct KeyInterner<K> {
+	ids: HashMap<K, KeyId>,
+	keys: Vec<K>,
+}
+
+impl<K: Hash + Eq + Clone> KeyInterner<K> {
+	pub fn new() -> Self {
+		Self {
+			ids: HashMap::new(),
+			keys: Vec::new(),
+		}
+	}
+
+	pub fn intern(&mut self, k: &K) -> KeyId {
+		if let Some(id) = self.ids.get(k) {
+			return *id;
+		}
+		let id = KeyId(self.keys.len().try_into().expect("too many keys to intern"));
+		self.keys.push(k.clone());
+		self.ids.insert(k.clone(), id);
+		id
+	}
+
+	pub fn get(&self, k: &K) -> Option<KeyId> {
+		self.ids.get(k).copied()
+	}
+
+	// panics on ids of another interner
+	pub fn resolve(&self, id: KeyId) -> &K {
+		&self.keys[id.index()]
+	}
+
+	pub fn len(&self) -> usize {
+		self.keys.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.keys.is_empty()
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = (KeyId, &K)> + '_ {
+		self.keys
+			.iter()
+			.enumerate()
+			.map(|(i, k)| (KeyId(i as u32), k))
+	}
+
+	// Maps each key of this space to at most one key of another, e.g. districts to their state. `f` is evaluated once per key, however many series are rekeyed with the result.
+	pub fn map<U: Hash + Eq + Clone, F: Fn(&K) -> Option<U>>(&self, f: F) -> KeyMap<U> {
+		let mut targets = KeyInterner::new();
+		let table = self
+			.keys
+			.iter()
+			.map(|k| f(k).map(|u| targets.intern(&u)))
+			.collect();
+		KeyMap { targets, table }
+	}
+}
+
+impl<K: Hash + Eq + Clone> Default for KeyInterner<K> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+// The ids of one key space mapped to those of another, see KeyInterner::map.
+#[derive(Debug, Clone)]
+pub struct KeyMap<U> {
+	targets: KeyInterner<U>,
+	table: Vec<Option<KeyId>>,
+}
+
+impl<U> KeyMap<U> {
+	// the id of the target key of the source key `id`, if it has one
+	#[inline(always)]
+	pub fn get(&self, id: KeyId) -> Option<KeyId> {
+		self.table[id.index()]
+	}
+
+	pub fn targets(&self) -> &KeyInterner<U> {
+		&self.targets
+	}
+}
@@ -1,16 +1,162 @@
 use std::fs;
 use std::io;
-use std::io::Read;
+use std::io::{BufRead, BufReader, Read};
 use std::path::Path;
+use std::sync::mpsc;
+use std::thread;
 
 use flate2;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+	None,
+	Gzip,
+	Zstd,
+	Xz,
+	Bzip2,
+}
+
+impl Compression {
+	pub fn from_extension<P: AsRef<Path>>(path: P) -> Option<Self> {
+		match path.as_ref().extension()?.to_str()? {
+			"gz" => Some(Self::Gzip),
+			"zst" => Some(Self::Zstd),
+			"xz" => Some(Self::Xz),
+			"bz2" => Some(Self::Bzip2),
+			_ => None,
+		}
+	}
+
+	pub fn from_magic(buf: &[u8]) -> Option<Self> {
+		if buf.starts_with(&[0x1f, 0x8b]) {
+			Some(Self::Gzip)
+		} else if buf.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+			Some(Self::Zstd)
+		} else if buf.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+			Some(Self::Xz)
+		} else if buf.starts_with(b"BZh") {
+			Some(Self::Bzip2)
+		} else {
+			None
+		}
+	}
+
+	pub fn wrap<R: Read + 'static>(&self, r: R) -> io::Result<Box<dyn Read>> {
+		Ok(match self {
+			Self::None => Box::new(r),
+			Self::Gzip => Box::new(flate2::read::GzDecoder::new(r)),
+			Self::Zstd => Box::new(zstd::stream::read::Decoder::new(r)?),
+			Self::Xz => Box::new(xz2::read::XzDecoder::new(r)),
+			Self::Bzip2 => Box::new(bzip2::read::BzDecoder::new(r)),
+		})
+	}
+}
+
+// size of the chunks handed over by the read-ahead thread
+static READ_AHEAD_CHUNK: usize = 256 * 1024;
+// number of chunks which may be in flight between the threads
+static READ_AHEAD_DEPTH: usize = 16;
+
+// Runs a reader on a background thread and hands its output over in chunks through a bounded channel, so that e.g. decompression can proceed while the consumer is busy parsing.
+struct ReadAhead {
+	rx: mpsc::Receiver<io::Result<Vec<u8>>>,
+	thread: Option<thread::JoinHandle<()>>,
+	buf: Vec<u8>,
+	pos: usize,
+}
+
+impl ReadAhead {
+	// The reader is constructed on the background thread, so it does not need to be Send itself.
+	fn spawn<F: FnOnce() -> io::Result<Box<dyn Read>> + Send + 'static>(f: F) -> Self {
+		let (tx, rx) = mpsc::sync_channel(READ_AHEAD_DEPTH);
+		let thread = thread::spawn(move || {
+			let mut r = match f() {
+				Ok(r) => r,
+				Err(e) => {
+					let _ = tx.send(Err(e));
+					return;
+				}
+			};
+			loop {
+				let mut chunk = vec![0u8; READ_AHEAD_CHUNK];
+				let mut filled = 0;
+				while filled < chunk.len() {
+					match r.read(&mut chunk[filled..]) {
+						Ok(0) => break,
+						Ok(n) => filled += n,
+						Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+						Err(e) => {
+							let _ = tx.send(Err(e));
+							return;
+						}
+					}
+				}
+				if filled == 0 {
+					return;
+				}
+				chunk.truncate(filled);
+				if tx.send(Ok(chunk)).is_err() {
+					// consumer went away
+					return;
+				}
+			}
+		});
+		Self {
+			rx,
+			thread: Some(thread),
+			buf: Vec::new(),
+			pos: 0,
+		}
+	}
+}
+
+impl Read for ReadAhead {
+	fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+		if self.pos >= self.buf.len() {
+			match self.rx.recv() {
+				Ok(Ok(chunk)) => {
+					self.buf = chunk;
+					self.pos = 0;
+				}
+				Ok(Err(e)) => return Err(e),
+				Err(_) => {
+					// the sender is gone: either a clean end of stream or a panic on the reader thread, which must not look like a truncated file
+					if let Some(thread) = self.thread.take() {
+						if thread.join().is_err() {
+							return Err(io::Error::other("read-ahead thread panicked"));
+						}
+					}
+					return Ok(0);
+				}
+			}
+		}
+		let n = out.len().min(self.buf.len() - self.pos);
+		out[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+		self.pos += n;
+		Ok(n)
+	}
+}
+
+pub fn magic_wrap<R: Read + Send + 'static, P: AsRef<Path>>(
+	r: R,
+	name_hint: P,
+) -> io::Result<Box<dyn Read>> {
+	let mut r = BufReader::new(r);
+	// the magic bytes take precedence over the extension: files are sometimes recompressed without renaming, and plain CSVs cannot start with any of the magic sequences anyway
+	let compression = match Compression::from_magic(r.fill_buf()?) {
+		Some(v) => v,
+		None => Compression::from_extension(name_hint).unwrap_or(Compression::None),
+	};
+	match compression {
+		// the expensive codecs run on their own thread to overlap decompression with parsing
+		Compression::Gzip | Compression::Zstd => {
+			Ok(Box::new(ReadAhead::spawn(move || compression.wrap(r))))
+		}
+		_ => compression.wrap(r),
+	}
+}
+
 pub fn magic_open<P: AsRef<Path>>(path: P) -> io::Result<Box<dyn Read>> {
 	let path = path.as_ref();
-	match path.extension() {
-		Some(x) if x == "gz" => Ok(Box::new(flate2::read::GzDecoder::new(fs::File::open(
-			path,
-		)?))),
-		_ => Ok(Box::new(fs::File::open(path)?)),
-	}
+	magic_wrap(fs::File::open(path)?, path)
 }
@@ -1,29 +1,84 @@
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::io::Write;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::Arc;
 
-use chrono::{Datelike, NaiveDate, TimeZone, Utc};
+use chrono::{TimeZone, Utc};
 
-use bytes::{BufMut, Bytes, BytesMut};
+use bytes::{BufMut, BytesMut};
+
+use log::{debug, info};
 
 use smartstring::alias::String as SmartString;
 
+mod age_groups;
+mod aggregate;
+mod checkpoint;
 mod context;
+mod csvin;
+mod csvout;
+mod dayhash;
 mod destatis;
+mod diffstore;
 mod divi;
+mod error;
+mod events;
+mod fanout;
+mod fetch;
+mod holidays;
 pub mod influxdb;
+mod intern;
 mod ioutil;
+mod logging;
+mod nowcast;
+mod parquetout;
+pub mod pipeline;
 mod progress;
 mod rki;
+mod runlog;
+mod snapshot;
+mod sqlite;
+mod submit;
+#[cfg(feature = "testing")]
+pub mod testing;
 pub mod timeseries;
+mod waning;
 
+pub use age_groups::*;
+pub use aggregate::*;
+pub use checkpoint::*;
 pub use context::*;
+pub use csvin::CsvRecords;
+pub use csvout::*;
+pub use dayhash::*;
 pub use destatis::*;
+pub use diffstore::*;
 pub use divi::*;
-pub use ioutil::magic_open;
+pub use error::{Error, Result};
+pub use events::*;
+pub use fanout::*;
+pub use fetch::*;
+pub use holidays::*;
+pub use intern::*;
+pub use ioutil::{magic_open, Compression};
+pub use logging::init_logging;
+pub use nowcast::*;
+pub use parquetout::*;
 pub use progress::*;
 pub use rki::*;
+pub use runlog::*;
+pub use snapshot::*;
+pub use sqlite::*;
+pub use submit::*;
 pub use timeseries::*;
+pub use waning::*;
+
+// re-exported so that users of write_measurement do not need to depend on these crates themselves
+pub use bytes::Bytes;
+pub use chrono::NaiveDate;
 
 pub fn naive_today() -> NaiveDate {
 	Utc::today().naive_local()
@@ -33,19 +88,91 @@ pub fn global_start_date() -> NaiveDate {
 	NaiveDate::from_ymd(2020, 1, 1)
 }
 
+// How a field is written in the line protocol. Integer fields are rounded and get the `i` suffix; InfluxDB refuses points whose field type differs from the one stored already, so integers are only written with StreamOptions::integer_fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldType {
+	Float,
+	Integer,
+}
+
+// What the outputs write for a day on which a field has no value. The field is left out of the line (or the cell empty) with Skip; a key and day without any value is not written at all.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NullPolicy {
+	Skip,
+	Zero,
+	Pad(f64),
+}
+
+impl NullPolicy {
+	// Replaces the NaNs which get_range writes for absent values.
+	pub fn apply(&self, values: &mut [f64]) {
+		let pad = match self {
+			Self::Skip => return,
+			Self::Zero => 0.,
+			Self::Pad(v) => *v,
+		};
+		for v in values.iter_mut().filter(|v| v.is_nan()) {
+			*v = pad;
+		}
+	}
+}
+
 #[derive(Debug, Clone)]
 pub struct FieldDescriptor<T> {
 	name: &'static str,
 	inner: T,
+	field_type: FieldType,
+	null_policy: NullPolicy,
+	// how the field is rolled up into weeks or months, see pipeline::resampled
+	aggregation: Aggregation,
 }
 
 impl<T> FieldDescriptor<T> {
 	pub fn new(inner: T, name: &'static str) -> Self {
-		Self { inner, name }
+		Self {
+			inner,
+			name,
+			field_type: FieldType::Float,
+			null_policy: NullPolicy::Skip,
+			aggregation: Aggregation::Mean,
+		}
+	}
+
+	// for series which only take integer values, i.e. counts
+	pub fn integer(inner: T, name: &'static str) -> Self {
+		Self::new(inner, name).with_type(FieldType::Integer)
 	}
 
-	pub fn name(&self) -> &str {
-		&self.name
+	pub fn with_type(mut self, field_type: FieldType) -> Self {
+		self.field_type = field_type;
+		self
+	}
+
+	pub fn with_null_policy(mut self, null_policy: NullPolicy) -> Self {
+		self.null_policy = null_policy;
+		self
+	}
+
+	// Sum for daily counts, Last for cumulative and windowed values; the default, Mean, is for gauges and rates.
+	pub fn with_aggregation(mut self, aggregation: Aggregation) -> Self {
+		self.aggregation = aggregation;
+		self
+	}
+
+	pub fn name(&self) -> &'static str {
+		self.name
+	}
+
+	pub fn field_type(&self) -> FieldType {
+		self.field_type
+	}
+
+	pub fn null_policy(&self) -> NullPolicy {
+		self.null_policy
+	}
+
+	pub fn aggregation(&self) -> Aggregation {
+		self.aggregation
 	}
 
 	pub fn inner(&self) -> &T {
@@ -53,46 +180,354 @@ impl<T> FieldDescriptor<T> {
 	}
 }
 
-pub fn prepare_keyset<
-	'x,
-	K: TimeSeriesKey,
-	I: Iterator<Item = &'x K>,
-	F: Fn(&K, &mut Vec<SmartString>) -> (),
->(
-	tags: &[&str],
-	keys: I,
-	f: F,
-) -> Vec<(&'x K, Bytes)> {
-	let mut result = Vec::new();
-	let mut tmp = Vec::with_capacity(tags.len());
-	for k in keys {
-		tmp.clear();
-		f(k, &mut tmp);
-		assert_eq!(tmp.len(), tags.len());
+impl<K: TimeSeriesKey> FieldDescriptor<Arc<dyn ViewTimeSeries<K>>> {
+	// ViewTimeSeries::get_range with the null policy applied; this is what the outputs write.
+	pub fn get_range(&self, k: &K, start: NaiveDate, ndays: usize, out: &mut [f64]) {
+		self.inner.get_range(k, start, ndays, out);
+		self.null_policy.apply(&mut out[..ndays]);
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct KeysetEntry<'x, K> {
+	pub key: &'x K,
+	// in the order of KeySet::tag_names
+	pub tag_values: Vec<SmartString>,
+	// the same tags, preserialized for the line protocol
+	pub tagset: Bytes,
+}
+
+// The keys of a measurement with their tags: the tag names, and per key the rendered tag values and their line protocol. Every entry has a value for each tag name, which the outputs rely on; the constructors refuse anything else.
+// All outputs take a keyset (stream_dynamic, write_measurement, Output::stream_dynamic and the sinks), and the CSV, SQLite and Parquet columns are its tag_names, so a keyset is checked once, where it is built: new, from_tagged, push, extended and append fail with Error::Schema on a wrong number of tag values or on differing tag names.
+#[derive(Debug, Clone)]
+pub struct KeySet<'x, K> {
+	tag_names: Vec<SmartString>,
+	entries: Vec<KeysetEntry<'x, K>>,
+}
+
+impl<'x, K: TimeSeriesKey> KeySet<'x, K> {
+	pub fn empty(tag_names: &[&str]) -> Self {
+		Self {
+			tag_names: tag_names.iter().map(|name| (*name).into()).collect(),
+			entries: Vec::new(),
+		}
+	}
+
+	// Renders the tag values of each key with `f`, which pushes one value per tag name.
+	pub fn new<I: IntoIterator<Item = &'x K>, F: Fn(&K, &mut Vec<SmartString>)>(
+		tag_names: &[&str],
+		keys: I,
+		f: F,
+	) -> Result<Self> {
+		let mut result = Self::empty(tag_names);
+		for k in keys {
+			let mut tag_values = Vec::with_capacity(tag_names.len());
+			f(k, &mut tag_values);
+			result.push(k, tag_values)?;
+		}
+		Ok(result)
+	}
+
+	// From keys with their tag values already rendered.
+	pub fn from_tagged<I: IntoIterator<Item = (&'x K, Vec<SmartString>)>>(
+		tag_names: &[&str],
+		items: I,
+	) -> Result<Self> {
+		let mut result = Self::empty(tag_names);
+		for (k, tag_values) in items {
+			result.push(k, tag_values)?;
+		}
+		Ok(result)
+	}
+
+	// Fails unless there is one value per tag name, in the order of tag_names.
+	pub fn push(&mut self, key: &'x K, tag_values: Vec<SmartString>) -> Result<()> {
+		if tag_values.len() != self.tag_names.len() {
+			return Err(Error::Schema(format!(
+				"{} tag values {:?} for the tags {:?}",
+				tag_values.len(),
+				tag_values,
+				self.tag_names
+			)));
+		}
 		let mut buffer = BytesMut::new().writer();
-		for (tagname, tagv) in tags.iter().zip(tmp.drain(..)) {
+		for (name, value) in self.tag_names.iter().zip(tag_values.iter()) {
 			buffer.get_mut().put_u8(b',');
-			influxdb::readout::write_name(&mut buffer, tagname).expect("write to BytesMut failed");
+			influxdb::readout::write_name(&mut buffer, name).expect("write to BytesMut failed");
 			buffer.get_mut().put_u8(b'=');
-			influxdb::readout::write_name(&mut buffer, &tagv).expect("write to BytesMut failed");
+			influxdb::readout::write_name(&mut buffer, value).expect("write to BytesMut failed");
 		}
-		result.push((k, buffer.into_inner().freeze()));
+		self.entries.push(KeysetEntry {
+			key,
+			tag_values,
+			tagset: buffer.into_inner().freeze(),
+		});
+		Ok(())
+	}
+
+	// Adds the tags `tag_names` after the existing ones, with the values which `f` pushes for each key.
+	pub fn extended<F: Fn(&K, &mut Vec<SmartString>)>(
+		self,
+		tag_names: &[&str],
+		f: F,
+	) -> Result<Self> {
+		let mut names: Vec<&str> = self.tag_names.iter().map(|name| name.as_str()).collect();
+		names.extend_from_slice(tag_names);
+		let mut result = Self::empty(&names);
+		for entry in self.entries {
+			let mut tag_values = entry.tag_values;
+			f(entry.key, &mut tag_values);
+			result.push(entry.key, tag_values)?;
+		}
+		Ok(result)
+	}
+
+	// Appends the entries of a keyset with the same tag names.
+	pub fn append(&mut self, other: KeySet<'x, K>) -> Result<()> {
+		if other.tag_names != self.tag_names {
+			return Err(Error::Schema(format!(
+				"cannot append a keyset with the tags {:?} to one with the tags {:?}",
+				other.tag_names, self.tag_names
+			)));
+		}
+		self.entries.extend(other.entries);
+		Ok(())
+	}
+
+	// The entries for which `f` is true, given the key and its tag values.
+	pub fn filtered<F: Fn(&K, &[SmartString]) -> bool>(&self, f: F) -> Self {
+		Self {
+			tag_names: self.tag_names.clone(),
+			entries: self
+				.entries
+				.iter()
+				.filter(|entry| f(entry.key, &entry.tag_values))
+				.cloned()
+				.collect(),
+		}
+	}
+
+	pub fn tag_names(&self) -> &[SmartString] {
+		&self.tag_names
+	}
+
+	pub fn entries(&self) -> &[KeysetEntry<'x, K>] {
+		&self.entries
+	}
+
+	pub fn iter(&self) -> std::slice::Iter<'_, KeysetEntry<'x, K>> {
+		self.entries.iter()
+	}
+
+	pub fn len(&self) -> usize {
+		self.entries.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.entries.is_empty()
 	}
-	result
 }
 
+#[derive(Debug, Clone)]
+pub struct StreamOptions {
+	pub sample_filter: influxdb::SampleFilter,
+	pub database: String,
+	// None writes into the default retention policy of the database
+	pub retention_policy: Option<String>,
+	// output names of measurements which are not written under their built-in name
+	pub measurement_names: HashMap<String, String>,
+	// built-in names of measurements which are not written at all
+	pub disabled_measurements: HashSet<String>,
+	// a request is sent as soon as the buffered line protocol reaches either limit; 0 disables the respective limit
+	pub chunk_lines: usize,
+	pub chunk_bytes: usize,
+	// file with the high-water mark of each measurement (see Checkpoint); only maintained when writing to InfluxDB
+	pub checkpoint: Option<PathBuf>,
+	// skip the days which the checkpoint records as written already
+	pub resume: bool,
+	// file with the hash of each day written per measurement (see DayHashes); if set, only days whose lines differ from the previous run are submitted
+	pub incremental_state: Option<PathBuf>,
+	// number of threads serializing the line protocol in stream_dynamic; the output is the same for any number
+	pub serialize_threads: usize,
+	// built-in names of measurements which are only written at daily resolution for the most recent days, see Downsampling
+	pub downsampling: HashMap<String, Downsampling>,
+	// write the fields declared as FieldType::Integer as integers instead of floats
+	pub integer_fields: bool,
+	// only stream the keys of each measurement which match, see KeyFilter
+	pub key_filter: Option<KeyFilter>,
+}
+
+// Splits a measurement for long-range dashboards: only the trailing `daily_days` days are written at daily resolution, older days go into `measurement` as weekly rollups (see pipeline::resampled), typically with a longer retention policy than the daily data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Downsampling {
+	pub daily_days: usize,
+	// output name of the weekly measurement
+	pub measurement: String,
+	// None writes into the retention policy of the daily data
+	pub retention_policy: Option<String>,
+}
+
+impl FromStr for Downsampling {
+	type Err = String;
+
+	// DAYS:MEASUREMENT or DAYS:MEASUREMENT@RETENTION_POLICY
+	fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+		let (days, rest) = s
+			.split_once(':')
+			.ok_or_else(|| format!("expected days:measurement, got {:?}", s))?;
+		let daily_days = days
+			.trim()
+			.parse()
+			.map_err(|e| format!("invalid number of days {:?}: {}", days, e))?;
+		let (measurement, retention_policy) = match rest.split_once('@') {
+			Some((measurement, rp)) => (measurement, Some(rp.trim().into())),
+			None => (rest, None),
+		};
+		let measurement = measurement.trim();
+		if measurement.is_empty() {
+			return Err(format!("missing measurement in {:?}", s));
+		}
+		Ok(Self {
+			daily_days,
+			measurement: measurement.into(),
+			retention_policy,
+		})
+	}
+}
+
+// Restricts the keys which are streamed by their tag values, e.g. to iterate on dashboards with a single state. Each condition names a tag and the values it may have; several conditions on the same tag allow any of their values, conditions on different tags must all hold. Measurements without a tag ignore the conditions on it, so that e.g. a filter on the district leaves the state-level measurements complete.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct KeyFilter {
+	conditions: Vec<(SmartString, Vec<SmartString>)>,
+}
+
+impl KeyFilter {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn with_condition(mut self, tag: &str, value: &str) -> Self {
+		match self.conditions.iter_mut().find(|(name, _)| name == tag) {
+			Some((_, values)) => values.push(value.into()),
+			None => self.conditions.push((tag.into(), vec![value.into()])),
+		}
+		self
+	}
+
+	pub fn matches(&self, tag_names: &[SmartString], tag_values: &[SmartString]) -> bool {
+		self.conditions.iter().all(|(tag, values)| {
+			match tag_names.iter().position(|name| name == tag) {
+				Some(i) => values.contains(&tag_values[i]),
+				None => true,
+			}
+		})
+	}
+
+	pub fn apply<'x, K: TimeSeriesKey>(&self, keyset: &KeySet<'x, K>) -> KeySet<'x, K> {
+		keyset.filtered(|_, tag_values| self.matches(keyset.tag_names(), tag_values))
+	}
+}
+
+impl FromStr for KeyFilter {
+	type Err = String;
+
+	// TAG=VALUE[,TAG=VALUE...]
+	fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+		let mut result = Self::new();
+		for condition in s.split(',') {
+			let (tag, value) = condition
+				.split_once('=')
+				.ok_or_else(|| format!("expected tag=value, got {:?}", condition))?;
+			let tag = tag.trim();
+			if tag.is_empty() {
+				return Err(format!("missing tag in {:?}", condition));
+			}
+			result = result.with_condition(tag, value.trim());
+		}
+		Ok(result)
+	}
+}
+
+impl StreamOptions {
+	pub fn measurement_name<'x>(&'x self, name: &'x str) -> &'x str {
+		match self.measurement_names.get(name) {
+			Some(v) => v,
+			None => name,
+		}
+	}
+
+	pub fn is_enabled(&self, name: &str) -> bool {
+		!self.disabled_measurements.contains(name)
+	}
+}
+
+impl Default for StreamOptions {
+	fn default() -> Self {
+		Self {
+			sample_filter: influxdb::SampleFilter::default(),
+			database: "covid".into(),
+			retention_policy: None,
+			measurement_names: HashMap::new(),
+			disabled_measurements: HashSet::new(),
+			chunk_lines: 5000,
+			chunk_bytes: 4 * 1024 * 1024,
+			checkpoint: None,
+			resume: false,
+			incremental_state: None,
+			serialize_threads: std::thread::available_parallelism()
+				.map(|n| n.get())
+				.unwrap_or(1),
+			downsampling: HashMap::new(),
+			integer_fields: false,
+			key_filter: None,
+		}
+	}
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn stream_dynamic<K: TimeSeriesKey, S: ProgressSink + ?Sized>(
 	sink: &influxdb::Client,
 	progress: &mut S,
 	measurement: &str,
 	start: NaiveDate,
 	ndays: usize,
-	keyset: &[(&K, Bytes)],
+	keyset: &KeySet<K>,
 	fields: &[FieldDescriptor<Arc<dyn ViewTimeSeries<K>>>],
-) -> Result<(), influxdb::Error> {
-	static TARGET_METRICS_PER_CHUNK: usize = 5000;
+	options: &StreamOptions,
+) -> Result<PhaseStats> {
+	// number of days evaluated per get_range call; bounds the size of the value buffer for large keysets
+	static RANGE_BLOCK_DAYS: usize = 32;
+
+	if !options.is_enabled(measurement) {
+		return Ok(PhaseStats::default());
+	}
+	let measurement = options.measurement_name(measurement);
+	let database = &options.database[..];
 
-	let chunk_size = (TARGET_METRICS_PER_CHUNK / keyset.len()).max(1);
+	let mut checkpoint = match options.checkpoint.as_ref() {
+		Some(path) => Some(Checkpoint::load(path)?),
+		None => None,
+	};
+	let (start, ndays) = match checkpoint.as_mut() {
+		Some(cp) if options.resume => match cp.get(measurement) {
+			Some(done) => {
+				let skip = ((done - start).num_days() + 1).clamp(0, ndays as i64);
+				info!(
+					"{}: resuming after {}, skipping {} days",
+					measurement, done, skip
+				);
+				(start + chrono::Duration::days(skip), ndays - skip as usize)
+			}
+			None => (start, ndays),
+		},
+		Some(cp) => {
+			// a fresh run invalidates the previous high-water mark until the first chunk is through
+			cp.set(measurement, None)?;
+			(start, ndays)
+		}
+		None => (start, ndays),
+	};
+	let retention_policy = options.retention_policy.as_deref();
 
 	let measurement_bytes = {
 		let mut buf = BytesMut::new().writer();
@@ -102,65 +537,522 @@ pub fn stream_dynamic<K: TimeSeriesKey, S: ProgressSink + ?Sized>(
 	};
 
 	let precision = influxdb::Precision::Seconds;
+	let filter = options.sample_filter;
 
-	let mut buffer = BytesMut::new();
+	let mut day_hashes = match options.incremental_state.as_ref() {
+		Some(path) => Some(DayHashes::load(path)?),
+		None => None,
+	};
+	let mut new_hashes = Vec::new();
+	let mut unchanged_days = 0;
+
+	let mut stats = PhaseStats::default();
+	let mut writer = ChunkedWriter {
+		sink,
+		database,
+		retention_policy,
+		precision,
+		chunk_lines: options.chunk_lines,
+		chunk_bytes: options.chunk_bytes,
+		buffer: BytesMut::new(),
+		buffered_lines: 0,
+		bytes_submitted: 0,
+	};
 	let mut pm = StepMeter::new(progress, ndays);
-	let mut fields_serialized = BytesMut::new().writer();
-	let mut timestamp_serialized = BytesMut::new().writer();
-	for (i, date) in start.iter_days().take(ndays).enumerate() {
-		timestamp_serialized.get_mut().clear();
-		precision
-			.encode_timestamp(
-				&mut timestamp_serialized,
-				&Utc.ymd(date.year(), date.month(), date.day())
-					.and_hms(0, 0, 0),
-			)
-			.expect("write to BytesMut failed");
+	let field_names: Vec<&str> = fields.iter().map(|desc| desc.name()).collect();
+	let field_types: Vec<FieldType> = fields
+		.iter()
+		.map(|desc| {
+			if options.integer_fields {
+				desc.field_type()
+			} else {
+				FieldType::Float
+			}
+		})
+		.collect();
+	let tagsets: Vec<&[u8]> = keyset.iter().map(|entry| &entry.tagset[..]).collect();
+	// keys serialized per thread; small keysets are not worth spawning threads for
+	let keys_per_thread = {
+		static MIN_KEYS_PER_THREAD: usize = 64;
+		let threads = options.serialize_threads.max(1);
+		keyset.len().div_ceil(threads).max(MIN_KEYS_PER_THREAD)
+	};
+	// last written field set per key; only used if unchanged samples are to be skipped
+	let mut previous: Vec<Vec<u8>> = vec![Vec::new(); keyset.len()];
+	let mut day_buffer = BytesMut::new();
+	// values[(key index * number of fields + field index) * RANGE_BLOCK_DAYS + day in block], NaN if absent
+	let mut values = vec![f64::NAN; keyset.len() * fields.len() * RANGE_BLOCK_DAYS];
+	for block_start in (0..ndays).step_by(RANGE_BLOCK_DAYS) {
+		let block_len = (ndays - block_start).min(RANGE_BLOCK_DAYS);
+		let block_date = start + chrono::Duration::days(block_start as i64);
+		// the views are not necessarily thread-safe, so they are evaluated here and only the serialization is spread over threads
+		for (j, entry) in keyset.iter().enumerate() {
+			for (l, desc) in fields.iter().enumerate() {
+				let offset = (j * fields.len() + l) * RANGE_BLOCK_DAYS;
+				desc.get_range(
+					entry.key,
+					block_date,
+					block_len,
+					&mut values[offset..offset + RANGE_BLOCK_DAYS],
+				);
+			}
+		}
+		let timestamps: Vec<BytesMut> = block_date
+			.iter_days()
+			.take(block_len)
+			.map(|date| {
+				let mut buf = BytesMut::new().writer();
+				precision
+					.encode_timestamp(
+						&mut buf,
+						&Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap()),
+					)
+					.expect("write to BytesMut failed");
+				buf.into_inner()
+			})
+			.collect();
 
-		for (k, tagset) in keyset.iter() {
-			fields_serialized.get_mut().clear();
-			for desc in fields.iter() {
-				let v = desc.inner().getf(k, date);
-				if let Some(v) = v {
-					if fields_serialized.get_mut().len() > 0 {
-						// write separator
-						fields_serialized.get_mut().put_u8(b',');
+		let block = BlockLayout {
+			measurement: &measurement_bytes[..],
+			field_names: &field_names[..],
+			field_types: &field_types[..],
+			timestamps: &timestamps[..],
+			block_days: RANGE_BLOCK_DAYS,
+			filter,
+		};
+		let values_per_key = fields.len() * RANGE_BLOCK_DAYS;
+		// serialized lines per key chunk and day; concatenated in key order below, so that the output does not depend on the number of threads
+		let chunks: Vec<Vec<BytesMut>> = if keyset.len() <= keys_per_thread {
+			vec![block.serialize(&tagsets[..], &values[..], &mut previous[..])]
+		} else {
+			std::thread::scope(|scope| {
+				let handles: Vec<_> = tagsets
+					.chunks(keys_per_thread)
+					.zip(values.chunks(keys_per_thread * values_per_key))
+					.zip(previous.chunks_mut(keys_per_thread))
+					.map(|((tagsets, values), previous)| {
+						let block = &block;
+						scope.spawn(move || block.serialize(tagsets, values, previous))
+					})
+					.collect();
+				handles
+					.into_iter()
+					.map(|h| h.join().expect("serialization thread panicked"))
+					.collect()
+			})
+		};
+
+		for (block_day, date) in block_date.iter_days().take(block_len).enumerate() {
+			let i = block_start + block_day;
+			day_buffer.clear();
+			for chunk in chunks.iter() {
+				day_buffer.put(&chunk[block_day][..]);
+			}
+			if let Some(day_hashes) = day_hashes.as_ref() {
+				let hash = day_hash(&day_buffer[..]);
+				if day_hashes.get(measurement, date) == Some(hash) {
+					unchanged_days += 1;
+					continue;
+				}
+				new_hashes.push((date, hash));
+			}
+			if !day_buffer.is_empty() {
+				stats.last_day = Some(date);
+			}
+			for line in day_buffer.split_inclusive(|ch| *ch == b'\n') {
+				stats.rows += 1;
+				if writer.push(line)? {
+					// the current day may be incomplete, all earlier ones are through
+					if let Some(cp) = checkpoint.as_mut().filter(|_| i > 0) {
+						cp.set(measurement, date.pred_opt())?;
 					}
-					influxdb::readout::write_name(&mut fields_serialized, desc.name())
-						.expect("write to BytesMut failed");
-					fields_serialized.get_mut().put_u8(b'=');
-					write!(&mut fields_serialized, "{:?}", v).expect("write to BytesMut failed");
+					pm.update(i + 1);
 				}
 			}
+		}
+	}
+	writer.flush()?;
+	stats.bytes = writer.bytes_submitted;
+	if let Some(cp) = checkpoint.as_mut().filter(|_| ndays > 0) {
+		cp.set(
+			measurement,
+			Some(start + chrono::Duration::days(ndays as i64 - 1)),
+		)?;
+	}
+	// only recorded once everything went through, so that a failed run is repeated in full
+	if let Some(day_hashes) = day_hashes.as_mut() {
+		info!(
+			"{}: {} of {} days unchanged since the last run",
+			measurement, unchanged_days, ndays
+		);
+		day_hashes.update(measurement, new_hashes);
+		day_hashes.save()?;
+	}
+	pm.finish();
+	Ok(stats)
+}
+
+// What stream_dynamic needs to turn a block of evaluated values into line protocol; shared by the serialization threads.
+struct BlockLayout<'x> {
+	measurement: &'x [u8],
+	field_names: &'x [&'x str],
+	field_types: &'x [FieldType],
+	// one per day of the block
+	timestamps: &'x [BytesMut],
+	// stride of the values of one key and field
+	block_days: usize,
+	filter: influxdb::SampleFilter,
+}
+
+impl<'x> BlockLayout<'x> {
+	// Serializes the lines of a consecutive range of keys, returning one buffer per day. `values` and `previous` are the parts belonging to these keys.
+	fn serialize(
+		&self,
+		tagsets: &[&[u8]],
+		values: &[f64],
+		previous: &mut [Vec<u8>],
+	) -> Vec<BytesMut> {
+		let mut result: Vec<BytesMut> = self.timestamps.iter().map(|_| BytesMut::new()).collect();
+		let mut fields_serialized = BytesMut::new().writer();
+		for (block_day, out) in result.iter_mut().enumerate() {
+			for (j, tagset) in tagsets.iter().enumerate() {
+				fields_serialized.get_mut().clear();
+				let mut all_default = true;
+				for (l, name) in self.field_names.iter().enumerate() {
+					let v = values[(j * self.field_names.len() + l) * self.block_days + block_day];
+					if !v.is_nan() {
+						if v != 0. {
+							all_default = false;
+						}
+						if !fields_serialized.get_mut().is_empty() {
+							// write separator
+							fields_serialized.get_mut().put_u8(b',');
+						}
+						influxdb::readout::write_name(&mut fields_serialized, name)
+							.expect("write to BytesMut failed");
+						fields_serialized.get_mut().put_u8(b'=');
+						match self.field_types[l] {
+							FieldType::Float => write!(&mut fields_serialized, "{:?}", v),
+							FieldType::Integer => {
+								write!(&mut fields_serialized, "{}i", v.round() as i64)
+							}
+						}
+						.expect("write to BytesMut failed");
+					}
+				}
 
-			if fields_serialized.get_mut().len() == 0 {
-				continue;
+				if fields_serialized.get_mut().is_empty() {
+					continue;
+				}
+
+				if self.filter.skip_default && all_default {
+					continue;
+				}
+
+				if self.filter.skip_unchanged {
+					let prev = &mut previous[j];
+					if prev[..] == fields_serialized.get_ref()[..] {
+						continue;
+					}
+					prev.clear();
+					prev.extend_from_slice(&fields_serialized.get_ref()[..]);
+				}
+
+				out.put(self.measurement);
+				out.put(&tagset[..]);
+				out.put_u8(b' ');
+				out.put(&fields_serialized.get_mut()[..]);
+				out.put_u8(b' ');
+				out.put(&self.timestamps[block_day][..]);
+				out.put_u8(b'\n');
 			}
+		}
+		result
+	}
+}
+
+// Collects line protocol and posts it in requests bounded by chunk_lines and chunk_bytes (see StreamOptions).
+struct ChunkedWriter<'x> {
+	sink: &'x influxdb::Client,
+	database: &'x str,
+	retention_policy: Option<&'x str>,
+	precision: influxdb::Precision,
+	chunk_lines: usize,
+	chunk_bytes: usize,
+	buffer: BytesMut,
+	buffered_lines: usize,
+	bytes_submitted: usize,
+}
 
-			buffer.put(&measurement_bytes[..]);
-			buffer.put(&tagset[..]);
-			buffer.put_u8(b' ');
-			buffer.put(&fields_serialized.get_mut()[..]);
-			buffer.put_u8(b' ');
-			buffer.put(&timestamp_serialized.get_mut()[..]);
-			buffer.put_u8(b'\n');
+impl<'x> ChunkedWriter<'x> {
+	// Appends a single line (including the newline) and returns whether this caused a request to be sent.
+	fn push(&mut self, line: &[u8]) -> Result<bool> {
+		self.buffer.put(line);
+		self.buffered_lines += 1;
+		if (self.chunk_lines > 0 && self.buffered_lines >= self.chunk_lines)
+			|| (self.chunk_bytes > 0 && self.buffer.len() >= self.chunk_bytes)
+		{
+			self.flush()?;
+			return Ok(true);
 		}
+		Ok(false)
+	}
 
-		if i % chunk_size == 0 {
-			let mut to_submit = BytesMut::with_capacity(buffer.capacity());
-			std::mem::swap(&mut to_submit, &mut buffer);
-			sink.post_raw("covid", None, None, precision, to_submit.freeze())?;
-			pm.update(i + 1);
+	fn flush(&mut self) -> Result<()> {
+		if self.buffer.is_empty() {
+			return Ok(());
 		}
+		let mut to_submit = BytesMut::with_capacity(self.buffer.capacity());
+		std::mem::swap(&mut to_submit, &mut self.buffer);
+		debug!(
+			"posting {} lines ({} bytes) to {}",
+			self.buffered_lines,
+			to_submit.len(),
+			self.database
+		);
+		self.bytes_submitted += to_submit.len();
+		self.buffered_lines = 0;
+		Ok(self.sink.post_raw(
+			self.database,
+			self.retention_policy,
+			None,
+			self.precision,
+			to_submit.freeze(),
+		)?)
 	}
-	if buffer.len() > 0 {
-		sink.post_raw("covid", None, None, precision, buffer.freeze())?;
+}
+
+// Writes the given fields for all keys in `keyset` and all days in `range` into `measurement`. This is the entry point for pushing custom datasets through the same pipeline as the RKI data; see examples/custom_measurement.rs.
+pub fn write_measurement<K: TimeSeriesKey>(
+	client: &influxdb::Client,
+	measurement: &str,
+	keyset: &KeySet<K>,
+	fields: &[FieldDescriptor<Arc<dyn ViewTimeSeries<K>>>],
+	range: Range<NaiveDate>,
+) -> Result<PhaseStats> {
+	let ndays = (range.end - range.start).num_days().max(0) as usize;
+	stream_dynamic(
+		client,
+		&mut *default_output(),
+		measurement,
+		range.start,
+		ndays,
+		keyset,
+		fields,
+		&env_stream_options(),
+	)
+}
+
+// The destination for stream_dynamic: an InfluxDB server, a local SQLite file, a directory of CSV or Parquet files, a line protocol file or several of these at once.
+pub enum Output {
+	Influx(influxdb::Client),
+	Sqlite(SqliteSink),
+	Csv(CsvSink),
+	Parquet(ParquetSink),
+	// a client whose writes go into a file, see Output::line_protocol_file
+	LineProtocol(influxdb::Client),
+	FanOut(FanOut),
+}
+
+impl Output {
+	// Writes the line protocol which would be sent to InfluxDB into the file at `path`, which is truncated first.
+	pub fn line_protocol_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+		let path = path.as_ref();
+		let transport = influxdb::FileTransport::create(path)?;
+		Ok(Self::LineProtocol(
+			influxdb::Client::new(path.display().to_string(), influxdb::Auth::None)
+				.with_transport(Box::new(transport)),
+		))
+	}
+
+	// The InfluxDB servers written to, with the error policy of their fan-out target; line protocol files are not included.
+	pub fn influx_clients(&self) -> Vec<(&influxdb::Client, SinkErrorPolicy)> {
+		match self {
+			Self::Influx(client) => vec![(client, SinkErrorPolicy::FailFast)],
+			Self::FanOut(fanout) => fanout
+				.targets()
+				.iter()
+				.flat_map(|target| {
+					target
+						.output
+						.influx_clients()
+						.into_iter()
+						// a target which may fail may fail as a whole
+						.map(move |(client, policy)| match target.policy {
+							SinkErrorPolicy::FailFast => (client, policy),
+							SinkErrorPolicy::LogAndContinue => (client, target.policy),
+						})
+				})
+				.collect(),
+			_ => Vec::new(),
+		}
+	}
+
+	// The InfluxDB server the checkpoint refers to, i.e. the output itself or the first target of a fan-out.
+	pub fn primary_influx(&self) -> Option<&influxdb::Client> {
+		match self {
+			Self::Influx(client) => Some(client),
+			Self::FanOut(fanout) => fanout
+				.targets()
+				.first()
+				.and_then(|target| target.output.primary_influx()),
+			_ => None,
+		}
+	}
+
+	// Writes the measurement to the destination; measurements with Downsampling configured are split into the daily and the weekly part.
+	#[allow(clippy::too_many_arguments)]
+	pub fn stream_dynamic<K: TimeSeriesKey, S: ProgressSink + ?Sized>(
+		&mut self,
+		progress: &mut S,
+		measurement: &str,
+		start: NaiveDate,
+		ndays: usize,
+		keyset: &KeySet<K>,
+		fields: &[FieldDescriptor<Arc<dyn ViewTimeSeries<K>>>],
+		options: &StreamOptions,
+	) -> Result<PhaseStats> {
+		let filtered;
+		let keyset = match options.key_filter.as_ref() {
+			Some(filter) if options.is_enabled(measurement) => {
+				filtered = filter.apply(keyset);
+				info!(
+					"{}: streaming {} of {} keys",
+					measurement,
+					filtered.len(),
+					keyset.len()
+				);
+				&filtered
+			}
+			_ => keyset,
+		};
+		let downsampling = match options.downsampling.get(measurement) {
+			Some(v) if options.is_enabled(measurement) => v,
+			_ => {
+				return self.stream_dynamic_to(
+					progress,
+					measurement,
+					start,
+					ndays,
+					keyset,
+					fields,
+					options,
+				)
+			}
+		};
+		let weekly_days = ndays.saturating_sub(downsampling.daily_days);
+		// the week which straddles the split is only written at daily resolution
+		let mut weekly_options = options.clone();
+		weekly_options
+			.measurement_names
+			.insert(measurement.into(), downsampling.measurement.clone());
+		if downsampling.retention_policy.is_some() {
+			weekly_options.retention_policy = downsampling.retention_policy.clone();
+		}
+		let weekly = self.stream_dynamic_to(
+			progress,
+			measurement,
+			start,
+			weekly_days,
+			keyset,
+			&pipeline::resampled(fields, Bucket::Week)[..],
+			&weekly_options,
+		)?;
+		let daily = self.stream_dynamic_to(
+			progress,
+			measurement,
+			start + chrono::Duration::days(weekly_days as i64),
+			ndays - weekly_days,
+			keyset,
+			fields,
+			options,
+		)?;
+		Ok(PhaseStats {
+			rows: weekly.rows + daily.rows,
+			errors: weekly.errors + daily.errors,
+			bytes: weekly.bytes + daily.bytes,
+			// of the daily measurement only, which keeps the name
+			last_day: daily.last_day,
+		})
+	}
+
+	#[allow(clippy::too_many_arguments)]
+	fn stream_dynamic_to<K: TimeSeriesKey, S: ProgressSink + ?Sized>(
+		&mut self,
+		progress: &mut S,
+		measurement: &str,
+		start: NaiveDate,
+		ndays: usize,
+		keyset: &KeySet<K>,
+		fields: &[FieldDescriptor<Arc<dyn ViewTimeSeries<K>>>],
+		options: &StreamOptions,
+	) -> Result<PhaseStats> {
+		match self {
+			Self::Influx(client) | Self::LineProtocol(client) => Ok(stream_dynamic(
+				client,
+				progress,
+				measurement,
+				start,
+				ndays,
+				keyset,
+				fields,
+				options,
+			)?),
+			Self::Sqlite(sink) => {
+				sink.stream_dynamic(progress, measurement, start, ndays, keyset, fields, options)
+			}
+			Self::Csv(sink) => {
+				sink.stream_dynamic(progress, measurement, start, ndays, keyset, fields, options)
+			}
+			Self::Parquet(sink) => {
+				sink.stream_dynamic(progress, measurement, start, ndays, keyset, fields, options)
+			}
+			Self::FanOut(fanout) => fanout
+				.each(measurement, options, |output, options| {
+					output.stream_dynamic_to(
+						progress,
+						measurement,
+						start,
+						ndays,
+						keyset,
+						fields,
+						options,
+					)
+				})
+				.map(Option::unwrap_or_default),
+		}
+	}
+
+	pub fn submit_runs(&mut self, runs: &RunLog, options: &StreamOptions) -> Result<()> {
+		match self {
+			Self::Influx(client) | Self::LineProtocol(client) => Ok(runs.submit(client, options)?),
+			Self::Sqlite(sink) => match runs.readout_for(options) {
+				Some(readout) => sink.write_readout(&readout),
+				None => Ok(()),
+			},
+			Self::Csv(sink) => match runs.readout_for(options) {
+				Some(readout) => sink.write_readout(&readout),
+				None => Ok(()),
+			},
+			Self::Parquet(sink) => match runs.readout_for(options) {
+				Some(readout) => sink.write_readout(&readout),
+				None => Ok(()),
+			},
+			Self::FanOut(fanout) => fanout
+				.each("run statistics", options, |output, options| {
+					output.submit_runs(runs, options)
+				})
+				.map(|_| ()),
+		}
 	}
-	pm.finish();
-	Ok(())
 }
 
 pub fn env_client() -> influxdb::Client {
+	env_client_with_url(env::var("INFLUXDB_URL").unwrap_or("http://127.0.0.1:8086".into()))
+}
+
+// Like env_client, but for the server at `url`, e.g. a second server the run is also written to.
+pub fn env_client_with_url(url: String) -> influxdb::Client {
 	let user = env::var("INFLUXDB_USER");
 	let pass = env::var("INFLUXDB_PASSWORD");
 	let auth = match (user, pass) {
@@ -171,8 +1063,104 @@ pub fn env_client() -> influxdb::Client {
 		),
 		(Err(_), Err(_)) => influxdb::Auth::None,
 	};
-	influxdb::Client::new(
-		env::var("INFLUXDB_URL").unwrap_or("http://127.0.0.1:8086".into()),
-		auth,
-	)
+	// bodies of at least this many bytes are gzip-compressed; unset or 0 disables compression
+	let gzip_threshold = match env::var("INFLUXDB_GZIP_THRESHOLD") {
+		Ok(v) => Some(
+			v.parse::<usize>()
+				.unwrap_or_else(|e| panic!("failed to parse INFLUXDB_GZIP_THRESHOLD: {}", e)),
+		)
+		.filter(|v| *v > 0),
+		Err(_) => None,
+	};
+	influxdb::Client::new(url, auth).with_gzip(gzip_threshold)
+}
+
+pub fn env_fetcher() -> Fetcher {
+	Fetcher::new(env::var_os("COVID_CACHE_DIR").map(|v| v.into()))
+}
+
+pub fn env_stream_options() -> StreamOptions {
+	let mut result = StreamOptions::default();
+	if let Ok(v) = env::var("COVID_SAMPLE_FILTER") {
+		result.sample_filter = v
+			.parse()
+			.unwrap_or_else(|e| panic!("failed to parse COVID_SAMPLE_FILTER: {}", e));
+	}
+	if let Ok(v) = env::var("COVID_INFLUXDB_DATABASE") {
+		result.database = v;
+	}
+	result.retention_policy = env::var("COVID_INFLUXDB_RETENTION_POLICY").ok();
+	// comma separated list of builtin=name pairs
+	if let Ok(v) = env::var("COVID_MEASUREMENT_NAMES") {
+		for item in v.split(',').filter(|x| !x.is_empty()) {
+			match item.split_once('=') {
+				Some((builtin, name)) => {
+					result
+						.measurement_names
+						.insert(builtin.trim().into(), name.trim().into());
+				}
+				None => panic!(
+					"failed to parse COVID_MEASUREMENT_NAMES: expected builtin=name, got {:?}",
+					item
+				),
+			}
+		}
+	}
+	if let Ok(v) = env::var("COVID_CHUNK_LINES") {
+		result.chunk_lines = v
+			.parse()
+			.unwrap_or_else(|e| panic!("failed to parse COVID_CHUNK_LINES: {}", e));
+	}
+	if let Ok(v) = env::var("COVID_CHUNK_BYTES") {
+		result.chunk_bytes = v
+			.parse()
+			.unwrap_or_else(|e| panic!("failed to parse COVID_CHUNK_BYTES: {}", e));
+	}
+	// comma separated list of built-in measurement names
+	if let Ok(v) = env::var("COVID_DISABLED_MEASUREMENTS") {
+		result.disabled_measurements.extend(
+			v.split(',')
+				.map(|x| x.trim())
+				.filter(|x| !x.is_empty())
+				.map(|x| x.into()),
+		);
+	}
+	result.checkpoint = env::var_os("COVID_CHECKPOINT_FILE").map(PathBuf::from);
+	result.incremental_state = env::var_os("COVID_INCREMENTAL_STATE").map(PathBuf::from);
+	if let Ok(v) = env::var("COVID_STREAM_THREADS") {
+		result.serialize_threads = v
+			.parse()
+			.unwrap_or_else(|e| panic!("failed to parse COVID_STREAM_THREADS: {}", e));
+	}
+	// comma separated list of builtin=days:measurement[@retention_policy]
+	if let Ok(v) = env::var("COVID_DOWNSAMPLING") {
+		for item in v.split(',').filter(|x| !x.is_empty()) {
+			let (builtin, spec) = item.split_once('=').unwrap_or_else(|| {
+				panic!(
+					"failed to parse COVID_DOWNSAMPLING: expected builtin=days:measurement, got {:?}",
+					item
+				)
+			});
+			let spec = spec
+				.parse()
+				.unwrap_or_else(|e| panic!("failed to parse COVID_DOWNSAMPLING: {}", e));
+			result.downsampling.insert(builtin.trim().into(), spec);
+		}
+	}
+	if let Ok(v) = env::var("COVID_INTEGER_FIELDS") {
+		result.integer_fields = v
+			.parse::<u32>()
+			.unwrap_or_else(|e| panic!("failed to parse COVID_INTEGER_FIELDS: {}", e))
+			!= 0;
+	}
+	result
+}
+
+pub fn env_submit_policy() -> SubmitPolicy {
+	match env::var("COVID_SUBMIT_POLICY") {
+		Ok(v) => v
+			.parse()
+			.unwrap_or_else(|e| panic!("failed to parse COVID_SUBMIT_POLICY: {}", e)),
+		Err(_) => SubmitPolicy::default(),
+	}
 }
@@ -0,0 +1,82 @@
+use std::env;
+use std::io;
+use std::io::Write;
+
+use chrono::{SecondsFormat, Utc};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+// Writes log records to stderr, either as one line of text or as one JSON object per record.
+struct Logger {
+	level: LevelFilter,
+	json: bool,
+}
+
+impl Log for Logger {
+	fn enabled(&self, metadata: &Metadata) -> bool {
+		metadata.level() <= self.level
+	}
+
+	fn log(&self, record: &Record) {
+		if !self.enabled(record.metadata()) {
+			return;
+		}
+		let time = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+		let stderr = io::stderr();
+		let mut w = stderr.lock();
+		// there is nowhere left to report a failure to
+		let _ = if self.json {
+			writeln!(
+				w,
+				"{}",
+				serde_json::json!({
+					"time": time,
+					"level": record.level().as_str(),
+					"target": record.target(),
+					"message": record.args().to_string(),
+				})
+			)
+		} else {
+			writeln!(
+				w,
+				"{} {:<5} {}: {}",
+				time,
+				record.level(),
+				record.target(),
+				record.args()
+			)
+		};
+	}
+
+	fn flush(&self) {
+		let _ = io::stderr().flush();
+	}
+}
+
+// Installs the logger of the binaries. COVID_LOG sets the level (error, warn, info, debug, trace or off; default info), COVID_LOG_FORMAT=json switches to JSON lines. Calling it more than once is harmless.
+pub fn init_logging() -> io::Result<()> {
+	let level = match env::var("COVID_LOG") {
+		Ok(v) => v.parse::<LevelFilter>().map_err(|_| {
+			io::Error::new(
+				io::ErrorKind::InvalidInput,
+				format!("invalid COVID_LOG level: {:?}", v),
+			)
+		})?,
+		Err(_) => Level::Info.to_level_filter(),
+	};
+	let json = match env::var("COVID_LOG_FORMAT") {
+		Ok(v) if v == "json" => true,
+		Ok(v) if v == "text" => false,
+		Ok(v) => {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidInput,
+				format!("invalid COVID_LOG_FORMAT: {:?}", v),
+			))
+		}
+		Err(_) => false,
+	};
+	if log::set_boxed_logger(Box::new(Logger { level, json })).is_ok() {
+		log::set_max_level(level);
+	}
+	Ok(())
+}
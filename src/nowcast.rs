@@ -0,0 +1,118 @@
+use chrono::NaiveDate;
+
+use super::timeseries::{Counters, TimeSeriesKey, ViewTimeSeries};
+
+// days at the end of the data which are corrected
+pub static NOWCAST_DAYS: u32 = 14;
+// publication days over which the delay is estimated
+pub static NOWCAST_ESTIMATION_DAYS: u32 = 28;
+
+// The delay between the report date (Meldedatum) and the publication of a case by the RKI. rki_diff only records the sum and the number of delays per day, so the distribution is assumed to be geometric with the observed mean, starting at one day, as a case is published the day after its report at the earliest.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DelayDistribution {
+	pub mean: f64,
+}
+
+impl DelayDistribution {
+	// means below one day are taken as one day
+	pub fn new(mean: f64) -> Self {
+		Self { mean: mean.max(1.) }
+	}
+
+	// The share of the cases of a report date which are published within `days` days, i.e. P(delay <= days).
+	pub fn reported_share(&self, days: i64) -> f64 {
+		if days < 1 {
+			return 0.;
+		}
+		let p = 1. / self.mean;
+		1. - (1. - p).powi(days as i32)
+	}
+}
+
+// The delay over the estimation window, with the 10th and 90th percentile of the mean delays of the single publication days as bounds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DelayEstimate {
+	pub expected: DelayDistribution,
+	pub low: DelayDistribution,
+	pub high: DelayDistribution,
+}
+
+fn sorted_percentile(sl: &[f64], q: f64) -> f64 {
+	sl[((sl.len() - 1) as f64 * q).round() as usize]
+}
+
+// Estimates the delay from the diff counters (case_delay_total and cases_delayed, summed over all keys) of the `window` days before `end`. None if there were no delayed cases in the window.
+pub fn estimate_delay<K: TimeSeriesKey>(
+	delay_total: &Counters<K>,
+	cases_delayed: &Counters<K>,
+	end: NaiveDate,
+	window: u32,
+) -> Option<DelayEstimate> {
+	let mut sum_delay = 0u64;
+	let mut sum_cases = 0u64;
+	let mut daily_means = Vec::new();
+	for i in 1..=window as i64 {
+		let index = match cases_delayed.date_index(end - chrono::Duration::days(i)) {
+			Some(v) => v,
+			None => continue,
+		};
+		let delay: u64 = delay_total
+			.keys()
+			.map(|k| delay_total.get_value(k, index).unwrap_or(0))
+			.sum();
+		let cases: u64 = cases_delayed
+			.keys()
+			.map(|k| cases_delayed.get_value(k, index).unwrap_or(0))
+			.sum();
+		if cases == 0 {
+			continue;
+		}
+		sum_delay += delay;
+		sum_cases += cases;
+		daily_means.push(delay as f64 / cases as f64);
+	}
+	if sum_cases == 0 {
+		return None;
+	}
+	daily_means.sort_by(|a, b| a.partial_cmp(b).unwrap());
+	Some(DelayEstimate {
+		expected: DelayDistribution::new(sum_delay as f64 / sum_cases as f64),
+		low: DelayDistribution::new(sorted_percentile(&daily_means, 0.1)),
+		high: DelayDistribution::new(sorted_percentile(&daily_means, 0.9)),
+	})
+}
+
+// Corrects a series by report date for the cases which are not published yet: the values of the last `days` days before `asof` (the publication date of the data) are divided by the share which the delay distribution expects to be published already. Earlier values are passed through.
+pub struct Nowcast<I> {
+	inner: I,
+	delay: DelayDistribution,
+	asof: NaiveDate,
+	days: u32,
+}
+
+impl<I> Nowcast<I> {
+	pub fn new(inner: I, delay: DelayDistribution, asof: NaiveDate, days: u32) -> Self {
+		Self {
+			inner,
+			delay,
+			asof,
+			days,
+		}
+	}
+}
+
+impl<K: TimeSeriesKey, I: ViewTimeSeries<K>> ViewTimeSeries<K> for Nowcast<I> {
+	fn getf(&self, k: &K, at: NaiveDate) -> Option<f64> {
+		let v = self.inner.getf(k, at)?;
+		let age = (self.asof - at).num_days();
+		if age > self.days as i64 {
+			return Some(v);
+		}
+		let share = self.delay.reported_share(age);
+		if share > 0. {
+			Some(v / share)
+		} else {
+			None
+		}
+	}
+}
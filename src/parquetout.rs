@@ -0,0 +1,337 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use chrono::{Datelike, NaiveDate};
+
+use arrow_array::builder::{
+	BooleanBuilder, Date32Builder, Float64Builder, Int64Builder, StringBuilder,
+	TimestampSecondBuilder,
+};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+
+use super::influxdb;
+use super::progress::{ProgressSink, StepMeter};
+use super::runlog::PhaseStats;
+use super::timeseries::{TimeSeriesKey, ViewTimeSeries};
+use super::{Error, FieldDescriptor, FieldType, KeySet, Result, StreamOptions};
+
+// rows per record batch, i.e. per row group of the file
+static BATCH_ROWS: usize = 65536;
+
+fn wrap_err<E: Into<Box<dyn std::error::Error + Send + Sync>>>(err: E) -> io::Error {
+	io::Error::other(err)
+}
+
+// days from 0001-01-01 to 1970-01-01, the epoch of Parquet's DATE
+static EPOCH_DAYS_FROM_CE: i32 = 719_163;
+
+fn epoch_days(date: NaiveDate) -> i32 {
+	date.num_days_from_ce() - EPOCH_DAYS_FROM_CE
+}
+
+enum FieldColumn {
+	Float(Float64Builder),
+	Integer(Int64Builder),
+	Bool(BooleanBuilder),
+	String(StringBuilder),
+}
+
+impl FieldColumn {
+	fn for_value(v: &influxdb::readout::FieldValue) -> Self {
+		match v {
+			influxdb::readout::FieldValue::Numeric(_) => Self::Float(Float64Builder::new()),
+			influxdb::readout::FieldValue::Integer(_) => Self::Integer(Int64Builder::new()),
+			influxdb::readout::FieldValue::Bool(_) => Self::Bool(BooleanBuilder::new()),
+			influxdb::readout::FieldValue::String(_) => Self::String(StringBuilder::new()),
+		}
+	}
+
+	fn data_type(&self) -> DataType {
+		match self {
+			Self::Float(_) => DataType::Float64,
+			Self::Integer(_) => DataType::Int64,
+			Self::Bool(_) => DataType::Boolean,
+			Self::String(_) => DataType::Utf8,
+		}
+	}
+
+	// NaN is null
+	fn append_f64(&mut self, v: f64) {
+		match self {
+			Self::Float(b) => b.append_option(Some(v).filter(|v| !v.is_nan())),
+			Self::Integer(b) => {
+				b.append_option(Some(v).filter(|v| !v.is_nan()).map(|v| v.round() as i64))
+			}
+			Self::Bool(_) | Self::String(_) => unreachable!(),
+		}
+	}
+
+	// false if the value does not fit the type of the column
+	fn append_value(&mut self, v: &influxdb::readout::FieldValue) -> bool {
+		match (self, v) {
+			(Self::Float(b), influxdb::readout::FieldValue::Numeric(v)) => b.append_value(*v),
+			(Self::Integer(b), influxdb::readout::FieldValue::Integer(v)) => b.append_value(*v),
+			(Self::Bool(b), influxdb::readout::FieldValue::Bool(v)) => b.append_value(*v),
+			(Self::String(b), influxdb::readout::FieldValue::String(v)) => {
+				b.append_value(v.as_str())
+			}
+			_ => return false,
+		}
+		true
+	}
+
+	fn finish(&mut self) -> ArrayRef {
+		match self {
+			Self::Float(b) => Arc::new(b.finish()),
+			Self::Integer(b) => Arc::new(b.finish()),
+			Self::Bool(b) => Arc::new(b.finish()),
+			Self::String(b) => Arc::new(b.finish()),
+		}
+	}
+}
+
+// Writes keyed daily series as Parquet files into a directory, one file per measurement (`<measurement>.parquet`) with a date column (DATE), one string column per tag and one column per field; the fields are doubles, or 64 bit integers for integer fields with StreamOptions::integer_fields, and absent values are null. As with CsvSink, there is one row per key and date, and existing files are overwritten.
+pub struct ParquetSink {
+	dir: PathBuf,
+}
+
+impl ParquetSink {
+	pub fn new<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+		let dir = dir.as_ref().to_path_buf();
+		fs::create_dir_all(&dir)?;
+		Ok(Self { dir })
+	}
+
+	fn path(&self, measurement: &str) -> PathBuf {
+		// measurement names are plain identifiers in practice, but they must not escape the output directory
+		let name: String = measurement
+			.chars()
+			.map(|ch| match ch {
+				'/' | '\\' => '_',
+				ch => ch,
+			})
+			.collect();
+		self.dir.join(format!("{}.parquet", name))
+	}
+
+	fn writer(path: &Path, schema: SchemaRef) -> io::Result<ArrowWriter<fs::File>> {
+		let props = WriterProperties::builder()
+			.set_compression(Compression::SNAPPY)
+			.build();
+		ArrowWriter::try_new(fs::File::create(path)?, schema, Some(props)).map_err(wrap_err)
+	}
+
+	#[allow(clippy::too_many_arguments)]
+	pub fn stream_dynamic<K: TimeSeriesKey, S: ProgressSink + ?Sized>(
+		&mut self,
+		progress: &mut S,
+		measurement: &str,
+		start: NaiveDate,
+		ndays: usize,
+		keyset: &KeySet<K>,
+		fields: &[FieldDescriptor<Arc<dyn ViewTimeSeries<K>>>],
+		options: &StreamOptions,
+	) -> Result<PhaseStats> {
+		let mut stats = PhaseStats::default();
+		if !options.is_enabled(measurement) {
+			return Ok(stats);
+		}
+		let measurement = options.measurement_name(measurement);
+		let mut field_columns: Vec<FieldColumn> = fields
+			.iter()
+			.map(|desc| match desc.field_type() {
+				FieldType::Integer if options.integer_fields => {
+					FieldColumn::Integer(Int64Builder::new())
+				}
+				_ => FieldColumn::Float(Float64Builder::new()),
+			})
+			.collect();
+		let schema: SchemaRef = {
+			let mut columns = vec![Field::new("date", DataType::Date32, false)];
+			columns.extend(
+				keyset
+					.tag_names()
+					.iter()
+					.map(|name| Field::new(name.as_str(), DataType::Utf8, false)),
+			);
+			columns.extend(
+				fields
+					.iter()
+					.zip(field_columns.iter())
+					.map(|(desc, column)| Field::new(desc.name(), column.data_type(), true)),
+			);
+			Arc::new(Schema::new(columns))
+		};
+		let mut w = Self::writer(&self.path(measurement), schema.clone())?;
+		let mut dates = Date32Builder::new();
+		let mut tag_columns: Vec<StringBuilder> = keyset
+			.tag_names()
+			.iter()
+			.map(|_| StringBuilder::new())
+			.collect();
+		let mut nbatch = 0;
+		let mut flush = |dates: &mut Date32Builder,
+		                 tag_columns: &mut [StringBuilder],
+		                 field_columns: &mut [FieldColumn]|
+		 -> Result<()> {
+			let mut columns: Vec<ArrayRef> = vec![Arc::new(dates.finish())];
+			columns.extend(
+				tag_columns
+					.iter_mut()
+					.map(|b| Arc::new(b.finish()) as ArrayRef),
+			);
+			columns.extend(field_columns.iter_mut().map(|b| b.finish()));
+			let batch = RecordBatch::try_new(schema.clone(), columns).map_err(wrap_err)?;
+			w.write(&batch).map_err(wrap_err)?;
+			Ok(())
+		};
+
+		let filter = options.sample_filter;
+		let mut pm = StepMeter::new(progress, keyset.len());
+		// values[field index * ndays + day]
+		let mut values = vec![f64::NAN; fields.len() * ndays];
+		for (j, entry) in keyset.iter().enumerate() {
+			for (l, desc) in fields.iter().enumerate() {
+				desc.get_range(
+					entry.key,
+					start,
+					ndays,
+					&mut values[l * ndays..(l + 1) * ndays],
+				);
+			}
+			let mut previous: Option<Vec<u64>> = None;
+			for (i, date) in start.iter_days().take(ndays).enumerate() {
+				let day_values = (0..fields.len()).map(|l| values[l * ndays + i]);
+				let any_present = day_values.clone().any(|v| !v.is_nan());
+				let all_default = day_values.clone().all(|v| v.is_nan() || v == 0.);
+				if !any_present || (filter.skip_default && all_default) {
+					continue;
+				}
+				if filter.skip_unchanged {
+					// by bits, so that absent values compare equal
+					let current: Vec<u64> = day_values.clone().map(f64::to_bits).collect();
+					if previous.as_ref() == Some(&current) {
+						continue;
+					}
+					previous = Some(current);
+				}
+				dates.append_value(epoch_days(date));
+				for (b, tagv) in tag_columns.iter_mut().zip(entry.tag_values.iter()) {
+					b.append_value(tagv.as_str());
+				}
+				for (b, v) in field_columns.iter_mut().zip(day_values) {
+					b.append_f64(v);
+				}
+				stats.rows += 1;
+				nbatch += 1;
+				if nbatch >= BATCH_ROWS {
+					flush(&mut dates, &mut tag_columns, &mut field_columns)?;
+					nbatch = 0;
+				}
+			}
+			pm.update(j + 1);
+		}
+		if nbatch > 0 {
+			flush(&mut dates, &mut tag_columns, &mut field_columns)?;
+		}
+		pm.finish();
+		w.close().map_err(wrap_err)?;
+		Ok(stats)
+	}
+
+	// Appends an arbitrary readout (e.g. the run statistics) to `<measurement>.parquet`, with a time column (seconds, UTC), one string column per tag and one column per field, typed after the values of the first sample. Parquet files cannot be appended to, so the existing rows are read and written back; they must have the same columns.
+	pub fn write_readout(&mut self, readout: &influxdb::Readout) -> Result<()> {
+		let first = match readout.samples.first() {
+			Some(v) => v,
+			None => return Ok(()),
+		};
+		let mut field_columns: Vec<FieldColumn> =
+			first.fieldv.iter().map(FieldColumn::for_value).collect();
+		let schema: SchemaRef = {
+			let mut columns = vec![Field::new(
+				"time",
+				DataType::Timestamp(TimeUnit::Second, Some("UTC".into())),
+				false,
+			)];
+			columns.extend(
+				readout
+					.tags
+					.iter()
+					.map(|name| Field::new(name.as_str(), DataType::Utf8, false)),
+			);
+			columns.extend(
+				readout
+					.fields
+					.iter()
+					.zip(field_columns.iter())
+					.map(|(name, column)| Field::new(name.as_str(), column.data_type(), false)),
+			);
+			Arc::new(Schema::new(columns))
+		};
+
+		let mut times = TimestampSecondBuilder::new().with_timezone("UTC");
+		let mut tag_columns: Vec<StringBuilder> =
+			readout.tags.iter().map(|_| StringBuilder::new()).collect();
+		for sample in readout.samples.iter() {
+			times.append_value(readout.ts.timestamp());
+			for (b, tagv) in tag_columns.iter_mut().zip(sample.tagv.iter()) {
+				b.append_value(tagv.as_str());
+			}
+			for ((b, v), name) in field_columns
+				.iter_mut()
+				.zip(sample.fieldv.iter())
+				.zip(readout.fields.iter())
+			{
+				if !b.append_value(v) {
+					return Err(Error::Schema(format!(
+						"{}: field {} changes its type between samples",
+						readout.measurement, name
+					)));
+				}
+			}
+		}
+		let mut columns: Vec<ArrayRef> = vec![Arc::new(times.finish())];
+		columns.extend(
+			tag_columns
+				.iter_mut()
+				.map(|b| Arc::new(b.finish()) as ArrayRef),
+		);
+		columns.extend(field_columns.iter_mut().map(|b| b.finish()));
+		let batch = RecordBatch::try_new(schema.clone(), columns).map_err(wrap_err)?;
+
+		let path = self.path(&readout.measurement);
+		let mut batches = Vec::new();
+		if path.exists() {
+			let reader = ParquetRecordBatchReaderBuilder::try_new(fs::File::open(&path)?)
+				.map_err(wrap_err)?;
+			if reader.schema().fields() != schema.fields() {
+				return Err(Error::Schema(format!(
+					"{} has different columns than {}, move it out of the way",
+					path.display(),
+					readout.measurement
+				)));
+			}
+			for existing in reader.build().map_err(wrap_err)? {
+				batches.push(existing.map_err(wrap_err)?);
+			}
+		}
+		batches.push(batch);
+		// the existing file is only replaced once it has been written in full
+		let tmp_path = path.with_extension("parquet.tmp");
+		let mut w = Self::writer(&tmp_path, schema)?;
+		for batch in batches.iter() {
+			w.write(batch).map_err(wrap_err)?;
+		}
+		w.close().map_err(wrap_err)?;
+		fs::rename(&tmp_path, &path)?;
+		Ok(())
+	}
+}
@@ -0,0 +1,3556 @@
+// The composition of the datasets behind the to_influx measurements: loading, cooking and the field descriptors of each dataset, usable without the binary. PipelineBuilder loads all of them at once.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+
+use chrono::{Datelike, NaiveDate, Weekday};
+
+use smartstring::alias::String as SmartString;
+
+use log::{info, warn};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+	age_rekey, diff_partition_of, estimate_delay, global_start_date, AgeGroup, AgeScheme,
+	Aggregation, Bucket, Constant, CountMeter, CounterGroup, CounterValue, Counters, CsvRecords,
+	DelayEstimate, Diff, DiffRecord, DiffSchema, DiffStore, DistrictAdjacency, DistrictCentroids,
+	DistrictId, DistrictInfo, DistrictRemapping, Error, FGauge, FillStrategy, Filled, FullCaseKey,
+	GeoCaseKey, HospitalizationNowcastRecord, HospitalizationRecord, ICULoadRecord,
+	ICUSiteLoadRecord, ICUUnavailableReasonRecord, InfectionRecord, InfectionSchema, InputHash,
+	Interpolate, KeyConstant, KeyFactor, KeyInterner, KeyMap, KeyedClamp, LinearCombination, Mean,
+	MissingPolicy, MovingAverage, MovingSum, Nowcast, PhaseStats, ProgressSink, RValue, Ratio,
+	RawDestatisDeathByWeekRow, RawDestatisRow, Resample, RunLog, ScaleByKey, SelectByKey, Sex,
+	SnapshotCache, SparseMovingAverage, SparseTimeSeries, StateId, Storage, SubmitError, SubmitLog,
+	SubmitPolicy, TestingRecord, TimeMap, TimeSeriesKey, VaccinationKey, VaccinationLevel,
+	VaccinationRecord, ViewTimeSeries, Waning, WaningModel, WastewaterRecord, WeekdayAdjusted,
+	WeeklyCounterGroup, WindowEdge, NOWCAST_DAYS, NOWCAST_ESTIMATION_DAYS,
+};
+
+mod run;
+pub use run::{run, take_flag, take_switch};
+
+// the fields as the measurements take them; constructing through this type coerces the series to trait objects, which does not happen by itself once with_aggregation is chained
+type Field<T> = crate::FieldDescriptor<Arc<dyn ViewTimeSeries<T>>>;
+
+// the RKI uses state ID 0 for the sum over all states, which is dropped on load; the national aggregate takes its place
+pub static NATIONAL_ID: StateId = 0;
+pub static NATIONAL_NAME: &str = "Deutschland";
+// delay between case and death for the lagged case fatality rate, roughly the median reported by the RKI
+pub static CFR_LAG_DAYS: i64 = 14;
+// trailing window of the weekday multipliers of cases_pub_d1_adj, four weeks
+pub static WEEKDAY_WINDOW_DAYS: u32 = 28;
+// Days of diff data needed before the first day written, see PipelineBuilder::since: the weekday adjustment window, plus a week for the weekly rollups which straddle that day.
+static DIFF_LEAD_DAYS: i64 = WEEKDAY_WINDOW_DAYS as i64 + 7;
+// days without a DIVI report of a district show the latest report up to this age instead of zero
+pub static ICU_FILL_MAX_AGE: u32 = 3;
+
+pub static INCIDENCE_SCALE: f64 = 100000.;
+
+// age bands of the official hospitalization incidence, with the name of the per-band field on state keys
+pub static HOSP_AGE_BANDS: [(AgeGroup, &str); 6] = [
+	(
+		AgeGroup {
+			low: 0,
+			high: Some(4),
+		},
+		"hosp_d7_per100k_a00_a04",
+	),
+	(
+		AgeGroup {
+			low: 5,
+			high: Some(14),
+		},
+		"hosp_d7_per100k_a05_a14",
+	),
+	(
+		AgeGroup {
+			low: 15,
+			high: Some(34),
+		},
+		"hosp_d7_per100k_a15_a34",
+	),
+	(
+		AgeGroup {
+			low: 35,
+			high: Some(59),
+		},
+		"hosp_d7_per100k_a35_a59",
+	),
+	(
+		AgeGroup {
+			low: 60,
+			high: Some(79),
+		},
+		"hosp_d7_per100k_a60_a79",
+	),
+	(
+		AgeGroup {
+			low: 80,
+			high: None,
+		},
+		"hosp_d7_per100k_a80",
+	),
+];
+
+// the hospitalization file also contains the sum over all ages as 00+
+pub fn is_all_ages(ag: &AgeGroup) -> bool {
+	ag.low == 0 && ag.high.is_none()
+}
+
+// The counts of a single case record fit into an i32 and the cumulative sums, even over all of germany, stay far below four billion, hence u32 to halve the memory of the full keyspace.
+#[derive(Serialize, Deserialize)]
+pub struct RawCaseData {
+	pub cases_by_ref: Counters<FullCaseKey, u32>,
+	pub cases_by_report: Counters<FullCaseKey, u32>,
+	pub deaths: Counters<FullCaseKey, u32>,
+	pub recovered: Counters<FullCaseKey, u32>,
+}
+
+impl RawCaseData {
+	pub fn new(start: NaiveDate, end: NaiveDate) -> Self {
+		Self {
+			cases_by_ref: Counters::new(start, end),
+			cases_by_report: Counters::new(start, end),
+			deaths: Counters::new(start, end),
+			recovered: Counters::new(start, end),
+		}
+	}
+
+	pub fn submit(
+		&mut self,
+		district_map: &HashMap<DistrictId, Arc<DistrictInfo>>,
+		remap: &DistrictRemapping,
+		rec: &InfectionRecord,
+	) -> Result<(), SubmitError> {
+		let case_count = if rec.case.valid() { rec.case_count } else { 0 };
+		let death_count = if rec.death.valid() {
+			rec.death_count
+		} else {
+			0
+		};
+		let recovered_count = if rec.recovered.valid() {
+			rec.recovered_count
+		} else {
+			0
+		};
+		for count in [case_count, death_count, recovered_count] {
+			if count < 0 {
+				return Err(SubmitError::NegativeCount(count as i64));
+			}
+		}
+
+		let district_id = remap.resolve(rec.district_id, rec.report_date);
+		let district_info = district_map
+			.get(&district_id)
+			.ok_or(SubmitError::UnknownDistrict(district_id))?;
+		let k = (district_info.state.id, district_id, rec.age_group, rec.sex);
+		let ref_index = self
+			.cases_by_ref
+			.date_index(rec.reference_date)
+			.ok_or(SubmitError::DateOutOfRange(rec.reference_date))?;
+		if case_count > 0 {
+			// checked before touching any counter so that a rejected row leaves no partial data behind
+			let report_index = self
+				.cases_by_report
+				.date_index(rec.report_date)
+				.ok_or(SubmitError::DateOutOfRange(rec.report_date))?;
+			self.cases_by_ref.get_or_create(k)[ref_index] += case_count as u32;
+			self.cases_by_report.get_or_create(k)[report_index] += case_count as u32;
+		}
+		if death_count > 0 {
+			self.deaths.get_or_create(k)[ref_index] += death_count as u32;
+		}
+		if recovered_count > 0 {
+			self.recovered.get_or_create(k)[ref_index] += recovered_count as u32;
+		}
+		Ok(())
+	}
+
+	pub fn remapped<F: Fn(&FullCaseKey) -> Option<FullCaseKey>>(&self, f: F) -> RawCaseData {
+		RawCaseData {
+			cases_by_ref: self.cases_by_ref.rekeyed(&f),
+			cases_by_report: self.cases_by_report.rekeyed(&f),
+			deaths: self.deaths.rekeyed(&f),
+			recovered: self.recovered.rekeyed(&f),
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ParboiledCaseData {
+	pub cases_by_pub: Counters<FullCaseKey>,
+	pub case_delay_total: Counters<FullCaseKey>,
+	pub cases_delayed: Counters<FullCaseKey>,
+	// newly reported cases beyond the delay cutoff of rki_diff, which are not in cases_delayed
+	pub late_cases: Counters<FullCaseKey>,
+	pub deaths_by_pub: Counters<FullCaseKey>,
+	pub recovered_by_pub: Counters<FullCaseKey>,
+	pub cases_by_pubrep_d7: Counters<FullCaseKey>,
+	pub cases_retracted: Counters<FullCaseKey>,
+	// earliest date with a diff record, per key
+	pub first_seen: HashMap<FullCaseKey, NaiveDate>,
+}
+
+impl ParboiledCaseData {
+	pub fn new(start: NaiveDate, end: NaiveDate) -> Self {
+		Self {
+			cases_by_pub: Counters::new(start, end),
+			case_delay_total: Counters::new(start, end),
+			cases_delayed: Counters::new(start, end),
+			late_cases: Counters::new(start, end),
+			deaths_by_pub: Counters::new(start, end),
+			recovered_by_pub: Counters::new(start, end),
+			cases_by_pubrep_d7: Counters::new(start, end),
+			cases_retracted: Counters::new(start, end),
+			first_seen: HashMap::new(),
+		}
+	}
+
+	pub fn submit(
+		&mut self,
+		district_map: &HashMap<DistrictId, Arc<DistrictInfo>>,
+		remap: &DistrictRemapping,
+		rec: &DiffRecord,
+	) -> Result<(), SubmitError> {
+		let district_id = remap.resolve(rec.district_id, rec.date);
+		let district_info = district_map
+			.get(&district_id)
+			.ok_or(SubmitError::UnknownDistrict(district_id))?;
+		let k = (district_info.state.id, district_id, rec.age_group, rec.sex);
+		let ref_index = self
+			.cases_by_pub
+			.date_index(rec.date)
+			.ok_or(SubmitError::DateOutOfRange(rec.date))?;
+		self.cases_by_pub.get_or_create(k)[ref_index] += rec.cases;
+		self.case_delay_total.get_or_create(k)[ref_index] += rec.delay_total;
+		self.cases_delayed.get_or_create(k)[ref_index] += rec.cases_delayed;
+		self.late_cases.get_or_create(k)[ref_index] += rec.late_cases;
+		self.deaths_by_pub.get_or_create(k)[ref_index] += rec.deaths;
+		self.cases_by_pubrep_d7.get_or_create(k)[ref_index] += rec.cases_rep_d7;
+		self.cases_retracted.get_or_create(k)[ref_index] += rec.cases_retracted;
+		let first_seen = self.first_seen.entry(k).or_insert(rec.date);
+		if rec.date < *first_seen {
+			*first_seen = rec.date;
+		}
+		Ok(())
+	}
+
+	pub fn remapped<F: Fn(&FullCaseKey) -> Option<FullCaseKey>>(&self, f: F) -> ParboiledCaseData {
+		ParboiledCaseData {
+			cases_by_pub: self.cases_by_pub.rekeyed(&f),
+			case_delay_total: self.case_delay_total.rekeyed(&f),
+			cases_delayed: self.cases_delayed.rekeyed(&f),
+			late_cases: self.late_cases.rekeyed(&f),
+			deaths_by_pub: self.deaths_by_pub.rekeyed(&f),
+			recovered_by_pub: self.recovered_by_pub.rekeyed(&f),
+			cases_by_pubrep_d7: self.cases_by_pubrep_d7.rekeyed(&f),
+			cases_retracted: self.cases_retracted.rekeyed(&f),
+			first_seen: rekeyed_first_seen(&self.first_seen, &f),
+		}
+	}
+}
+
+impl StoredCounters for RawCaseData {
+	type Key = FullCaseKey;
+	type Value = u32;
+
+	fn counters_mut(&mut self) -> Vec<&mut Counters<FullCaseKey, u32>> {
+		vec![
+			&mut self.cases_by_ref,
+			&mut self.cases_by_report,
+			&mut self.deaths,
+			&mut self.recovered,
+		]
+	}
+}
+
+impl StoredCounters for ParboiledCaseData {
+	type Key = FullCaseKey;
+	type Value = u64;
+
+	fn counters_mut(&mut self) -> Vec<&mut Counters<FullCaseKey>> {
+		vec![
+			&mut self.cases_by_pub,
+			&mut self.case_delay_total,
+			&mut self.cases_delayed,
+			&mut self.late_cases,
+			&mut self.deaths_by_pub,
+			&mut self.recovered_by_pub,
+			&mut self.cases_by_pubrep_d7,
+			&mut self.cases_retracted,
+		]
+	}
+}
+
+// Interns the keys of all series of a dataset and maps them with `f`, for rekeying all series with the same mapping (see TimeSeries::rekeyed_via).
+fn interned_key_map<
+	'x,
+	T: TimeSeriesKey,
+	U: TimeSeriesKey,
+	I: IntoIterator<Item = &'x T>,
+	F: Fn(&T) -> Option<U>,
+>(
+	keysets: impl IntoIterator<Item = I>,
+	f: F,
+) -> (KeyInterner<T>, KeyMap<U>) {
+	let mut source = KeyInterner::new();
+	for keys in keysets {
+		for k in keys {
+			source.intern(k);
+		}
+	}
+	let map = source.map(f);
+	(source, map)
+}
+
+fn rekeyed_first_seen<T: TimeSeriesKey, U: TimeSeriesKey, F: Fn(&T) -> Option<U>>(
+	first_seen: &HashMap<T, NaiveDate>,
+	f: F,
+) -> HashMap<U, NaiveDate> {
+	let mut result: HashMap<U, NaiveDate> = HashMap::new();
+	for (k, date) in first_seen.iter() {
+		let k = match f(k) {
+			Some(k) => k,
+			None => continue,
+		};
+		let v = result.entry(k).or_insert(*date);
+		if *date < *v {
+			*v = *date;
+		}
+	}
+	result
+}
+
+// Number of days at the end of the data for which values are suppressed, because the data is still incomplete due to reporting delays. Set to zero to get all data, e.g. when analysing completed waves.
+#[derive(Debug, Clone, Copy)]
+pub struct ClampWindows {
+	// deaths and recoveries by reference date
+	deaths: u32,
+	hosp: u32,
+	// also suppress the diff-based fields of each series before its first diff record, instead of only before diffstart; avoids a zero plateau for series whose diff data starts later when re-importing a window
+	diff_key_gaps: bool,
+}
+
+impl Default for ClampWindows {
+	fn default() -> Self {
+		Self {
+			deaths: 28,
+			hosp: 21,
+			diff_key_gaps: false,
+		}
+	}
+}
+
+impl ClampWindows {
+	pub fn from_env() -> Result<Self, std::num::ParseIntError> {
+		let mut result = Self::default();
+		if let Ok(v) = std::env::var("COVID_CLAMP_DEATHS_DAYS") {
+			result.deaths = v.parse()?;
+		}
+		if let Ok(v) = std::env::var("COVID_CLAMP_HOSP_DAYS") {
+			result.hosp = v.parse()?;
+		}
+		if let Ok(v) = std::env::var("COVID_DIFF_KEY_GAPS") {
+			result.diff_key_gaps = v.parse::<u32>()? != 0;
+		}
+		Ok(result)
+	}
+}
+
+pub struct CookedCaseData<T: TimeSeriesKey> {
+	pub cases_by_pub: CounterGroup<T>,
+	pub case_delay_total: Arc<Counters<T>>,
+	pub cases_delayed: Arc<Counters<T>>,
+	pub late_cases: Arc<Counters<T>>,
+	pub cases_by_ref: CounterGroup<T, u32>,
+	pub cases_by_report: CounterGroup<T, u32>,
+	pub deaths: CounterGroup<T, u32>,
+	pub deaths_by_pub: CounterGroup<T>,
+	pub recovered: CounterGroup<T, u32>,
+	pub recovered_by_pub: CounterGroup<T>,
+	pub cases_by_pubrep_d7: Arc<Counters<T>>,
+	pub cases_retracted: Arc<Counters<T>>,
+	diffstart: NaiveDate,
+	// per-key start of the diff data, if the diff fields are to be clamped per key
+	diff_starts: Option<Arc<HashMap<T, NaiveDate>>>,
+	clamp_days: u32,
+	// reporting delay at the end of the data, over all keys; None without delayed cases in the diff data
+	pub delay: Option<DelayEstimate>,
+}
+
+impl CookedCaseData<FullCaseKey> {
+	pub fn cook(
+		raw: RawCaseData,
+		parboiled: ParboiledCaseData,
+		diffstart: NaiveDate,
+		clamp: &ClampWindows,
+	) -> Self {
+		let diff_starts = if clamp.diff_key_gaps {
+			Some(Arc::new(parboiled.first_seen))
+		} else {
+			None
+		};
+		let delay = estimate_delay(
+			&parboiled.case_delay_total,
+			&parboiled.cases_delayed,
+			parboiled.cases_delayed.end(),
+			NOWCAST_ESTIMATION_DAYS,
+		);
+		Self {
+			cases_by_pub: CounterGroup::from_d1(parboiled.cases_by_pub),
+			case_delay_total: Arc::new(parboiled.case_delay_total),
+			cases_delayed: Arc::new(parboiled.cases_delayed),
+			late_cases: Arc::new(parboiled.late_cases),
+			cases_by_ref: CounterGroup::from_d1(raw.cases_by_ref),
+			cases_by_report: CounterGroup::from_d1(raw.cases_by_report),
+			deaths: CounterGroup::from_d1(raw.deaths),
+			deaths_by_pub: CounterGroup::from_d1(parboiled.deaths_by_pub),
+			recovered: CounterGroup::from_d1(raw.recovered),
+			recovered_by_pub: CounterGroup::from_d1(parboiled.recovered_by_pub),
+			cases_by_pubrep_d7: Arc::new(parboiled.cases_by_pubrep_d7),
+			cases_retracted: Arc::new(parboiled.cases_retracted),
+			diffstart,
+			diff_starts,
+			clamp_days: clamp.deaths,
+			delay,
+		}
+	}
+}
+
+impl<T: TimeSeriesKey> CookedCaseData<T> {
+	pub fn rekeyed<U: TimeSeriesKey, F: Fn(&T) -> Option<U>>(&self, f: F) -> CookedCaseData<U> {
+		let keysets = [
+			self.cases_by_pub.cum.keys(),
+			self.case_delay_total.keys(),
+			self.cases_delayed.keys(),
+			self.late_cases.keys(),
+			self.cases_by_ref.cum.keys(),
+			self.cases_by_report.cum.keys(),
+			self.deaths.cum.keys(),
+			self.deaths_by_pub.cum.keys(),
+			self.recovered.cum.keys(),
+			self.recovered_by_pub.cum.keys(),
+			self.cases_by_pubrep_d7.keys(),
+			self.cases_retracted.keys(),
+		];
+		let (source, map) = interned_key_map(keysets, &f);
+		CookedCaseData::<U> {
+			cases_by_pub: self.cases_by_pub.rekeyed_via(&source, &map),
+			case_delay_total: Arc::new(self.case_delay_total.rekeyed_via(&source, &map)),
+			cases_delayed: Arc::new(self.cases_delayed.rekeyed_via(&source, &map)),
+			late_cases: Arc::new(self.late_cases.rekeyed_via(&source, &map)),
+			cases_by_ref: self.cases_by_ref.rekeyed_via(&source, &map),
+			cases_by_report: self.cases_by_report.rekeyed_via(&source, &map),
+			deaths: self.deaths.rekeyed_via(&source, &map),
+			deaths_by_pub: self.deaths_by_pub.rekeyed_via(&source, &map),
+			recovered: self.recovered.rekeyed_via(&source, &map),
+			recovered_by_pub: self.recovered_by_pub.rekeyed_via(&source, &map),
+			cases_by_pubrep_d7: Arc::new(self.cases_by_pubrep_d7.rekeyed_via(&source, &map)),
+			cases_retracted: Arc::new(self.cases_retracted.rekeyed_via(&source, &map)),
+			diffstart: self.diffstart,
+			diff_starts: self
+				.diff_starts
+				.as_ref()
+				.map(|starts| Arc::new(rekeyed_first_seen(starts, &f))),
+			clamp_days: self.clamp_days,
+			delay: self.delay,
+		}
+	}
+}
+
+impl<T: TimeSeriesKey + 'static> CookedCaseData<T> {
+	pub fn clamp_result<I>(&self, t: I) -> Arc<TimeMap<I>> {
+		let end = self.cases_by_ref.cum.end() - chrono::Duration::days(self.clamp_days as i64);
+		Arc::new(TimeMap::clamp(t, None, Some(end)))
+	}
+
+	pub fn clamp_diff<I>(&self, t: I, offset: i64) -> Arc<KeyedClamp<T, TimeMap<I>>> {
+		Arc::new(KeyedClamp::new(
+			TimeMap::clamp(
+				t,
+				Some(self.diffstart + chrono::Duration::days(offset)),
+				None,
+			),
+			self.diff_starts.clone(),
+			offset,
+		))
+	}
+
+	pub fn write_field_descriptors(
+		&self,
+		out: &mut Vec<crate::FieldDescriptor<Arc<dyn crate::ViewTimeSeries<T>>>>,
+	) {
+		out.push(
+			Field::integer(
+				self.clamp_diff(self.cases_by_pub.d1.clone(), 0),
+				"cases_pub_d1",
+			)
+			.with_aggregation(Aggregation::Sum),
+		);
+		out.push(
+			Field::integer(
+				self.clamp_diff(self.cases_by_pub.d7.clone(), 6),
+				"cases_pub_d7",
+			)
+			.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::integer(
+				self.clamp_diff(self.cases_by_pub.d7s7.clone(), 13),
+				"cases_pub_d7s7",
+			)
+			.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::new(
+				Arc::new(MovingAverage::new(
+					self.clamp_diff(self.cases_by_pub.d1.clone(), 0),
+					7,
+					WindowEdge::Partial,
+				)),
+				"cases_pub_ma7",
+			)
+			.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::new(
+				Arc::new(WeekdayAdjusted::new(
+					self.clamp_diff(self.cases_by_pub.d1.clone(), 0),
+					WEEKDAY_WINDOW_DAYS,
+				)),
+				"cases_pub_d1_adj",
+			)
+			.with_aggregation(Aggregation::Sum),
+		);
+		out.push(
+			Field::integer(self.cases_by_ref.cum.clone(), "cases_ref_cum")
+				.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::integer(self.cases_by_ref.d1.clone(), "cases_ref_d1")
+				.with_aggregation(Aggregation::Sum),
+		);
+		out.push(
+			Field::integer(self.cases_by_ref.d7.clone(), "cases_ref_d7")
+				.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::integer(self.cases_by_ref.d7s7.clone(), "cases_ref_d7s7")
+				.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::new(
+				Arc::new(MovingAverage::new(
+					self.cases_by_ref.d1.clone(),
+					7,
+					WindowEdge::Partial,
+				)),
+				"cases_ref_ma7",
+			)
+			.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::integer(
+				Arc::new(Diff::padded(self.cases_by_ref.cum.clone(), 28, 0.)),
+				"cases_ref_d28",
+			)
+			.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::integer(
+				Arc::new(Diff::padded(self.cases_by_ref.cum.clone(), 112, 0.)),
+				"cases_ref_d112",
+			)
+			.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::integer(self.cases_by_report.cum.clone(), "cases_rep_cum")
+				.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::integer(self.cases_by_report.d1.clone(), "cases_rep_d1")
+				.with_aggregation(Aggregation::Sum),
+		);
+		out.push(
+			Field::integer(self.cases_by_report.d7.clone(), "cases_rep_d7")
+				.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::integer(self.cases_by_report.d7s7.clone(), "cases_rep_d7s7")
+				.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::new(
+				Arc::new(MovingAverage::new(
+					self.cases_by_report.d1.clone(),
+					7,
+					WindowEdge::Partial,
+				)),
+				"cases_rep_ma7",
+			)
+			.with_aggregation(Aggregation::Last),
+		);
+		if let Some(delay) = self.delay {
+			let asof = self.cases_by_report.cum.end();
+			for (delay, d1_name, d7_name) in [
+				(
+					delay.expected,
+					"cases_rep_d1_nowcast",
+					"cases_rep_d7_nowcast",
+				),
+				(
+					delay.low,
+					"cases_rep_d1_nowcast_lo",
+					"cases_rep_d7_nowcast_lo",
+				),
+				(
+					delay.high,
+					"cases_rep_d1_nowcast_hi",
+					"cases_rep_d7_nowcast_hi",
+				),
+			] {
+				let d1: Arc<dyn crate::ViewTimeSeries<T>> = Arc::new(Nowcast::new(
+					self.cases_by_report.d1.clone(),
+					delay,
+					asof,
+					NOWCAST_DAYS,
+				));
+				out.push(Field::new(d1.clone(), d1_name).with_aggregation(Aggregation::Sum));
+				out.push(
+					Field::new(Arc::new(MovingSum::new(d1, 7)), d7_name)
+						.with_aggregation(Aggregation::Last),
+				);
+			}
+		}
+
+		out.push(
+			Field::integer(
+				self.clamp_diff(self.cases_by_pubrep_d7.clone(), 7),
+				"cases_pubrep_d7",
+			)
+			.with_aggregation(Aggregation::Last),
+		);
+
+		out.push(
+			Field::integer(self.deaths.cum.clone(), "deaths_ref_cum")
+				.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::integer(self.deaths.d1.clone(), "deaths_ref_d1")
+				.with_aggregation(Aggregation::Sum),
+		);
+		out.push(
+			Field::integer(self.clamp_result(self.deaths.d7.clone()), "deaths_ref_d7")
+				.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::integer(
+				self.clamp_result(self.deaths.d7s7.clone()),
+				"deaths_ref_d7s7",
+			)
+			.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::new(
+				self.clamp_result(Arc::new(MovingAverage::new(
+					self.deaths.d1.clone(),
+					7,
+					WindowEdge::Partial,
+				))),
+				"deaths_ref_ma7",
+			)
+			.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::integer(
+				self.clamp_result(Arc::new(Diff::padded(self.deaths.cum.clone(), 28, 0.))),
+				"deaths_ref_d28",
+			)
+			.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::integer(
+				self.clamp_result(Arc::new(Diff::padded(self.deaths.cum.clone(), 112, 0.))),
+				"deaths_ref_d112",
+			)
+			.with_aggregation(Aggregation::Last),
+		);
+		out.push(Field::new(
+			Arc::new(Constant(self.clamp_days as f64)),
+			"deaths_ref_clamp_days",
+		));
+		out.push(
+			Field::integer(
+				self.clamp_diff(self.deaths_by_pub.d1.clone(), 0),
+				"deaths_pub_d1",
+			)
+			.with_aggregation(Aggregation::Sum),
+		);
+		out.push(
+			Field::integer(
+				self.clamp_diff(self.deaths_by_pub.d7.clone(), 6),
+				"deaths_pub_d7",
+			)
+			.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::integer(
+				self.clamp_diff(self.deaths_by_pub.d7s7.clone(), 13),
+				"deaths_pub_d7s7",
+			)
+			.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::new(
+				Arc::new(MovingAverage::new(
+					self.clamp_diff(self.deaths_by_pub.d1.clone(), 0),
+					7,
+					WindowEdge::Partial,
+				)),
+				"deaths_pub_ma7",
+			)
+			.with_aggregation(Aggregation::Last),
+		);
+
+		out.push(
+			Field::integer(self.recovered.cum.clone(), "recovered_ref_cum")
+				.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::integer(self.recovered.d1.clone(), "recovered_ref_d1")
+				.with_aggregation(Aggregation::Sum),
+		);
+		out.push(
+			Field::integer(
+				self.clamp_result(self.recovered.d7.clone()),
+				"recovered_ref_d7",
+			)
+			.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::integer(
+				self.clamp_result(self.recovered.d7s7.clone()),
+				"recovered_ref_d7s7",
+			)
+			.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::integer(
+				self.clamp_diff(self.recovered_by_pub.d1.clone(), 0),
+				"recovered_pub_d1",
+			)
+			.with_aggregation(Aggregation::Sum),
+		);
+		out.push(
+			Field::integer(
+				self.clamp_diff(self.recovered_by_pub.d7.clone(), 6),
+				"recovered_pub_d7",
+			)
+			.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::integer(
+				self.clamp_diff(self.recovered_by_pub.d7s7.clone(), 13),
+				"recovered_pub_d7s7",
+			)
+			.with_aggregation(Aggregation::Last),
+		);
+		out.push(Field::new(
+			Arc::new(LinearCombination::new(
+				vec![
+					(1., self.cases_by_ref.cum.clone()),
+					(-1., self.recovered.cum.clone()),
+					(-1., self.deaths.cum.clone()),
+				],
+				MissingPolicy::Strict,
+			)),
+			"cases_ref_active",
+		));
+		// case fatality rate; the lagged variant relates deaths to the cases from when those people were infected
+		out.push(Field::new(
+			self.clamp_result(Arc::new(Ratio::new(
+				self.deaths.cum.clone(),
+				self.cases_by_ref.cum.clone(),
+			))),
+			"cfr_ref",
+		));
+		out.push(Field::new(
+			self.clamp_result(Arc::new(Ratio::new(
+				self.deaths.cum.clone(),
+				TimeMap::shift(self.cases_by_ref.cum.clone(), -CFR_LAG_DAYS),
+			))),
+			"cfr_ref_lag14",
+		));
+
+		out.push(
+			Field::integer(
+				self.clamp_diff(self.cases_delayed.clone(), 0),
+				"meta_delay_cases",
+			)
+			.with_aggregation(Aggregation::Sum),
+		);
+		out.push(
+			Field::integer(
+				self.clamp_diff(self.case_delay_total.clone(), 0),
+				"meta_delay_total",
+			)
+			.with_aggregation(Aggregation::Sum),
+		);
+		out.push(
+			Field::integer(
+				self.clamp_diff(self.cases_retracted.clone(), 0),
+				"cases_retracted",
+			)
+			.with_aggregation(Aggregation::Sum),
+		);
+	}
+
+	// Reporting quality of the diff data over the last seven days of publication: the mean delay between report and publication of the cases within the delay cutoff, the share of newly published cases beyond the cutoff, and the retractions relative to the newly published cases.
+	pub fn write_data_quality_field_descriptors(
+		&self,
+		out: &mut Vec<crate::FieldDescriptor<Arc<dyn crate::ViewTimeSeries<T>>>>,
+	) {
+		let d7 = |counters: &Arc<Counters<T>>| -> Arc<dyn crate::ViewTimeSeries<T>> {
+			Arc::new(MovingSum::new(self.clamp_diff(counters.clone(), 0), 7))
+		};
+		let delayed = d7(&self.cases_delayed);
+		let late = d7(&self.late_cases);
+		let retracted = d7(&self.cases_retracted);
+		// cases_pub also nets out the retractions, so newly published cases are counted from their split instead
+		let published: Arc<dyn crate::ViewTimeSeries<T>> = Arc::new(LinearCombination::new(
+			vec![(1., delayed.clone()), (1., late.clone())],
+			MissingPolicy::Strict,
+		));
+		out.push(
+			Field::integer(self.clamp_diff(self.late_cases.clone(), 0), "cases_late_d1")
+				.with_aggregation(Aggregation::Sum),
+		);
+		out.push(Field::integer(late.clone(), "cases_late_d7").with_aggregation(Aggregation::Last));
+		out.push(
+			Field::integer(retracted.clone(), "cases_retracted_d7")
+				.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::new(
+				Arc::new(Ratio::new(d7(&self.case_delay_total), delayed)),
+				"delay_mean_d7",
+			)
+			.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::new(
+				Arc::new(Ratio::new(late, published.clone())),
+				"late_share_d7",
+			)
+			.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::new(
+				Arc::new(Ratio::new(retracted, published)),
+				"retraction_rate_d7",
+			)
+			.with_aggregation(Aggregation::Last),
+		);
+	}
+
+	pub fn write_r_value_field_descriptors(
+		&self,
+		out: &mut Vec<crate::FieldDescriptor<Arc<dyn crate::ViewTimeSeries<T>>>>,
+	) {
+		// based on the reporting date, because the reference date data is too incomplete for the most recent days without a nowcast
+		out.push(Field::new(
+			Arc::new(RValue::plain(self.cases_by_report.d1.clone())),
+			"r_value",
+		));
+		out.push(
+			Field::new(
+				Arc::new(RValue::smoothed(self.cases_by_report.d1.clone())),
+				"r_value_d7",
+			)
+			.with_aggregation(Aggregation::Last),
+		);
+	}
+
+	pub fn write_incidence_field_descriptors(
+		&self,
+		population: &CookedPopulationData<T>,
+		out: &mut Vec<crate::FieldDescriptor<Arc<dyn crate::ViewTimeSeries<T>>>>,
+	) {
+		out.push(
+			Field::new(
+				Arc::new(Ratio::scaled(
+					self.cases_by_report.d7.clone(),
+					population.view(),
+					INCIDENCE_SCALE,
+				)),
+				"cases_rep_d7_per100k",
+			)
+			.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::new(
+				Arc::new(Ratio::scaled(
+					self.clamp_result(self.deaths.d7.clone()),
+					population.view(),
+					INCIDENCE_SCALE,
+				)),
+				"deaths_ref_d7_per100k",
+			)
+			.with_aggregation(Aggregation::Last),
+		);
+	}
+}
+
+impl CookedCaseData<GeoCaseKey> {
+	// Incidence over each district together with its neighbours, which is less noisy than the incidence of small districts on their own.
+	pub fn write_region_incidence_field_descriptors(
+		&self,
+		population: &CookedPopulationData<GeoCaseKey>,
+		adjacency: &DistrictAdjacency,
+		out: &mut Vec<crate::FieldDescriptor<Arc<dyn crate::ViewTimeSeries<GeoCaseKey>>>>,
+	) {
+		let state_of: HashMap<DistrictId, StateId> = population
+			.count
+			.keys()
+			.map(|(state_id, district_id)| (*district_id, *state_id))
+			.collect();
+		// as the adjacency is symmetric, the sum for each district is formed by letting every district contribute to itself and all its neighbours
+		let region_of = |(_, district_id): &GeoCaseKey| -> Vec<GeoCaseKey> {
+			adjacency
+				.region(*district_id)
+				.filter_map(|id| Some((*state_of.get(&id)?, id)))
+				.collect()
+		};
+		let cases = self.cases_by_report.aggregated(region_of);
+		let population = population.aggregated(region_of);
+		out.push(
+			Field::new(
+				Arc::new(Ratio::scaled(
+					cases.d7.clone(),
+					population.view(),
+					INCIDENCE_SCALE,
+				)),
+				"cases_rep_d7_per100k_region",
+			)
+			.with_aggregation(Aggregation::Last),
+		);
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RawICULoadData {
+	pub curr_covid_cases: Counters<GeoCaseKey>,
+	pub curr_covid_cases_invasive: Counters<GeoCaseKey>,
+	pub curr_beds_free: Counters<GeoCaseKey>,
+	pub curr_beds_in_use: Counters<GeoCaseKey>,
+	pub curr_beds_free_adult: Counters<GeoCaseKey>,
+	pub curr_beds_in_use_adult: Counters<GeoCaseKey>,
+	pub num_sites: Counters<GeoCaseKey>,
+	pub num_regions: Counters<GeoCaseKey>,
+}
+
+impl RawICULoadData {
+	pub fn new(start: NaiveDate, end: NaiveDate) -> Self {
+		Self {
+			curr_covid_cases: Counters::new(start, end),
+			curr_covid_cases_invasive: Counters::new(start, end),
+			curr_beds_free: Counters::new(start, end),
+			curr_beds_in_use: Counters::new(start, end),
+			curr_beds_free_adult: Counters::new(start, end),
+			curr_beds_in_use_adult: Counters::new(start, end),
+			num_sites: Counters::new(start, end),
+			num_regions: Counters::new(start, end),
+		}
+	}
+
+	pub fn rekeyed<F: Fn(&GeoCaseKey) -> Option<GeoCaseKey>>(&self, f: F) -> RawICULoadData {
+		Self {
+			curr_covid_cases: self.curr_covid_cases.rekeyed(&f),
+			curr_covid_cases_invasive: self.curr_covid_cases_invasive.rekeyed(&f),
+			curr_beds_free: self.curr_beds_free.rekeyed(&f),
+			curr_beds_in_use: self.curr_beds_in_use.rekeyed(&f),
+			curr_beds_free_adult: self.curr_beds_free_adult.rekeyed(&f),
+			curr_beds_in_use_adult: self.curr_beds_in_use_adult.rekeyed(&f),
+			num_sites: self.num_sites.rekeyed(&f),
+			num_regions: self.num_regions.rekeyed(&f),
+		}
+	}
+}
+
+impl StoredCounters for RawICULoadData {
+	type Key = GeoCaseKey;
+	type Value = u64;
+
+	fn counters_mut(&mut self) -> Vec<&mut Counters<GeoCaseKey>> {
+		vec![
+			&mut self.curr_covid_cases,
+			&mut self.curr_covid_cases_invasive,
+			&mut self.curr_beds_free,
+			&mut self.curr_beds_in_use,
+			&mut self.curr_beds_free_adult,
+			&mut self.curr_beds_in_use_adult,
+			&mut self.num_sites,
+			&mut self.num_regions,
+		]
+	}
+}
+
+pub struct CookedICULoadData<T: TimeSeriesKey> {
+	pub curr_covid_cases: Arc<Counters<T>>,
+	pub curr_covid_cases_invasive: Arc<Counters<T>>,
+	pub curr_beds_free: Arc<Counters<T>>,
+	pub curr_beds_in_use: Arc<Counters<T>>,
+	pub curr_beds_free_adult: Arc<Counters<T>>,
+	pub curr_beds_in_use_adult: Arc<Counters<T>>,
+	pub num_sites: Arc<Counters<T>>,
+	pub num_regions: Arc<Counters<T>>,
+}
+
+impl CookedICULoadData<GeoCaseKey> {
+	pub fn cook(raw: RawICULoadData) -> Self {
+		Self {
+			curr_covid_cases: Arc::new(raw.curr_covid_cases),
+			curr_covid_cases_invasive: Arc::new(raw.curr_covid_cases_invasive),
+			curr_beds_free: Arc::new(raw.curr_beds_free),
+			curr_beds_in_use: Arc::new(raw.curr_beds_in_use),
+			curr_beds_free_adult: Arc::new(raw.curr_beds_free_adult),
+			curr_beds_in_use_adult: Arc::new(raw.curr_beds_in_use_adult),
+			num_sites: Arc::new(raw.num_sites),
+			num_regions: Arc::new(raw.num_regions),
+		}
+	}
+}
+
+impl<T: TimeSeriesKey> CookedICULoadData<T> {
+	pub fn rekeyed<U: TimeSeriesKey, F: Fn(&T) -> Option<U>>(&self, f: F) -> CookedICULoadData<U> {
+		CookedICULoadData::<U> {
+			curr_covid_cases: Arc::new(self.curr_covid_cases.rekeyed(&f)),
+			curr_covid_cases_invasive: Arc::new(self.curr_covid_cases_invasive.rekeyed(&f)),
+			curr_beds_free: Arc::new(self.curr_beds_free.rekeyed(&f)),
+			curr_beds_in_use: Arc::new(self.curr_beds_in_use.rekeyed(&f)),
+			curr_beds_free_adult: Arc::new(self.curr_beds_free_adult.rekeyed(&f)),
+			curr_beds_in_use_adult: Arc::new(self.curr_beds_in_use_adult.rekeyed(&f)),
+			num_sites: Arc::new(self.num_sites.rekeyed(&f)),
+			num_regions: Arc::new(self.num_regions.rekeyed(&f)),
+		}
+	}
+}
+
+impl<T: TimeSeriesKey + 'static> CookedICULoadData<T> {
+	pub fn clamp<I>(inner: I) -> Arc<TimeMap<I>> {
+		// no data available before 2020-04-24
+		Arc::new(TimeMap::clamp(
+			inner,
+			NaiveDate::from_ymd_opt(2020, 4, 24),
+			None,
+		))
+	}
+
+	// A gauge, forward-filled over the days on which no site of the key reported.
+	pub fn filled<I>(&self, inner: I) -> Arc<TimeMap<Interpolate<I, Arc<Counters<T>>>>> {
+		Self::clamp(Interpolate::masked(
+			inner,
+			self.num_sites.clone(),
+			FillStrategy::Forward {
+				max_age: ICU_FILL_MAX_AGE,
+			},
+		))
+	}
+
+	pub fn write_field_descriptors(
+		&self,
+		out: &mut Vec<crate::FieldDescriptor<Arc<dyn crate::ViewTimeSeries<T>>>>,
+	) {
+		out.push(Field::new(
+			self.filled(self.curr_covid_cases.clone()),
+			"icu_covid_cases",
+		));
+		out.push(Field::new(
+			self.filled(self.curr_covid_cases_invasive.clone()),
+			"icu_covid_cases_invasive",
+		));
+		out.push(Field::new(
+			self.filled(self.curr_beds_free.clone()),
+			"icu_beds_free",
+		));
+		out.push(Field::new(
+			self.filled(self.curr_beds_in_use.clone()),
+			"icu_beds_in_use",
+		));
+		out.push(Field::integer(
+			Self::clamp(self.num_sites.clone()),
+			"icu_num_sites",
+		));
+		out.push(Field::integer(
+			Self::clamp(self.num_regions.clone()),
+			"icu_num_regions",
+		));
+
+		let covid_cases: Arc<dyn crate::ViewTimeSeries<T>> =
+			self.filled(self.curr_covid_cases.clone());
+		let beds_free: Arc<dyn crate::ViewTimeSeries<T>> = self.filled(self.curr_beds_free.clone());
+		let beds_in_use: Arc<dyn crate::ViewTimeSeries<T>> =
+			self.filled(self.curr_beds_in_use.clone());
+		let beds_total = LinearCombination::new(
+			vec![(1., beds_in_use.clone()), (1., beds_free.clone())],
+			MissingPolicy::Strict,
+		);
+		let utilization: Arc<dyn crate::ViewTimeSeries<T>> =
+			Arc::new(Ratio::new(beds_in_use.clone(), beds_total));
+		out.push(Field::new(utilization.clone(), "icu_utilization"));
+		out.push(Field::new(
+			Arc::new(Ratio::new(covid_cases.clone(), beds_in_use.clone())),
+			"icu_covid_share",
+		));
+		// these are occupancy numbers, not counters: the change over the last 7 days, which may be negative
+		out.push(
+			Field::new(Arc::new(Diff::new(covid_cases, 7)), "icu_covid_cases_d7")
+				.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::new(
+				Arc::new(Diff::new(beds_free.clone(), 7)),
+				"icu_beds_free_d7",
+			)
+			.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::new(
+				Arc::new(Diff::new(beds_in_use.clone(), 7)),
+				"icu_beds_in_use_d7",
+			)
+			.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::new(Arc::new(Diff::new(utilization, 7)), "icu_utilization_d7")
+				.with_aggregation(Aggregation::Last),
+		);
+
+		let beds_free_adult: Arc<dyn crate::ViewTimeSeries<T>> =
+			self.filled(self.curr_beds_free_adult.clone());
+		let beds_in_use_adult: Arc<dyn crate::ViewTimeSeries<T>> =
+			self.filled(self.curr_beds_in_use_adult.clone());
+		out.push(Field::new(beds_free_adult.clone(), "icu_beds_free_adult"));
+		out.push(Field::new(
+			beds_in_use_adult.clone(),
+			"icu_beds_in_use_adult",
+		));
+		// DIVI does not report paediatric beds as such; everything which is not reserved for adults is counted, which includes beds open to both
+		out.push(Field::new(
+			Arc::new(LinearCombination::new(
+				vec![(1., beds_free), (-1., beds_free_adult)],
+				MissingPolicy::Strict,
+			)),
+			"icu_beds_free_paediatric_est",
+		));
+		out.push(Field::new(
+			Arc::new(LinearCombination::new(
+				vec![(1., beds_in_use), (-1., beds_in_use_adult)],
+				MissingPolicy::Strict,
+			)),
+			"icu_beds_in_use_paediatric_est",
+		));
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RawVaccinationData {
+	pub first_vacc: Counters<VaccinationKey>,
+	pub basic_vacc: Counters<VaccinationKey>,
+	pub full_vacc: Counters<VaccinationKey>,
+	pub fourth_vacc: Counters<VaccinationKey>,
+	pub fifth_vacc: Counters<VaccinationKey>,
+	pub sixth_vacc: Counters<VaccinationKey>,
+}
+
+impl RawVaccinationData {
+	pub fn new(start: NaiveDate, end: NaiveDate) -> Self {
+		Self {
+			first_vacc: Counters::new(start, end),
+			basic_vacc: Counters::new(start, end),
+			full_vacc: Counters::new(start, end),
+			fourth_vacc: Counters::new(start, end),
+			fifth_vacc: Counters::new(start, end),
+			sixth_vacc: Counters::new(start, end),
+		}
+	}
+
+	pub fn submit(
+		&mut self,
+		district_map: &HashMap<DistrictId, Arc<DistrictInfo>>,
+		remap: &DistrictRemapping,
+		rec: &VaccinationRecord,
+	) -> Result<(), SubmitError> {
+		let mapped_district_id = match rec.district_id.0 {
+			// Bundesfoo, unmap
+			Some(17000) => None,
+			v => v.map(|district_id| remap.resolve(district_id, rec.date)),
+		};
+		let state_id = match mapped_district_id {
+			Some(district_id) => {
+				let district_info = district_map
+					.get(&district_id)
+					.ok_or(SubmitError::UnknownDistrict(district_id))?;
+				Some(district_info.state.id)
+			}
+			None => None,
+		};
+		let k = (state_id, mapped_district_id, rec.age_group);
+		let ts = match rec.level {
+			VaccinationLevel::First => &mut self.first_vacc,
+			VaccinationLevel::Basic => &mut self.basic_vacc,
+			VaccinationLevel::Full => &mut self.full_vacc,
+			VaccinationLevel::Fourth => &mut self.fourth_vacc,
+			VaccinationLevel::Fifth => &mut self.fifth_vacc,
+			VaccinationLevel::Sixth => &mut self.sixth_vacc,
+			// we ignore those for now; according to the documentation, those
+			// are vaccinations between the first and the basic level, but
+			// without fulfilling the need for basic; probably because of a
+			// long delay between first and second shot.
+			VaccinationLevel::Partial => return Ok(()),
+		};
+		let index = ts
+			.date_index(rec.date)
+			.ok_or(SubmitError::DateOutOfRange(rec.date))?;
+		ts.get_or_create(k)[index] += rec.count;
+		Ok(())
+	}
+
+	pub fn remapped<F: Fn(&VaccinationKey) -> Option<VaccinationKey>>(
+		&self,
+		f: F,
+	) -> RawVaccinationData {
+		RawVaccinationData {
+			first_vacc: self.first_vacc.rekeyed(&f),
+			basic_vacc: self.basic_vacc.rekeyed(&f),
+			full_vacc: self.full_vacc.rekeyed(&f),
+			fourth_vacc: self.fourth_vacc.rekeyed(&f),
+			fifth_vacc: self.fifth_vacc.rekeyed(&f),
+			sixth_vacc: self.sixth_vacc.rekeyed(&f),
+		}
+	}
+}
+
+impl StoredCounters for RawVaccinationData {
+	type Key = VaccinationKey;
+	type Value = u64;
+
+	fn counters_mut(&mut self) -> Vec<&mut Counters<VaccinationKey>> {
+		vec![
+			&mut self.first_vacc,
+			&mut self.basic_vacc,
+			&mut self.full_vacc,
+			&mut self.fourth_vacc,
+			&mut self.fifth_vacc,
+			&mut self.sixth_vacc,
+		]
+	}
+}
+
+pub struct CookedVaccinationData<T: TimeSeriesKey> {
+	pub first_vacc: CounterGroup<T>,
+	pub basic_vacc: CounterGroup<T>,
+	pub basic_vacc_d180: Arc<Diff<Arc<Counters<T>>>>,
+	pub full_vacc: CounterGroup<T>,
+	pub fourth_vacc: CounterGroup<T>,
+	pub fifth_vacc: CounterGroup<T>,
+	pub sixth_vacc: CounterGroup<T>,
+}
+
+impl CookedVaccinationData<VaccinationKey> {
+	pub fn cook(raw: RawVaccinationData) -> Self {
+		let basic_vacc = CounterGroup::from_d1(raw.basic_vacc);
+		let basic_vacc_d180 = Arc::new(Diff::padded(basic_vacc.cum.clone(), 180, 0.));
+		Self {
+			first_vacc: CounterGroup::from_d1(raw.first_vacc),
+			basic_vacc,
+			basic_vacc_d180,
+			full_vacc: CounterGroup::from_d1(raw.full_vacc),
+			fourth_vacc: CounterGroup::from_d1(raw.fourth_vacc),
+			fifth_vacc: CounterGroup::from_d1(raw.fifth_vacc),
+			sixth_vacc: CounterGroup::from_d1(raw.sixth_vacc),
+		}
+	}
+}
+
+impl<T: TimeSeriesKey> CookedVaccinationData<T> {
+	pub fn rekeyed<U: TimeSeriesKey, F: Fn(&T) -> Option<U>>(
+		&self,
+		f: F,
+	) -> CookedVaccinationData<U> {
+		let groups = [
+			&self.first_vacc,
+			&self.basic_vacc,
+			&self.full_vacc,
+			&self.fourth_vacc,
+			&self.fifth_vacc,
+			&self.sixth_vacc,
+		];
+		let (source, map) = interned_key_map(groups.iter().map(|g| g.cum.keys()), &f);
+		let basic_vacc = self.basic_vacc.rekeyed_via(&source, &map);
+		let basic_vacc_d180 = Arc::new(Diff::padded(basic_vacc.cum.clone(), 180, 0.));
+		CookedVaccinationData::<U> {
+			first_vacc: self.first_vacc.rekeyed_via(&source, &map),
+			basic_vacc,
+			basic_vacc_d180,
+			full_vacc: self.full_vacc.rekeyed_via(&source, &map),
+			fourth_vacc: self.fourth_vacc.rekeyed_via(&source, &map),
+			fifth_vacc: self.fifth_vacc.rekeyed_via(&source, &map),
+			sixth_vacc: self.sixth_vacc.rekeyed_via(&source, &map),
+		}
+	}
+}
+
+impl<T: TimeSeriesKey + 'static> CookedVaccinationData<T> {
+	pub fn write_field_descriptors(
+		&self,
+		out: &mut Vec<crate::FieldDescriptor<Arc<dyn crate::ViewTimeSeries<T>>>>,
+	) {
+		out.push(
+			Field::integer(self.first_vacc.cum.clone(), "vacc_first_cum")
+				.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::integer(self.first_vacc.d1.clone(), "vacc_first_d1")
+				.with_aggregation(Aggregation::Sum),
+		);
+		out.push(
+			Field::integer(self.first_vacc.d7.clone(), "vacc_first_d7")
+				.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::integer(self.first_vacc.d7s7.clone(), "vacc_first_d7s7")
+				.with_aggregation(Aggregation::Last),
+		);
+
+		out.push(
+			Field::integer(self.basic_vacc.cum.clone(), "vacc_basic_cum")
+				.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::integer(self.basic_vacc.d1.clone(), "vacc_basic_d1")
+				.with_aggregation(Aggregation::Sum),
+		);
+		out.push(
+			Field::integer(self.basic_vacc.d7.clone(), "vacc_basic_d7")
+				.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::integer(self.basic_vacc.d7s7.clone(), "vacc_basic_d7s7")
+				.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::integer(
+				self.basic_vacc_d180.clone() as Arc<dyn ViewTimeSeries<T>>,
+				"vacc_basic_d180",
+			)
+			.with_aggregation(Aggregation::Last),
+		);
+
+		out.push(
+			Field::integer(self.full_vacc.cum.clone(), "vacc_full_cum")
+				.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::integer(self.full_vacc.d1.clone(), "vacc_full_d1")
+				.with_aggregation(Aggregation::Sum),
+		);
+		out.push(
+			Field::integer(self.full_vacc.d7.clone(), "vacc_full_d7")
+				.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::integer(self.full_vacc.d7s7.clone(), "vacc_full_d7s7")
+				.with_aggregation(Aggregation::Last),
+		);
+
+		out.push(
+			Field::integer(self.fourth_vacc.cum.clone(), "vacc_fourth_cum")
+				.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::integer(self.fourth_vacc.d1.clone(), "vacc_fourth_d1")
+				.with_aggregation(Aggregation::Sum),
+		);
+		out.push(
+			Field::integer(self.fourth_vacc.d7.clone(), "vacc_fourth_d7")
+				.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::integer(self.fourth_vacc.d7s7.clone(), "vacc_fourth_d7s7")
+				.with_aggregation(Aggregation::Last),
+		);
+
+		out.push(
+			Field::integer(self.fifth_vacc.cum.clone(), "vacc_fifth_cum")
+				.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::integer(self.fifth_vacc.d1.clone(), "vacc_fifth_d1")
+				.with_aggregation(Aggregation::Sum),
+		);
+		out.push(
+			Field::integer(self.fifth_vacc.d7.clone(), "vacc_fifth_d7")
+				.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::integer(self.fifth_vacc.d7s7.clone(), "vacc_fifth_d7s7")
+				.with_aggregation(Aggregation::Last),
+		);
+
+		out.push(
+			Field::integer(self.sixth_vacc.cum.clone(), "vacc_sixth_cum")
+				.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::integer(self.sixth_vacc.d1.clone(), "vacc_sixth_d1")
+				.with_aggregation(Aggregation::Sum),
+		);
+		out.push(
+			Field::integer(self.sixth_vacc.d7.clone(), "vacc_sixth_d7")
+				.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::integer(self.sixth_vacc.d7s7.clone(), "vacc_sixth_d7s7")
+				.with_aggregation(Aggregation::Last),
+		);
+	}
+
+	// The people protected under `model`, with the basic immunization and the boosters as the successive levels (first doses alone do not count).
+	pub fn protected(&self, model: &WaningModel) -> Arc<Waning<Arc<Counters<T>>>> {
+		Arc::new(Waning::new(
+			vec![
+				self.basic_vacc.cum.clone(),
+				self.full_vacc.cum.clone(),
+				self.fourth_vacc.cum.clone(),
+				self.fifth_vacc.cum.clone(),
+				self.sixth_vacc.cum.clone(),
+			],
+			model,
+		))
+	}
+
+	pub fn write_waning_field_descriptors(
+		&self,
+		model: &WaningModel,
+		population: &CookedPopulationData<T>,
+		out: &mut Vec<crate::FieldDescriptor<Arc<dyn crate::ViewTimeSeries<T>>>>,
+	) {
+		let protected = self.protected(model);
+		out.push(Field::new(
+			protected.clone() as Arc<dyn ViewTimeSeries<T>>,
+			"vacc_protected_est",
+		));
+		out.push(Field::new(
+			Arc::new(Ratio::scaled(protected, population.view(), 100.)),
+			"vacc_protected_est_pct",
+		));
+	}
+}
+
+// Weighted (interval in days, number of doses) pairs for the doses of the later level given on the days `from..to`, matched first-in-first-out against the earlier level: the n-th dose of the later level is assumed to go to the n-th recipient of the earlier one. Both inputs are cumulative counts over the same days; doses which cannot be matched are ignored.
+fn fifo_intervals(earlier: &[u64], later: &[u64], from: usize, to: usize) -> Vec<(i64, u64)> {
+	let mut result = Vec::new();
+	// first day at which the earlier level exceeds the current rank
+	let mut t = 0;
+	for d in from..to {
+		let lo = if d > 0 { later[d - 1] } else { 0 };
+		let hi = later[d];
+		if hi <= lo {
+			continue;
+		}
+		while t < earlier.len() && earlier[t] <= lo {
+			t += 1;
+		}
+		let mut u = t;
+		while u < earlier.len() {
+			let prev = if u > 0 { earlier[u - 1] } else { 0 };
+			if prev >= hi {
+				break;
+			}
+			let overlap = earlier[u].min(hi) - prev.max(lo);
+			if overlap > 0 {
+				result.push((d as i64 - u as i64, overlap));
+			}
+			u += 1;
+		}
+	}
+	result
+}
+
+fn weighted_percentile(sorted: &[(i64, u64)], total: u64, p: f64) -> f64 {
+	let threshold = p * total as f64;
+	let mut acc = 0;
+	for (v, w) in sorted.iter() {
+		acc += w;
+		if acc as f64 >= threshold {
+			return *v as f64;
+		}
+	}
+	f64::NAN
+}
+
+// Distribution of the time between two vaccination levels, per ISO week: the percentiles of the interval to the earlier level over all doses of the later level administered in that week, stored on the Sunday of the week. This is an estimate, as the data has no per-person records (see fifo_intervals).
+pub struct CookedVaccinationIntervals<T: TimeSeriesKey> {
+	pub keys: Vec<T>,
+	pub first_basic: [Arc<SparseTimeSeries<T, f64>>; 3],
+	pub basic_full: [Arc<SparseTimeSeries<T, f64>>; 3],
+}
+
+impl<T: TimeSeriesKey> CookedVaccinationIntervals<T> {
+	const PERCENTILES: [f64; 3] = [0.1, 0.5, 0.9];
+
+	pub fn estimate(vacc: &CookedVaccinationData<T>) -> Self {
+		let keys: Vec<T> = vacc.basic_vacc.cum.keys().cloned().collect();
+		Self {
+			first_basic: Self::estimate_pair(&keys, &vacc.first_vacc.cum, &vacc.basic_vacc.cum),
+			basic_full: Self::estimate_pair(&keys, &vacc.basic_vacc.cum, &vacc.full_vacc.cum),
+			keys,
+		}
+	}
+
+	pub fn estimate_pair(
+		keys: &[T],
+		earlier: &Counters<T>,
+		later: &Counters<T>,
+	) -> [Arc<SparseTimeSeries<T, f64>>; 3] {
+		let mut result = [
+			SparseTimeSeries::new(),
+			SparseTimeSeries::new(),
+			SparseTimeSeries::new(),
+		];
+		assert_eq!(earlier.start(), later.start());
+		let start = later.start();
+		for k in keys.iter() {
+			let (earlier, later) = match (earlier.get(k), later.get(k)) {
+				(Some(a), Some(b)) => (a, b),
+				_ => continue,
+			};
+			let mut week_start = 0;
+			for (i, date) in start.iter_days().take(later.len()).enumerate() {
+				if date.weekday() != Weekday::Sun {
+					continue;
+				}
+				let mut intervals = fifo_intervals(&earlier, &later, week_start, i + 1);
+				week_start = i + 1;
+				intervals.sort_unstable();
+				let total: u64 = intervals.iter().map(|(_, w)| w).sum();
+				if total == 0 {
+					continue;
+				}
+				for (ts, p) in result.iter_mut().zip(Self::PERCENTILES.iter()) {
+					*ts.insert_default(k, &date, &0.) = weighted_percentile(&intervals, total, *p);
+				}
+			}
+		}
+		let [p10, p50, p90] = result;
+		[Arc::new(p10), Arc::new(p50), Arc::new(p90)]
+	}
+}
+
+impl<T: TimeSeriesKey + 'static> CookedVaccinationIntervals<T> {
+	pub fn write_field_descriptors(
+		&self,
+		out: &mut Vec<crate::FieldDescriptor<Arc<dyn crate::ViewTimeSeries<T>>>>,
+	) {
+		let [p10, p50, p90] = &self.first_basic;
+		out.push(Field::new(p10.clone(), "vacc_interval_first_basic_p10"));
+		out.push(Field::new(p50.clone(), "vacc_interval_first_basic_p50"));
+		out.push(Field::new(p90.clone(), "vacc_interval_first_basic_p90"));
+		let [p10, p50, p90] = &self.basic_full;
+		out.push(Field::new(p10.clone(), "vacc_interval_basic_full_p10"));
+		out.push(Field::new(p50.clone(), "vacc_interval_basic_full_p50"));
+		out.push(Field::new(p90.clone(), "vacc_interval_basic_full_p90"));
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RawHospitalizationData {
+	pub cases_d7: Counters<(StateId, AgeGroup)>,
+}
+
+impl RawHospitalizationData {
+	pub fn new(start: NaiveDate, end: NaiveDate) -> Self {
+		Self {
+			cases_d7: Counters::new(start, end),
+		}
+	}
+
+	pub fn submit(&mut self, rec: &HospitalizationRecord) {
+		// sum of everything, we don't want that
+		if rec.state_id == 0 {
+			return;
+		}
+		let index = match self.cases_d7.date_index(rec.date) {
+			Some(i) => i,
+			// hospitalization data may have today's data, which does not
+			// match the publication rhythm of the data -> skip
+			None => return,
+		};
+		let k = (rec.state_id, rec.age_group);
+		self.cases_d7.get_or_create(k)[index] += rec.cases_d7;
+	}
+}
+
+impl StoredCounters for RawHospitalizationData {
+	type Key = (StateId, AgeGroup);
+	type Value = u64;
+
+	fn counters_mut(&mut self) -> Vec<&mut Counters<(StateId, AgeGroup)>> {
+		vec![&mut self.cases_d7]
+	}
+}
+
+pub struct CookedHospitalizationData<T: TimeSeriesKey> {
+	pub cases: CounterGroup<T>,
+	clamp_days: u32,
+}
+
+impl CookedHospitalizationData<(StateId, AgeGroup)> {
+	pub fn cook(raw: RawHospitalizationData, clamp_days: u32) -> Self {
+		Self {
+			cases: CounterGroup::from_d7(raw.cases_d7),
+			clamp_days,
+		}
+	}
+
+	// The official hospitalization incidence per age band, as one field per band on the state key. Each band is put in relation to the population of that band only.
+	pub fn write_age_band_incidence_field_descriptors(
+		&self,
+		population: &CookedPopulationData<(StateId, AgeGroup)>,
+		out: &mut Vec<crate::FieldDescriptor<Arc<dyn crate::ViewTimeSeries<StateId>>>>,
+	) {
+		for (band, name) in HOSP_AGE_BANDS.iter() {
+			let hosp =
+				self.rekeyed(|(state_id, ag)| if ag == band { Some(*state_id) } else { None });
+			let population = population
+				.rekeyed(|(state_id, ag)| if ag == band { Some(*state_id) } else { None });
+			out.push(
+				Field::new(
+					Arc::new(Ratio::scaled(
+						hosp.clamped(hosp.cases.d7.clone()),
+						population.view(),
+						INCIDENCE_SCALE,
+					)),
+					name,
+				)
+				.with_aggregation(Aggregation::Last),
+			);
+		}
+	}
+}
+
+impl<T: TimeSeriesKey> CookedHospitalizationData<T> {
+	pub fn rekeyed<U: TimeSeriesKey, F: Fn(&T) -> Option<U>>(
+		&self,
+		f: F,
+	) -> CookedHospitalizationData<U> {
+		CookedHospitalizationData::<U> {
+			cases: self.cases.rekeyed(&f),
+			clamp_days: self.clamp_days,
+		}
+	}
+}
+
+impl<T: TimeSeriesKey + 'static> CookedHospitalizationData<T> {
+	pub fn clamped<I>(&self, t: I) -> Arc<TimeMap<I>> {
+		let end = self.cases.cum.end() - chrono::Duration::days(self.clamp_days as i64);
+		Arc::new(TimeMap::clamp(t, None, Some(end)))
+	}
+
+	pub fn write_field_descriptors(
+		&self,
+		out: &mut Vec<crate::FieldDescriptor<Arc<dyn crate::ViewTimeSeries<T>>>>,
+	) {
+		out.push(
+			Field::integer(self.clamped(self.cases.cum.clone()), "hosp_cum")
+				.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::integer(self.clamped(self.cases.d1.clone()), "hosp_d1")
+				.with_aggregation(Aggregation::Sum),
+		);
+		out.push(
+			Field::integer(self.clamped(self.cases.d7.clone()), "hosp_d7")
+				.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::integer(self.clamped(self.cases.d7s7.clone()), "hosp_d7s7")
+				.with_aggregation(Aggregation::Last),
+		);
+		out.push(Field::new(
+			Arc::new(Constant(self.clamp_days as f64)),
+			"hosp_clamp_days",
+		));
+	}
+
+	pub fn write_incidence_field_descriptors(
+		&self,
+		population: &CookedPopulationData<T>,
+		out: &mut Vec<crate::FieldDescriptor<Arc<dyn crate::ViewTimeSeries<T>>>>,
+	) {
+		out.push(
+			Field::new(
+				Arc::new(Ratio::scaled(
+					self.clamped(self.cases.d7.clone()),
+					population.view(),
+					INCIDENCE_SCALE,
+				)),
+				"hosp_d7_per100k",
+			)
+			.with_aggregation(Aggregation::Last),
+		);
+	}
+}
+
+// DIVI does not publish ICU occupancy by age. This is a crude proxy: the COVID ICU occupancy of each state is distributed over the age groups in proportion to their share of the 7 day hospitalizations. It ignores that the ICU rate and the length of stay differ between age groups, hence it is kept in a separate measurement whose name marks it as an estimate.
+pub struct CookedICUAgeEstimate {
+	pub keys: Vec<(StateId, AgeGroup)>,
+	pub icu_covid_cases_est: Arc<ClampedGauge<(StateId, AgeGroup)>>,
+	pub hosp_share: Arc<ClampedGauge<(StateId, AgeGroup)>>,
+}
+
+// a gauge restricted to the days its inputs are valid on
+pub type ClampedGauge<T> = TimeMap<Arc<FGauge<T>>>;
+
+impl CookedICUAgeEstimate {
+	pub fn estimate(
+		icu_load: &CookedICULoadData<StateId>,
+		hosp: &CookedHospitalizationData<(StateId, AgeGroup)>,
+	) -> Self {
+		let start = hosp.cases.cum.start();
+		let end = hosp.cases.cum.end();
+		let len = hosp.cases.cum.len();
+		let keys: Vec<(StateId, AgeGroup)> = hosp
+			.cases
+			.cum
+			.keys()
+			.filter(|(_, ag)| !is_all_ages(ag))
+			.copied()
+			.collect();
+
+		let mut totals: HashMap<StateId, Vec<f64>> = HashMap::new();
+		for k in keys.iter() {
+			let total = totals.entry(k.0).or_insert_with(|| vec![0.; len]);
+			for (i, date) in start.iter_days().take(len).enumerate() {
+				total[i] += hosp.cases.d7.getf(k, date).unwrap_or(0.);
+			}
+		}
+
+		let mut share = FGauge::new(start, end);
+		let mut est = FGauge::new(start, end);
+		for k in keys.iter() {
+			let total = &totals[&k.0];
+			let share_ts: Vec<f64> = start
+				.iter_days()
+				.take(len)
+				.enumerate()
+				.map(|(i, date)| {
+					if total[i] > 0. {
+						hosp.cases.d7.getf(k, date).unwrap_or(0.) / total[i]
+					} else {
+						f64::NAN
+					}
+				})
+				.collect();
+			let est_ts = est.get_or_create(*k);
+			for (i, date) in start.iter_days().take(len).enumerate() {
+				est_ts[i] = match icu_load.curr_covid_cases.getf(&k.0, date) {
+					Some(icu) => icu * share_ts[i],
+					None => f64::NAN,
+				};
+			}
+			share.get_or_create(*k).copy_from_slice(&share_ts);
+		}
+
+		// valid where both inputs are: DIVI data starts on 2020-04-24, the recent hospitalizations are incomplete
+		let clamp = |t: FGauge<(StateId, AgeGroup)>| {
+			Arc::new(TimeMap::clamp(
+				Arc::new(t),
+				NaiveDate::from_ymd_opt(2020, 4, 24),
+				Some(end - chrono::Duration::days(hosp.clamp_days as i64)),
+			))
+		};
+		Self {
+			keys,
+			icu_covid_cases_est: clamp(est),
+			hosp_share: clamp(share),
+		}
+	}
+
+	pub fn write_field_descriptors(&self, out: &mut Vec<Field<(StateId, AgeGroup)>>) {
+		out.push(Field::new(
+			self.icu_covid_cases_est.clone(),
+			"icu_covid_cases_est",
+		));
+		out.push(Field::new(self.hosp_share.clone(), "icu_est_hosp_share"));
+	}
+}
+
+pub struct RawHospitalizationNowcast {
+	pub cases_d7: SparseTimeSeries<StateId, f64>,
+	pub cases_d7_lo: SparseTimeSeries<StateId, f64>,
+	pub cases_d7_hi: SparseTimeSeries<StateId, f64>,
+}
+
+impl RawHospitalizationNowcast {
+	pub fn new() -> Self {
+		Self {
+			cases_d7: SparseTimeSeries::new(),
+			cases_d7_lo: SparseTimeSeries::new(),
+			cases_d7_hi: SparseTimeSeries::new(),
+		}
+	}
+
+	pub fn submit(&mut self, rec: &HospitalizationNowcastRecord) {
+		// the sum of everything (state 0) is kept; it is only served for the national aggregate, see NATIONAL_ID
+		// the age groups are only broken down for the country as a whole
+		if rec.age_group.low != 0 || rec.age_group.high.is_some() {
+			return;
+		}
+		let k = rec.state_id;
+		// sparse storage, so that days without estimate do not show up as zero
+		for (ts, v) in [
+			(&mut self.cases_d7, rec.cases_d7),
+			(&mut self.cases_d7_lo, rec.cases_d7_lo),
+			(&mut self.cases_d7_hi, rec.cases_d7_hi),
+		] {
+			if let Some(v) = v {
+				*ts.insert_default(&k, &rec.date, &0.) = v;
+			}
+		}
+	}
+}
+
+impl Default for RawHospitalizationNowcast {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+pub struct CookedHospitalizationNowcast<T: TimeSeriesKey> {
+	pub cases_d7: Arc<SparseTimeSeries<T, f64>>,
+	pub cases_d7_lo: Arc<SparseTimeSeries<T, f64>>,
+	pub cases_d7_hi: Arc<SparseTimeSeries<T, f64>>,
+}
+
+impl CookedHospitalizationNowcast<StateId> {
+	pub fn cook(raw: RawHospitalizationNowcast) -> Self {
+		Self {
+			cases_d7: Arc::new(raw.cases_d7),
+			cases_d7_lo: Arc::new(raw.cases_d7_lo),
+			cases_d7_hi: Arc::new(raw.cases_d7_hi),
+		}
+	}
+}
+
+impl<T: TimeSeriesKey + 'static> CookedHospitalizationNowcast<T> {
+	pub fn write_field_descriptors(
+		&self,
+		out: &mut Vec<crate::FieldDescriptor<Arc<dyn crate::ViewTimeSeries<T>>>>,
+	) {
+		out.push(
+			Field::new(self.cases_d7.clone(), "hosp_nowcast").with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::new(self.cases_d7_lo.clone(), "hosp_nowcast_lo")
+				.with_aggregation(Aggregation::Last),
+		);
+		out.push(
+			Field::new(self.cases_d7_hi.clone(), "hosp_nowcast_hi")
+				.with_aggregation(Aggregation::Last),
+		);
+	}
+}
+
+// the testing numbers are only available for the country as a whole, hence the empty key
+pub struct RawTestingData {
+	pub tests: Counters<()>,
+	pub positive: Counters<()>,
+}
+
+impl RawTestingData {
+	pub fn new(start: NaiveDate, end: NaiveDate) -> Self {
+		Self {
+			tests: Counters::new(start, end),
+			positive: Counters::new(start, end),
+		}
+	}
+
+	pub fn submit(&mut self, rec: &TestingRecord) {
+		let index = match self.tests.date_index(rec.week_end) {
+			Some(i) => i,
+			// the current week ends in the future
+			None => return,
+		};
+		self.tests.get_or_create(())[index] += rec.tests;
+		self.positive.get_or_create(())[index] += rec.positive;
+	}
+}
+
+pub struct CookedTestingData {
+	pub tests: WeeklyCounterGroup<()>,
+	pub positive: WeeklyCounterGroup<()>,
+}
+
+impl CookedTestingData {
+	pub fn cook(raw: RawTestingData) -> Self {
+		Self {
+			tests: WeeklyCounterGroup::from_weekly(raw.tests),
+			positive: WeeklyCounterGroup::from_weekly(raw.positive),
+		}
+	}
+
+	pub fn write_field_descriptors(
+		&self,
+		out: &mut Vec<crate::FieldDescriptor<Arc<dyn crate::ViewTimeSeries<()>>>>,
+	) {
+		out.push(Field::new(self.tests.d7(), "test_count").with_aggregation(Aggregation::Last));
+		out.push(
+			Field::new(self.positive.d7(), "test_positive").with_aggregation(Aggregation::Last),
+		);
+		// in percent, like the source data
+		out.push(
+			Field::new(
+				Arc::new(Ratio::scaled(
+					self.positive.d7.clone(),
+					self.tests.d7.clone(),
+					100.,
+				)),
+				"test_positivity",
+			)
+			.with_aggregation(Aggregation::Last),
+		);
+	}
+}
+
+pub struct RawICUUnavailableData {
+	pub num_regions: Counters<()>,
+	pub missing_staff: Counters<()>,
+	pub missing_space: Counters<()>,
+	pub missing_material: Counters<()>,
+	pub missing_ventilator: Counters<()>,
+}
+
+impl RawICUUnavailableData {
+	pub fn new(start: NaiveDate, end: NaiveDate) -> Self {
+		Self {
+			num_regions: Counters::new(start, end),
+			missing_staff: Counters::new(start, end),
+			missing_space: Counters::new(start, end),
+			missing_material: Counters::new(start, end),
+			missing_ventilator: Counters::new(start, end),
+		}
+	}
+
+	pub fn submit(&mut self, rec: &ICUUnavailableReasonRecord) {
+		let index = match self.num_regions.date_index(rec.date) {
+			Some(i) => i,
+			None => return,
+		};
+		// these are current numbers; with several reports on a day, the last one wins
+		self.num_regions.get_or_create(())[index] = rec.num_regions as u64;
+		self.missing_staff.get_or_create(())[index] = rec.missing_staff as u64;
+		self.missing_space.get_or_create(())[index] = rec.missing_space as u64;
+		self.missing_material.get_or_create(())[index] = rec.missing_material as u64;
+		self.missing_ventilator.get_or_create(())[index] = rec.missing_ventilator as u64;
+	}
+}
+
+pub struct CookedICUUnavailableData {
+	pub num_regions: Arc<Counters<()>>,
+	pub missing_staff: Arc<Counters<()>>,
+	pub missing_space: Arc<Counters<()>>,
+	pub missing_material: Arc<Counters<()>>,
+	pub missing_ventilator: Arc<Counters<()>>,
+}
+
+impl CookedICUUnavailableData {
+	pub fn cook(raw: RawICUUnavailableData) -> Self {
+		Self {
+			num_regions: Arc::new(raw.num_regions),
+			missing_staff: Arc::new(raw.missing_staff),
+			missing_space: Arc::new(raw.missing_space),
+			missing_material: Arc::new(raw.missing_material),
+			missing_ventilator: Arc::new(raw.missing_ventilator),
+		}
+	}
+
+	pub fn write_field_descriptors(
+		&self,
+		out: &mut Vec<crate::FieldDescriptor<Arc<dyn crate::ViewTimeSeries<()>>>>,
+	) {
+		let num_regions = CookedICULoadData::<()>::clamp(self.num_regions.clone());
+		out.push(Field::integer(num_regions.clone(), "icu_num_regions"));
+		for (missing, name, share_name) in [
+			(
+				&self.missing_staff,
+				"icu_limited_staff",
+				"icu_limited_staff_share",
+			),
+			(
+				&self.missing_space,
+				"icu_limited_space",
+				"icu_limited_space_share",
+			),
+			(
+				&self.missing_material,
+				"icu_limited_material",
+				"icu_limited_material_share",
+			),
+			(
+				&self.missing_ventilator,
+				"icu_limited_ventilator",
+				"icu_limited_ventilator_share",
+			),
+		] {
+			let missing = CookedICULoadData::<()>::clamp(missing.clone());
+			out.push(Field::new(missing.clone(), name));
+			out.push(Field::new(
+				Arc::new(Ratio::new(missing, num_regions.clone())),
+				share_name,
+			));
+		}
+	}
+}
+
+// (state code, site)
+pub type WastewaterKey = (SmartString, SmartString);
+
+pub struct RawWastewaterData {
+	pub viral_load: SparseTimeSeries<WastewaterKey, f64>,
+}
+
+impl RawWastewaterData {
+	pub fn new() -> Self {
+		Self {
+			viral_load: SparseTimeSeries::new(),
+		}
+	}
+
+	pub fn submit(&mut self, rec: &WastewaterRecord) {
+		match rec.pathogen.as_deref() {
+			None | Some("SARS-CoV-2") => (),
+			// influenza and RSV are in the same file in newer releases
+			Some(_) => return,
+		}
+		let viral_load = match rec.viral_load {
+			Some(v) => v,
+			// not every site reports every day
+			None => return,
+		};
+		let k = (rec.state_code.clone(), rec.site.clone());
+		*self.viral_load.insert_default(&k, &rec.date, &0.) = viral_load;
+	}
+}
+
+impl Default for RawWastewaterData {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+pub struct CookedWastewaterData {
+	pub viral_load: Arc<SparseTimeSeries<WastewaterKey, f64>>,
+	// relative to the mean of the respective site, as the absolute loads depend on the sewer system and the lab and are thus not comparable across sites
+	pub viral_load_norm: Arc<SparseTimeSeries<WastewaterKey, f64>>,
+}
+
+impl CookedWastewaterData {
+	pub fn cook(raw: RawWastewaterData) -> Self {
+		let mut viral_load_norm = SparseTimeSeries::new();
+		for (k, series) in raw.viral_load.iter() {
+			let mean = series.iter().map(|(_, v)| *v).sum::<f64>() / series.len() as f64;
+			if mean <= 0. {
+				continue;
+			}
+			for (date, v) in series.iter() {
+				*viral_load_norm.insert_default(k, date, &0.) = *v / mean;
+			}
+		}
+		Self {
+			viral_load: Arc::new(raw.viral_load),
+			viral_load_norm: Arc::new(viral_load_norm),
+		}
+	}
+
+	pub fn write_field_descriptors(
+		&self,
+		out: &mut Vec<crate::FieldDescriptor<Arc<dyn crate::ViewTimeSeries<WastewaterKey>>>>,
+	) {
+		out.push(Field::new(self.viral_load.clone(), "viral_load"));
+		out.push(
+			Field::new(
+				Arc::new(SparseMovingAverage::new(
+					self.viral_load.clone(),
+					7,
+					Mean::Geometric,
+				)),
+				"viral_load_gm7",
+			)
+			.with_aggregation(Aggregation::Last),
+		);
+		out.push(Field::new(self.viral_load_norm.clone(), "viral_load_norm"));
+		out.push(
+			Field::new(
+				Arc::new(SparseMovingAverage::new(
+					self.viral_load_norm.clone(),
+					7,
+					Mean::Geometric,
+				)),
+				"viral_load_norm_gm7",
+			)
+			.with_aggregation(Aggregation::Last),
+		);
+	}
+}
+
+// the years whose weekly deaths make up the expected deaths of the pandemic years
+pub static DEATHS_BASELINE_YEARS: std::ops::RangeInclusive<i32> = 2016..=2019;
+
+pub struct RawWeeklyDeathsData {
+	pub deaths: SparseTimeSeries<(StateId, AgeGroup), f64>,
+}
+
+impl RawWeeklyDeathsData {
+	pub fn new() -> Self {
+		Self {
+			deaths: SparseTimeSeries::new(),
+		}
+	}
+
+	pub fn submit(&mut self, rec: &RawDestatisDeathByWeekRow) {
+		let (deaths, week_end) = match (rec.deaths, rec.week_end()) {
+			(Some(deaths), Some(week_end)) => (deaths, week_end),
+			// the most recent weeks are not published yet
+			_ => return,
+		};
+		let k = (rec.state_id, rec.age_group);
+		*self.deaths.insert_default(&k, &week_end, &0.) += deaths;
+	}
+}
+
+impl Default for RawWeeklyDeathsData {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+// Weekly deaths, keyed like the hospitalization data, with the state 0 being the whole country as published by destatis.
+pub struct CookedWeeklyDeathsData {
+	pub deaths: Arc<SparseTimeSeries<(StateId, AgeGroup), f64>>,
+	// median of the same calendar week over DEATHS_BASELINE_YEARS, at the weeks after the baseline
+	pub expected: Arc<SparseTimeSeries<(StateId, AgeGroup), f64>>,
+	pub excess: Arc<SparseTimeSeries<(StateId, AgeGroup), f64>>,
+}
+
+impl CookedWeeklyDeathsData {
+	pub fn cook(raw: RawWeeklyDeathsData) -> Self {
+		let mut expected = SparseTimeSeries::new();
+		let mut excess = SparseTimeSeries::new();
+		for (k, series) in raw.deaths.iter() {
+			let mut samples: HashMap<u32, Vec<f64>> = HashMap::new();
+			for (date, v) in series.iter() {
+				let week = date.iso_week();
+				if DEATHS_BASELINE_YEARS.contains(&week.year()) {
+					samples.entry(week.week()).or_default().push(*v);
+				}
+			}
+			for v in samples.values_mut() {
+				v.sort_by(|a, b| a.partial_cmp(b).unwrap());
+			}
+			for (date, v) in series.iter() {
+				let week = date.iso_week();
+				if week.year() <= *DEATHS_BASELINE_YEARS.end() {
+					continue;
+				}
+				// none of the baseline years has a week 53
+				let baseline = match samples
+					.get(&week.week())
+					.or_else(|| samples.get(&(week.week() - 1)))
+				{
+					Some(v) => crate::sorted_median(v),
+					None => continue,
+				};
+				*expected.insert_default(k, date, &0.) = baseline;
+				*excess.insert_default(k, date, &0.) = *v - baseline;
+			}
+		}
+		Self {
+			deaths: Arc::new(raw.deaths),
+			expected: Arc::new(expected),
+			excess: Arc::new(excess),
+		}
+	}
+
+	pub fn write_field_descriptors(&self, out: &mut Vec<Field<(StateId, AgeGroup)>>) {
+		out.push(Field::new(self.deaths.clone(), "deaths_w").with_aggregation(Aggregation::Sum));
+		out.push(
+			Field::new(self.expected.clone(), "deaths_w_expected")
+				.with_aggregation(Aggregation::Sum),
+		);
+		out.push(
+			Field::new(self.excess.clone(), "deaths_w_excess").with_aggregation(Aggregation::Sum),
+		);
+		// in percent of the expected deaths
+		out.push(Field::new(
+			Arc::new(Ratio::scaled(
+				self.excess.clone(),
+				self.expected.clone(),
+				100.,
+			)),
+			"deaths_w_excess_pct",
+		));
+	}
+}
+
+// Population counts by reference date ("vintage"), e.g. one per year of the destatis population statistics.
+pub struct RawPopulationData<T: TimeSeriesKey> {
+	pub vintages: BTreeMap<NaiveDate, HashMap<T, u64>>,
+}
+
+impl<T: TimeSeriesKey> RawPopulationData<T> {
+	// the date of counts which are not dated, such as the population in the districts file
+	pub fn ref_date() -> NaiveDate {
+		// arbitrary
+		NaiveDate::from_ymd_opt(2020, 1, 1).unwrap()
+	}
+
+	pub fn new() -> Self {
+		Self {
+			vintages: BTreeMap::new(),
+		}
+	}
+
+	pub fn vintage(&mut self, date: NaiveDate) -> &mut HashMap<T, u64> {
+		self.vintages.entry(date).or_default()
+	}
+
+	pub fn remapped<U: TimeSeriesKey, F: Fn(&T) -> Option<U>>(&self, f: F) -> RawPopulationData<U> {
+		let mut result = RawPopulationData::<U>::new();
+		for (date, counts) in self.vintages.iter() {
+			let vintage = result.vintage(*date);
+			for (k, count) in counts.iter() {
+				if let Some(k) = f(k) {
+					*vintage.entry(k).or_insert(0) += count;
+				}
+			}
+		}
+		result
+	}
+}
+
+impl<T: TimeSeriesKey> Default for RawPopulationData<T> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl RawPopulationData<(StateId, AgeGroup, Sex)> {
+	pub fn submit(&mut self, rec: RawDestatisRow) {
+		let k = (rec.state_id, rec.age_group, rec.sex);
+		let date = rec.date.unwrap_or_else(Self::ref_date);
+		*self.vintage(date).entry(k).or_insert(0) += rec.count;
+	}
+}
+
+// The population as a time series with a value on each vintage date; view() carries each vintage forward until the next one.
+pub struct CookedPopulationData<T: TimeSeriesKey> {
+	pub count: Arc<Counters<T>>,
+	// ascending, never empty
+	pub vintages: Arc<Vec<NaiveDate>>,
+}
+
+impl<T: TimeSeriesKey> CookedPopulationData<T> {
+	pub fn cook(raw: RawPopulationData<T>) -> Self {
+		let mut vintages: Vec<NaiveDate> = raw.vintages.keys().copied().collect();
+		if vintages.is_empty() {
+			vintages.push(RawPopulationData::<T>::ref_date());
+		}
+		let mut count = Counters::new(
+			vintages[0],
+			vintages[vintages.len() - 1] + chrono::Duration::days(1),
+		);
+		for (date, counts) in raw.vintages.into_iter() {
+			let index = count.date_index(date).unwrap();
+			for (k, v) in counts.into_iter() {
+				count.get_or_create(k)[index] = v;
+			}
+		}
+		Self {
+			count: Arc::new(count),
+			vintages: Arc::new(vintages),
+		}
+	}
+
+	pub fn rekeyed<U: TimeSeriesKey, F: Fn(&T) -> Option<U>>(
+		&self,
+		f: F,
+	) -> CookedPopulationData<U> {
+		CookedPopulationData::<U> {
+			count: Arc::new(self.count.rekeyed(&f)),
+			vintages: self.vintages.clone(),
+		}
+	}
+
+	pub fn aggregated<U: TimeSeriesKey, I: IntoIterator<Item = U>, F: Fn(&T) -> I>(
+		&self,
+		f: F,
+	) -> CookedPopulationData<U> {
+		CookedPopulationData::<U> {
+			count: Arc::new(self.count.aggregated(&f)),
+			vintages: self.vintages.clone(),
+		}
+	}
+
+	// Adds the keys of `other` which are not present yet, e.g. to combine populations binned by different age groups. The vintages of `self` are kept; the added keys get the values of `other` which apply on these dates.
+	pub fn merged(&self, other: &Self) -> Self {
+		let mut count = (*self.count).clone();
+		let other_view = other.view();
+		for k in other.count.keys() {
+			if count.get(k).is_some() {
+				continue;
+			}
+			for date in self.vintages.iter() {
+				let index = count.date_index(*date).unwrap();
+				if let Some(v) = other_view.getf(k, *date) {
+					count.get_or_create(k.clone())[index] = v as u64;
+				}
+			}
+		}
+		Self {
+			count: Arc::new(count),
+			vintages: self.vintages.clone(),
+		}
+	}
+
+	pub fn view(&self) -> Arc<Filled<Arc<Counters<T>>>> {
+		Arc::new(Filled::stepwise(self.count.clone(), self.vintages.clone()))
+	}
+}
+
+impl<T: TimeSeriesKey + 'static> CookedPopulationData<T> {
+	pub fn write_field_descriptors(
+		&self,
+		out: &mut Vec<crate::FieldDescriptor<Arc<dyn crate::ViewTimeSeries<T>>>>,
+	) {
+		out.push(Field::integer(self.view(), "population"));
+	}
+
+	// Inhabitants per km²; keys without an area have no value.
+	pub fn write_density_field_descriptors<A: KeyFactor<T> + 'static>(
+		&self,
+		areas: A,
+		out: &mut Vec<crate::FieldDescriptor<Arc<dyn crate::ViewTimeSeries<T>>>>,
+	) {
+		out.push(Field::new(
+			Arc::new(ScaleByKey::divide(self.view(), areas)),
+			"population_density",
+		));
+	}
+}
+
+// The area in km² of each district known in `districts`, keyed like the population. Usable with ScaleByKey::divide for per-area values. The Berlin boroughs are left out, as their sum is SK Berlin.
+pub fn district_areas(
+	districts: &HashMap<DistrictId, Arc<DistrictInfo>>,
+) -> Arc<HashMap<GeoCaseKey, f64>> {
+	Arc::new(
+		districts
+			.values()
+			.filter(|d| !(d.id > 11000 && d.id < 12000))
+			.filter_map(|d| Some(((d.state.id, d.id), d.area?)))
+			.collect(),
+	)
+}
+
+// The position of each district as `lat` and `lon` fields, so that map panels can place the districts without a lookup of their own.
+pub fn write_centroid_field_descriptors(
+	centroids: &Arc<DistrictCentroids>,
+	out: &mut Vec<crate::FieldDescriptor<Arc<dyn crate::ViewTimeSeries<GeoCaseKey>>>>,
+) {
+	let lat = centroids.clone();
+	out.push(Field::new(
+		Arc::new(KeyConstant(move |k: &GeoCaseKey| {
+			lat.get(k.1).map(|(lat, _)| lat)
+		})),
+		"lat",
+	));
+	let lon = centroids.clone();
+	out.push(Field::new(
+		Arc::new(KeyConstant(move |k: &GeoCaseKey| {
+			lon.get(k.1).map(|(_, lon)| lon)
+		})),
+		"lon",
+	));
+}
+
+pub fn load_diff_data<P: AsRef<Path>, S: ProgressSink + ?Sized>(
+	s: &mut S,
+	p: P,
+	district_map: &HashMap<DistrictId, Arc<DistrictInfo>>,
+	remap: &DistrictRemapping,
+	policy: SubmitPolicy,
+	diffstart: NaiveDate,
+	cases: &mut ParboiledCaseData,
+) -> Result<PhaseStats, Error> {
+	// The counters may start after `diffstart`, see PipelineBuilder::since. The records in between are not needed and skipped, while those before `diffstart` are out of range as usual.
+	let from = cases.cases_by_pub.start();
+	let mut pm = CountMeter::new(s);
+	let mut log = SubmitLog::new(policy);
+	let mut n = 0;
+	let mut nearlier = 0;
+	let mut submit_all = |r: &mut CsvRecords<Box<dyn std::io::Read>>| -> Result<(), Error> {
+		for row in r.deserialize() {
+			let rec: DiffRecord = row?;
+			if rec.date >= diffstart && rec.date < from {
+				nearlier += 1;
+				continue;
+			}
+			log.handle(n, cases.submit(district_map, remap, &rec))?;
+			n += 1;
+			if n % 500000 == 0 {
+				pm.update(n);
+			}
+		}
+		Ok(())
+	};
+	if DiffStore::is_store(&p) {
+		let store = DiffStore::open(&p)?;
+		info!(
+			"{}: diff store with {} partitions",
+			p.as_ref().display(),
+			store.partitions().count()
+		);
+		// the partitions from the month of `diffstart` on and before the one of `from` hold nothing but skipped records
+		for partition in store
+			.partitions()
+			.filter(|p| p.month < diffstart || p.month >= diff_partition_of(from))
+		{
+			submit_all(&mut store.read_partition(partition)?)?;
+		}
+	} else {
+		let mut r = CsvRecords::open(&p)?;
+		let schema = DiffSchema::detect(r.headers())?;
+		info!("{}: {} diff file", r.file(), schema);
+		submit_all(&mut r)?;
+	}
+	pm.finish(n);
+	if nearlier > 0 {
+		info!(
+			"skipped {} diff records from {} to {}",
+			nearlier, diffstart, from
+		);
+	}
+	log.log_summary();
+	Ok(PhaseStats {
+		rows: n,
+		errors: log.nskipped(),
+		..PhaseStats::default()
+	})
+}
+
+pub fn load_case_data<P: AsRef<Path>, S: ProgressSink + ?Sized>(
+	s: &mut S,
+	p: P,
+	district_map: &HashMap<DistrictId, Arc<DistrictInfo>>,
+	remap: &DistrictRemapping,
+	policy: SubmitPolicy,
+	cases: &mut RawCaseData,
+) -> Result<PhaseStats, Error> {
+	let mut r = CsvRecords::open(p)?;
+	let schema = InfectionSchema::detect(r.headers())?;
+	info!("{}: {} case file", r.file(), schema);
+	let mut pm = CountMeter::new(s);
+	let mut log = SubmitLog::new(policy);
+	let mut n = 0;
+	for (i, row) in r.deserialize().enumerate() {
+		let rec: InfectionRecord = row?;
+		log.handle(i, cases.submit(district_map, remap, &rec))?;
+		if i % 500000 == 499999 {
+			pm.update(i + 1);
+		}
+		n = i + 1;
+	}
+	pm.finish(n);
+	log.log_summary();
+	Ok(PhaseStats {
+		rows: n,
+		errors: log.nskipped(),
+		..PhaseStats::default()
+	})
+}
+
+pub fn load_divi_load_data<P: AsRef<Path>, S: ProgressSink + ?Sized>(
+	s: &mut S,
+	p: P,
+	remap: &DistrictRemapping,
+	data: &mut RawICULoadData,
+) -> Result<PhaseStats, Error> {
+	let mut r = CsvRecords::open(p)?;
+	let mut pm = CountMeter::new(s);
+	let mut n = 0;
+	for (i, row) in r.deserialize().enumerate() {
+		let rec: ICULoadRecord = row?;
+		let index = match data.curr_covid_cases.date_index(rec.date) {
+			Some(i) => i,
+			// DIVI data may have today's data, which does not match the
+			// publication rhythm of the data -> skip
+			None => continue,
+		};
+		let k = (rec.state_id, remap.resolve(rec.district_id, rec.date));
+		data.curr_covid_cases.get_or_create(k)[index] = rec.current_covid_cases as u64;
+		data.curr_covid_cases_invasive.get_or_create(k)[index] =
+			rec.current_covid_cases_invasive_ventilation as u64;
+		data.curr_beds_free.get_or_create(k)[index] = rec.beds_free as u64;
+		data.curr_beds_in_use.get_or_create(k)[index] = rec.beds_in_use as u64;
+		data.curr_beds_free_adult.get_or_create(k)[index] = rec.beds_free_adult_only as u64;
+		data.curr_beds_in_use_adult.get_or_create(k)[index] = rec.beds_in_use_adult_only as u64;
+		data.num_sites.get_or_create(k)[index] = rec.num_stations as u64;
+		data.num_regions.get_or_create(k)[index] = rec.num_regions as u64;
+		if i % 500000 == 499999 {
+			pm.update(i + 1);
+		}
+		n = i + 1;
+	}
+	pm.finish(n);
+	Ok(PhaseStats {
+		rows: n,
+		..PhaseStats::default()
+	})
+}
+
+// Alternative to load_divi_load_data for the site level register: the sites are summed up per district, and num_sites counts the sites which reported on the day. Repeated rows of a site on the same day are skipped.
+pub fn load_divi_site_load_data<P: AsRef<Path>, S: ProgressSink + ?Sized>(
+	s: &mut S,
+	p: P,
+	remap: &DistrictRemapping,
+	data: &mut RawICULoadData,
+) -> Result<PhaseStats, Error> {
+	let mut r = CsvRecords::open(p)?;
+	let mut pm = CountMeter::new(s);
+	let mut seen: HashSet<(NaiveDate, String)> = HashSet::new();
+	let mut n = 0;
+	let mut nduplicate = 0;
+	for (i, row) in r.deserialize().enumerate() {
+		let rec: ICUSiteLoadRecord = row?;
+		let index = match data.curr_covid_cases.date_index(rec.date) {
+			Some(i) => i,
+			// DIVI data may have today's data, which does not match the
+			// publication rhythm of the data -> skip
+			None => continue,
+		};
+		if !seen.insert((rec.date, rec.site_id)) {
+			nduplicate += 1;
+			continue;
+		}
+		let k = (rec.state_id, remap.resolve(rec.district_id, rec.date));
+		data.curr_covid_cases.get_or_create(k)[index] += rec.current_covid_cases as u64;
+		data.curr_covid_cases_invasive.get_or_create(k)[index] +=
+			rec.current_covid_cases_invasive_ventilation as u64;
+		data.curr_beds_free.get_or_create(k)[index] += rec.beds_free as u64;
+		data.curr_beds_in_use.get_or_create(k)[index] += rec.beds_in_use as u64;
+		data.curr_beds_free_adult.get_or_create(k)[index] += rec.beds_free_adult_only as u64;
+		data.curr_beds_in_use_adult.get_or_create(k)[index] += rec.beds_in_use_adult_only as u64;
+		data.num_sites.get_or_create(k)[index] += 1;
+		data.num_regions.get_or_create(k)[index] += rec.num_regions as u64;
+		if i % 500000 == 499999 {
+			pm.update(i + 1);
+		}
+		n = i + 1;
+	}
+	pm.finish(n);
+	if nduplicate > 0 {
+		warn!("skipped {} repeated site rows", nduplicate);
+	}
+	Ok(PhaseStats {
+		rows: n,
+		errors: nduplicate,
+		..PhaseStats::default()
+	})
+}
+
+pub fn load_vacc_data<P: AsRef<Path>, S: ProgressSink + ?Sized>(
+	s: &mut S,
+	p: P,
+	district_map: &HashMap<DistrictId, Arc<DistrictInfo>>,
+	remap: &DistrictRemapping,
+	policy: SubmitPolicy,
+	data: &mut RawVaccinationData,
+) -> Result<PhaseStats, Error> {
+	let mut r = CsvRecords::open(p)?;
+	let mut pm = CountMeter::new(s);
+	let mut log = SubmitLog::new(policy);
+	let mut n = 0;
+	for (i, row) in r.deserialize().enumerate() {
+		let rec: VaccinationRecord = row?;
+		log.handle(i, data.submit(district_map, remap, &rec))?;
+		if i % 500000 == 499999 {
+			pm.update(i + 1);
+		}
+		n = i + 1;
+	}
+	pm.finish(n);
+	log.log_summary();
+	Ok(PhaseStats {
+		rows: n,
+		errors: log.nskipped(),
+		..PhaseStats::default()
+	})
+}
+
+pub fn load_hosp_data<P: AsRef<Path>, S: ProgressSink + ?Sized>(
+	s: &mut S,
+	p: P,
+	data: &mut RawHospitalizationData,
+) -> Result<PhaseStats, Error> {
+	let mut r = CsvRecords::open(p)?;
+	let mut pm = CountMeter::new(s);
+	let mut n = 0;
+	// for some reason, they have NA in some cells?!
+	for (i, row) in r.deserialize_skipping().enumerate() {
+		let rec: HospitalizationRecord = row?;
+		data.submit(&rec);
+		if i % 500000 == 499999 {
+			pm.update(i + 1);
+		}
+		n = i + 1;
+	}
+	pm.finish(n);
+	r.log_summary();
+	Ok(PhaseStats {
+		rows: n + r.nskipped(),
+		errors: r.nskipped(),
+		..PhaseStats::default()
+	})
+}
+
+pub fn load_hosp_nowcast_data<P: AsRef<Path>, S: ProgressSink + ?Sized>(
+	s: &mut S,
+	p: P,
+	data: &mut RawHospitalizationNowcast,
+) -> Result<PhaseStats, Error> {
+	let mut r = CsvRecords::open(p)?;
+	let mut pm = CountMeter::new(s);
+	let mut n = 0;
+	for (i, row) in r.deserialize().enumerate() {
+		let rec: HospitalizationNowcastRecord = row?;
+		data.submit(&rec);
+		if i % 500000 == 499999 {
+			pm.update(i + 1);
+		}
+		n = i + 1;
+	}
+	pm.finish(n);
+	Ok(PhaseStats {
+		rows: n,
+		..PhaseStats::default()
+	})
+}
+
+pub fn load_testing_data<P: AsRef<Path>, S: ProgressSink + ?Sized>(
+	s: &mut S,
+	p: P,
+	data: &mut RawTestingData,
+) -> Result<PhaseStats, Error> {
+	let mut r = CsvRecords::open(p)?;
+	let mut pm = CountMeter::new(s);
+	let mut n = 0;
+	for (i, row) in r.deserialize().enumerate() {
+		let rec: TestingRecord = row?;
+		data.submit(&rec);
+		if i % 500000 == 499999 {
+			pm.update(i + 1);
+		}
+		n = i + 1;
+	}
+	pm.finish(n);
+	Ok(PhaseStats {
+		rows: n,
+		..PhaseStats::default()
+	})
+}
+
+pub fn load_divi_unavailable_data<P: AsRef<Path>, S: ProgressSink + ?Sized>(
+	s: &mut S,
+	p: P,
+	data: &mut RawICUUnavailableData,
+) -> Result<PhaseStats, Error> {
+	let mut r = CsvRecords::open(p)?;
+	let mut pm = CountMeter::new(s);
+	let mut n = 0;
+	for (i, row) in r.deserialize().enumerate() {
+		let rec: ICUUnavailableReasonRecord = row?;
+		data.submit(&rec);
+		if i % 500000 == 499999 {
+			pm.update(i + 1);
+		}
+		n = i + 1;
+	}
+	pm.finish(n);
+	Ok(PhaseStats {
+		rows: n,
+		..PhaseStats::default()
+	})
+}
+
+pub fn load_wastewater_data<P: AsRef<Path>, S: ProgressSink + ?Sized>(
+	s: &mut S,
+	p: P,
+	data: &mut RawWastewaterData,
+) -> Result<PhaseStats, Error> {
+	let mut r = CsvRecords::open_with(p, csv::ReaderBuilder::new().delimiter(b'\t'))?;
+	let mut pm = CountMeter::new(s);
+	let mut n = 0;
+	for (i, row) in r.deserialize().enumerate() {
+		let rec: WastewaterRecord = row?;
+		data.submit(&rec);
+		if i % 500000 == 499999 {
+			pm.update(i + 1);
+		}
+		n = i + 1;
+	}
+	pm.finish(n);
+	Ok(PhaseStats {
+		rows: n,
+		..PhaseStats::default()
+	})
+}
+
+pub fn load_destatis_data<P: AsRef<Path>, S: ProgressSink + ?Sized>(
+	s: &mut S,
+	p: P,
+	data: &mut RawPopulationData<(StateId, AgeGroup, Sex)>,
+) -> Result<PhaseStats, Error> {
+	let mut r = CsvRecords::open(p)?;
+	let mut pm = CountMeter::new(s);
+	let mut n = 0;
+	// for some reason, they have NA in some cells?!
+	for (i, row) in r.deserialize_skipping().enumerate() {
+		let rec: RawDestatisRow = row?;
+		data.submit(rec);
+		if i % 100 == 99 {
+			pm.update(i + 1);
+		}
+		n = i + 1;
+	}
+	pm.finish(n);
+	r.log_summary();
+	Ok(PhaseStats {
+		rows: n + r.nskipped(),
+		errors: r.nskipped(),
+		..PhaseStats::default()
+	})
+}
+
+pub fn load_weekly_deaths_data<P: AsRef<Path>, S: ProgressSink + ?Sized>(
+	s: &mut S,
+	p: P,
+	data: &mut RawWeeklyDeathsData,
+) -> Result<PhaseStats, Error> {
+	let mut r = CsvRecords::open(p)?;
+	let mut pm = CountMeter::new(s);
+	let mut n = 0;
+	for (i, row) in r.deserialize().enumerate() {
+		let rec: RawDestatisDeathByWeekRow = row?;
+		data.submit(&rec);
+		if i % 500000 == 499999 {
+			pm.update(i + 1);
+		}
+		n = i + 1;
+	}
+	pm.finish(n);
+	Ok(PhaseStats {
+		rows: n,
+		..PhaseStats::default()
+	})
+}
+
+pub fn remap_berlin(id: DistrictId) -> DistrictId {
+	if (11000..12000).contains(&id) {
+		11000
+	} else {
+		id
+	}
+}
+
+pub fn load_cooked_hosp_nowcast_data(
+	runs: &mut RunLog,
+	nowcastfile: &str,
+) -> Result<CookedHospitalizationNowcast<StateId>, Error> {
+	let mut nowcast = RawHospitalizationNowcast::new();
+	let started = Instant::now();
+	let stats = load_hosp_nowcast_data(
+		&mut runs.begin_phase("loading hospitalization nowcast data"),
+		nowcastfile,
+		&mut nowcast,
+	)?;
+	runs.record("load_hosp_nowcast", started, stats);
+	Ok(CookedHospitalizationNowcast::cook(nowcast))
+}
+
+pub fn load_cooked_testing_data<P: AsRef<Path>>(
+	runs: &mut RunLog,
+	start: NaiveDate,
+	end: NaiveDate,
+	testingfile: P,
+) -> Result<CookedTestingData, Error> {
+	let mut testing = RawTestingData::new(start, end);
+	let started = Instant::now();
+	let stats = load_testing_data(
+		&mut runs.begin_phase("loading testing data"),
+		testingfile,
+		&mut testing,
+	)?;
+	runs.record("load_testing", started, stats);
+	Ok(CookedTestingData::cook(testing))
+}
+
+pub fn load_cooked_divi_unavailable_data<P: AsRef<Path>>(
+	runs: &mut RunLog,
+	start: NaiveDate,
+	end: NaiveDate,
+	unavailablefile: P,
+) -> Result<CookedICUUnavailableData, Error> {
+	let mut unavailable = RawICUUnavailableData::new(start, end);
+	let started = Instant::now();
+	let stats = load_divi_unavailable_data(
+		&mut runs.begin_phase("loading ICU limitation data"),
+		unavailablefile,
+		&mut unavailable,
+	)?;
+	runs.record("load_divi_unavailable", started, stats);
+	Ok(CookedICUUnavailableData::cook(unavailable))
+}
+
+pub fn load_cooked_wastewater_data<P: AsRef<Path>>(
+	runs: &mut RunLog,
+	wastewaterfile: P,
+) -> Result<CookedWastewaterData, Error> {
+	let mut wastewater = RawWastewaterData::new();
+	let started = Instant::now();
+	let stats = load_wastewater_data(
+		&mut runs.begin_phase("loading wastewater data"),
+		wastewaterfile,
+		&mut wastewater,
+	)?;
+	runs.record("load_wastewater", started, stats);
+	Ok(CookedWastewaterData::cook(wastewater))
+}
+
+pub fn load_cooked_weekly_deaths_data<P: AsRef<Path>>(
+	runs: &mut RunLog,
+	deathsfile: P,
+) -> Result<CookedWeeklyDeathsData, Error> {
+	let mut deaths = RawWeeklyDeathsData::new();
+	let started = Instant::now();
+	let stats = load_weekly_deaths_data(
+		&mut runs.begin_phase("loading weekly deaths data"),
+		deathsfile,
+		&mut deaths,
+	)?;
+	runs.record("load_weekly_deaths", started, stats);
+	Ok(CookedWeeklyDeathsData::cook(deaths))
+}
+
+// The load parameters which the case and vaccination loaders depend on besides their input file.
+fn hash_load_context(
+	h: &mut InputHash,
+	districts: &HashMap<DistrictId, Arc<crate::DistrictInfo>>,
+	remap: &DistrictRemapping,
+	policy: SubmitPolicy,
+) {
+	let mut ids: Vec<_> = districts.values().map(|d| (d.id, d.state.id)).collect();
+	ids.sort();
+	h.param(&ids);
+	h.param(remap);
+	h.param(&policy);
+}
+
+// A raw dataset, whose counters can be kept in another Storage.
+trait StoredCounters {
+	type Key: TimeSeriesKey;
+	type Value: CounterValue;
+
+	fn counters_mut(&mut self) -> Vec<&mut Counters<Self::Key, Self::Value>>;
+}
+
+// Switches the counters of the dataset `name` to the storage selected for it, if any, and logs the memory this saves.
+fn apply_storage<D: StoredCounters>(storage: &HashMap<String, Storage>, name: &str, data: &mut D) {
+	let storage = match storage.get(name) {
+		Some(v) => *v,
+		None => return,
+	};
+	let mut before = 0;
+	let mut after = 0;
+	for ts in data.counters_mut() {
+		before += ts.memory_usage();
+		ts.set_storage(storage);
+		after += ts.memory_usage();
+	}
+	let mib = |bytes: usize| bytes as f64 / (1024. * 1024.);
+	info!(
+		"{}: {:?} storage uses {:.1} MiB instead of {:.1} MiB",
+		name,
+		storage,
+		mib(after),
+		mib(before)
+	);
+}
+
+// Runs `load`, unless there is a snapshot of `name` taken from the same inputs, as hashed by `hash`. Without snapshots, this is just `load`.
+fn load_snapshotted<
+	T: Serialize + DeserializeOwned,
+	H: FnOnce(&mut InputHash) -> std::io::Result<()>,
+	F: FnOnce(&mut RunLog) -> Result<T, Error>,
+>(
+	runs: &mut RunLog,
+	snapshots: Option<&SnapshotCache>,
+	name: &str,
+	hash: H,
+	load: F,
+) -> Result<T, Error> {
+	let snapshots = match snapshots {
+		Some(v) => v,
+		None => return load(runs),
+	};
+	let started = Instant::now();
+	let mut h = InputHash::new();
+	hash(&mut h)?;
+	let hash = h.finish();
+	if let Some(v) = snapshots.load(name, hash)? {
+		info!("{}: loaded from snapshot", name);
+		// in place of the loading phase, to keep the progress count
+		drop(runs.begin_phase(&format!("loading {} snapshot", name)));
+		runs.record(
+			&format!("load_{}_snapshot", name),
+			started,
+			PhaseStats::default(),
+		);
+		return Ok(v);
+	}
+	let v = load(runs)?;
+	snapshots.store(name, hash, &v)?;
+	Ok(v)
+}
+
+// Serves the national aggregate from `national` and all other keys from `fields`, matching the fields by name. `national` holds the same fields computed on the data rekeyed to NATIONAL_ID.
+pub fn merge_national<K: TimeSeriesKey>(
+	fields: Vec<Field<K>>,
+	national: Vec<Field<K>>,
+	is_national: fn(&K) -> bool,
+) -> Result<Vec<Field<K>>, Error> {
+	fields
+		.into_iter()
+		.map(|desc| {
+			let national = match national.iter().find(|n| n.name() == desc.name()) {
+				Some(n) => n.inner().clone(),
+				None => {
+					return Err(Error::Schema(format!(
+						"national aggregate lacks field {}",
+						desc.name()
+					)))
+				}
+			};
+			Ok(Field::new(
+				Arc::new(SelectByKey::new(
+					is_national,
+					national,
+					desc.inner().clone(),
+				)) as Arc<dyn crate::ViewTimeSeries<K>>,
+				desc.name(),
+			)
+			.with_type(desc.field_type())
+			.with_null_policy(desc.null_policy())
+			.with_aggregation(desc.aggregation()))
+		})
+		.collect()
+}
+
+// The given fields resampled to `bucket`, each aggregated as declared by FieldDescriptor::with_aggregation.
+pub fn resampled<K: TimeSeriesKey>(
+	fields: &[crate::FieldDescriptor<Arc<dyn crate::ViewTimeSeries<K>>>],
+	bucket: Bucket,
+) -> Vec<crate::FieldDescriptor<Arc<dyn crate::ViewTimeSeries<K>>>> {
+	fields
+		.iter()
+		.map(|desc| {
+			let aggregation = desc.aggregation();
+			// a mean of counts is no count
+			let field_type = match aggregation {
+				Aggregation::Mean => crate::FieldType::Float,
+				_ => desc.field_type(),
+			};
+			// the null policy is not carried over, as all days but the last of each bucket are empty by design
+			Field::new(
+				Arc::new(Resample::new(desc.inner().clone(), bucket, aggregation))
+					as Arc<dyn crate::ViewTimeSeries<K>>,
+				desc.name(),
+			)
+			.with_type(field_type)
+			.with_aggregation(aggregation)
+		})
+		.collect()
+}
+
+// Hides the given fields for all keys not in `keys`. Needed where keys of different origin share a measurement, as counters report zero for keys they do not have.
+pub fn restrict_keys<'x, K: TimeSeriesKey, I: IntoIterator<Item = &'x K>>(
+	fields: Vec<crate::FieldDescriptor<Arc<dyn crate::ViewTimeSeries<K>>>>,
+	keys: I,
+) -> Vec<crate::FieldDescriptor<Arc<dyn crate::ViewTimeSeries<K>>>> {
+	let starts: Arc<HashMap<K, NaiveDate>> = Arc::new(
+		keys.into_iter()
+			.map(|k| (k.clone(), global_start_date()))
+			.collect(),
+	);
+	fields
+		.into_iter()
+		.map(|desc| {
+			Field::new(
+				Arc::new(KeyedClamp::new(
+					desc.inner().clone(),
+					Some(starts.clone()),
+					0,
+				)) as Arc<dyn crate::ViewTimeSeries<K>>,
+				desc.name(),
+			)
+			.with_type(desc.field_type())
+			.with_null_policy(desc.null_policy())
+			.with_aggregation(desc.aggregation())
+		})
+		.collect()
+}
+
+// All datasets loaded by PipelineBuilder::load. The optional ones are None unless their file was given.
+pub struct Datasets {
+	pub start: NaiveDate,
+	pub end: NaiveDate,
+	pub population: CookedPopulationData<GeoCaseKey>,
+	pub population_vacc: CookedPopulationData<(StateId, AgeGroup)>,
+	pub population_demo: CookedPopulationData<(StateId, AgeGroup, Sex)>,
+	pub cases: CookedCaseData<FullCaseKey>,
+	pub vacc: CookedVaccinationData<VaccinationKey>,
+	pub hosp: CookedHospitalizationData<(StateId, AgeGroup)>,
+	pub icu_load: CookedICULoadData<GeoCaseKey>,
+	pub hosp_nowcast: Option<CookedHospitalizationNowcast<StateId>>,
+	pub vacc_residence: Option<CookedVaccinationData<VaccinationKey>>,
+	pub testing: Option<CookedTestingData>,
+	pub icu_unavailable: Option<CookedICUUnavailableData>,
+	pub wastewater: Option<CookedWastewaterData>,
+	pub weekly_deaths: Option<CookedWeeklyDeathsData>,
+}
+
+// Input files and settings of the pipeline. The required files are those of the to_influx command line, everything else is optional; the defaults match a to_influx run without any environment variables set.
+#[derive(Debug, Clone)]
+pub struct PipelineBuilder {
+	casefile: String,
+	difffile: String,
+	diffstart: NaiveDate,
+	divifile: String,
+	vaccfile: String,
+	hospfile: String,
+	destatisfile: String,
+	destatisvintages: Vec<PathBuf>,
+	divisitefile: Option<PathBuf>,
+	nowcastfile: Option<String>,
+	vacc_residencefile: Option<String>,
+	testingfile: Option<PathBuf>,
+	icu_unavailablefile: Option<PathBuf>,
+	wastewaterfile: Option<PathBuf>,
+	weeklydeathsfile: Option<PathBuf>,
+	remap: DistrictRemapping,
+	policy: SubmitPolicy,
+	clamp: ClampWindows,
+	collapse_sex: bool,
+	start: NaiveDate,
+	end: NaiveDate,
+	since: Option<NaiveDate>,
+	snapshots: Option<SnapshotCache>,
+	storage: HashMap<String, Storage>,
+}
+
+impl PipelineBuilder {
+	pub fn new<S: Into<String>>(
+		casefile: S,
+		difffile: S,
+		diffstart: NaiveDate,
+		divifile: S,
+		vaccfile: S,
+		hospfile: S,
+		destatisfile: S,
+	) -> Self {
+		Self {
+			casefile: casefile.into(),
+			difffile: difffile.into(),
+			diffstart,
+			divifile: divifile.into(),
+			vaccfile: vaccfile.into(),
+			hospfile: hospfile.into(),
+			destatisfile: destatisfile.into(),
+			destatisvintages: Vec::new(),
+			divisitefile: None,
+			nowcastfile: None,
+			vacc_residencefile: None,
+			testingfile: None,
+			icu_unavailablefile: None,
+			wastewaterfile: None,
+			weeklydeathsfile: None,
+			remap: DistrictRemapping::new(),
+			policy: SubmitPolicy::default(),
+			clamp: ClampWindows::default(),
+			collapse_sex: false,
+			start: global_start_date(),
+			end: crate::naive_today(),
+			since: None,
+			snapshots: None,
+			storage: HashMap::new(),
+		}
+	}
+
+	// An additional destatis population file, for another year. The rows need the date of the count (Zeit), which decides the days the population applies to.
+	pub fn population_vintage<P: Into<PathBuf>>(mut self, path: P) -> Self {
+		self.destatisvintages.push(path.into());
+		self
+	}
+
+	// the DIVI register at hospital site level, used instead of the district file
+	pub fn divi_sites<P: Into<PathBuf>>(mut self, path: P) -> Self {
+		self.divisitefile = Some(path.into());
+		self
+	}
+
+	pub fn hosp_nowcast<S: Into<String>>(mut self, path: S) -> Self {
+		self.nowcastfile = Some(path.into());
+		self
+	}
+
+	// vaccinations by district of residence instead of vaccination site
+	pub fn vacc_residence<S: Into<String>>(mut self, path: S) -> Self {
+		self.vacc_residencefile = Some(path.into());
+		self
+	}
+
+	pub fn testing<P: Into<PathBuf>>(mut self, path: P) -> Self {
+		self.testingfile = Some(path.into());
+		self
+	}
+
+	pub fn icu_unavailable<P: Into<PathBuf>>(mut self, path: P) -> Self {
+		self.icu_unavailablefile = Some(path.into());
+		self
+	}
+
+	pub fn wastewater<P: Into<PathBuf>>(mut self, path: P) -> Self {
+		self.wastewaterfile = Some(path.into());
+		self
+	}
+
+	// the destatis weekly deaths by state and age band, which must reach back to the first of DEATHS_BASELINE_YEARS
+	pub fn weekly_deaths<P: Into<PathBuf>>(mut self, path: P) -> Self {
+		self.weeklydeathsfile = Some(path.into());
+		self
+	}
+
+	pub fn remap(mut self, remap: DistrictRemapping) -> Self {
+		self.remap = remap;
+		self
+	}
+
+	pub fn policy(mut self, policy: SubmitPolicy) -> Self {
+		self.policy = policy;
+		self
+	}
+
+	pub fn clamp(mut self, clamp: ClampWindows) -> Self {
+		self.clamp = clamp;
+		self
+	}
+
+	// Sums the cases and the demographic population over the sexes (Sex::All) on load, which shrinks the case keyspace to a third for deployments which do not need the sex dimension.
+	pub fn collapse_sex(mut self, collapse: bool) -> Self {
+		self.collapse_sex = collapse;
+		self
+	}
+
+	// the days to load; defaults to global_start_date() until today
+	pub fn range(mut self, start: NaiveDate, end: NaiveDate) -> Self {
+		self.start = start;
+		self.end = end;
+		self
+	}
+
+	// Only the days from `since` on are going to be written. The diff data is then loaded from DIFF_LEAD_DAYS before it, which covers the windows of the diff-based fields; everything else is loaded in full, as the cumulative fields need the entire history. Without effect with COVID_DIFF_KEY_GAPS, which needs the first diff record of each key.
+	pub fn since(mut self, since: NaiveDate) -> Self {
+		self.since = Some(since);
+		self
+	}
+
+	// The first day of the diff data to load.
+	pub fn diff_load_start(&self) -> NaiveDate {
+		match self.since {
+			Some(since) if !self.clamp.diff_key_gaps => self
+				.diffstart
+				.max(since - chrono::Duration::days(DIFF_LEAD_DAYS))
+				.min(self.end),
+			_ => self.diffstart,
+		}
+	}
+
+	// Keeps snapshots of the loaded case, diff, vaccination, ICU and hospitalization counters in `dir` and reuses them as long as the input files and load parameters do not change. Only the loading is skipped, the cooking is cheap in comparison.
+	pub fn snapshots<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+		self.snapshots = Some(SnapshotCache::new(dir));
+		self
+	}
+
+	// The storage of the counters of a dataset, by the name of its snapshot (cases, diff, vacc, vacc_residence, divi or hosp). Datasets are kept dense unless set otherwise; Storage::Compact trades some cooking time for memory.
+	pub fn storage<S: Into<String>>(mut self, dataset: S, storage: Storage) -> Self {
+		self.storage.insert(dataset.into(), storage);
+		self
+	}
+
+	pub fn district_remapping(&self) -> &DistrictRemapping {
+		&self.remap
+	}
+
+	// The number of loading phases, for RunLog::plan_phases.
+	pub fn load_phases(&self) -> usize {
+		6 + self.destatisvintages.len()
+			+ [
+				self.nowcastfile.is_some(),
+				self.vacc_residencefile.is_some(),
+				self.testingfile.is_some(),
+				self.icu_unavailablefile.is_some(),
+				self.wastewaterfile.is_some(),
+				self.weeklydeathsfile.is_some(),
+			]
+			.iter()
+			.filter(|x| **x)
+			.count()
+	}
+
+	// Loads and cooks all datasets. Berlin is injected into `districts` on the way, as the district data of the RKI requires.
+	pub fn load(
+		&self,
+		runs: &mut RunLog,
+		states: &HashMap<StateId, Arc<crate::StateInfo>>,
+		districts: &mut HashMap<DistrictId, Arc<crate::DistrictInfo>>,
+	) -> Result<Datasets, Error> {
+		let diffstart = self.diff_load_start();
+		for (from, to) in [(self.start, diffstart), (diffstart, self.end)] {
+			if from > to {
+				return Err(Error::DateRange {
+					start: from,
+					end: to,
+				});
+			}
+		}
+
+		info!("loading population data ...");
+		let mut population = RawPopulationData::<(StateId, DistrictId)>::new();
+		let vintage = population.vintage(RawPopulationData::<(StateId, DistrictId)>::ref_date());
+		for district in districts.values() {
+			vintage.insert((district.state.id, district.id), district.population);
+		}
+		let cooked_population = CookedPopulationData::cook(
+			population
+				.remapped(|(state_id, district_id)| Some((*state_id, remap_berlin(*district_id)))),
+		);
+
+		// We inject berlin only later. This allows us to rekey the population above to eliminate the separate berlin districts.
+		crate::inject_berlin(states, districts);
+
+		let mut destatis_population = RawPopulationData::new();
+		let started = Instant::now();
+		let stats = load_destatis_data(
+			&mut runs.begin_phase("loading destatis population data"),
+			&self.destatisfile,
+			&mut destatis_population,
+		)?;
+		runs.record("load_destatis", started, stats);
+		// further years, told apart by the date of their counts
+		for path in self.destatisvintages.iter() {
+			let started = Instant::now();
+			let stats = load_destatis_data(
+				&mut runs.begin_phase("loading destatis population data"),
+				path,
+				&mut destatis_population,
+			)?;
+			runs.record("load_destatis", started, stats);
+		}
+		info!(
+			"population vintages: {:?}",
+			destatis_population.vintages.keys().collect::<Vec<_>>()
+		);
+
+		// destatis has single ages (and an open-ended top group), each of which is within one band
+		let to_vacc = age_rekey(AgeScheme::Vaccination);
+		let cooked_vacc_population = CookedPopulationData::cook(
+			destatis_population.remapped(|(state_id, ag, _)| Some((*state_id, to_vacc(ag)?))),
+		);
+		let to_cases = age_rekey(AgeScheme::Cases);
+		let cooked_demo_population =
+			CookedPopulationData::cook(destatis_population.remapped(|(state_id, ag, sex)| {
+				let sex = if self.collapse_sex { Sex::All } else { *sex };
+				Some((*state_id, to_cases(ag)?, sex))
+			}));
+		drop(destatis_population);
+
+		let cases = self.load_cases(runs, districts)?;
+		let vacc = self.load_vacc(runs, districts, &self.vaccfile, "vacc")?;
+		let icu_load = self.load_divi(runs)?;
+		let hosp = self.load_hosp(runs)?;
+		let hosp_nowcast = match self.nowcastfile.as_ref() {
+			Some(nowcastfile) => Some(load_cooked_hosp_nowcast_data(runs, nowcastfile)?),
+			None => None,
+		};
+		let vacc_residence = match self.vacc_residencefile.as_ref() {
+			Some(vaccfile) => Some(self.load_vacc(runs, districts, vaccfile, "vacc_residence")?),
+			None => None,
+		};
+		let testing = match self.testingfile.as_ref() {
+			Some(testingfile) => Some(load_cooked_testing_data(
+				runs,
+				self.start,
+				self.end,
+				testingfile,
+			)?),
+			None => None,
+		};
+		let icu_unavailable = match self.icu_unavailablefile.as_ref() {
+			Some(unavailablefile) => Some(load_cooked_divi_unavailable_data(
+				runs,
+				self.start,
+				self.end,
+				unavailablefile,
+			)?),
+			None => None,
+		};
+		let wastewater = match self.wastewaterfile.as_ref() {
+			Some(wastewaterfile) => Some(load_cooked_wastewater_data(runs, wastewaterfile)?),
+			None => None,
+		};
+		let weekly_deaths = match self.weeklydeathsfile.as_ref() {
+			Some(deathsfile) => Some(load_cooked_weekly_deaths_data(runs, deathsfile)?),
+			None => None,
+		};
+		Ok(Datasets {
+			start: self.start,
+			end: self.end,
+			population: cooked_population,
+			population_vacc: cooked_vacc_population,
+			population_demo: cooked_demo_population,
+			cases,
+			vacc,
+			hosp,
+			icu_load,
+			hosp_nowcast,
+			vacc_residence,
+			testing,
+			icu_unavailable,
+			wastewater,
+			weekly_deaths,
+		})
+	}
+
+	// Loads the case and diff data and cooks them into the case measurements, as `load` does.
+	pub fn load_cases(
+		&self,
+		runs: &mut RunLog,
+		districts: &HashMap<DistrictId, Arc<crate::DistrictInfo>>,
+	) -> Result<CookedCaseData<FullCaseKey>, Error> {
+		let (casefile, difffile) = (&self.casefile, &self.difffile);
+		let (start, diffstart, end) = (self.start, self.diff_load_start(), self.end);
+		let (remap, policy) = (&self.remap, self.policy);
+		let mut cases = load_snapshotted(
+			runs,
+			self.snapshots.as_ref(),
+			"cases",
+			|h| {
+				h.file(casefile)?;
+				hash_load_context(h, districts, remap, policy);
+				h.param(&(start, end));
+				Ok(())
+			},
+			|runs| {
+				let mut cases = RawCaseData::new(start, end);
+				let started = Instant::now();
+				let stats = load_case_data(
+					&mut runs.begin_phase("loading case data"),
+					casefile,
+					districts,
+					remap,
+					policy,
+					&mut cases,
+				)?;
+				runs.record("load_cases", started, stats);
+				Ok(cases.remapped(|(state_id, district_id, mag, sex)| {
+					Some((*state_id, remap_berlin(*district_id), *mag, *sex))
+				}))
+			},
+		)?;
+
+		let mut diff_cases = load_snapshotted(
+			runs,
+			self.snapshots.as_ref(),
+			"diff",
+			|h| {
+				h.file(difffile)?;
+				hash_load_context(h, districts, remap, policy);
+				h.param(&(self.diffstart, diffstart, end));
+				Ok(())
+			},
+			|runs| {
+				let mut diff_cases = ParboiledCaseData::new(diffstart, end);
+				let started = Instant::now();
+				let stats = load_diff_data(
+					&mut runs.begin_phase("loading diff data"),
+					difffile,
+					districts,
+					remap,
+					policy,
+					self.diffstart,
+					&mut diff_cases,
+				)?;
+				runs.record("load_diff", started, stats);
+				Ok(diff_cases.remapped(|(state_id, district_id, mag, sex)| {
+					Some((*state_id, remap_berlin(*district_id), *mag, *sex))
+				}))
+			},
+		)?;
+
+		// after the snapshots, which hold all sexes either way
+		if self.collapse_sex {
+			cases = cases.remapped(|(state_id, district_id, mag, _)| {
+				Some((*state_id, *district_id, *mag, Sex::All))
+			});
+			diff_cases = diff_cases.remapped(|(state_id, district_id, mag, _)| {
+				Some((*state_id, *district_id, *mag, Sex::All))
+			});
+		}
+		apply_storage(&self.storage, "cases", &mut cases);
+		apply_storage(&self.storage, "diff", &mut diff_cases);
+
+		info!("crunching case data...");
+		let cooked_cases = CookedCaseData::cook(cases, diff_cases, diffstart, &self.clamp);
+
+		Ok(cooked_cases)
+	}
+
+	// `name` is the name of the dataset in the run log and the snapshots
+	fn load_vacc(
+		&self,
+		runs: &mut RunLog,
+		districts: &HashMap<DistrictId, Arc<crate::DistrictInfo>>,
+		vaccfile: &str,
+		name: &str,
+	) -> Result<CookedVaccinationData<VaccinationKey>, Error> {
+		let (remap, policy, start, end) = (&self.remap, self.policy, self.start, self.end);
+		let mut vacc = load_snapshotted(
+			runs,
+			self.snapshots.as_ref(),
+			name,
+			|h| {
+				h.file(vaccfile)?;
+				hash_load_context(h, districts, remap, policy);
+				h.param(&(start, end));
+				Ok(())
+			},
+			|runs| {
+				let mut vacc = RawVaccinationData::new(start, end);
+				let started = Instant::now();
+				let stats = load_vacc_data(
+					&mut runs.begin_phase("loading vaccination data"),
+					vaccfile,
+					districts,
+					remap,
+					policy,
+					&mut vacc,
+				)?;
+				runs.record(&format!("load_{}", name), started, stats);
+				Ok(vacc.remapped(|(state_id, district_id, ag)| {
+					Some((*state_id, district_id.map(remap_berlin), *ag))
+				}))
+			},
+		)?;
+		apply_storage(&self.storage, name, &mut vacc);
+		Ok(CookedVaccinationData::cook(vacc))
+	}
+
+	fn load_divi(&self, runs: &mut RunLog) -> Result<CookedICULoadData<GeoCaseKey>, Error> {
+		let (divifile, sitefile) = (&self.divifile, self.divisitefile.as_deref());
+		let (remap, start, end) = (&self.remap, self.start, self.end);
+		let mut icu_load = load_snapshotted(
+			runs,
+			self.snapshots.as_ref(),
+			"divi",
+			|h| {
+				match sitefile {
+					Some(sitefile) => h.file(sitefile)?,
+					None => h.file(divifile)?,
+				}
+				h.param(remap);
+				h.param(&(start, end));
+				Ok(())
+			},
+			|runs| {
+				let mut icu_load = RawICULoadData::new(start, end);
+				let started = Instant::now();
+				// the site level register, if given, replaces the district file
+				let stats = match sitefile {
+					Some(sitefile) => load_divi_site_load_data(
+						&mut runs.begin_phase("loading ICU data"),
+						sitefile,
+						remap,
+						&mut icu_load,
+					)?,
+					None => load_divi_load_data(
+						&mut runs.begin_phase("loading ICU data"),
+						divifile,
+						remap,
+						&mut icu_load,
+					)?,
+				};
+				runs.record("load_divi", started, stats);
+				Ok(icu_load.rekeyed(|(state_id, district_id)| {
+					Some((*state_id, remap_berlin(*district_id)))
+				}))
+			},
+		)?;
+		apply_storage(&self.storage, "divi", &mut icu_load);
+		Ok(CookedICULoadData::cook(icu_load))
+	}
+
+	fn load_hosp(
+		&self,
+		runs: &mut RunLog,
+	) -> Result<CookedHospitalizationData<(StateId, AgeGroup)>, Error> {
+		let (hospfile, start, end) = (&self.hospfile, self.start, self.end);
+		let mut hosp = load_snapshotted(
+			runs,
+			self.snapshots.as_ref(),
+			"hosp",
+			|h| {
+				h.file(hospfile)?;
+				h.param(&(start, end));
+				Ok(())
+			},
+			|runs| {
+				let mut hosp = RawHospitalizationData::new(start, end);
+				let started = Instant::now();
+				let stats = load_hosp_data(
+					&mut runs.begin_phase("loading hospitalization data"),
+					hospfile,
+					&mut hosp,
+				)?;
+				runs.record("load_hosp", started, stats);
+				Ok(hosp)
+			},
+		)?;
+		apply_storage(&self.storage, "hosp", &mut hosp);
+		let cooked_hosp = CookedHospitalizationData::cook(hosp, self.clamp.hosp);
+
+		Ok(cooked_hosp)
+	}
+}
@@ -0,0 +1,1331 @@
+// The driver behind the to_influx and to_csv binaries: the command line and environment options, the load and the streaming of each measurement.
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::sync::Arc;
+use std::time::Instant;
+
+use chrono::{NaiveDate, TimeZone, Utc};
+
+use log::{error, info, warn};
+
+use super::{
+	district_areas, is_all_ages, merge_national, resampled, restrict_keys,
+	write_centroid_field_descriptors, ClampWindows, CookedCaseData, CookedHospitalizationData,
+	CookedICUAgeEstimate, CookedICULoadData, CookedICUUnavailableData, CookedPopulationData,
+	CookedTestingData, CookedVaccinationData, CookedVaccinationIntervals, CookedWastewaterData,
+	CookedWeeklyDeathsData, Datasets, Field, PipelineBuilder, NATIONAL_ID, NATIONAL_NAME,
+};
+use crate::{
+	influxdb, AgeGroup, AgeScheme, Bucket, DistrictAdjacency, DistrictCentroids, DistrictId,
+	DistrictInfo, DistrictRemapping, Error, FullCaseKey, GeoCaseKey, KeySet, Result, RunLog, Sex,
+	StateId, StateInfo, Storage, StreamOptions, VaccinationKey,
+};
+
+static GEO_MEASUREMENT_NAME: &str = "data_v2_geo";
+static GEO_LIGHT_MEASUREMENT_NAME: &str = "data_v2_geo_light";
+static GEO_WEEKLY_MEASUREMENT_NAME: &str = "data_v2_geo_weekly";
+static GEO_LIGHT_WEEKLY_MEASUREMENT_NAME: &str = "data_v2_geo_light_weekly";
+static DEMO_MEASUREMENT_NAME: &str = "data_v2_demo";
+static VACC_MEASUREMENT_NAME: &str = "data_v2_vacc";
+static VACC_RESIDENCE_MEASUREMENT_NAME: &str = "data_v2_vacc_residence";
+static VACC_INTERVAL_MEASUREMENT_NAME: &str = "data_v2_vacc_interval";
+static TESTING_MEASUREMENT_NAME: &str = "data_v2_testing";
+static ICU_UNAVAILABLE_MEASUREMENT_NAME: &str = "data_v2_icu_unavailable";
+static WASTEWATER_MEASUREMENT_NAME: &str = "data_v2_wastewater";
+static WEEKLY_DEATHS_MEASUREMENT_NAME: &str = "data_v2_deaths_weekly";
+static ICU_AGE_ESTIMATE_MEASUREMENT_NAME: &str = "data_v2_icu_age_estimate";
+static AGE_MEASUREMENT_NAME: &str = "data_v2_age";
+static HOSP_MEASUREMENT_NAME: &str = "data_v2_hosp";
+static QUALITY_MEASUREMENT_NAME: &str = "data_v2_quality";
+// static DEMO_LIGHT_MEASUREMENT_NAME: &str = "data_v2_demo_light";
+
+fn parse_date(what: &str, v: &str) -> Result<NaiveDate> {
+	v.parse()
+		.map_err(|e| Error::Schema(format!("invalid date {:?} for {}: {}", v, what, e)))
+}
+
+// An optional switch in the environment, 0 or 1; unset is 0.
+fn env_switch(name: &str) -> Result<bool> {
+	match std::env::var(name) {
+		Ok(v) => Ok(v
+			.parse::<u32>()
+			.map_err(|e| Error::Schema(format!("invalid {}: {}", name, e)))?
+			!= 0),
+		Err(_) => Ok(false),
+	}
+}
+
+// Removes `flag VALUE` from argv and returns VALUE, if the flag is present.
+pub fn take_flag(argv: &mut Vec<String>, flag: &str) -> Result<Option<String>> {
+	match argv.iter().position(|arg| arg == flag) {
+		Some(i) => {
+			if i + 1 >= argv.len() {
+				return Err(Error::Schema(format!("{} requires a value", flag)));
+			}
+			let value = argv.remove(i + 1);
+			argv.remove(i);
+			Ok(Some(value))
+		}
+		None => Ok(None),
+	}
+}
+
+// Removes all occurrences of a switch without value from argv and returns whether it was present.
+pub fn take_switch(argv: &mut Vec<String>, switch: &str) -> bool {
+	let before = argv.len();
+	argv.retain(|arg| arg != switch);
+	argv.len() != before
+}
+
+// Reads back the latest point of each measurement this run streamed and checks that it is not older than the last day written.
+fn verify_written(client: &influxdb::Client, runs: &RunLog, options: &StreamOptions) -> Result<()> {
+	let mut failed = Vec::new();
+	for (phase, stats) in runs.phases() {
+		let (measurement, last_day) = match (phase.strip_prefix("stream_"), stats.last_day) {
+			(Some(measurement), Some(last_day)) => {
+				(options.measurement_name(measurement), last_day)
+			}
+			_ => continue,
+		};
+		let expected = Utc.from_utc_datetime(&last_day.and_hms_opt(0, 0, 0).unwrap());
+		match client.last_timestamp(
+			&options.database,
+			options.retention_policy.as_deref(),
+			measurement,
+		)? {
+			Some(ts) if ts >= expected => info!("{}: verified up to {}", measurement, ts),
+			found => {
+				error!(
+					"{}: expected a point at {}, the latest is {:?}",
+					measurement, expected, found
+				);
+				failed.push(measurement.to_string());
+			}
+		}
+	}
+	if failed.is_empty() {
+		Ok(())
+	} else {
+		Err(Error::Schema(format!(
+			"verification failed for {}",
+			failed.join(", ")
+		)))
+	}
+}
+
+// With COVID_ID_TAGS, the keysets get the official ids (AGS) next to the names, as state_id and district_id tags.
+fn with_state_id<'x, K: crate::TimeSeriesKey, F: Fn(&K) -> StateId>(
+	keys: KeySet<'x, K>,
+	id_tags: bool,
+	state_id: F,
+) -> Result<KeySet<'x, K>> {
+	if !id_tags {
+		return Ok(keys);
+	}
+	keys.extended(&["state_id"][..], |k, out| {
+		out.push(format!("{:02}", state_id(k)).into());
+	})
+}
+
+fn with_district_id<'x, K: crate::TimeSeriesKey, F: Fn(&K) -> (StateId, DistrictId)>(
+	keys: KeySet<'x, K>,
+	id_tags: bool,
+	ids: F,
+) -> Result<KeySet<'x, K>> {
+	if !id_tags {
+		return Ok(keys);
+	}
+	keys.extended(&["state_id", "district_id"][..], |k, out| {
+		let (state_id, district_id) = ids(k);
+		out.push(format!("{:02}", state_id).into());
+		out.push(format!("{:05}", district_id).into());
+	})
+}
+
+// What the measurements share besides their data: the days to stream, the stream options and the run-wide settings from the environment.
+struct StepOptions<'x> {
+	start: NaiveDate,
+	ndays: usize,
+	stream: &'x StreamOptions,
+	states: &'x HashMap<StateId, Arc<StateInfo>>,
+	districts: &'x HashMap<DistrictId, Arc<DistrictInfo>>,
+	// COVID_ID_TAGS and COVID_NATIONAL_AGGREGATE
+	id_tags: bool,
+	national: bool,
+	weekly_rollup: bool,
+	waning: &'x crate::WaningModel,
+	age_bands: &'x crate::AgeBandConfig,
+	adjacency: Option<&'x DistrictAdjacency>,
+	centroids: Option<&'x Arc<DistrictCentroids>>,
+}
+
+impl<'x> StepOptions<'x> {
+	fn is_enabled(&self, measurement: &str) -> bool {
+		self.stream.is_enabled(measurement)
+	}
+
+	// Keys by state and district, tagged with the names (and the ids, with COVID_ID_TAGS).
+	fn district_keys<'k>(&self, key_list: &'k [GeoCaseKey]) -> Result<KeySet<'k, GeoCaseKey>> {
+		let keys = KeySet::new(&["state", "district"][..], key_list.iter(), |k, out| {
+			let state_id = k.0;
+			let district_id = k.1;
+			let state_name = &self.states.get(&state_id).unwrap().name;
+			let district_name = match self.districts.get(&district_id) {
+				Some(i) => &i.name,
+				None => panic!("failed to find district {} in data", district_id),
+			};
+			out.push(state_name.into());
+			out.push(district_name.into());
+		})?;
+		with_district_id(keys, self.id_tags, |k| (k.0, k.1))
+	}
+
+	fn state_keys<'k>(&self, key_list: &'k [StateId]) -> Result<KeySet<'k, StateId>> {
+		let keys = KeySet::new(&["state"][..], key_list.iter(), |k, out| {
+			let state_name = &self.states.get(k).unwrap().name;
+			out.push(state_name.into());
+		})?;
+		with_state_id(keys, self.id_tags, |k| *k)
+	}
+
+	fn state_age_keys<'k>(
+		&self,
+		key_list: &'k [(StateId, AgeGroup)],
+	) -> Result<KeySet<'k, (StateId, AgeGroup)>> {
+		let keys = KeySet::new(&["state", "age"][..], key_list.iter(), |k, out| {
+			let state_name = &self.states.get(&k.0).unwrap().name;
+			out.push(state_name.into());
+			out.push(k.1.to_string().into());
+		})?;
+		with_state_id(keys, self.id_tags, |k| k.0)
+	}
+}
+
+// Streams one measurement and records it in the run log as stream_<name>.
+fn stream_measurement<K: crate::TimeSeriesKey>(
+	output: &mut crate::Output,
+	runs: &mut RunLog,
+	name: &str,
+	keys: &KeySet<K>,
+	fields: &[Field<K>],
+	options: &StepOptions,
+) -> Result<()> {
+	let started = Instant::now();
+	let stats = output.stream_dynamic(
+		&mut runs.begin_phase(&format!("streaming {}", name)),
+		name,
+		options.start,
+		options.ndays,
+		keys,
+		fields,
+		options.stream,
+	)?;
+	runs.record(&format!("stream_{}", name), started, stats);
+	Ok(())
+}
+
+// Adds the national aggregate (state "Deutschland") as a state and as a district of its own.
+fn insert_national(
+	states: &mut HashMap<StateId, Arc<StateInfo>>,
+	districts: &mut HashMap<DistrictId, Arc<DistrictInfo>>,
+) {
+	let state = Arc::new(StateInfo {
+		id: NATIONAL_ID,
+		name: NATIONAL_NAME.into(),
+	});
+	// the Berlin districts are already counted in the injected SK Berlin
+	let population = districts
+		.values()
+		.filter(|d| !(d.id > 11000 && d.id < 12000))
+		.map(|d| d.population)
+		.sum();
+	let area = districts
+		.values()
+		.filter(|d| !(d.id > 11000 && d.id < 12000))
+		.map(|d| d.area)
+		.sum();
+	districts.insert(
+		NATIONAL_ID,
+		Arc::new(DistrictInfo {
+			id: NATIONAL_ID,
+			name: NATIONAL_NAME.into(),
+			state: state.clone(),
+			population,
+			area,
+		}),
+	);
+	states.insert(NATIONAL_ID, state);
+}
+
+// Cases, vaccinations, ICU load and population by district, daily and as weekly rollup.
+fn stream_geo(
+	output: &mut crate::Output,
+	runs: &mut RunLog,
+	options: &StepOptions,
+	data: &Datasets,
+) -> Result<()> {
+	// measurements can be disabled in the stream options; skip the preparation, too
+	let weekly = options.weekly_rollup && options.is_enabled(GEO_WEEKLY_MEASUREMENT_NAME);
+	if !options.is_enabled(GEO_MEASUREMENT_NAME) && !weekly {
+		return Ok(());
+	}
+	info!("preparing {} ...", GEO_MEASUREMENT_NAME);
+
+	let areas = district_areas(options.districts);
+	let geo_fields = |cases: &CookedCaseData<GeoCaseKey>,
+	                  vacc: &CookedVaccinationData<GeoCaseKey>,
+	                  icu_load: &CookedICULoadData<GeoCaseKey>,
+	                  population: &CookedPopulationData<GeoCaseKey>| {
+		let mut fields = Vec::new();
+		cases.write_field_descriptors(&mut fields);
+		cases.write_incidence_field_descriptors(population, &mut fields);
+		cases.write_r_value_field_descriptors(&mut fields);
+		if let Some(adjacency) = options.adjacency {
+			cases.write_region_incidence_field_descriptors(population, adjacency, &mut fields);
+		}
+		vacc.write_field_descriptors(&mut fields);
+		icu_load.write_field_descriptors(&mut fields);
+		population.write_field_descriptors(&mut fields);
+		population.write_density_field_descriptors(areas.clone(), &mut fields);
+		if let Some(centroids) = options.centroids {
+			write_centroid_field_descriptors(centroids, &mut fields);
+		}
+		fields
+	};
+
+	let mut fields = geo_fields(
+		&data
+			.cases
+			.rekeyed(|(state_id, district_id, _, _)| Some((*state_id, *district_id))),
+		&data.vacc.rekeyed(|(state_id, district_id, _)| {
+			// drop vaccinations without properly defined state + district
+			match (state_id, district_id) {
+				(Some(state_id), Some(district_id)) => Some((*state_id, *district_id)),
+				_ => None,
+			}
+		}),
+		&data.icu_load,
+		&data.population,
+	);
+	let mut key_list: Vec<GeoCaseKey> = data.population.count.keys().cloned().collect();
+	if options.national {
+		let national_fields = geo_fields(
+			&data.cases.rekeyed(|_| Some((NATIONAL_ID, NATIONAL_ID))),
+			&data.vacc.rekeyed(|_| Some((NATIONAL_ID, NATIONAL_ID))),
+			&data.icu_load.rekeyed(|_| Some((NATIONAL_ID, NATIONAL_ID))),
+			&data
+				.population
+				.rekeyed(|_| Some((NATIONAL_ID, NATIONAL_ID))),
+		);
+		fields = merge_national(fields, national_fields, |k| k.0 == NATIONAL_ID)?;
+		key_list.push((NATIONAL_ID, NATIONAL_ID));
+	}
+	let keys = options.district_keys(&key_list)?;
+
+	if options.is_enabled(GEO_MEASUREMENT_NAME) {
+		stream_measurement(output, runs, GEO_MEASUREMENT_NAME, &keys, &fields, options)?;
+	}
+	if weekly {
+		let fields = resampled(&fields, Bucket::Week);
+		stream_measurement(
+			output,
+			runs,
+			GEO_WEEKLY_MEASUREMENT_NAME,
+			&keys,
+			&fields,
+			options,
+		)?;
+	}
+	Ok(())
+}
+
+// The state level counterpart of stream_geo, with the hospitalizations and the nowcast, daily and as weekly rollup.
+fn stream_geo_light(
+	output: &mut crate::Output,
+	runs: &mut RunLog,
+	options: &StepOptions,
+	data: &Datasets,
+) -> Result<()> {
+	let weekly = options.weekly_rollup && options.is_enabled(GEO_LIGHT_WEEKLY_MEASUREMENT_NAME);
+	if !options.is_enabled(GEO_LIGHT_MEASUREMENT_NAME) && !weekly {
+		return Ok(());
+	}
+	info!("preparing {} ...", GEO_LIGHT_MEASUREMENT_NAME);
+
+	let light_fields =
+		|cases: &CookedCaseData<StateId>,
+		 vacc: &CookedVaccinationData<StateId>,
+		 icu_load: &CookedICULoadData<StateId>,
+		 hosp: &CookedHospitalizationData<(StateId, AgeGroup)>,
+		 population: &CookedPopulationData<StateId>,
+		 population_age: &CookedPopulationData<(StateId, AgeGroup)>| {
+			let mut fields = Vec::new();
+			cases.write_field_descriptors(&mut fields);
+			cases.write_incidence_field_descriptors(population, &mut fields);
+			cases.write_r_value_field_descriptors(&mut fields);
+			vacc.write_field_descriptors(&mut fields);
+			icu_load.write_field_descriptors(&mut fields);
+			// only the 00+ group, the age groups would count everything twice
+			let hosp_total = hosp.rekeyed(|(state_id, ag)| {
+				if is_all_ages(ag) {
+					Some(*state_id)
+				} else {
+					None
+				}
+			});
+			hosp_total.write_field_descriptors(&mut fields);
+			hosp_total.write_incidence_field_descriptors(population, &mut fields);
+			hosp.write_age_band_incidence_field_descriptors(population_age, &mut fields);
+			// keyed by state already; the RKI publishes the national nowcast under NATIONAL_ID
+			if let Some(hosp_nowcast) = data.hosp_nowcast.as_ref() {
+				hosp_nowcast.write_field_descriptors(&mut fields);
+			}
+			population.write_field_descriptors(&mut fields);
+			fields
+		};
+
+	let population_light = data.population.rekeyed(|(state_id, _)| Some(*state_id));
+	let mut fields = light_fields(
+		&data.cases.rekeyed(|(state_id, _, _, _)| Some(*state_id)),
+		&data.vacc.rekeyed(|(state_id, district_id, _)| {
+			// drop vaccinations without properly defined state + district
+			match (state_id, district_id) {
+				(Some(state_id), Some(_)) => Some(*state_id),
+				_ => None,
+			}
+		}),
+		&data.icu_load.rekeyed(|(state_id, _)| Some(*state_id)),
+		&data.hosp,
+		&population_light,
+		&data
+			.population_demo
+			.rekeyed(|(state_id, ag, _)| Some((*state_id, *ag))),
+	);
+	let mut key_list: Vec<StateId> = population_light.count.keys().cloned().collect();
+	if options.national {
+		let national_fields = light_fields(
+			&data.cases.rekeyed(|_| Some(NATIONAL_ID)),
+			&data.vacc.rekeyed(|_| Some(NATIONAL_ID)),
+			&data.icu_load.rekeyed(|_| Some(NATIONAL_ID)),
+			&data.hosp.rekeyed(|(_, ag)| Some((NATIONAL_ID, *ag))),
+			&data.population.rekeyed(|_| Some(NATIONAL_ID)),
+			&data
+				.population_demo
+				.rekeyed(|(_, ag, _)| Some((NATIONAL_ID, *ag))),
+		);
+		fields = merge_national(fields, national_fields, |k| *k == NATIONAL_ID)?;
+		key_list.push(NATIONAL_ID);
+	}
+	let keys = options.state_keys(&key_list)?;
+
+	if options.is_enabled(GEO_LIGHT_MEASUREMENT_NAME) {
+		stream_measurement(
+			output,
+			runs,
+			GEO_LIGHT_MEASUREMENT_NAME,
+			&keys,
+			&fields,
+			options,
+		)?;
+	}
+	if weekly {
+		let fields = resampled(&fields, Bucket::Week);
+		stream_measurement(
+			output,
+			runs,
+			GEO_LIGHT_WEEKLY_MEASUREMENT_NAME,
+			&keys,
+			&fields,
+			options,
+		)?;
+	}
+	Ok(())
+}
+
+// Delay and completeness of the case reports by state.
+fn stream_quality(
+	output: &mut crate::Output,
+	runs: &mut RunLog,
+	options: &StepOptions,
+	cases: &CookedCaseData<FullCaseKey>,
+) -> Result<()> {
+	if !options.is_enabled(QUALITY_MEASUREMENT_NAME) {
+		return Ok(());
+	}
+	info!("preparing {} ...", QUALITY_MEASUREMENT_NAME);
+
+	let state_cases = cases.rekeyed(|(state_id, _, _, _)| Some(*state_id));
+	let mut fields = Vec::new();
+	state_cases.write_data_quality_field_descriptors(&mut fields);
+	let mut key_list: Vec<StateId> = state_cases.cases_by_pub.cum.keys().cloned().collect();
+	key_list.sort();
+	if options.national {
+		let mut national_fields = Vec::new();
+		cases
+			.rekeyed(|_| Some(NATIONAL_ID))
+			.write_data_quality_field_descriptors(&mut national_fields);
+		fields = merge_national(fields, national_fields, |k| *k == NATIONAL_ID)?;
+		key_list.push(NATIONAL_ID);
+	}
+	let keys = options.state_keys(&key_list)?;
+
+	stream_measurement(
+		output,
+		runs,
+		QUALITY_MEASUREMENT_NAME,
+		&keys,
+		&fields,
+		options,
+	)
+}
+
+// The ICU load by state, split up into age groups along the hospitalizations.
+fn stream_icu_age_estimate(
+	output: &mut crate::Output,
+	runs: &mut RunLog,
+	options: &StepOptions,
+	data: &Datasets,
+) -> Result<()> {
+	if !options.is_enabled(ICU_AGE_ESTIMATE_MEASUREMENT_NAME) {
+		return Ok(());
+	}
+	info!("preparing {} ...", ICU_AGE_ESTIMATE_MEASUREMENT_NAME);
+
+	let estimate = CookedICUAgeEstimate::estimate(
+		&data.icu_load.rekeyed(|(state_id, _)| Some(*state_id)),
+		&data.hosp,
+	);
+	let mut fields = Vec::new();
+	estimate.write_field_descriptors(&mut fields);
+	let mut key_list = estimate.keys.clone();
+	if options.national {
+		let national_estimate = CookedICUAgeEstimate::estimate(
+			&data.icu_load.rekeyed(|_| Some(NATIONAL_ID)),
+			&data.hosp.rekeyed(|(_, ag)| Some((NATIONAL_ID, *ag))),
+		);
+		let mut national_fields = Vec::new();
+		national_estimate.write_field_descriptors(&mut national_fields);
+		fields = merge_national(fields, national_fields, |k| k.0 == NATIONAL_ID)?;
+		key_list.extend(national_estimate.keys.iter().cloned());
+	}
+	let keys = options.state_age_keys(&key_list)?;
+
+	stream_measurement(
+		output,
+		runs,
+		ICU_AGE_ESTIMATE_MEASUREMENT_NAME,
+		&keys,
+		&fields,
+		options,
+	)
+}
+
+// Cases, hospitalizations and vaccinations by age group only.
+fn stream_age(
+	output: &mut crate::Output,
+	runs: &mut RunLog,
+	options: &StepOptions,
+	data: &Datasets,
+) -> Result<()> {
+	if !options.is_enabled(AGE_MEASUREMENT_NAME) {
+		return Ok(());
+	}
+	info!("preparing {} ...", AGE_MEASUREMENT_NAME);
+
+	// cases and hospitalizations share the age groups, vaccinations have their own; the keyset is the union of both
+	let case_population = data.population_demo.rekeyed(|(_, ag, _)| Some(*ag));
+	let vacc_population = data.population_vacc.rekeyed(|(_, ag)| Some(*ag));
+	let age_population = case_population.merged(&vacc_population);
+
+	let mut case_fields = Vec::new();
+	let age_cases = data.cases.rekeyed(|(_, _, ag, _)| **ag);
+	age_cases.write_field_descriptors(&mut case_fields);
+	age_cases.write_incidence_field_descriptors(&case_population, &mut case_fields);
+	// the sum over all ages (00+) is not an age group of its own and spans all bands, so the rekey drops it
+	let to_hosp = crate::age_rekey(AgeScheme::Hospitalization);
+	let age_hosp = data.hosp.rekeyed(|(_, ag)| to_hosp(ag));
+	age_hosp.write_field_descriptors(&mut case_fields);
+	age_hosp.write_incidence_field_descriptors(&case_population, &mut case_fields);
+	let mut vacc_fields = Vec::new();
+	data.vacc
+		.rekeyed(|(_, _, ag)| **ag)
+		.write_field_descriptors(&mut vacc_fields);
+
+	let mut fields = restrict_keys(case_fields, case_population.count.keys());
+	fields.append(&mut restrict_keys(
+		vacc_fields,
+		vacc_population.count.keys(),
+	));
+	age_population.write_field_descriptors(&mut fields);
+	let keys = KeySet::new(&["age"][..], age_population.count.keys(), |k, out| {
+		out.push(k.to_string().into());
+	})?;
+
+	stream_measurement(output, runs, AGE_MEASUREMENT_NAME, &keys, &fields, options)
+}
+
+// Hospitalizations by state and age band, including the 00+ total.
+fn stream_hosp(
+	output: &mut crate::Output,
+	runs: &mut RunLog,
+	options: &StepOptions,
+	data: &Datasets,
+) -> Result<()> {
+	if !options.is_enabled(HOSP_MEASUREMENT_NAME) {
+		return Ok(());
+	}
+	info!("preparing {} ...", HOSP_MEASUREMENT_NAME);
+
+	let hosp_fields = |hosp: &CookedHospitalizationData<(StateId, AgeGroup)>,
+	                   population: &CookedPopulationData<(StateId, AgeGroup)>| {
+		let mut fields = Vec::new();
+		hosp.write_field_descriptors(&mut fields);
+		hosp.write_incidence_field_descriptors(population, &mut fields);
+		population.write_field_descriptors(&mut fields);
+		fields
+	};
+
+	// the 00+ group is the total, which stays as it is
+	let banded = options.age_bands.rekey(HOSP_MEASUREMENT_NAME);
+	let to_band = |ag: &AgeGroup| {
+		if is_all_ages(ag) {
+			Some(*ag)
+		} else {
+			banded(ag)
+		}
+	};
+	let hosp = data
+		.hosp
+		.rekeyed(|(state_id, ag)| Some((*state_id, to_band(ag)?)));
+	// the population of the 00+ group is the sum over all age groups, as in the hospitalization file itself
+	let hosp_population = data
+		.population_demo
+		.rekeyed(|(state_id, ag, _)| Some((*state_id, to_band(ag)?)))
+		.merged(
+			&data
+				.population
+				.rekeyed(|(state_id, _)| Some((*state_id, AgeGroup { low: 0, high: None }))),
+		);
+	let mut fields = hosp_fields(&hosp, &hosp_population);
+	let mut key_list: Vec<_> = hosp_population.count.keys().cloned().collect();
+	if options.national {
+		let national_population = hosp_population.rekeyed(|(_, ag)| Some((NATIONAL_ID, *ag)));
+		let national_fields = hosp_fields(
+			&hosp.rekeyed(|(_, ag)| Some((NATIONAL_ID, *ag))),
+			&national_population,
+		);
+		fields = merge_national(fields, national_fields, |k| k.0 == NATIONAL_ID)?;
+		key_list.extend(national_population.count.keys().cloned());
+	}
+	let keys = options.state_age_keys(&key_list)?;
+
+	stream_measurement(output, runs, HOSP_MEASUREMENT_NAME, &keys, &fields, options)
+}
+
+// One of the user-defined aggregates of COVID_AGGREGATES.
+fn stream_aggregate(
+	output: &mut crate::Output,
+	runs: &mut RunLog,
+	options: &StepOptions,
+	spec: &crate::AggregateSpec,
+	cases: &CookedCaseData<FullCaseKey>,
+) -> Result<()> {
+	if !options.is_enabled(&spec.name) {
+		return Ok(());
+	}
+	info!("preparing {} ...", spec.name);
+
+	let cases = cases.rekeyed(|k| spec.rekey(k, options.states, options.districts));
+	let keys = KeySet::new(
+		&spec.tag_names()[..],
+		cases.cases_by_ref.cum.keys(),
+		|k, out| {
+			out.extend(k.iter().cloned());
+		},
+	)?;
+
+	let mut fields = Vec::new();
+	cases.write_field_descriptors(&mut fields);
+
+	stream_measurement(output, runs, &spec.name, &keys, &fields, options)
+}
+
+// Cases by state, age band and sex.
+fn stream_demo(
+	output: &mut crate::Output,
+	runs: &mut RunLog,
+	options: &StepOptions,
+	cases: &CookedCaseData<FullCaseKey>,
+	population_demo: &CookedPopulationData<(StateId, AgeGroup, Sex)>,
+) -> Result<()> {
+	if !options.is_enabled(DEMO_MEASUREMENT_NAME) {
+		return Ok(());
+	}
+	info!("preparing {} ...", DEMO_MEASUREMENT_NAME);
+
+	let demo_fields =
+		|cases: &CookedCaseData<(StateId, AgeGroup, Sex)>,
+		 population: &CookedPopulationData<(StateId, AgeGroup, Sex)>| {
+			let mut fields = Vec::new();
+			cases.write_field_descriptors(&mut fields);
+			cases.write_incidence_field_descriptors(population, &mut fields);
+			population.write_field_descriptors(&mut fields);
+			fields
+		};
+
+	let to_band = options.age_bands.rekey(DEMO_MEASUREMENT_NAME);
+	let population_demo =
+		population_demo.rekeyed(|(state_id, ag, s)| Some((*state_id, to_band(ag)?, *s)));
+	let mut fields = demo_fields(
+		&cases.rekeyed(|(state_id, _, ag, s)| Some((*state_id, to_band(&(**ag)?)?, *s))),
+		&population_demo,
+	);
+	let mut key_list: Vec<_> = population_demo.count.keys().cloned().collect();
+	if options.national {
+		let national_population =
+			population_demo.rekeyed(|(_, ag, s)| Some((NATIONAL_ID, *ag, *s)));
+		let national_fields = demo_fields(
+			&cases.rekeyed(|(_, _, ag, s)| Some((NATIONAL_ID, to_band(&(**ag)?)?, *s))),
+			&national_population,
+		);
+		fields = merge_national(fields, national_fields, |k| k.0 == NATIONAL_ID)?;
+		key_list.extend(national_population.count.keys().cloned());
+	}
+	let keys = KeySet::new(&["state", "age", "sex"][..], key_list.iter(), |k, out| {
+		let state_id = k.0;
+		let state_name = &options.states.get(&state_id).unwrap().name;
+		out.push(state_name.into());
+		out.push(k.1.to_string().into());
+		out.push(k.2.to_string().into());
+	})?;
+	let keys = with_state_id(keys, options.id_tags, |k| k.0)?;
+
+	stream_measurement(output, runs, DEMO_MEASUREMENT_NAME, &keys, &fields, options)
+}
+
+// Vaccinations by state and age band, with the waning immunity.
+fn stream_vacc(
+	output: &mut crate::Output,
+	runs: &mut RunLog,
+	options: &StepOptions,
+	vacc: &CookedVaccinationData<VaccinationKey>,
+	population_vacc: &CookedPopulationData<(StateId, AgeGroup)>,
+) -> Result<()> {
+	if !options.is_enabled(VACC_MEASUREMENT_NAME) {
+		return Ok(());
+	}
+	info!("preparing {} ...", VACC_MEASUREMENT_NAME);
+
+	let vacc_fields = |vacc: &CookedVaccinationData<(StateId, AgeGroup)>,
+	                   population: &CookedPopulationData<(StateId, AgeGroup)>| {
+		let mut fields = Vec::new();
+		vacc.write_field_descriptors(&mut fields);
+		vacc.write_waning_field_descriptors(options.waning, population, &mut fields);
+		population.write_field_descriptors(&mut fields);
+		fields
+	};
+
+	let to_band = options.age_bands.rekey(VACC_MEASUREMENT_NAME);
+	let population_vacc = population_vacc.rekeyed(|(state_id, ag)| Some((*state_id, to_band(ag)?)));
+	let mut fields = vacc_fields(
+		&vacc.rekeyed(|(state_id, _, ag)| {
+			// drop vaccinations without properly defined state + district
+			match (state_id, **ag) {
+				(Some(state_id), Some(ag)) => Some((*state_id, to_band(&ag)?)),
+				_ => None,
+			}
+		}),
+		&population_vacc,
+	);
+	let mut key_list: Vec<_> = population_vacc.count.keys().cloned().collect();
+	if options.national {
+		let national_population = population_vacc.rekeyed(|(_, ag)| Some((NATIONAL_ID, *ag)));
+		let national_fields = vacc_fields(
+			&vacc.rekeyed(|(_, _, ag)| Some((NATIONAL_ID, to_band(&(**ag)?)?))),
+			&national_population,
+		);
+		fields = merge_national(fields, national_fields, |k| k.0 == NATIONAL_ID)?;
+		key_list.extend(national_population.count.keys().cloned());
+	}
+	let keys = options.state_age_keys(&key_list)?;
+
+	stream_measurement(output, runs, VACC_MEASUREMENT_NAME, &keys, &fields, options)
+}
+
+// Percentiles of the intervals between the doses, by state and age group.
+fn stream_vacc_intervals(
+	output: &mut crate::Output,
+	runs: &mut RunLog,
+	options: &StepOptions,
+	vacc: &CookedVaccinationData<VaccinationKey>,
+) -> Result<()> {
+	if !options.is_enabled(VACC_INTERVAL_MEASUREMENT_NAME) {
+		return Ok(());
+	}
+	info!("preparing {} ...", VACC_INTERVAL_MEASUREMENT_NAME);
+
+	let intervals =
+		CookedVaccinationIntervals::estimate(&vacc.rekeyed(|(state_id, _, ag)| {
+			match (state_id, **ag) {
+				(Some(state_id), Some(ag)) => Some((*state_id, ag)),
+				_ => None,
+			}
+		}));
+	let mut fields = Vec::new();
+	intervals.write_field_descriptors(&mut fields);
+	let mut key_list = intervals.keys.clone();
+	if options.national {
+		// estimated on the national counts, as percentiles do not add up
+		let national_intervals = CookedVaccinationIntervals::estimate(
+			&vacc.rekeyed(|(_, _, ag)| Some((NATIONAL_ID, (**ag)?))),
+		);
+		let mut national_fields = Vec::new();
+		national_intervals.write_field_descriptors(&mut national_fields);
+		fields = merge_national(fields, national_fields, |k| k.0 == NATIONAL_ID)?;
+		key_list.extend(national_intervals.keys.iter().cloned());
+	}
+	let keys = options.state_age_keys(&key_list)?;
+
+	stream_measurement(
+		output,
+		runs,
+		VACC_INTERVAL_MEASUREMENT_NAME,
+		&keys,
+		&fields,
+		options,
+	)
+}
+
+// Vaccinations by district of residence, with the waning immunity.
+fn stream_vacc_residence(
+	output: &mut crate::Output,
+	runs: &mut RunLog,
+	options: &StepOptions,
+	vacc: &CookedVaccinationData<VaccinationKey>,
+	population: &CookedPopulationData<GeoCaseKey>,
+) -> Result<()> {
+	if !options.is_enabled(VACC_RESIDENCE_MEASUREMENT_NAME) {
+		return Ok(());
+	}
+	info!("preparing {} ...", VACC_RESIDENCE_MEASUREMENT_NAME);
+
+	let residence_fields = |vacc: &CookedVaccinationData<GeoCaseKey>,
+	                        population: &CookedPopulationData<GeoCaseKey>| {
+		let mut fields = Vec::new();
+		vacc.write_field_descriptors(&mut fields);
+		vacc.write_waning_field_descriptors(options.waning, population, &mut fields);
+		population.write_field_descriptors(&mut fields);
+		fields
+	};
+
+	let mut fields = residence_fields(
+		&vacc.rekeyed(|(state_id, district_id, _)| {
+			// drop vaccinations without properly defined state + district
+			match (state_id, district_id) {
+				(Some(state_id), Some(district_id)) => Some((*state_id, *district_id)),
+				_ => None,
+			}
+		}),
+		population,
+	);
+	let mut key_list: Vec<GeoCaseKey> = population.count.keys().cloned().collect();
+	if options.national {
+		let national_fields = residence_fields(
+			&vacc.rekeyed(|_| Some((NATIONAL_ID, NATIONAL_ID))),
+			&population.rekeyed(|_| Some((NATIONAL_ID, NATIONAL_ID))),
+		);
+		fields = merge_national(fields, national_fields, |k| k.0 == NATIONAL_ID)?;
+		key_list.push((NATIONAL_ID, NATIONAL_ID));
+	}
+	let keys = options.district_keys(&key_list)?;
+
+	stream_measurement(
+		output,
+		runs,
+		VACC_RESIDENCE_MEASUREMENT_NAME,
+		&keys,
+		&fields,
+		options,
+	)
+}
+
+// National weekly PCR tests, without any tags.
+fn stream_testing(
+	output: &mut crate::Output,
+	runs: &mut RunLog,
+	options: &StepOptions,
+	testing: &CookedTestingData,
+) -> Result<()> {
+	if !options.is_enabled(TESTING_MEASUREMENT_NAME) {
+		return Ok(());
+	}
+	info!("preparing {} ...", TESTING_MEASUREMENT_NAME);
+
+	let keys = KeySet::new(&[][..], [()].iter(), |_, _| ())?;
+	let mut fields = Vec::new();
+	testing.write_field_descriptors(&mut fields);
+
+	stream_measurement(
+		output,
+		runs,
+		TESTING_MEASUREMENT_NAME,
+		&keys,
+		&fields,
+		options,
+	)
+}
+
+// National reasons for limited ICU operation, without any tags.
+fn stream_icu_unavailable(
+	output: &mut crate::Output,
+	runs: &mut RunLog,
+	options: &StepOptions,
+	icu_unavailable: &CookedICUUnavailableData,
+) -> Result<()> {
+	if !options.is_enabled(ICU_UNAVAILABLE_MEASUREMENT_NAME) {
+		return Ok(());
+	}
+	info!("preparing {} ...", ICU_UNAVAILABLE_MEASUREMENT_NAME);
+
+	let keys = KeySet::new(&[][..], [()].iter(), |_, _| ())?;
+	let mut fields = Vec::new();
+	icu_unavailable.write_field_descriptors(&mut fields);
+
+	stream_measurement(
+		output,
+		runs,
+		ICU_UNAVAILABLE_MEASUREMENT_NAME,
+		&keys,
+		&fields,
+		options,
+	)
+}
+
+// Viral loads by state and sewage plant.
+fn stream_wastewater(
+	output: &mut crate::Output,
+	runs: &mut RunLog,
+	options: &StepOptions,
+	wastewater: &CookedWastewaterData,
+) -> Result<()> {
+	if !options.is_enabled(WASTEWATER_MEASUREMENT_NAME) {
+		return Ok(());
+	}
+	info!("preparing {} ...", WASTEWATER_MEASUREMENT_NAME);
+
+	let keys = KeySet::new(
+		&["state", "site"][..],
+		wastewater.viral_load.keys(),
+		|k, out| {
+			let state_name = match crate::STATE_CODES.iter().find(|(code, _)| *code == k.0) {
+				Some((_, name)) => (*name).into(),
+				None => k.0.clone(),
+			};
+			out.push(state_name);
+			out.push(k.1.clone());
+		},
+	)?;
+	let mut fields = Vec::new();
+	wastewater.write_field_descriptors(&mut fields);
+
+	stream_measurement(
+		output,
+		runs,
+		WASTEWATER_MEASUREMENT_NAME,
+		&keys,
+		&fields,
+		options,
+	)
+}
+
+// Weekly deaths by state and age band, with the baseline of the years before.
+fn stream_weekly_deaths(
+	output: &mut crate::Output,
+	runs: &mut RunLog,
+	options: &StepOptions,
+	weekly_deaths: &CookedWeeklyDeathsData,
+) -> Result<()> {
+	if !options.is_enabled(WEEKLY_DEATHS_MEASUREMENT_NAME) {
+		return Ok(());
+	}
+	info!("preparing {} ...", WEEKLY_DEATHS_MEASUREMENT_NAME);
+
+	// destatis publishes the national numbers itself, they are only dropped if the national aggregate is not wanted
+	let key_list: Vec<_> = weekly_deaths
+		.deaths
+		.keys()
+		.filter(|k| options.national || k.0 != NATIONAL_ID)
+		.cloned()
+		.collect();
+	let keys = options.state_age_keys(&key_list)?;
+	let mut fields = Vec::new();
+	weekly_deaths.write_field_descriptors(&mut fields);
+
+	stream_measurement(
+		output,
+		runs,
+		WEEKLY_DEATHS_MEASUREMENT_NAME,
+		&keys,
+		&fields,
+		options,
+	)
+}
+
+// Loads the data and streams all measurements, recording each phase in `runs`.
+#[allow(clippy::too_many_arguments)]
+fn load_and_stream(
+	output: &mut crate::Output,
+	runs: &mut RunLog,
+	pipeline: PipelineBuilder,
+	districtfile: &str,
+	since: Option<NaiveDate>,
+	until: Option<NaiveDate>,
+	stream_options: &StreamOptions,
+	waning: &crate::WaningModel,
+	weekly_rollup: bool,
+) -> Result<()> {
+	// for the progress display only
+	let mut planned_phases = pipeline.load_phases()
+		+ [
+			(GEO_MEASUREMENT_NAME, true),
+			(GEO_LIGHT_MEASUREMENT_NAME, true),
+			(GEO_WEEKLY_MEASUREMENT_NAME, weekly_rollup),
+			(GEO_LIGHT_WEEKLY_MEASUREMENT_NAME, weekly_rollup),
+			(QUALITY_MEASUREMENT_NAME, true),
+			(ICU_AGE_ESTIMATE_MEASUREMENT_NAME, true),
+			(AGE_MEASUREMENT_NAME, true),
+			(HOSP_MEASUREMENT_NAME, true),
+			(DEMO_MEASUREMENT_NAME, true),
+			(VACC_MEASUREMENT_NAME, true),
+			(VACC_INTERVAL_MEASUREMENT_NAME, true),
+			(
+				VACC_RESIDENCE_MEASUREMENT_NAME,
+				std::env::var_os("COVID_VACC_RESIDENCE_DATA").is_some(),
+			),
+			(
+				TESTING_MEASUREMENT_NAME,
+				std::env::var_os("COVID_TESTING_DATA").is_some(),
+			),
+			(
+				ICU_UNAVAILABLE_MEASUREMENT_NAME,
+				std::env::var_os("COVID_DIVI_UNAVAILABLE_DATA").is_some(),
+			),
+			(
+				WASTEWATER_MEASUREMENT_NAME,
+				std::env::var_os("COVID_WASTEWATER_DATA").is_some(),
+			),
+			(
+				WEEKLY_DEATHS_MEASUREMENT_NAME,
+				std::env::var_os("COVID_DESTATIS_WEEKLY_DEATHS").is_some(),
+			),
+		]
+		.iter()
+		.filter(|(name, available)| *available && stream_options.is_enabled(name))
+		.count();
+	runs.plan_phases(planned_phases);
+
+	let (mut states, mut districts) = crate::load_districts(districtfile)?;
+	// optional neighbourhood relation between districts, used for the region incidence
+	let adjacency = match std::env::var_os("COVID_DISTRICT_ADJACENCY") {
+		Some(path) => Some(DistrictAdjacency::load(crate::env_fetcher().open(path)?)?),
+		None => None,
+	};
+	// optional position of each district, for map panels
+	let centroids = match std::env::var_os("COVID_DISTRICT_CENTROIDS") {
+		Some(path) => Some(Arc::new(DistrictCentroids::load(
+			crate::env_fetcher().open(path)?,
+		)?)),
+		None => None,
+	};
+
+	let data = pipeline.load(runs, &states, &mut districts)?;
+	// the data is loaded in full (but see PipelineBuilder::since), only the streaming is restricted
+	let start = since.map_or(data.start, |since| since.max(data.start));
+	let end = until.map_or(data.end, |until| {
+		(until + chrono::Duration::days(1)).min(data.end)
+	});
+	if start >= end {
+		return Err(Error::Schema(format!(
+			"no days to stream between {} and {}",
+			start, end
+		)));
+	}
+	let ndays: usize = (end - start).num_days().try_into().unwrap();
+
+	// optional, user-defined aggregates over the case data (JSON list of AggregateSpec)
+	let aggregates = match std::env::var_os("COVID_AGGREGATES") {
+		Some(path) => crate::load_aggregate_specs(crate::env_fetcher().open(path)?)?,
+		None => Vec::new(),
+	};
+	// optional, custom age bands of the measurements keyed by age (JSON object of measurement name to the lower bounds of the bands)
+	let age_bands = match std::env::var_os("COVID_AGE_BANDS") {
+		Some(path) => crate::load_age_band_config(crate::env_fetcher().open(path)?)?,
+		None => crate::AgeBandConfig::default(),
+	};
+	// the population of these is in the bands of the data, so it can be rebanded alike
+	age_bands.validate(&[
+		(DEMO_MEASUREMENT_NAME, AgeScheme::Cases),
+		(VACC_MEASUREMENT_NAME, AgeScheme::Vaccination),
+		(HOSP_MEASUREMENT_NAME, AgeScheme::Hospitalization),
+	])?;
+	planned_phases += aggregates
+		.iter()
+		.filter(|spec| stream_options.is_enabled(&spec.name))
+		.count();
+	runs.plan_phases(planned_phases);
+
+	// optional, a national aggregate (state "Deutschland") in all measurements keyed by state
+	let national = env_switch("COVID_NATIONAL_AGGREGATE")?;
+	if national {
+		insert_national(&mut states, &mut districts);
+	}
+
+	let options = StepOptions {
+		start,
+		ndays,
+		stream: stream_options,
+		states: &states,
+		districts: &districts,
+		// optional, the ids of states and districts as additional tags
+		id_tags: env_switch("COVID_ID_TAGS")?,
+		national,
+		weekly_rollup,
+		waning,
+		age_bands: &age_bands,
+		adjacency: adjacency.as_ref(),
+		centroids: centroids.as_ref(),
+	};
+	stream_geo(output, runs, &options, &data)?;
+	stream_geo_light(output, runs, &options, &data)?;
+	stream_quality(output, runs, &options, &data.cases)?;
+	stream_icu_age_estimate(output, runs, &options, &data)?;
+	stream_age(output, runs, &options, &data)?;
+	stream_hosp(output, runs, &options, &data)?;
+	for spec in aggregates.iter() {
+		stream_aggregate(output, runs, &options, spec, &data.cases)?;
+	}
+	stream_demo(output, runs, &options, &data.cases, &data.population_demo)?;
+	// the remaining measurements do not need the case data
+	drop(data.cases);
+	stream_vacc(output, runs, &options, &data.vacc, &data.population_vacc)?;
+	stream_vacc_intervals(output, runs, &options, &data.vacc)?;
+	if let Some(vacc) = data.vacc_residence.as_ref() {
+		stream_vacc_residence(output, runs, &options, vacc, &data.population)?;
+	}
+	if let Some(testing) = data.testing.as_ref() {
+		stream_testing(output, runs, &options, testing)?;
+	}
+	if let Some(icu_unavailable) = data.icu_unavailable.as_ref() {
+		stream_icu_unavailable(output, runs, &options, icu_unavailable)?;
+	}
+	if let Some(wastewater) = data.wastewater.as_ref() {
+		stream_wastewater(output, runs, &options, wastewater)?;
+	}
+	if let Some(weekly_deaths) = data.weekly_deaths.as_ref() {
+		stream_weekly_deaths(output, runs, &options, weekly_deaths)?;
+	}
+	Ok(())
+}
+
+// The whole pipeline from the input files given in argv to the output, i.e. to_influx and to_csv without the choice of the output.
+pub fn run(argv: &[String], mut output: crate::Output) -> Result<()> {
+	let mut argv = argv.to_vec();
+	// --resume skips the days which COVID_CHECKPOINT_FILE records as written by an earlier, aborted run
+	let resume = take_switch(&mut argv, "--resume");
+	// --verify reads the latest point of each written measurement back from the (first) InfluxDB server after the run
+	let verify = take_switch(&mut argv, "--verify");
+	if verify && output.primary_influx().is_none() {
+		return Err(Error::Schema(
+			"--verify requires the InfluxDB output".into(),
+		));
+	}
+	// read early, so that configuration errors surface before the lengthy load
+	let mut stream_options = crate::env_stream_options();
+	let waning = crate::WaningModel::from_env()
+		.map_err(|e| Error::Schema(format!("invalid COVID_WANING_MODEL: {}", e)))?;
+	if resume {
+		if stream_options.checkpoint.is_none() {
+			return Err(Error::Schema(
+				"--resume requires COVID_CHECKPOINT_FILE to be set".into(),
+			));
+		}
+		stream_options.resume = true;
+	}
+	// --filter TAG=VALUE,... only streams the matching keys of each measurement, e.g. --filter state=Sachsen
+	if let Some(filter) = take_flag(&mut argv, "--filter")? {
+		let filter = filter
+			.parse::<crate::KeyFilter>()
+			.map_err(|e| Error::Schema(format!("invalid --filter: {}", e)))?;
+		if resume {
+			return Err(Error::Schema(
+				"--filter cannot be combined with --resume".into(),
+			));
+		}
+		// a partial run must not advance the high-water marks of the complete measurements
+		stream_options.checkpoint = None;
+		stream_options.key_filter = Some(filter);
+	}
+	// fail fast if the server is unreachable or the database is missing, instead of after the load
+	for (client, policy) in output.influx_clients() {
+		let e = match client
+			.ping()
+			.and_then(|_| client.ensure_database(&stream_options.database))
+		{
+			Ok(()) => continue,
+			Err(e) => e,
+		};
+		let msg = format!(
+			"InfluxDB at {} (database {}) is not usable: {}",
+			client.api_url(),
+			stream_options.database,
+			e
+		);
+		if policy == crate::SinkErrorPolicy::LogAndContinue {
+			// the run is still written to the other outputs; writes to this one will be logged as failed
+			warn!("{}", msg);
+		} else {
+			error!("{}", msg);
+			return Err(Error::Sink(e));
+		}
+	}
+	// --since DATE and --until DATE (inclusive) only stream the days in between, e.g. for re-streaming the last weeks
+	let since = take_flag(&mut argv, "--since")?
+		.map(|v| parse_date("--since", &v))
+		.transpose()?;
+	let until = take_flag(&mut argv, "--until")?
+		.map(|v| parse_date("--until", &v))
+		.transpose()?;
+	if let (Some(since), Some(until)) = (since, until) {
+		if since > until {
+			return Err(Error::Schema(format!(
+				"--since {} is after --until {}",
+				since, until
+			)));
+		}
+	}
+	let casefile = &argv[1];
+	let districts = &argv[2];
+	let difffile = &argv[3];
+	let diffstart = parse_date("the diff start", &argv[4])?;
+	let divifile = &argv[5];
+	let vaccfile = &argv[6];
+	let hospfile = &argv[7];
+	let destatisfile = &argv[8];
+
+	let mut pipeline = PipelineBuilder::new(
+		casefile,
+		difffile,
+		diffstart,
+		divifile,
+		vaccfile,
+		hospfile,
+		destatisfile,
+	)
+	.policy(crate::env_submit_policy())
+	.clamp(
+		ClampWindows::from_env()
+			.map_err(|e| Error::Schema(format!("invalid clamp window: {}", e)))?,
+	);
+	if let Some(since) = since {
+		pipeline = pipeline.since(since);
+	}
+	// optional, as the nowcast was only published for part of the pandemic
+	if let Some(nowcastfile) = argv.get(9) {
+		pipeline = pipeline.hosp_nowcast(nowcastfile);
+	}
+	// optional, destatis population files of further years
+	if let Some(paths) = std::env::var_os("COVID_DESTATIS_VINTAGES") {
+		for path in std::env::split_paths(&paths) {
+			pipeline = pipeline.population_vintage(path);
+		}
+	}
+	// optional, the site level DIVI register instead of the district file
+	if let Some(path) = std::env::var_os("COVID_DIVI_SITE_DATA") {
+		pipeline = pipeline.divi_sites(path);
+	}
+	// optional, vaccinations keyed by district of residence instead of vaccination site
+	if let Ok(path) = std::env::var("COVID_VACC_RESIDENCE_DATA") {
+		pipeline = pipeline.vacc_residence(path);
+	}
+	// optional, national weekly PCR testing numbers
+	if let Some(path) = std::env::var_os("COVID_TESTING_DATA") {
+		pipeline = pipeline.testing(path);
+	}
+	// optional, DIVI reasons for limited ICU operation
+	if let Some(path) = std::env::var_os("COVID_DIVI_UNAVAILABLE_DATA") {
+		pipeline = pipeline.icu_unavailable(path);
+	}
+	// optional, AMELAG wastewater surveillance (tab separated)
+	if let Some(path) = std::env::var_os("COVID_WASTEWATER_DATA") {
+		pipeline = pipeline.wastewater(path);
+	}
+	// optional, destatis weekly deaths by state and age band, including the baseline years
+	if let Some(path) = std::env::var_os("COVID_DESTATIS_WEEKLY_DEATHS") {
+		pipeline = pipeline.weekly_deaths(path);
+	}
+	// optional, sums up the cases and population over the sexes, for the smaller keyspace
+	if std::env::var_os("COVID_COLLAPSE_SEX").is_some() {
+		pipeline = pipeline.collapse_sex(env_switch("COVID_COLLAPSE_SEX")?);
+	}
+	// optional table of district ID changes, used to stitch the history of merged districts
+	if let Some(path) = std::env::var_os("COVID_DISTRICT_REMAP") {
+		pipeline = pipeline.remap(DistrictRemapping::load(crate::env_fetcher().open(path)?)?);
+	}
+	// optional, snapshots of the loaded data which are reused while the input files do not change
+	if let Some(dir) = std::env::var_os("COVID_SNAPSHOT_DIR") {
+		pipeline = pipeline.snapshots(dir);
+	}
+	// optional, comma separated datasets (cases, diff, vacc, vacc_residence, divi, hosp) whose counters drop their leading zeros to save memory
+	if let Ok(datasets) = std::env::var("COVID_COMPACT_STORAGE") {
+		for dataset in datasets.split(',').filter(|s| !s.is_empty()) {
+			pipeline = pipeline.storage(dataset.trim(), Storage::Compact);
+		}
+	}
+
+	// optional, weekly rollups of the geo measurements for long-range dashboards
+	let weekly_rollup = env_switch("COVID_WEEKLY_ROLLUP")?;
+
+	let mut runs = RunLog::new();
+	if let Err(e) = load_and_stream(
+		&mut output,
+		&mut runs,
+		pipeline,
+		districts,
+		since,
+		until,
+		&stream_options,
+		&waning,
+		weekly_rollup,
+	) {
+		// failed runs are logged as well, with one error in the phase they failed in
+		runs.record_failure();
+		if let Err(submit_err) = output.submit_runs(&runs, &stream_options) {
+			warn!("failed to write the run statistics: {}", submit_err);
+		}
+		return Err(e);
+	}
+
+	info!("writing run statistics ...");
+	output.submit_runs(&runs, &stream_options)?;
+	if let crate::Output::FanOut(fanout) = &output {
+		for target in fanout.targets() {
+			if target.failures() > 0 {
+				error!(
+					"{}: {} writes failed, the output is incomplete",
+					target.name,
+					target.failures()
+				);
+			}
+		}
+	}
+
+	if let Some(client) = output.primary_influx() {
+		if verify {
+			info!("verifying ...");
+			verify_written(client, &runs, &stream_options)?;
+		}
+	}
+
+	Ok(())
+}
@@ -1,9 +1,19 @@
+use std::env;
+use std::fmt;
 use std::fmt::Write;
+use std::fs;
 use std::io;
+#[cfg(unix)]
+use std::os::unix::io::FromRawFd;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::sync::{Mutex, OnceLock};
 use std::time;
 
 use atty;
 
+use log::{info, warn};
+
 pub struct NullSink();
 
 impl ProgressSink for NullSink {
@@ -16,9 +26,73 @@ pub enum Status {
 	Step(usize, usize),
 }
 
+// Position of a phase within a run, e.g. phase 3 of 10. The number of phases is an estimate made at the start of the run.
+#[derive(Debug, Clone)]
+pub struct Phase {
+	pub name: String,
+	// starting at 1
+	pub index: usize,
+	pub count: usize,
+}
+
+impl fmt::Display for Phase {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "phase {}/{}: {}", self.index, self.count, self.name)
+	}
+}
+
 pub trait ProgressSink {
 	fn update(&mut self, status: Status, elapsed: time::Duration, rate: f64);
 	fn finish(&mut self);
+
+	fn begin_phase(&mut self, _phase: &Phase) {}
+	fn end_phase(&mut self) {}
+}
+
+impl<S: ProgressSink + ?Sized> ProgressSink for Box<S> {
+	fn update(&mut self, status: Status, elapsed: time::Duration, rate: f64) {
+		(**self).update(status, elapsed, rate)
+	}
+
+	fn finish(&mut self) {
+		(**self).finish()
+	}
+
+	fn begin_phase(&mut self, phase: &Phase) {
+		(**self).begin_phase(phase)
+	}
+
+	fn end_phase(&mut self) {
+		(**self).end_phase()
+	}
+}
+
+// Announces a phase to the inner sink and ends it when dropped.
+pub struct PhaseSink<S: ProgressSink> {
+	inner: S,
+}
+
+impl<S: ProgressSink> PhaseSink<S> {
+	pub fn begin(mut inner: S, phase: &Phase) -> Self {
+		inner.begin_phase(phase);
+		Self { inner }
+	}
+}
+
+impl<S: ProgressSink> ProgressSink for PhaseSink<S> {
+	fn update(&mut self, status: Status, elapsed: time::Duration, rate: f64) {
+		self.inner.update(status, elapsed, rate)
+	}
+
+	fn finish(&mut self) {
+		self.inner.finish()
+	}
+}
+
+impl<S: ProgressSink> Drop for PhaseSink<S> {
+	fn drop(&mut self) {
+		self.inner.end_phase();
+	}
 }
 
 pub struct StepMeter<'x, S: ProgressSink + ?Sized> {
@@ -124,6 +198,7 @@ pub struct TtySink<W: io::Write> {
 	w: W,
 	tick: u8,
 	longest_rate: usize,
+	phase: Option<String>,
 }
 
 impl TtySink<io::Stdout> {
@@ -132,6 +207,7 @@ impl TtySink<io::Stdout> {
 			w: io::stdout(),
 			tick: 0,
 			longest_rate: 0,
+			phase: None,
 		}
 	}
 }
@@ -181,62 +257,197 @@ impl<W: io::Write> ProgressSink for TtySink<W> {
 			None => (),
 		}
 
-		let _ = write!(self.w, "\x1b[K  {}  [{}]\r", lhs, rate_s);
+		let _ = match self.phase.as_ref() {
+			Some(phase) => write!(self.w, "\x1b[K  {}  {}  [{}]\r", phase, lhs, rate_s),
+			None => write!(self.w, "\x1b[K  {}  [{}]\r", lhs, rate_s),
+		};
 		let _ = self.w.flush();
 	}
 
 	fn finish(&mut self) {
 		let _ = write!(self.w, "\n");
 	}
+
+	fn begin_phase(&mut self, phase: &Phase) {
+		self.phase = Some(phase.to_string());
+	}
+
+	fn end_phase(&mut self) {
+		self.phase = None;
+	}
 }
 
-pub struct SummarySink<W: io::Write> {
-	w: W,
+// Reports only a summary of each operation, through the log; for non-interactive runs.
+pub struct LogSink {
 	last_info: Option<(Status, time::Duration)>,
 }
 
-impl<W: io::Write> SummarySink<W> {
-	fn new(w: W) -> Self {
-		Self { w, last_info: None }
+impl LogSink {
+	fn new() -> Self {
+		Self { last_info: None }
 	}
 }
 
-impl<W: io::Write> ProgressSink for SummarySink<W> {
+impl ProgressSink for LogSink {
 	fn update(&mut self, status: Status, elapsed: time::Duration, _rate: f64) {
 		self.last_info = Some((status, elapsed))
 	}
 
 	fn finish(&mut self) {
 		match self.last_info.take() {
-			Some((status, elapsed)) => {
-				match status.count() {
-					Some(c) => {
-						write!(
-							self.w,
-							"... processed {} items in {:.2} seconds\n",
-							c,
-							elapsed.as_secs_f64()
-						)
-					}
-					None => {
-						write!(
-							self.w,
-							"... operation took {:.2} seconds\n",
-							elapsed.as_secs_f64()
-						)
-					}
-				}
-				.expect("failed to write summary to output");
-			}
+			Some((status, elapsed)) => match status.count() {
+				Some(c) => info!(
+					"... processed {} items in {:.2} seconds",
+					c,
+					elapsed.as_secs_f64()
+				),
+				None => info!("... operation took {:.2} seconds", elapsed.as_secs_f64()),
+			},
 			None => (),
 		}
 	}
+
+	fn begin_phase(&mut self, phase: &Phase) {
+		info!("{}", phase);
+	}
+}
+
+// Emits one JSON object per line for every update, for consumption by other programs. All sinks share the target, so that the progress of consecutive phases ends up in one stream.
+pub struct JsonLinesSink {
+	w: &'static Mutex<Box<dyn io::Write + Send>>,
+	phase: Option<Phase>,
+}
+
+impl JsonLinesSink {
+	pub fn new(w: &'static Mutex<Box<dyn io::Write + Send>>) -> Self {
+		Self { w, phase: None }
+	}
+
+	fn emit(&mut self, event: serde_json::Value) {
+		let mut w = match self.w.lock() {
+			Ok(w) => w,
+			// a panic while writing leaves at most a truncated line
+			Err(poisoned) => poisoned.into_inner(),
+		};
+		// the consumer going away must not abort the run
+		let _ = writeln!(w, "{}", event).and_then(|_| w.flush());
+	}
+}
+
+impl ProgressSink for JsonLinesSink {
+	fn update(&mut self, status: Status, elapsed: time::Duration, rate: f64) {
+		let (items, total) = match status {
+			Status::Count(i) => (i, None),
+			Status::Step(i, n) => (i, Some(n)),
+		};
+		let eta = match total {
+			Some(n) if rate > 0. && rate.is_finite() => Some(n.saturating_sub(items) as f64 / rate),
+			_ => None,
+		};
+		let rate = if rate.is_finite() { Some(rate) } else { None };
+		let event = serde_json::json!({
+			"event": "update",
+			"phase": self.phase.as_ref().map(|p| &p.name),
+			"phase_index": self.phase.as_ref().map(|p| p.index),
+			"phase_count": self.phase.as_ref().map(|p| p.count),
+			"items": items,
+			"total": total,
+			"elapsed": elapsed.as_secs_f64(),
+			"rate": rate,
+			"eta": eta,
+		});
+		self.emit(event);
+	}
+
+	fn finish(&mut self) {
+		let event = serde_json::json!({
+			"event": "finish",
+			"phase": self.phase.as_ref().map(|p| &p.name),
+		});
+		self.emit(event);
+	}
+
+	fn begin_phase(&mut self, phase: &Phase) {
+		self.phase = Some(phase.clone());
+		let event = serde_json::json!({
+			"event": "begin_phase",
+			"phase": phase.name,
+			"phase_index": phase.index,
+			"phase_count": phase.count,
+		});
+		self.emit(event);
+	}
+
+	fn end_phase(&mut self) {
+		if let Some(phase) = self.phase.take() {
+			let event = serde_json::json!({
+				"event": "end_phase",
+				"phase": phase.name,
+			});
+			self.emit(event);
+		}
+	}
+}
+
+// Target of the JSON progress, from COVID_PROGRESS_JSON: `fd:N` for an inherited file descriptor, `unix:PATH` for a Unix stream socket (both only on Unix), anything else is a file to append to.
+fn open_progress_target(target: &str) -> io::Result<Box<dyn io::Write + Send>> {
+	#[cfg(unix)]
+	{
+		if let Some(fd) = target.strip_prefix("fd:") {
+			return Ok(Box::new(dup_inherited_fd(fd)?));
+		}
+		if let Some(path) = target.strip_prefix("unix:") {
+			return Ok(Box::new(UnixStream::connect(path)?));
+		}
+	}
+	Ok(Box::new(
+		fs::OpenOptions::new()
+			.create(true)
+			.append(true)
+			.open(target)?,
+	))
+}
+
+// A duplicate of the inherited descriptor `fd`, so that the descriptor itself stays with whoever else owns it in this process. Fails with EBADF if it is not open.
+#[cfg(unix)]
+fn dup_inherited_fd(fd: &str) -> io::Result<fs::File> {
+	let fd = fd
+		.parse::<i32>()
+		.map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+	if unsafe { libc::fcntl(fd, libc::F_GETFD) } < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	let dup = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0) };
+	if dup < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	// the duplicate is not owned by anything else
+	Ok(unsafe { fs::File::from_raw_fd(dup) })
+}
+
+fn json_progress_target() -> Option<&'static Mutex<Box<dyn io::Write + Send>>> {
+	static TARGET: OnceLock<Option<Mutex<Box<dyn io::Write + Send>>>> = OnceLock::new();
+	TARGET
+		.get_or_init(|| {
+			let target = env::var("COVID_PROGRESS_JSON").ok()?;
+			match open_progress_target(&target) {
+				Ok(w) => Some(Mutex::new(w)),
+				Err(e) => {
+					warn!("failed to open progress target {:?}: {}", target, e);
+					None
+				}
+			}
+		})
+		.as_ref()
 }
 
 pub fn default_output() -> Box<dyn ProgressSink> {
+	if let Some(w) = json_progress_target() {
+		return Box::new(JsonLinesSink::new(w));
+	}
 	if atty::is(atty::Stream::Stdout) {
 		Box::new(TtySink::stdout())
 	} else {
-		Box::new(SummarySink::new(io::stdout()))
+		Box::new(LogSink::new())
 	}
 }
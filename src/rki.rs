@@ -1,13 +1,22 @@
 use std::collections::HashMap;
-use std::hash::Hash;
+use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::io;
+use std::path::Path;
 use std::sync::Arc;
 
-use serde::{de, Deserialize, Deserializer};
+use serde::{de, Deserialize, Deserializer, Serialize};
+
+use smartstring::alias::String as SmartString;
 
 use chrono::naive::NaiveDate;
+use chrono::Weekday;
+
+use log::warn;
 
 use super::context::{AgeGroup, DistrictId, MaybeAgeGroup, MaybeDistrictId, Sex, StateId};
+use super::error::Error;
+use super::ioutil::Compression;
 
 pub type FullCaseKey = (StateId, DistrictId, MaybeAgeGroup, Sex);
 pub type GeoCaseKey = (StateId, DistrictId);
@@ -55,6 +64,21 @@ where
 	}
 }
 
+// ISO calendar week (e.g. "2021-W07"), mapped to the sunday which ends the week
+fn iso_week_end<'de, D>(deserializer: D) -> Result<NaiveDate, D::Error>
+where
+	D: Deserializer<'de>,
+{
+	let s = String::deserialize(deserializer)?;
+	let (year, week) = s
+		.split_once("-W")
+		.ok_or_else(|| de::Error::custom("invalid calendar week, must be YYYY-Www"))?;
+	let year = year.parse::<i32>().map_err(de::Error::custom)?;
+	let week = week.parse::<u32>().map_err(de::Error::custom)?;
+	NaiveDate::from_isoywd_opt(year, week, Weekday::Sun)
+		.ok_or_else(|| de::Error::custom("calendar week out of range"))
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct InfectionRecord {
 	#[serde(rename = "IdLandkreis")]
@@ -83,6 +107,111 @@ pub struct InfectionRecord {
 	pub recovered_count: i32,
 }
 
+// The columns of InfectionRecord, which all versions of the case file have.
+static INFECTION_COLUMNS: [&str; 12] = [
+	"IdLandkreis",
+	"Altersgruppe",
+	"Geschlecht",
+	"Meldedatum",
+	"Refdatum",
+	"IstErkrankungsbeginn",
+	"NeuerFall",
+	"NeuerTodesfall",
+	"NeuGenesen",
+	"AnzahlFall",
+	"AnzahlTodesfall",
+	"AnzahlGenesen",
+];
+
+// Additional columns of the NPGEO export; the older files have FID instead of ObjectId.
+static LEGACY_INFECTION_COLUMNS: [&str; 7] = [
+	"ObjectId",
+	"FID",
+	"IdBundesland",
+	"Bundesland",
+	"Landkreis",
+	"Datenstand",
+	"Altersgruppe2",
+];
+
+// The versions of the RKI case file. Both are read into InfectionRecord; the differing date formats are handled by legacy_date_compat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InfectionSchema {
+	// the NPGEO export (RKI_COVID19.csv): state and district names, the date of the data and dates as YYYY/MM/DD 00:00:00
+	Legacy,
+	// the export on GitHub (Aktuell_Deutschland_SarsCov2_Infektionen.csv): only the columns of InfectionRecord, ISO dates
+	Current,
+}
+
+impl InfectionSchema {
+	// Detects the version from the header of a case file. Missing columns are an error, unknown columns are only logged.
+	pub fn detect(headers: &csv::StringRecord) -> Result<Self, Error> {
+		// the NPGEO files start with a byte order mark
+		let columns: Vec<&str> = headers
+			.iter()
+			.map(|h| h.trim_start_matches('\u{feff}'))
+			.collect();
+		let schema = if columns.contains(&"Datenstand") {
+			Self::Legacy
+		} else {
+			Self::Current
+		};
+		let missing: Vec<&str> = INFECTION_COLUMNS
+			.iter()
+			.copied()
+			.filter(|c| !columns.contains(c))
+			.collect();
+		let unexpected: Vec<&str> = columns
+			.iter()
+			.copied()
+			.filter(|c| !schema.has_column(c))
+			.collect();
+		if !missing.is_empty() {
+			let mut msg = format!(
+				"{} case file lacks the columns {}",
+				schema,
+				missing.join(", ")
+			);
+			if !unexpected.is_empty() {
+				msg.push_str(&format!("; unexpected columns: {}", unexpected.join(", ")));
+			}
+			return Err(Error::Schema(msg));
+		}
+		if !unexpected.is_empty() {
+			warn!(
+				"{} case file: ignoring unexpected columns {}",
+				schema,
+				unexpected.join(", ")
+			);
+		}
+		Ok(schema)
+	}
+
+	fn has_column(&self, column: &str) -> bool {
+		INFECTION_COLUMNS.contains(&column)
+			|| (*self == Self::Legacy && LEGACY_INFECTION_COLUMNS.contains(&column))
+	}
+}
+
+impl fmt::Display for InfectionSchema {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::Legacy => f.write_str("legacy (NPGEO)"),
+			Self::Current => f.write_str("current"),
+		}
+	}
+}
+
+// The publication date in the name of a case file dump, e.g. RKI_COVID19_2023-05-12.csv.gz or 2023-05-12_Deutschland_SarsCov2_Infektionen.csv; the last YYYY-MM-DD in the file name, if any.
+pub fn dump_publication_date<P: AsRef<Path>>(path: P) -> Option<NaiveDate> {
+	let name = path.as_ref().file_name()?.to_str()?;
+	(0..name.len().saturating_sub(9))
+		.rev()
+		.filter_map(|i| name.get(i..i + 10))
+		.filter(|s| s.starts_with(|c: char| c.is_ascii_digit()))
+		.find_map(|s| NaiveDate::parse_from_str(s, "%Y-%m-%d").ok())
+}
+
 #[derive(Debug, Clone)]
 pub struct StateInfo {
 	pub id: DistrictId,
@@ -95,8 +224,16 @@ pub struct DistrictInfo {
 	pub name: String,
 	pub state: Arc<StateInfo>,
 	pub population: u64,
+	// in km², if the districts file has it (KFL)
+	pub area: Option<f64>,
 }
 
+// the states and the districts by ID, as returned by the district loaders
+pub type DistrictMaps = (
+	HashMap<DistrictId, Arc<StateInfo>>,
+	HashMap<DistrictId, Arc<DistrictInfo>>,
+);
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct RawDistrictRow {
 	#[serde(rename = "BL_ID")]
@@ -109,22 +246,109 @@ pub struct RawDistrictRow {
 	pub district_name: String,
 	#[serde(rename = "EWZ")]
 	pub population: u64,
+	#[serde(rename = "KFL", default)]
+	pub area: Option<f64>,
+}
+
+// NPGEO encodes the IDs as strings (RS "01001", BL_ID "1"), the GeoJSON export sometimes as numbers
+fn npgeo_id<'de, D>(deserializer: D) -> Result<DistrictId, D::Error>
+where
+	D: Deserializer<'de>,
+{
+	#[derive(Deserialize)]
+	#[serde(untagged)]
+	enum Id {
+		Number(DistrictId),
+		String(String),
+	}
+	match Id::deserialize(deserializer)? {
+		Id::Number(v) => Ok(v),
+		Id::String(s) => s.parse::<DistrictId>().map_err(de::Error::custom),
+	}
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct NpgeoDistrictAttributes {
+	#[serde(rename = "BL_ID", deserialize_with = "npgeo_id")]
+	state_id: DistrictId,
+	#[serde(rename = "BL")]
+	state_name: String,
+	#[serde(rename = "RS", deserialize_with = "npgeo_id")]
+	district_id: DistrictId,
+	county: String,
+	#[serde(rename = "EWZ")]
+	population: u64,
+	#[serde(rename = "KFL", default)]
+	area: Option<f64>,
 }
 
-pub fn load_rki_districts<R: io::Read>(
-	r: &mut R,
-) -> Result<
-	(
-		HashMap<DistrictId, Arc<StateInfo>>,
-		HashMap<DistrictId, Arc<DistrictInfo>>,
-	),
-	io::Error,
-> {
+#[derive(Debug, Clone, Deserialize)]
+struct NpgeoDistrictFeature {
+	// "attributes" in the ArcGIS JSON, "properties" in the GeoJSON export
+	#[serde(alias = "properties")]
+	attributes: NpgeoDistrictAttributes,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct NpgeoFeatureCollection {
+	features: Vec<NpgeoDistrictFeature>,
+}
+
+impl From<NpgeoDistrictAttributes> for RawDistrictRow {
+	fn from(other: NpgeoDistrictAttributes) -> Self {
+		Self {
+			state_id: other.state_id,
+			state_name: other.state_name,
+			district_id: other.district_id,
+			district_name: other.county,
+			population: other.population,
+			area: other.area,
+		}
+	}
+}
+
+pub fn load_rki_districts<R: io::Read>(r: &mut R) -> Result<DistrictMaps, Error> {
+	let mut r = csv::Reader::from_reader(r);
+	let rows: Vec<RawDistrictRow> = r.deserialize().collect::<Result<_, _>>()?;
+	Ok(index_districts(rows))
+}
+
+// Loads the districts from the NPGEO feature collection ("RKI Corona Landkreise"), as JSON or GeoJSON. The geometries are ignored.
+pub fn load_rki_districts_json<R: io::Read>(r: R) -> Result<DistrictMaps, Error> {
+	let collection: NpgeoFeatureCollection = serde_json::from_reader(io::BufReader::new(r))
+		.map_err(|e| Error::Schema(format!("invalid NPGEO district data: {}", e)))?;
+	Ok(index_districts(
+		collection
+			.features
+			.into_iter()
+			.map(|feature| feature.attributes.into()),
+	))
+}
+
+// Loads the districts file with the loader matching its extension: .json and .geojson (possibly compressed) are read as NPGEO feature collection, everything else as CSV.
+pub fn load_districts<P: AsRef<Path>>(path: P) -> Result<DistrictMaps, Error> {
+	let path = path.as_ref();
+	// the extension below the compression, if any
+	let name = match Compression::from_extension(path) {
+		Some(_) => Path::new(path.file_stem().unwrap_or_default()),
+		None => path,
+	};
+	let is_json = matches!(
+		name.extension().and_then(|ext| ext.to_str()),
+		Some("json") | Some("geojson")
+	);
+	let mut r = super::env_fetcher().open(path)?;
+	if is_json {
+		load_rki_districts_json(r)
+	} else {
+		load_rki_districts(&mut r)
+	}
+}
+
+fn index_districts<I: IntoIterator<Item = RawDistrictRow>>(rows: I) -> DistrictMaps {
 	let mut states: HashMap<DistrictId, Arc<StateInfo>> = HashMap::new();
 	let mut districts = HashMap::new();
-	let mut r = csv::Reader::from_reader(r);
-	for row in r.deserialize() {
-		let rec: RawDistrictRow = row?;
+	for rec in rows {
 		let state_entry = match states.get(&rec.state_id) {
 			Some(e) => e.clone(),
 			None => {
@@ -140,14 +364,257 @@ pub fn load_rki_districts<R: io::Read>(
 			id: rec.district_id,
 			name: rec.district_name,
 			population: rec.population,
+			area: rec.area,
 			state: state_entry,
 		});
 		districts.insert(district.id, district);
 	}
-	Ok((states, districts))
+	(states, districts)
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DistrictRemapRecord {
+	pub old_id: DistrictId,
+	pub new_id: DistrictId,
+	pub effective: NaiveDate,
+}
+
+// Tracks district ID changes (mergers, renumbering). Data recorded under an old ID before the change becomes effective is attributed to the new ID, so that the new district gets a continuous history. Records dated after the change keep their ID, which allows for IDs to be reused.
+#[derive(Debug, Clone, Default)]
+pub struct DistrictRemapping {
+	entries: HashMap<DistrictId, Vec<(NaiveDate, DistrictId)>>,
+}
+
+// in the order of the old IDs, for the input hash of the snapshots
+impl Hash for DistrictRemapping {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		let mut entries: Vec<_> = self.entries.iter().collect();
+		entries.sort_by_key(|(old_id, _)| **old_id);
+		entries.hash(state);
+	}
+}
+
+impl DistrictRemapping {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn load<R: io::Read>(r: R) -> Result<Self, Error> {
+		let mut result = Self::new();
+		let mut r = csv::Reader::from_reader(r);
+		for row in r.deserialize() {
+			let rec: DistrictRemapRecord = row?;
+			result.insert(rec.old_id, rec.new_id, rec.effective);
+		}
+		Ok(result)
+	}
+
+	pub fn insert(&mut self, old_id: DistrictId, new_id: DistrictId, effective: NaiveDate) {
+		let changes = self.entries.entry(old_id).or_default();
+		let index = match changes.binary_search_by_key(&effective, |(date, _)| *date) {
+			Ok(i) | Err(i) => i,
+		};
+		changes.insert(index, (effective, new_id));
+	}
+
+	pub fn resolve(&self, id: DistrictId, at: NaiveDate) -> DistrictId {
+		let mut id = id;
+		// bounded to protect against cycles in the table
+		for _ in 0..16 {
+			let changes = match self.entries.get(&id) {
+				Some(v) => v,
+				None => return id,
+			};
+			// the first change which happened after the date of the record applies
+			match changes.iter().find(|(effective, _)| at < *effective) {
+				Some((_, new_id)) => id = *new_id,
+				None => return id,
+			}
+		}
+		panic!("district remapping for {} does not terminate", id);
+	}
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DistrictAdjacencyRecord {
+	pub district_id: DistrictId,
+	pub neighbor_id: DistrictId,
+}
+
+// Neighbourhood relation between districts, used to aggregate each district with the districts around it. The relation is symmetric, so it is sufficient to list each pair once.
+#[derive(Debug, Clone, Default)]
+pub struct DistrictAdjacency {
+	neighbors: HashMap<DistrictId, Vec<DistrictId>>,
+}
+
+impl DistrictAdjacency {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn load<R: io::Read>(r: R) -> Result<Self, Error> {
+		let mut result = Self::new();
+		let mut r = csv::Reader::from_reader(r);
+		for row in r.deserialize() {
+			let rec: DistrictAdjacencyRecord = row?;
+			result.insert(rec.district_id, rec.neighbor_id);
+		}
+		Ok(result)
+	}
+
+	pub fn insert(&mut self, a: DistrictId, b: DistrictId) {
+		if a == b {
+			return;
+		}
+		for (from, to) in [(a, b), (b, a)] {
+			let neighbors = self.neighbors.entry(from).or_default();
+			if !neighbors.contains(&to) {
+				neighbors.push(to);
+			}
+		}
+	}
+
+	pub fn neighbors(&self, id: DistrictId) -> &[DistrictId] {
+		match self.neighbors.get(&id) {
+			Some(v) => &v[..],
+			None => &[],
+		}
+	}
+
+	// The district itself, followed by all of its neighbours.
+	pub fn region(&self, id: DistrictId) -> impl Iterator<Item = DistrictId> + '_ {
+		std::iter::once(id).chain(self.neighbors(id).iter().copied())
+	}
 }
 
 #[derive(Debug, Clone, Deserialize)]
+pub struct DistrictCentroidRecord {
+	pub district_id: DistrictId,
+	pub lat: f64,
+	pub lon: f64,
+}
+
+// A point per district (WGS 84, in degrees) to place it on a map, e.g. the centroid of its area. Berlin is placed as a whole, under 11000.
+#[derive(Debug, Clone, Default)]
+pub struct DistrictCentroids {
+	positions: HashMap<DistrictId, (f64, f64)>,
+}
+
+impl DistrictCentroids {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn load<R: io::Read>(r: R) -> Result<Self, Error> {
+		let mut result = Self::new();
+		let mut r = csv::Reader::from_reader(r);
+		for row in r.deserialize() {
+			let rec: DistrictCentroidRecord = row?;
+			result.insert(rec.district_id, rec.lat, rec.lon)?;
+		}
+		Ok(result)
+	}
+
+	pub fn insert(&mut self, id: DistrictId, lat: f64, lon: f64) -> Result<(), Error> {
+		if !(-90. ..=90.).contains(&lat) || !(-180. ..=180.).contains(&lon) {
+			return Err(Error::Schema(format!(
+				"district {}: {}, {} is not a position in degrees",
+				id, lat, lon
+			)));
+		}
+		if self.positions.insert(id, (lat, lon)).is_some() {
+			return Err(Error::Schema(format!(
+				"district {} is listed more than once",
+				id
+			)));
+		}
+		Ok(())
+	}
+
+	// latitude and longitude
+	pub fn get(&self, id: DistrictId) -> Option<(f64, f64)> {
+		self.positions.get(&id).copied()
+	}
+
+	pub fn len(&self) -> usize {
+		self.positions.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.positions.is_empty()
+	}
+}
+
+// The columns of the current (second) version of the diff file, in the order they are written.
+static DIFF_COLUMNS: [&str; 12] = [
+	"Datum",
+	"LandkreisId",
+	"Altersgruppe",
+	"Geschlecht",
+	"VerzugGesamt",
+	"AnzahlFallVerzoegert",
+	"AnzahlFallVerspaetet",
+	"AnzahlFall",
+	"AnzahlTodesfall",
+	"AnzahlGenesen",
+	"AnzahlFaelle7Tage",
+	"AnzahlZurueckgezogen",
+];
+
+// the columns which the first version of the diff file lacks
+static DIFF_V2_COLUMNS: [&str; 2] = ["AnzahlFaelle7Tage", "AnzahlZurueckgezogen"];
+
+// The versions of the diff file written by rki_diff. Both are read into DiffRecord; the columns missing from the first one default to zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffSchema {
+	// without cases_rep_d7 and cases_retracted
+	V1,
+	V2,
+}
+
+impl DiffSchema {
+	pub fn detect(headers: &csv::StringRecord) -> Result<Self, Error> {
+		let columns: Vec<&str> = headers.iter().collect();
+		let schema = if DIFF_V2_COLUMNS.iter().all(|c| columns.contains(c)) {
+			Self::V2
+		} else {
+			Self::V1
+		};
+		let missing: Vec<&str> = DIFF_COLUMNS
+			.iter()
+			.copied()
+			.filter(|c| schema == Self::V2 || !DIFF_V2_COLUMNS.contains(c))
+			.filter(|c| !columns.contains(c))
+			.collect();
+		if !missing.is_empty() {
+			return Err(Error::Schema(format!(
+				"{} diff file lacks the columns {}",
+				schema,
+				missing.join(", ")
+			)));
+		}
+		if schema == Self::V1 {
+			warn!(
+				"{} diff file: {} are read as zero until the file is rewritten by rki_diff",
+				schema,
+				DIFF_V2_COLUMNS.join(", ")
+			);
+		}
+		Ok(schema)
+	}
+}
+
+impl fmt::Display for DiffSchema {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::V1 => f.write_str("v1"),
+			Self::V2 => f.write_str("v2"),
+		}
+	}
+}
+
+// One row of the diff file. The serde names are the columns; the field order is the column order of DIFF_COLUMNS.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct DiffRecord {
 	#[serde(rename = "Datum")]
 	pub date: NaiveDate,
@@ -169,35 +636,18 @@ pub struct DiffRecord {
 	pub deaths: u64,
 	#[serde(rename = "AnzahlGenesen")]
 	pub recovered: u64,
-	#[serde(rename = "AnzahlFaelle7Tage")]
+	#[serde(rename = "AnzahlFaelle7Tage", default)]
 	pub cases_rep_d7: u64,
-	#[serde(rename = "AnzahlZurueckgezogen")]
+	#[serde(rename = "AnzahlZurueckgezogen", default)]
 	pub cases_retracted: u64,
 }
 
 impl DiffRecord {
-	pub fn write_header<W: io::Write>(w: &mut W) -> io::Result<()> {
-		w.write("Datum,LandkreisId,Altersgruppe,Geschlecht,VerzugGesamt,AnzahlFallVerzoegert,AnzahlFallVerspaetet,AnzahlFall,AnzahlTodesfall,AnzahlGenesen,AnzahlFaelle7Tage,AnzahlZurueckgezogen\n".as_bytes())?;
-		Ok(())
-	}
-
-	pub fn write<W: io::Write>(&self, w: &mut W) -> io::Result<()> {
-		write!(
-			w,
-			"{},{},{},{},{},{},{},{},{},{},{},{}\n",
-			self.date,
-			self.district_id,
-			self.age_group,
-			self.sex,
-			self.delay_total,
-			self.cases_delayed,
-			self.late_cases,
-			self.cases,
-			self.deaths,
-			self.recovered,
-			self.cases_rep_d7,
-			self.cases_retracted
-		)
+	// A writer for diff files in the current version, with the header already written (also if no record follows).
+	pub fn writer<W: io::Write>(w: W) -> Result<csv::Writer<W>, Error> {
+		let mut w = csv::WriterBuilder::new().has_headers(false).from_writer(w);
+		w.write_record(DIFF_COLUMNS)?;
+		Ok(w)
 	}
 }
 
@@ -211,6 +661,7 @@ pub enum VaccinationLevel {
 	Basic,
 	#[serde(rename = "3")]
 	Full,
+	// the levels above Full are the booster doses, i.e. Fourth is the second booster and Fifth the third; they are emitted as vacc_fourth_*, vacc_fifth_* and vacc_sixth_*
 	#[serde(rename = "4")]
 	Fourth,
 	#[serde(rename = "5")]
@@ -225,7 +676,8 @@ pub enum VaccinationLevel {
 pub struct VaccinationRecord {
 	#[serde(rename = "Impfdatum")]
 	pub date: NaiveDate,
-	#[serde(rename = "LandkreisId_Impfort")]
+	// the residence-based variant of the dataset has the district of residence instead of the district of the vaccination site; the two must not be mixed
+	#[serde(rename = "LandkreisId_Impfort", alias = "LandkreisId_Wohnort")]
 	pub district_id: MaybeDistrictId,
 	#[serde(rename = "Altersgruppe")]
 	pub age_group: MaybeAgeGroup,
@@ -247,6 +699,64 @@ pub struct HospitalizationRecord {
 	pub cases_d7: u64,
 }
 
+// nowcast-adjusted 7 day hospitalization counts; the most recent days are not available in all releases, hence the options
+#[derive(Debug, Clone, Deserialize)]
+pub struct HospitalizationNowcastRecord {
+	#[serde(rename = "Datum")]
+	pub date: NaiveDate,
+	#[serde(rename = "Bundesland_Id")]
+	pub state_id: StateId,
+	#[serde(rename = "Altersgruppe")]
+	pub age_group: AgeGroup,
+	#[serde(
+		rename = "PS_adjustierte_7T_Hospitalisierung_Faelle",
+		deserialize_with = "csv::invalid_option"
+	)]
+	pub cases_d7: Option<f64>,
+	#[serde(
+		rename = "UG_PI_adjustierte_7T_Hospitalisierung_Faelle",
+		deserialize_with = "csv::invalid_option"
+	)]
+	pub cases_d7_lo: Option<f64>,
+	#[serde(
+		rename = "OG_PI_adjustierte_7T_Hospitalisierung_Faelle",
+		deserialize_with = "csv::invalid_option"
+	)]
+	pub cases_d7_hi: Option<f64>,
+}
+
+// national PCR testing numbers, reported per calendar week
+#[derive(Debug, Clone, Deserialize)]
+pub struct TestingRecord {
+	#[serde(rename = "date", deserialize_with = "iso_week_end")]
+	pub week_end: NaiveDate,
+	#[serde(rename = "tests_total")]
+	pub tests: u64,
+	#[serde(rename = "tests_positive")]
+	pub positive: u64,
+	// in percent
+	#[serde(rename = "tests_positive_ratio")]
+	pub positivity: f64,
+}
+
+// AMELAG wastewater surveillance, one row per site and sampling day
+#[derive(Debug, Clone, Deserialize)]
+pub struct WastewaterRecord {
+	#[serde(rename = "standort")]
+	pub site: SmartString,
+	// ISO 3166-2:DE code without the country prefix
+	#[serde(rename = "bundesland")]
+	pub state_code: SmartString,
+	#[serde(rename = "datum")]
+	pub date: NaiveDate,
+	// gene copies per litre
+	#[serde(rename = "viruslast", deserialize_with = "csv::invalid_option")]
+	pub viral_load: Option<f64>,
+	// older releases only contain SARS-CoV-2 and have no such column
+	#[serde(rename = "typ", default)]
+	pub pathogen: Option<SmartString>,
+}
+
 pub fn find_berlin_districts(
 	districts: &HashMap<DistrictId, Arc<DistrictInfo>>,
 ) -> Vec<GeoCaseKey> {
@@ -267,9 +777,11 @@ pub fn inject_berlin(
 	districts: &mut HashMap<DistrictId, Arc<DistrictInfo>>,
 ) {
 	let mut total_pop = 0;
+	let mut total_area = Some(0.);
 	for (id, district) in districts.iter() {
 		if *id >= 11000 && *id < 12000 {
 			total_pop += district.population;
+			total_area = total_area.zip(district.area).map(|(a, b)| a + b);
 		}
 	}
 
@@ -280,6 +792,7 @@ pub fn inject_berlin(
 			state: states.get(&11).unwrap().clone(),
 			name: "SK Berlin".into(),
 			population: total_pop,
+			area: total_area,
 		}),
 	);
 }
@@ -0,0 +1,158 @@
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, NaiveDate, Utc};
+
+use smartstring::alias::String as SmartString;
+
+use super::influxdb;
+use super::progress::{default_output, Phase, PhaseSink, ProgressSink};
+use super::{Result, StreamOptions};
+
+static RUNS_MEASUREMENT: &str = "meta_v1_runs";
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PhaseStats {
+	pub rows: usize,
+	pub errors: usize,
+	pub bytes: usize,
+	// the last day lines were streamed to InfluxDB for, if any
+	pub last_day: Option<NaiveDate>,
+}
+
+#[derive(Debug, Clone)]
+struct PhaseRecord {
+	phase: SmartString,
+	duration: Duration,
+	stats: PhaseStats,
+}
+
+// Collects statistics about the phases of a pipeline run, so that they can be written to the database alongside the data. All phases of a run share the timestamp of the start of the run.
+#[derive(Debug, Clone)]
+pub struct RunLog {
+	started: DateTime<Utc>,
+	phases: Vec<PhaseRecord>,
+	// for the progress display: the expected number of phases and the number begun so far
+	planned_phases: usize,
+	begun_phases: usize,
+	// the phase begun last and when, until it is recorded; see record_failure
+	pending: Option<(SmartString, Instant)>,
+}
+
+impl RunLog {
+	pub fn new() -> Self {
+		Self {
+			started: Utc::now(),
+			phases: Vec::new(),
+			planned_phases: 0,
+			begun_phases: 0,
+			pending: None,
+		}
+	}
+
+	// Sets the expected number of phases of the run; it is only used to display the progress.
+	pub fn plan_phases(&mut self, n: usize) {
+		self.planned_phases = n;
+	}
+
+	// Progress output for the next phase of the run. The phase ends when the returned sink is dropped.
+	pub fn begin_phase(&mut self, name: &str) -> PhaseSink<Box<dyn ProgressSink>> {
+		self.begun_phases += 1;
+		self.pending = Some((name.into(), Instant::now()));
+		let phase = Phase {
+			name: name.into(),
+			index: self.begun_phases,
+			count: self.planned_phases.max(self.begun_phases),
+		};
+		PhaseSink::begin(default_output(), &phase)
+	}
+
+	pub fn record(&mut self, phase: &str, started: Instant, stats: PhaseStats) {
+		self.pending = None;
+		self.phases.push(PhaseRecord {
+			phase: phase.into(),
+			duration: started.elapsed(),
+			stats,
+		});
+	}
+
+	// Records the phase which was begun but not recorded, i.e. the one the run failed in, under its progress name and with one error. A failure between the phases is recorded as the phase "run".
+	pub fn record_failure(&mut self) {
+		let (phase, started) = self
+			.pending
+			.take()
+			.unwrap_or_else(|| ("run".into(), Instant::now()));
+		self.phases.push(PhaseRecord {
+			phase,
+			duration: started.elapsed(),
+			stats: PhaseStats {
+				errors: 1,
+				..PhaseStats::default()
+			},
+		});
+	}
+
+	// The name and statistics of each phase recorded so far, in order.
+	pub fn phases(&self) -> impl Iterator<Item = (&str, &PhaseStats)> {
+		self.phases
+			.iter()
+			.map(|rec| (rec.phase.as_str(), &rec.stats))
+	}
+
+	pub fn readout(&self) -> influxdb::Readout {
+		influxdb::Readout {
+			ts: self.started,
+			measurement: RUNS_MEASUREMENT.into(),
+			precision: influxdb::Precision::Seconds,
+			tags: vec!["phase".into()],
+			fields: vec![
+				"duration".into(),
+				"rows".into(),
+				"errors".into(),
+				"bytes".into(),
+			],
+			samples: self
+				.phases
+				.iter()
+				.map(|rec| influxdb::Sample {
+					tagv: vec![rec.phase.clone()],
+					fieldv: vec![
+						rec.duration.as_secs_f64().into(),
+						(rec.stats.rows as i64).into(),
+						(rec.stats.errors as i64).into(),
+						(rec.stats.bytes as i64).into(),
+					],
+				})
+				.collect(),
+		}
+	}
+
+	// The readout with the measurement renamed according to `options`, or None if it is disabled there.
+	pub fn readout_for(&self, options: &StreamOptions) -> Option<influxdb::Readout> {
+		if !options.is_enabled(RUNS_MEASUREMENT) {
+			return None;
+		}
+		let mut readout = self.readout();
+		readout.measurement = options.measurement_name(RUNS_MEASUREMENT).into();
+		Some(readout)
+	}
+
+	pub fn submit(&self, client: &influxdb::Client, options: &StreamOptions) -> Result<()> {
+		let readout = match self.readout_for(options) {
+			Some(v) => v,
+			None => return Ok(()),
+		};
+		Ok(client.post(
+			&options.database,
+			options.retention_policy.as_deref(),
+			None,
+			readout.precision,
+			&[readout],
+		)?)
+	}
+}
+
+impl Default for RunLog {
+	fn default() -> Self {
+		Self::new()
+	}
+}
@@ -0,0 +1,145 @@
+use std::convert::TryInto;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use fnv::FnvHasher;
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use log::{info, warn};
+
+use super::diffstore::DiffStore;
+
+static SNAPSHOT_MAGIC: &[u8; 4] = b"CVSS";
+// bumped whenever the layout of a snapshotted structure changes
+static SNAPSHOT_VERSION: u32 = 5;
+
+// Hash of everything a loaded dataset depends on: the content of its input files and the parameters of the load. FNV is used as it is stable across builds, unlike the std hasher.
+#[derive(Default)]
+pub struct InputHash(FnvHasher);
+
+impl InputHash {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	// The content of an input as its loader reads it, through env_fetcher: local files and diff stores, and URLs by their cached copy. A URL which is streamed is hashed by its ETag and Last-Modified, and only read if the server sends neither.
+	pub fn file<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+		let path = path.as_ref();
+		let fetcher = super::env_fetcher();
+		if let Some(local) = fetcher.local_copy(path)? {
+			if DiffStore::is_store(&local) {
+				return self.diff_store(&DiffStore::open(&local)?);
+			}
+			return self.contents(fs::File::open(local)?);
+		}
+		let url = path.to_string_lossy();
+		match fetcher.validators(&url)? {
+			Some(validators) => {
+				self.param(&*url);
+				self.param(&validators);
+				Ok(())
+			}
+			None => self.contents(fetcher.open(path)?),
+		}
+	}
+
+	// A diff store by its partitions: their months, rows and file sizes, as a merge changes at least one of them in the partitions it rewrites. Cheaper than reading the entire store, of which a load with PipelineBuilder::since only reads the last months.
+	pub fn diff_store(&mut self, store: &DiffStore) -> io::Result<()> {
+		for partition in store.partitions() {
+			self.param(&partition.month);
+			self.param(&partition.file);
+			self.param(&partition.rows);
+			self.param(&fs::metadata(store.partition_path(partition))?.len());
+		}
+		self.0.write_u64(u64::MAX);
+		Ok(())
+	}
+
+	fn contents<R: Read>(&mut self, mut r: R) -> io::Result<()> {
+		let mut buf = vec![0u8; 64 * 1024];
+		loop {
+			let n = r.read(&mut buf[..])?;
+			if n == 0 {
+				break;
+			}
+			self.0.write(&buf[..n]);
+		}
+		// so that the boundary between two files matters
+		self.0.write_u64(u64::MAX);
+		Ok(())
+	}
+
+	pub fn param<T: Hash + ?Sized>(&mut self, v: &T) {
+		v.hash(&mut self.0);
+	}
+
+	pub fn finish(&self) -> u64 {
+		self.0.finish()
+	}
+}
+
+// A directory of binary snapshots of loaded datasets, one file per dataset. A snapshot is only used if it was taken from inputs with the same InputHash; otherwise the dataset is loaded from its source and the snapshot replaced. The file starts with an uncompressed header (magic, version, input hash), followed by the zstd-compressed bincode of the dataset.
+#[derive(Debug, Clone)]
+pub struct SnapshotCache {
+	dir: PathBuf,
+}
+
+impl SnapshotCache {
+	pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+		Self { dir: dir.into() }
+	}
+
+	fn path(&self, name: &str) -> PathBuf {
+		self.dir.join(format!("{}.snapshot", name))
+	}
+
+	// The snapshot of `name`, if there is one for `hash`. An unreadable snapshot is treated as missing.
+	pub fn load<T: DeserializeOwned>(&self, name: &str, hash: u64) -> io::Result<Option<T>> {
+		let mut f = match fs::File::open(self.path(name)) {
+			Ok(f) => io::BufReader::new(f),
+			Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+			Err(e) => return Err(e),
+		};
+		let mut header = [0u8; 16];
+		if f.read_exact(&mut header[..]).is_err() || &header[..4] != SNAPSHOT_MAGIC {
+			warn!("{}: ignoring malformed snapshot", name);
+			return Ok(None);
+		}
+		let version = u32::from_le_bytes(header[4..8].try_into().unwrap());
+		let stored_hash = u64::from_le_bytes(header[8..16].try_into().unwrap());
+		if version != SNAPSHOT_VERSION || stored_hash != hash {
+			info!("{}: inputs changed since the snapshot", name);
+			return Ok(None);
+		}
+		match bincode::deserialize_from(zstd::Decoder::new(f)?) {
+			Ok(v) => Ok(Some(v)),
+			Err(e) => {
+				warn!("{}: ignoring unreadable snapshot: {}", name, e);
+				Ok(None)
+			}
+		}
+	}
+
+	// Replaces the snapshot of `name` (via a temporary file and rename).
+	pub fn store<T: Serialize>(&self, name: &str, hash: u64, value: &T) -> io::Result<()> {
+		fs::create_dir_all(&self.dir)?;
+		let path = self.path(name);
+		let mut tmp = path.clone().into_os_string();
+		tmp.push(".tmp");
+		let tmp = PathBuf::from(tmp);
+		{
+			let mut f = io::BufWriter::new(fs::File::create(&tmp)?);
+			f.write_all(SNAPSHOT_MAGIC)?;
+			f.write_all(&SNAPSHOT_VERSION.to_le_bytes())?;
+			f.write_all(&hash.to_le_bytes())?;
+			let mut encoder = zstd::Encoder::new(f, 3)?;
+			bincode::serialize_into(&mut encoder, value).map_err(io::Error::other)?;
+			encoder.finish()?.into_inner()?.sync_all()?;
+		}
+		fs::rename(tmp, path)
+	}
+}
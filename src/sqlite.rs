@@ -0,0 +1,237 @@
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use chrono::NaiveDate;
+
+use rusqlite::types::Value;
+
+use super::influxdb;
+use super::progress::{ProgressSink, StepMeter};
+use super::runlog::PhaseStats;
+use super::timeseries::{TimeSeriesKey, ViewTimeSeries};
+use super::{FieldDescriptor, KeySet, Result, StreamOptions};
+
+fn wrap_err(err: rusqlite::Error) -> io::Error {
+	io::Error::other(err)
+}
+
+fn quote_name(name: &str) -> String {
+	format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn field_value(v: &influxdb::readout::FieldValue) -> Value {
+	match v {
+		influxdb::readout::FieldValue::Numeric(v) => Value::Real(*v),
+		influxdb::readout::FieldValue::Integer(v) => Value::Integer(*v),
+		influxdb::readout::FieldValue::Bool(v) => Value::Integer(*v as i64),
+		influxdb::readout::FieldValue::String(v) => Value::Text(v.to_string()),
+	}
+}
+
+// Writes keyed daily series into a SQLite database instead of InfluxDB. Each measurement becomes a table with one TEXT column per tag, a `date` column (ISO 8601) and one REAL column per field; there is one row per key and date.
+pub struct SqliteSink {
+	conn: rusqlite::Connection,
+}
+
+impl SqliteSink {
+	pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+		let conn = rusqlite::Connection::open(path).map_err(wrap_err)?;
+		Ok(Self { conn })
+	}
+
+	fn prepare_table(
+		&self,
+		measurement: &str,
+		tags: &[&str],
+		time_column: &str,
+		fields: &[&str],
+		field_type: &str,
+	) -> io::Result<String> {
+		let mut columns = Vec::with_capacity(tags.len() + fields.len() + 1);
+		let mut key_columns = Vec::with_capacity(tags.len() + 1);
+		for tag in tags.iter().chain(std::iter::once(&time_column)) {
+			columns.push(format!("{} TEXT NOT NULL", quote_name(tag)));
+			key_columns.push(quote_name(tag));
+		}
+		for field in fields.iter() {
+			columns.push(
+				format!("{} {}", quote_name(field), field_type)
+					.trim_end()
+					.into(),
+			);
+		}
+		let table = quote_name(measurement);
+		self.conn
+			.execute(
+				&format!(
+					"CREATE TABLE IF NOT EXISTS {} ({}, PRIMARY KEY ({}))",
+					table,
+					columns.join(", "),
+					key_columns.join(", "),
+				),
+				[],
+			)
+			.map_err(wrap_err)?;
+
+		// the table may have been created by an earlier run with fewer fields
+		let existing: Vec<String> = {
+			let mut stmt = self
+				.conn
+				.prepare(&format!("PRAGMA table_info({})", table))
+				.map_err(wrap_err)?;
+			let rows = stmt
+				.query_map([], |row| row.get::<_, String>(1))
+				.map_err(wrap_err)?;
+			rows.collect::<rusqlite::Result<_>>().map_err(wrap_err)?
+		};
+		for field in fields.iter() {
+			if !existing.iter().any(|x| x == field) {
+				self.conn
+					.execute(
+						&format!(
+							"ALTER TABLE {} ADD COLUMN {} {}",
+							table,
+							quote_name(field),
+							field_type
+						),
+						[],
+					)
+					.map_err(wrap_err)?;
+			}
+		}
+
+		let names: Vec<_> = tags
+			.iter()
+			.chain(std::iter::once(&time_column))
+			.chain(fields.iter())
+			.map(|x| quote_name(x))
+			.collect();
+		let placeholders: Vec<_> = (1..=names.len()).map(|i| format!("?{}", i)).collect();
+		Ok(format!(
+			"INSERT OR REPLACE INTO {} ({}) VALUES ({})",
+			table,
+			names.join(", "),
+			placeholders.join(", "),
+		))
+	}
+
+	#[allow(clippy::too_many_arguments)]
+	pub fn stream_dynamic<K: TimeSeriesKey, S: ProgressSink + ?Sized>(
+		&mut self,
+		progress: &mut S,
+		measurement: &str,
+		start: NaiveDate,
+		ndays: usize,
+		keyset: &KeySet<K>,
+		fields: &[FieldDescriptor<Arc<dyn ViewTimeSeries<K>>>],
+		options: &StreamOptions,
+	) -> Result<PhaseStats> {
+		let mut stats = PhaseStats::default();
+		if !options.is_enabled(measurement) {
+			return Ok(stats);
+		}
+		let measurement = options.measurement_name(measurement);
+		if keyset.is_empty() {
+			return Ok(stats);
+		}
+		let tag_names: Vec<&str> = keyset
+			.tag_names()
+			.iter()
+			.map(|name| name.as_str())
+			.collect();
+		let field_names: Vec<&str> = fields.iter().map(|desc| desc.name()).collect();
+		let insert = self.prepare_table(measurement, &tag_names, "date", &field_names, "REAL")?;
+		let filter = options.sample_filter;
+		let dates: Vec<String> = start
+			.iter_days()
+			.take(ndays)
+			.map(|date| date.format("%Y-%m-%d").to_string())
+			.collect();
+
+		let tx = self.conn.transaction().map_err(wrap_err)?;
+		{
+			let mut stmt = tx.prepare(&insert).map_err(wrap_err)?;
+			let mut pm = StepMeter::new(progress, keyset.len());
+			// values[field index * ndays + day]
+			let mut values = vec![f64::NAN; fields.len() * ndays];
+			let mut fieldv: Vec<Value> = Vec::with_capacity(fields.len());
+			let mut row: Vec<Value> = Vec::with_capacity(tag_names.len() + 1 + fields.len());
+			let mut previous: Vec<Value> = Vec::new();
+			for (j, entry) in keyset.iter().enumerate() {
+				for (l, desc) in fields.iter().enumerate() {
+					desc.get_range(
+						entry.key,
+						start,
+						ndays,
+						&mut values[l * ndays..(l + 1) * ndays],
+					);
+				}
+				previous.clear();
+				for (i, date) in dates.iter().enumerate() {
+					fieldv.clear();
+					let mut any_present = false;
+					let mut all_default = true;
+					for l in 0..fields.len() {
+						let v = values[l * ndays + i];
+						if v.is_nan() {
+							fieldv.push(Value::Null);
+						} else {
+							any_present = true;
+							if v != 0. {
+								all_default = false;
+							}
+							fieldv.push(Value::Real(v));
+						}
+					}
+					if !any_present || (filter.skip_default && all_default) {
+						continue;
+					}
+					if filter.skip_unchanged {
+						if previous == fieldv {
+							continue;
+						}
+						previous.clear();
+						previous.extend_from_slice(&fieldv);
+					}
+					row.clear();
+					row.extend(entry.tag_values.iter().map(|v| Value::Text(v.to_string())));
+					row.push(Value::Text(date.clone()));
+					row.append(&mut fieldv);
+					stmt.execute(rusqlite::params_from_iter(row.iter()))
+						.map_err(wrap_err)?;
+					stats.rows += 1;
+				}
+				pm.update(j + 1);
+			}
+			pm.finish();
+		}
+		tx.commit().map_err(wrap_err)?;
+		Ok(stats)
+	}
+
+	// Writes an arbitrary readout (e.g. the run statistics) into a table named after its measurement, keyed by tags and the timestamp (RFC 3339).
+	pub fn write_readout(&mut self, readout: &influxdb::Readout) -> Result<()> {
+		let tag_names: Vec<&str> = readout.tags.iter().map(|x| x.as_str()).collect();
+		let field_names: Vec<&str> = readout.fields.iter().map(|x| x.as_str()).collect();
+		let insert =
+			self.prepare_table(&readout.measurement, &tag_names, "time", &field_names, "")?;
+		let ts = readout.ts.to_rfc3339();
+		let tx = self.conn.transaction().map_err(wrap_err)?;
+		{
+			let mut stmt = tx.prepare(&insert).map_err(wrap_err)?;
+			for sample in readout.samples.iter() {
+				let row: Vec<Value> = sample
+					.tagv
+					.iter()
+					.map(|v| Value::Text(v.to_string()))
+					.chain(std::iter::once(Value::Text(ts.clone())))
+					.chain(sample.fieldv.iter().map(field_value))
+					.collect();
+				stmt.execute(rusqlite::params_from_iter(row.iter()))
+					.map_err(wrap_err)?;
+			}
+		}
+		Ok(tx.commit().map_err(wrap_err)?)
+	}
+}
@@ -0,0 +1,230 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+use chrono::NaiveDate;
+
+use log::{debug, warn};
+
+use super::context::DistrictId;
+use super::error::Error;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SubmitError {
+	UnknownDistrict(DistrictId),
+	DateOutOfRange(NaiveDate),
+	NegativeCount(i64),
+}
+
+impl SubmitError {
+	fn kind(&self) -> &'static str {
+		match self {
+			Self::UnknownDistrict(_) => "unknown district",
+			Self::DateOutOfRange(_) => "date out of range",
+			Self::NegativeCount(_) => "negative count",
+		}
+	}
+}
+
+impl fmt::Display for SubmitError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::UnknownDistrict(id) => write!(f, "unknown district: {}", id),
+			Self::DateOutOfRange(date) => write!(f, "date out of range: {}", date),
+			Self::NegativeCount(v) => write!(f, "negative count: {}", v),
+		}
+	}
+}
+
+impl std::error::Error for SubmitError {}
+
+impl From<SubmitError> for io::Error {
+	fn from(err: SubmitError) -> Self {
+		Self::new(io::ErrorKind::InvalidData, err)
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum SubmitPolicy {
+	// abort the load on the first bad row
+	#[default]
+	Fail,
+	// skip bad rows and report how many were skipped
+	Skip,
+	// skip bad rows and report each of them
+	Collect,
+}
+
+#[derive(Debug, Clone)]
+pub struct ParseSubmitPolicyError(String);
+
+impl fmt::Display for ParseSubmitPolicyError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "unknown submit policy: {:?}", self.0)
+	}
+}
+
+impl std::error::Error for ParseSubmitPolicyError {}
+
+impl FromStr for SubmitPolicy {
+	type Err = ParseSubmitPolicyError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"fail" => Ok(Self::Fail),
+			"skip" => Ok(Self::Skip),
+			"collect" => Ok(Self::Collect),
+			other => Err(ParseSubmitPolicyError(other.into())),
+		}
+	}
+}
+
+pub struct SubmitLog {
+	policy: SubmitPolicy,
+	skipped: BTreeMap<&'static str, usize>,
+	collected: Vec<(usize, SubmitError)>,
+}
+
+impl SubmitLog {
+	pub fn new(policy: SubmitPolicy) -> Self {
+		Self {
+			policy,
+			skipped: BTreeMap::new(),
+			collected: Vec::new(),
+		}
+	}
+
+	pub fn handle(&mut self, row: usize, result: Result<(), SubmitError>) -> Result<(), Error> {
+		let err = match result {
+			Ok(()) => return Ok(()),
+			Err(e) => e,
+		};
+		match self.policy {
+			SubmitPolicy::Fail => return Err(Error::Rejected { row, source: err }),
+			SubmitPolicy::Skip => (),
+			SubmitPolicy::Collect => self.collected.push((row, err.clone())),
+		}
+		*self.skipped.entry(err.kind()).or_insert(0) += 1;
+		Ok(())
+	}
+
+	pub fn nskipped(&self) -> usize {
+		self.skipped.values().sum()
+	}
+
+	// Like write_summary, but through the log: the counts as a warning, the collected rows at debug level.
+	pub fn log_summary(&self) {
+		if self.skipped.is_empty() {
+			return;
+		}
+		let kinds: Vec<String> = self
+			.skipped
+			.iter()
+			.map(|(kind, n)| format!("{}: {}", kind, n))
+			.collect();
+		warn!(
+			"... skipped {} rows ({})",
+			self.nskipped(),
+			kinds.join(", ")
+		);
+		for (row, err) in self.collected.iter() {
+			debug!("    row {}: {}", row, err);
+		}
+	}
+
+	pub fn write_summary<W: io::Write>(&self, w: &mut W) -> io::Result<()> {
+		if self.skipped.is_empty() {
+			return Ok(());
+		}
+		write!(w, "... skipped {} rows", self.nskipped())?;
+		for (i, (kind, n)) in self.skipped.iter().enumerate() {
+			write!(w, "{}{}: {}", if i == 0 { " (" } else { ", " }, kind, n)?;
+		}
+		writeln!(w, ")")?;
+		for (row, err) in self.collected.iter() {
+			writeln!(w, "    row {}: {}", row, err)?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn date_error() -> SubmitError {
+		SubmitError::DateOutOfRange(NaiveDate::from_ymd_opt(2019, 12, 31).unwrap())
+	}
+
+	#[test]
+	fn parses_policies() {
+		assert_eq!("fail".parse::<SubmitPolicy>().unwrap(), SubmitPolicy::Fail);
+		assert_eq!("skip".parse::<SubmitPolicy>().unwrap(), SubmitPolicy::Skip);
+		assert_eq!(
+			"collect".parse::<SubmitPolicy>().unwrap(),
+			SubmitPolicy::Collect
+		);
+		assert_eq!(
+			"Skip".parse::<SubmitPolicy>().unwrap_err().to_string(),
+			"unknown submit policy: \"Skip\""
+		);
+		assert!("".parse::<SubmitPolicy>().is_err());
+	}
+
+	#[test]
+	fn fail_rejects_the_first_bad_row() {
+		let mut log = SubmitLog::new(SubmitPolicy::Fail);
+		log.handle(1, Ok(())).unwrap();
+		match log.handle(2, Err(date_error())) {
+			Err(Error::Rejected { row, source }) => {
+				assert_eq!(row, 2);
+				assert_eq!(source, date_error());
+			}
+			other => panic!("unexpected result: {:?}", other),
+		}
+		assert_eq!(log.nskipped(), 0);
+	}
+
+	#[test]
+	fn counts_skipped_rows_by_kind() {
+		let mut log = SubmitLog::new(SubmitPolicy::Skip);
+		log.handle(1, Err(date_error())).unwrap();
+		log.handle(2, Ok(())).unwrap();
+		log.handle(3, Err(SubmitError::UnknownDistrict(1234)))
+			.unwrap();
+		log.handle(4, Err(date_error())).unwrap();
+		assert_eq!(log.nskipped(), 3);
+
+		let mut out = Vec::new();
+		log.write_summary(&mut out).unwrap();
+		assert_eq!(
+			String::from_utf8(out).unwrap(),
+			"... skipped 3 rows (date out of range: 2, unknown district: 1)\n"
+		);
+	}
+
+	#[test]
+	fn collect_reports_each_row() {
+		let mut log = SubmitLog::new(SubmitPolicy::Collect);
+		log.handle(7, Err(SubmitError::NegativeCount(-1))).unwrap();
+		log.handle(9, Err(date_error())).unwrap();
+		assert_eq!(log.nskipped(), 2);
+
+		let mut out = Vec::new();
+		log.write_summary(&mut out).unwrap();
+		assert_eq!(
+			String::from_utf8(out).unwrap(),
+			"... skipped 2 rows (date out of range: 1, negative count: 1)\n    row 7: negative count: -1\n    row 9: date out of range: 2019-12-31\n"
+		);
+	}
+
+	#[test]
+	fn summary_is_empty_without_skipped_rows() {
+		let mut log = SubmitLog::new(SubmitPolicy::Skip);
+		log.handle(1, Ok(())).unwrap();
+		let mut out = Vec::new();
+		log.write_summary(&mut out).unwrap();
+		assert!(out.is_empty());
+	}
+}
@@ -0,0 +1,405 @@
+// Test support: in-process stand-ins for the InfluxDB write endpoint and for a file server, for exercising stream_dynamic, the client and URL inputs without a server. Only built with the `testing` feature.
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use bytes::Bytes;
+
+use super::influxdb;
+use super::influxdb::{HttpRequest, HttpResponse, Transport};
+use super::progress::NullSink;
+use super::runlog::PhaseStats;
+use super::timeseries::{TimeSeriesKey, ViewTimeSeries};
+use super::{FieldDescriptor, KeySet, NaiveDate, Output, Result, StreamOptions};
+
+#[derive(Debug, Clone)]
+pub struct RecordedWrite {
+	pub path: String,
+	// raw (still percent-encoded) query string
+	pub query: String,
+	// decompressed if the request was sent with Content-Encoding: gzip
+	pub body: Vec<u8>,
+	pub gzip: bool,
+	// status code the mock answered with
+	pub status: u16,
+}
+
+impl RecordedWrite {
+	pub fn param(&self, name: &str) -> Option<&str> {
+		self.query.split('&').find_map(|pair| {
+			let mut parts = pair.splitn(2, '=');
+			if parts.next()? == name {
+				Some(parts.next().unwrap_or(""))
+			} else {
+				None
+			}
+		})
+	}
+
+	pub fn lines(&self) -> impl Iterator<Item = &str> {
+		std::str::from_utf8(&self.body)
+			.expect("line protocol is not valid UTF-8")
+			.lines()
+	}
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+	writes: Vec<RecordedWrite>,
+	failures: VecDeque<u16>,
+}
+
+// A minimal HTTP/1.1 server on a random local port which accepts writes like InfluxDB 1.x (204 No Content) and records them. Status codes queued with `fail_next` are returned instead, in order, one per request; failed requests are recorded, too.
+pub struct MockInflux {
+	port: u16,
+	state: Arc<Mutex<MockState>>,
+	stop: Arc<AtomicBool>,
+	thread: Option<thread::JoinHandle<()>>,
+}
+
+impl MockInflux {
+	pub fn start() -> io::Result<Self> {
+		let listener = TcpListener::bind(("127.0.0.1", 0))?;
+		let port = listener.local_addr()?.port();
+		let state = Arc::new(Mutex::new(MockState::default()));
+		let stop = Arc::new(AtomicBool::new(false));
+		let thread = {
+			let state = state.clone();
+			let stop = stop.clone();
+			thread::spawn(move || {
+				for conn in listener.incoming() {
+					if stop.load(Ordering::SeqCst) {
+						break;
+					}
+					let conn = match conn {
+						Ok(v) => v,
+						Err(_) => continue,
+					};
+					let state = state.clone();
+					thread::spawn(move || {
+						// errors only mean that the client hung up
+						let _ = serve(conn, &state);
+					});
+				}
+			})
+		};
+		Ok(Self {
+			port,
+			state,
+			stop,
+			thread: Some(thread),
+		})
+	}
+
+	pub fn url(&self) -> String {
+		format!("http://127.0.0.1:{}", self.port)
+	}
+
+	pub fn client(&self) -> influxdb::Client {
+		influxdb::Client::new(self.url(), influxdb::Auth::None)
+	}
+
+	pub fn fail_next(&self, status: u16) {
+		self.state.lock().unwrap().failures.push_back(status);
+	}
+
+	pub fn writes(&self) -> Vec<RecordedWrite> {
+		self.state.lock().unwrap().writes.clone()
+	}
+
+	// All lines of the successful writes, in order.
+	pub fn lines(&self) -> Vec<String> {
+		self.state
+			.lock()
+			.unwrap()
+			.writes
+			.iter()
+			.filter(|w| w.status == 204)
+			.flat_map(|w| w.lines().map(|l| l.to_string()).collect::<Vec<_>>())
+			.collect()
+	}
+
+	pub fn clear(&self) {
+		self.state.lock().unwrap().writes.clear();
+	}
+}
+
+impl Drop for MockInflux {
+	fn drop(&mut self) {
+		self.stop.store(true, Ordering::SeqCst);
+		// wake up the accept loop so that it notices the stop flag
+		let _ = TcpStream::connect(("127.0.0.1", self.port));
+		if let Some(thread) = self.thread.take() {
+			let _ = thread.join();
+		}
+	}
+}
+
+fn serve(conn: TcpStream, state: &Mutex<MockState>) -> io::Result<()> {
+	let mut w = conn.try_clone()?;
+	let mut r = BufReader::new(conn);
+	loop {
+		let mut request_line = String::new();
+		if r.read_line(&mut request_line)? == 0 {
+			return Ok(());
+		}
+		let target = request_line
+			.split(' ')
+			.nth(1)
+			.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed request line"))?
+			.to_string();
+
+		let mut content_length = 0usize;
+		let mut gzip = false;
+		loop {
+			let mut header = String::new();
+			r.read_line(&mut header)?;
+			let header = header.trim_end();
+			if header.is_empty() {
+				break;
+			}
+			if let Some((name, value)) = header.split_once(':') {
+				let name = name.trim();
+				if name.eq_ignore_ascii_case("content-length") {
+					content_length = value
+						.trim()
+						.parse()
+						.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+				} else if name.eq_ignore_ascii_case("content-encoding") {
+					gzip = value.trim().eq_ignore_ascii_case("gzip");
+				} else if name.eq_ignore_ascii_case("transfer-encoding") {
+					return Err(io::Error::new(
+						io::ErrorKind::InvalidData,
+						"chunked requests are not supported",
+					));
+				}
+			}
+		}
+		let mut body = vec![0u8; content_length];
+		r.read_exact(&mut body)?;
+		if gzip {
+			let mut decoded = Vec::new();
+			flate2::read::GzDecoder::new(&body[..]).read_to_end(&mut decoded)?;
+			body = decoded;
+		}
+
+		let (path, query) = match target.split_once('?') {
+			Some((path, query)) => (path.to_string(), query.to_string()),
+			None => (target, String::new()),
+		};
+		let status = {
+			let mut state = state.lock().unwrap();
+			let status = state.failures.pop_front().unwrap_or(204);
+			state.writes.push(RecordedWrite {
+				path,
+				query,
+				body,
+				gzip,
+				status,
+			});
+			status
+		};
+		if status == 204 {
+			w.write_all(b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n")?;
+		} else {
+			let msg = b"{\"error\":\"injected failure\"}";
+			write!(
+				w,
+				"HTTP/1.1 {} Injected\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+				status,
+				msg.len()
+			)?;
+			w.write_all(msg)?;
+			w.flush()?;
+			let _ = w.shutdown(Shutdown::Both);
+			return Ok(());
+		}
+		w.flush()?;
+	}
+}
+
+#[derive(Debug, Default)]
+struct MockTransportState {
+	requests: Vec<HttpRequest>,
+	responses: VecDeque<HttpResponse>,
+}
+
+// A Transport which records the requests of a Client instead of sending them, for testing the client without any server. Responses queued with `respond` are returned in order, one per request; otherwise it answers 204 No Content like a successful write. Clones share their state, so that one can be handed to the client and the other kept for inspection.
+#[derive(Debug, Clone, Default)]
+pub struct MockTransport {
+	state: Arc<Mutex<MockTransportState>>,
+}
+
+impl MockTransport {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	// A client for http://influxdb.invalid, which sends through this mock.
+	pub fn client(&self, auth: influxdb::Auth) -> influxdb::Client {
+		influxdb::Client::new("http://influxdb.invalid".into(), auth)
+			.with_transport(Box::new(self.clone()))
+	}
+
+	pub fn respond(&self, status: u16, body: &str) {
+		self.state
+			.lock()
+			.unwrap()
+			.responses
+			.push_back(HttpResponse {
+				status,
+				body: Bytes::copy_from_slice(body.as_bytes()),
+			});
+	}
+
+	pub fn requests(&self) -> Vec<HttpRequest> {
+		self.state.lock().unwrap().requests.clone()
+	}
+}
+
+impl Transport for MockTransport {
+	fn send(&self, req: HttpRequest) -> std::result::Result<HttpResponse, influxdb::Error> {
+		let mut state = self.state.lock().unwrap();
+		state.requests.push(req);
+		Ok(state.responses.pop_front().unwrap_or(HttpResponse {
+			status: 204,
+			body: Bytes::new(),
+		}))
+	}
+}
+
+#[derive(Debug, Default)]
+struct MockFilesState {
+	files: HashMap<String, (Vec<u8>, String)>,
+	// the method and path of each request
+	requests: Vec<(String, String)>,
+}
+
+// A minimal HTTP/1.1 server on a random local port which serves files for GET and HEAD, with the ETag given to `put`, for testing the loading of URL inputs. One request per connection.
+pub struct MockFiles {
+	port: u16,
+	state: Arc<Mutex<MockFilesState>>,
+	stop: Arc<AtomicBool>,
+	thread: Option<thread::JoinHandle<()>>,
+}
+
+impl MockFiles {
+	pub fn start() -> io::Result<Self> {
+		let listener = TcpListener::bind(("127.0.0.1", 0))?;
+		let port = listener.local_addr()?.port();
+		let state = Arc::new(Mutex::new(MockFilesState::default()));
+		let stop = Arc::new(AtomicBool::new(false));
+		let thread = {
+			let state = state.clone();
+			let stop = stop.clone();
+			thread::spawn(move || {
+				for conn in listener.incoming() {
+					if stop.load(Ordering::SeqCst) {
+						break;
+					}
+					if let Ok(conn) = conn {
+						let _ = serve_file(conn, &state);
+					}
+				}
+			})
+		};
+		Ok(Self {
+			port,
+			state,
+			stop,
+			thread: Some(thread),
+		})
+	}
+
+	// The URL of `path`, which starts with a slash.
+	pub fn url(&self, path: &str) -> String {
+		format!("http://127.0.0.1:{}{}", self.port, path)
+	}
+
+	pub fn put(&self, path: &str, body: &[u8], etag: &str) {
+		self.state
+			.lock()
+			.unwrap()
+			.files
+			.insert(path.into(), (body.to_vec(), etag.into()));
+	}
+
+	pub fn requests(&self) -> Vec<(String, String)> {
+		self.state.lock().unwrap().requests.clone()
+	}
+}
+
+impl Drop for MockFiles {
+	fn drop(&mut self) {
+		self.stop.store(true, Ordering::SeqCst);
+		let _ = TcpStream::connect(("127.0.0.1", self.port));
+		if let Some(thread) = self.thread.take() {
+			let _ = thread.join();
+		}
+	}
+}
+
+fn serve_file(conn: TcpStream, state: &Mutex<MockFilesState>) -> io::Result<()> {
+	let mut w = conn.try_clone()?;
+	let mut r = BufReader::new(conn);
+	let mut request_line = String::new();
+	r.read_line(&mut request_line)?;
+	let mut parts = request_line.split(' ');
+	let method = parts.next().unwrap_or("").to_string();
+	let path = parts.next().unwrap_or("").to_string();
+	loop {
+		let mut header = String::new();
+		if r.read_line(&mut header)? == 0 || header.trim_end().is_empty() {
+			break;
+		}
+	}
+	let file = {
+		let mut state = state.lock().unwrap();
+		state.requests.push((method.clone(), path.clone()));
+		state.files.get(&path).cloned()
+	};
+	match file {
+		Some((body, etag)) => {
+			write!(
+				w,
+				"HTTP/1.1 200 OK\r\nETag: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+				etag,
+				body.len()
+			)?;
+			if method != "HEAD" {
+				w.write_all(&body)?;
+			}
+		}
+		None => w.write_all(
+			b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+		)?,
+	}
+	w.flush()?;
+	let _ = w.shutdown(Shutdown::Both);
+	Ok(())
+}
+
+// Runs Output::stream_dynamic against the mock without progress output.
+pub fn stream_to_mock<K: TimeSeriesKey>(
+	mock: &MockInflux,
+	measurement: &str,
+	start: NaiveDate,
+	ndays: usize,
+	keyset: &KeySet<K>,
+	fields: &[FieldDescriptor<Arc<dyn ViewTimeSeries<K>>>],
+	options: &StreamOptions,
+) -> Result<PhaseStats> {
+	Output::Influx(mock.client()).stream_dynamic(
+		&mut NullSink(),
+		measurement,
+		start,
+		ndays,
+		keyset,
+		fields,
+		options,
+	)
+}
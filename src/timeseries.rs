@@ -1,20 +1,45 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::hash::Hash;
+use std::ops::AddAssign;
 use std::sync::Arc;
 
-use num_traits::Zero;
+use num_traits::{AsPrimitive, NumCast, PrimInt, Unsigned, Zero};
 
 use chrono::{Datelike, NaiveDate};
 
+use serde::{Deserialize, Serialize};
+
+use super::intern::{KeyInterner, KeyMap};
+
 pub trait TimeSeriesKey: Hash + Eq + Clone + std::fmt::Debug + 'static {}
 impl<T: Hash + Eq + Clone + std::fmt::Debug + 'static> TimeSeriesKey for T {}
 
-#[derive(Debug, Clone)]
+// The value types of counters. u64 is the default; u32 halves the memory of counters which are known to stay below four billion even when summed up, such as the case counts over the full district x age group x sex keyspace.
+pub trait CounterValue:
+	PrimInt + Unsigned + AddAssign + AsPrimitive<f64> + std::fmt::Debug + Send + Sync + 'static
+{
+}
+impl CounterValue for u32 {}
+impl CounterValue for u64 {}
+
+// How the values of a TimeSeries are kept. Dense keeps every day of every key. Compact drops the leading zeros of each key in favour of an offset, which saves most of the memory for keys which only show up late in the pandemic, e.g. the smaller age groups of a district or anything vaccination related. Reading does not care; mutations work on the expanded series and compact them again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum Storage {
+	#[default]
+	Dense,
+	Compact,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TimeSeries<T: Hash + Eq, V: Copy> {
 	start: NaiveDate,
 	keys: HashMap<T, usize>,
 	time_series: Vec<Vec<V>>,
+	// number of leading zeros dropped from each of time_series, all zero with Storage::Dense
+	offsets: Vec<usize>,
+	storage: Storage,
 	len: usize,
 }
 
@@ -28,6 +53,8 @@ impl<T: Hash + Eq, V: Copy> TimeSeries<T, V> {
 			len,
 			keys: HashMap::new(),
 			time_series: Vec::new(),
+			offsets: Vec::new(),
+			storage: Storage::Dense,
 		}
 	}
 
@@ -63,15 +90,68 @@ impl<T: Hash + Eq, V: Copy> TimeSeries<T, V> {
 		self.start + chrono::Duration::days(self.len as i64)
 	}
 
+	// Drops all keys, keeping the date range.
 	pub fn clear(&mut self) {
 		self.keys.clear();
 		self.time_series.clear();
+		self.offsets.clear();
+	}
+
+	// Drops the keys for which `f` is false and returns how many were dropped. The remaining keys keep their relative order of indices.
+	pub fn retain_keys<F: FnMut(&T) -> bool>(&mut self, mut f: F) -> usize {
+		self.retain_indices(|k, _| f(k))
+	}
+
+	fn retain_indices<F: FnMut(&T, usize) -> bool>(&mut self, mut f: F) -> usize {
+		let before = self.keys.len();
+		let mut kept: Vec<(T, usize)> = self
+			.keys
+			.drain()
+			.filter(|(k, index)| f(k, *index))
+			.collect();
+		kept.sort_by_key(|(_, index)| *index);
+		let mut time_series = Vec::with_capacity(kept.len());
+		let mut offsets = Vec::with_capacity(kept.len());
+		for (new_index, (k, index)) in kept.into_iter().enumerate() {
+			time_series.push(std::mem::take(&mut self.time_series[index]));
+			offsets.push(self.offsets[index]);
+			self.keys.insert(k, new_index);
+		}
+		self.time_series = time_series;
+		self.offsets = offsets;
+		before - self.keys.len()
+	}
+
+	#[inline(always)]
+	pub fn key_count(&self) -> usize {
+		self.keys.len()
+	}
+
+	// Number of values held over all keys; less than key_count() * len() with Storage::Compact.
+	pub fn stored_values(&self) -> usize {
+		self.time_series.iter().map(|vec| vec.len()).sum()
+	}
+
+	#[inline(always)]
+	pub fn storage(&self) -> Storage {
+		self.storage
+	}
+
+	// Bytes allocated for the values of all keys.
+	pub fn memory_usage(&self) -> usize {
+		self.time_series
+			.iter()
+			.map(|vec| vec.capacity() * std::mem::size_of::<V>())
+			.sum::<usize>()
+			+ self.offsets.capacity() * std::mem::size_of::<usize>()
 	}
 }
 
 impl<T: TimeSeriesKey, V: Copy + Zero> TimeSeries<T, V> {
+	// With Storage::Compact, the series of `k` stays expanded until the next compaction.
 	pub fn get_or_create(&mut self, k: T) -> &mut [V] {
 		let index = self.get_index_or_create(k);
+		self.expand_series(index);
 		&mut self.time_series[index][..]
 	}
 
@@ -80,8 +160,11 @@ impl<T: TimeSeriesKey, V: Copy + Zero> TimeSeries<T, V> {
 			Some(v) => *v,
 			None => {
 				let v = self.time_series.len();
-				let mut vec = Vec::with_capacity(self.len);
-				vec.resize(self.len, V::zero());
+				let vec = match self.storage {
+					Storage::Dense => vec![V::zero(); self.len],
+					Storage::Compact => Vec::new(),
+				};
+				self.offsets.push(self.len - vec.len());
 				self.time_series.push(vec);
 				self.keys.insert(k, v);
 				v
@@ -96,7 +179,11 @@ impl<T: TimeSeriesKey, V: Copy + Zero> TimeSeries<T, V> {
 			None => {
 				let v = self.time_series.len();
 				self.time_series.push(vec);
+				self.offsets.push(0);
 				self.keys.insert(k, v);
+				if self.storage == Storage::Compact {
+					self.compact_series(v);
+				}
 				v
 			}
 		}
@@ -106,22 +193,171 @@ impl<T: TimeSeriesKey, V: Copy + Zero> TimeSeries<T, V> {
 		Some(*self.keys.get(k)?)
 	}
 
-	pub fn get(&self, k: &T) -> Option<&[V]> {
-		let index = self.get_index(k)?;
-		Some(&self.time_series[index][..])
+	// The values of `k` with all days, regardless of the storage; borrowed unless the series is compacted, in which case it is copied.
+	pub fn get(&self, k: &T) -> Option<Cow<'_, [V]>> {
+		Some(self.series(self.get_index(k)?))
 	}
 
 	pub fn get_value(&self, k: &T, i: usize) -> Option<V> {
 		if i >= self.len {
 			return None;
 		}
-		self.get(k).and_then(|v| Some(v[i]))
+		let index = self.get_index(k)?;
+		Some(match i.checked_sub(self.offsets[index]) {
+			Some(j) => self.time_series[index][j],
+			None => V::zero(),
+		})
+	}
+
+	fn series(&self, index: usize) -> Cow<'_, [V]> {
+		let offset = self.offsets[index];
+		let vec = &self.time_series[index];
+		if offset == 0 {
+			return Cow::Borrowed(&vec[..]);
+		}
+		let mut dense = Vec::with_capacity(self.len);
+		dense.resize(offset, V::zero());
+		dense.extend_from_slice(&vec[..]);
+		Cow::Owned(dense)
+	}
+
+	fn expand_series(&mut self, index: usize) {
+		if self.offsets[index] > 0 {
+			self.time_series[index] = self.series(index).into_owned();
+			self.offsets[index] = 0;
+		}
+	}
+
+	fn compact_series(&mut self, index: usize) {
+		let vec = &self.time_series[index];
+		let n = vec.iter().take_while(|v| v.is_zero()).count();
+		if n == 0 && vec.len() == vec.capacity() {
+			return;
+		}
+		self.time_series[index] = vec[n..].to_vec();
+		self.offsets[index] += n;
+	}
+
+	// Switches to `storage`, compacting or expanding all keys.
+	pub fn set_storage(&mut self, storage: Storage) {
+		self.storage = storage;
+		for index in 0..self.time_series.len() {
+			match storage {
+				Storage::Dense => self.expand_series(index),
+				Storage::Compact => self.compact_series(index),
+			}
+		}
+	}
+
+	// Applies `f` to the expanded values of each key, one key at a time, so that a compacted series never is expanded entirely.
+	fn for_each_series<F: FnMut(&mut Vec<V>)>(&mut self, mut f: F) {
+		for index in 0..self.time_series.len() {
+			self.expand_series(index);
+			f(&mut self.time_series[index]);
+			if self.storage == Storage::Compact {
+				self.compact_series(index);
+			}
+		}
 	}
 
 	pub fn keys(&self) -> std::collections::hash_map::Keys<'_, T, usize> {
 		self.keys.keys()
 	}
 
+	// The keys which are zero on all days.
+	pub fn zero_keys(&self) -> impl Iterator<Item = &T> + '_ {
+		self.keys
+			.iter()
+			.filter(move |(_, index)| self.time_series[**index].iter().all(|v| v.is_zero()))
+			.map(|(k, _)| k)
+	}
+
+	// Drops the keys which are zero on all days, e.g. those of a long-running state whose values were all retracted, and returns how many were dropped.
+	pub fn prune_zero_keys(&mut self) -> usize {
+		let keep: Vec<bool> = self
+			.time_series
+			.iter()
+			.map(|vec| !vec.iter().all(|v| v.is_zero()))
+			.collect();
+		if keep.iter().all(|keep| *keep) {
+			return 0;
+		}
+		self.retain_indices(|_, index| keep[index])
+	}
+
+	// A copy of the days from `start` to `end` (exclusive) with all keys; days outside of this series are zero.
+	pub fn slice(&self, start: NaiveDate, end: NaiveDate) -> Self {
+		let mut result = Self::new(start, end);
+		let offset = (self.start - start).num_days();
+		for (k, index) in self.keys.iter() {
+			let src = self.series(*index);
+			let mut dst = Vec::with_capacity(result.len);
+			dst.extend((0..result.len as i64).map(|i| {
+				(i - offset)
+					.try_into()
+					.ok()
+					.and_then(|j: usize| src.get(j).copied())
+					.unwrap_or_else(V::zero)
+			}));
+			result.get_index_or_insert(k.clone(), dst);
+		}
+		result.set_storage(self.storage);
+		result
+	}
+
+	// Extends the series with zeros up to `end` (exclusive); does nothing if it already reaches `end`.
+	pub fn extend_to(&mut self, end: NaiveDate) {
+		let len = (end - self.start).num_days();
+		if len <= self.len as i64 {
+			return;
+		}
+		self.len = len as usize;
+		for (vec, offset) in self.time_series.iter_mut().zip(self.offsets.iter()) {
+			vec.resize(self.len - offset, V::zero());
+		}
+	}
+
+	// Drops the days from `end` on; does nothing if the series ends before `end`. Days before the start cannot be dropped, so the series is at worst empty.
+	pub fn truncate(&mut self, end: NaiveDate) {
+		let len = (end - self.start).num_days().max(0);
+		if len >= self.len as i64 {
+			return;
+		}
+		self.len = len as usize;
+		for (vec, offset) in self.time_series.iter_mut().zip(self.offsets.iter_mut()) {
+			*offset = (*offset).min(self.len);
+			vec.truncate(self.len - *offset);
+		}
+	}
+
+	// Combines two series element-wise with `f`. The result spans both date ranges and has the keys of both; values outside a series or for keys it does not have are taken as zero.
+	pub fn merge<F: Fn(V, V) -> V>(&self, other: &Self, f: F) -> Self {
+		let start = self.start.min(other.start);
+		let mut result = Self::new(start, self.end().max(other.end()));
+		for k in self.keys().chain(other.keys()) {
+			result.get_index_or_create(k.clone());
+		}
+		let offset_a = (self.start - start).num_days() as usize;
+		let offset_b = (other.start - start).num_days() as usize;
+		for (k, index) in result.keys.iter() {
+			let a = self.get(k).unwrap_or(Cow::Borrowed(&[]));
+			let b = other.get(k).unwrap_or(Cow::Borrowed(&[]));
+			for (i, dst) in result.time_series[*index].iter_mut().enumerate() {
+				let va = i
+					.checked_sub(offset_a)
+					.and_then(|j| a.get(j).copied())
+					.unwrap_or_else(V::zero);
+				let vb = i
+					.checked_sub(offset_b)
+					.and_then(|j| b.get(j).copied())
+					.unwrap_or_else(V::zero);
+				*dst = f(va, vb);
+			}
+		}
+		result.set_storage(self.storage);
+		result
+	}
+
 	// occassionally useful for debugging
 	#[allow(dead_code)]
 	fn reverse_index(&self, i: usize) -> Option<&T> {
@@ -134,37 +370,72 @@ impl<T: TimeSeriesKey, V: Copy + Zero> TimeSeries<T, V> {
 	}
 }
 
-impl<T: TimeSeriesKey> TimeSeries<T, u64> {
-	pub fn rekeyed<U: TimeSeriesKey, F: Fn(&T) -> Option<U>>(&self, f: F) -> TimeSeries<U, u64> {
-		let mut result = TimeSeries::<U, u64> {
-			start: self.start,
-			len: self.len,
-			keys: HashMap::new(),
-			time_series: Vec::new(),
-		};
+impl<T: TimeSeriesKey, V: CounterValue> TimeSeries<T, V> {
+	pub fn rekeyed<U: TimeSeriesKey, F: Fn(&T) -> Option<U>>(&self, f: F) -> TimeSeries<U, V> {
+		self.aggregated(f)
+	}
+
+	// Like rekeyed, but each old key may contribute to any number of new keys, e.g. to sum each district with its neighbours.
+	pub fn aggregated<U: TimeSeriesKey, I: IntoIterator<Item = U>, F: Fn(&T) -> I>(
+		&self,
+		f: F,
+	) -> TimeSeries<U, V> {
+		let mut result = TimeSeries::<U, V>::new(self.start(), self.end());
 		for (k_old, index_old) in self.keys.iter() {
-			let k_new = match f(&k_old) {
-				Some(k) => k,
-				None => continue,
-			};
-			let ts_new = result.get_or_create(k_new);
+			let offset = self.offsets[*index_old];
 			let ts_old = &self.time_series[*index_old][..];
-			assert_eq!(ts_new.len(), ts_old.len());
-			for i in 0..ts_new.len() {
-				// This is safe because we asserted that both slices have the
-				// same length and the loop is only going up to that length
-				// minus one.
-				unsafe {
-					*ts_new.get_unchecked_mut(i) += *ts_old.get_unchecked(i);
+			for k_new in f(k_old) {
+				let ts_new = &mut result.get_or_create(k_new)[offset..];
+				assert_eq!(ts_new.len(), ts_old.len());
+				for i in 0..ts_new.len() {
+					// This is safe because we asserted that both slices have the
+					// same length and the loop is only going up to that length
+					// minus one.
+					unsafe {
+						*ts_new.get_unchecked_mut(i) += *ts_old.get_unchecked(i);
+					}
 				}
 			}
 		}
+		result.set_storage(self.storage);
+		result
+	}
+
+	// Like rekeyed, with the new keys looked up beforehand: `source` has to hold all keys of this series and `map` has to be built from it. When rekeying all series of a dataset, this evaluates the mapping once per key instead of once per key and series, and sums up into vectors indexed by the target ids instead of hashing the new keys.
+	pub fn rekeyed_via<U: TimeSeriesKey>(
+		&self,
+		source: &KeyInterner<T>,
+		map: &KeyMap<U>,
+	) -> TimeSeries<U, V> {
+		let mut sums: Vec<Option<Vec<V>>> = vec![None; map.targets().len()];
+		for (k, index) in self.keys.iter() {
+			let id = source.get(k).expect("key missing from the interner");
+			let target = match map.get(id) {
+				Some(v) => v,
+				None => continue,
+			};
+			let dst = sums[target.index()].get_or_insert_with(|| vec![V::zero(); self.len]);
+			let offset = self.offsets[*index];
+			for (d, v) in dst[offset..]
+				.iter_mut()
+				.zip(self.time_series[*index].iter())
+			{
+				*d += *v;
+			}
+		}
+		let mut result = TimeSeries::<U, V>::new(self.start(), self.end());
+		for (target, vec) in map.targets().iter().zip(sums) {
+			if let Some(vec) = vec {
+				result.get_index_or_insert(target.1.clone(), vec);
+			}
+		}
+		result.set_storage(self.storage);
 		result
 	}
 
 	pub fn synthesize(&mut self, kin: &[&T], kout: T) {
 		let mut vtemp = Vec::new();
-		vtemp.resize(self.len, 0);
+		vtemp.resize(self.len, V::zero());
 		for k in kin {
 			let tsin = match self.get(k) {
 				Some(ts) => ts,
@@ -183,26 +454,85 @@ impl<T: TimeSeriesKey> TimeSeries<T, u64> {
 		self.get_index_or_insert(kout, vtemp);
 	}
 
+	pub fn add(&self, other: &Self) -> Self {
+		self.merge(other, |a, b| a.checked_add(&b).expect("overflow in add"))
+	}
+
+	pub fn sub(&self, other: &Self) -> Self {
+		self.merge(other, |a, b| {
+			a.checked_sub(&b)
+				.expect("sub needs other to be at most self everywhere")
+		})
+	}
+
+	pub fn saturating_add(&self, other: &Self) -> Self {
+		self.merge(other, |a, b| a.saturating_add(b))
+	}
+
+	pub fn saturating_sub(&self, other: &Self) -> Self {
+		self.merge(other, |a, b| a.saturating_sub(b))
+	}
+
 	pub fn cumsum(&mut self) {
-		for vec in self.time_series.iter_mut() {
-			let mut accum: u64 = 0;
+		self.for_each_series(|vec| {
+			let mut accum = V::zero();
 			for v in vec.iter_mut() {
 				accum += *v;
 				*v = accum;
 			}
-		}
+		})
 	}
 
-	pub fn diff(&mut self, offset: usize) {
-		for vec in self.time_series.iter_mut() {
+	fn diff_by<F: Fn(V, V) -> V>(&mut self, offset: usize, f: F) {
+		self.for_each_series(|vec| {
+			// an offset beyond the end leaves nothing to diff against
+			let offset = offset.min(vec.len());
 			for i in offset..vec.len() {
 				let r = vec[i];
 				let i_l = i - offset;
-				vec[i_l] = r.checked_sub(vec[i_l]).expect("diff needs cumsum as input");
+				vec[i_l] = f(r, vec[i_l]);
 			}
 			vec.rotate_right(offset);
-			vec[..offset].fill(0);
+			vec[..offset].fill(V::zero());
+		})
+	}
+
+	pub fn diff(&mut self, offset: usize) {
+		self.diff_by(offset, |r, l| {
+			r.checked_sub(&l).expect("diff needs cumsum as input")
+		})
+	}
+
+	pub fn saturating_diff(&mut self, offset: usize) {
+		self.diff_by(offset, |r, l| r.saturating_sub(l))
+	}
+
+	pub fn signed_diff(&self, offset: usize) -> TimeSeries<T, i64> {
+		let mut result = TimeSeries::<T, i64> {
+			start: self.start,
+			len: self.len,
+			keys: self.keys.clone(),
+			time_series: Vec::with_capacity(self.time_series.len()),
+			offsets: vec![0; self.offsets.len()],
+			storage: Storage::Dense,
+		};
+		for index in 0..self.time_series.len() {
+			let src = self.series(index);
+			let mut dst = Vec::with_capacity(src.len());
+			dst.resize(offset.min(src.len()), 0i64);
+			for i in offset..src.len() {
+				let (r, l) = (src[i], src[i - offset]);
+				// differences beyond the range of i64 saturate
+				dst.push(if r >= l {
+					(r - l).to_i64().unwrap_or(i64::MAX)
+				} else {
+					(l - r).to_i64().map_or(i64::MIN, |v| -v)
+				});
+			}
+			result.time_series.push(dst);
 		}
+		result.set_storage(self.storage);
+		result
 	}
 
 	pub fn unrolled(&self, window_size: usize) -> Self {
@@ -211,21 +541,22 @@ impl<T: TimeSeriesKey> TimeSeries<T, u64> {
 		// The overall difference is something like a dozen or so, so good enough™.
 		// Most of the difference is also currently accured during the beginning of the pandemic, so it's rather likely that these are artifacts caused by retractions or somesuch.
 		let mut result = self.clone();
+		result.set_storage(Storage::Dense);
 		for (vec_index, dst) in result.time_series.iter_mut().enumerate() {
-			let src = &self.time_series[vec_index];
+			let src = self.series(vec_index);
 			let mut neg_carry: u64 = 0;
 			for i in 0..dst.len() {
 				let v_l: i64 = if i < window_size {
 					0
 				} else {
-					dst[i - window_size].try_into().unwrap()
+					dst[i - window_size].to_i64().unwrap()
 				};
 				let v_p: i64 = if i > 0 {
-					src[i - 1].try_into().unwrap()
+					src[i - 1].to_i64().unwrap()
 				} else {
 					0
 				};
-				let v_c: i64 = src[i].try_into().unwrap();
+				let v_c = src[i].to_i64().unwrap();
 				let new = (v_c - v_p) + v_l;
 				let new: u64 = if new < 0 {
 					// this can happen on weird data. we smooth this out by carrying the negative result downward
@@ -244,48 +575,122 @@ impl<T: TimeSeriesKey> TimeSeries<T, u64> {
 					}
 					new
 				};
-				dst[i] = new as u64;
+				dst[i] = <V as NumCast>::from(new).unwrap();
 			}
 		}
+		result.set_storage(self.storage);
 		result
 	}
 
 	pub fn shift_fwd(&mut self, offset: usize) {
 		if offset >= self.len {
-			for vec in self.time_series.iter_mut() {
-				vec.fill(0);
-			}
+			self.for_each_series(|vec| vec.fill(V::zero()));
+			return;
 		}
-		for vec in self.time_series.iter_mut() {
+		self.for_each_series(|vec| {
 			vec.rotate_right(offset);
-			vec[..offset].fill(0);
-		}
+			vec[..offset].fill(V::zero());
+		})
 	}
 
-	pub fn timesummed<F: Fn(NaiveDate) -> NaiveDate>(&self, f: F) -> SparseTimeSeries<T, u64> {
+	pub fn timesummed<F: Fn(NaiveDate) -> NaiveDate>(&self, f: F) -> SparseTimeSeries<T, V> {
 		let mut result = SparseTimeSeries {
 			keys: self.keys.clone(),
 			time_series: Vec::with_capacity(self.time_series.len()),
 		};
-		for (vec_index, vec) in self.time_series.iter().enumerate() {
+		for vec_index in 0..self.time_series.len() {
+			let vec = self.series(vec_index);
 			for (j, date_in) in self.start.iter_days().take(self.len).enumerate() {
 				// TODO: might want to optimize this for high-cardinality vectors by allocating a temporary mapping vector?
 				let date_out = f(date_in);
-				*result.insert_default_into_vector(vec_index, &date_out, &0u64) += vec[j];
+				*result.insert_default_into_vector(vec_index, &date_out, &V::zero()) += vec[j];
 			}
 		}
 		result
 	}
 }
 
+// f32 and f64 gauges share their arithmetic, but are distinct types for the counter impls to stay apart from.
+macro_rules! gauge_arithmetic {
+	($v:ty) => {
+		impl<T: TimeSeriesKey> TimeSeries<T, $v> {
+			pub fn add(&self, other: &Self) -> Self {
+				self.merge(other, |a, b| a + b)
+			}
+
+			pub fn sub(&self, other: &Self) -> Self {
+				self.merge(other, |a, b| a - b)
+			}
+
+			// clamps at zero, like the counter variant, for gauges which cannot go negative
+			pub fn saturating_sub(&self, other: &Self) -> Self {
+				self.merge(other, |a, b| (a - b).max(0.))
+			}
+		}
+
+		impl<T: TimeSeriesKey> ViewTimeSeries<T> for TimeSeries<T, $v> {
+			fn getf(&self, k: &T, at: NaiveDate) -> Option<f64> {
+				let i = self.date_index(at)?;
+				Some(self.get_value(k, i).unwrap_or(0.) as f64)
+			}
+
+			fn get_range(&self, k: &T, start: NaiveDate, ndays: usize, out: &mut [f64]) {
+				self.get_range_as_f64(k, start, ndays, out, |v| v as f64)
+			}
+		}
+	};
+}
+
+gauge_arithmetic!(f32);
+gauge_arithmetic!(f64);
+
 pub trait ViewTimeSeries<T: TimeSeriesKey> {
 	fn getf(&self, k: &T, at: NaiveDate) -> Option<f64>;
+
+	// Evaluates `ndays` days starting at `start` into the first `ndays` elements of `out`. Days without a value are written as NaN.
+	fn get_range(&self, k: &T, start: NaiveDate, ndays: usize, out: &mut [f64]) {
+		for (i, at) in start.iter_days().take(ndays).enumerate() {
+			out[i] = self.getf(k, at).unwrap_or(f64::NAN);
+		}
+	}
+}
+
+impl<T: TimeSeriesKey, V: Copy + Zero> TimeSeries<T, V> {
+	fn get_range_as_f64<F: Fn(V) -> f64>(
+		&self,
+		k: &T,
+		start: NaiveDate,
+		ndays: usize,
+		out: &mut [f64],
+		f: F,
+	) {
+		let out = &mut out[..ndays];
+		let offset = (start - self.start).num_days();
+		let (vec, leading) = match self.get_index(k) {
+			Some(index) => (&self.time_series[index][..], self.offsets[index] as i64),
+			None => (&[][..], self.len as i64),
+		};
+		for (i, v) in out.iter_mut().enumerate() {
+			let j = offset + i as i64;
+			*v = if j < 0 || j as usize >= self.len {
+				f64::NAN
+			} else if j < leading {
+				0.
+			} else {
+				f(vec[(j - leading) as usize])
+			};
+		}
+	}
 }
 
-impl<T: TimeSeriesKey> ViewTimeSeries<T> for TimeSeries<T, u64> {
+impl<T: TimeSeriesKey, V: CounterValue> ViewTimeSeries<T> for TimeSeries<T, V> {
 	fn getf(&self, k: &T, at: NaiveDate) -> Option<f64> {
 		let i = self.date_index(at)?;
-		Some(self.get_value(k, i).unwrap_or(0) as f64)
+		Some(self.get_value(k, i).unwrap_or_else(V::zero).as_())
+	}
+
+	fn get_range(&self, k: &T, start: NaiveDate, ndays: usize, out: &mut [f64]) {
+		self.get_range_as_f64(k, start, ndays, out, |v| v.as_())
 	}
 }
 
@@ -294,12 +699,9 @@ impl<T: TimeSeriesKey> ViewTimeSeries<T> for TimeSeries<T, i64> {
 		let i = self.date_index(at)?;
 		Some(self.get_value(k, i).unwrap_or(0) as f64)
 	}
-}
 
-impl<T: TimeSeriesKey> ViewTimeSeries<T> for TimeSeries<T, f64> {
-	fn getf(&self, k: &T, at: NaiveDate) -> Option<f64> {
-		let i = self.date_index(at)?;
-		Some(self.get_value(k, i).unwrap_or(0.))
+	fn get_range(&self, k: &T, start: NaiveDate, ndays: usize, out: &mut [f64]) {
+		self.get_range_as_f64(k, start, ndays, out, |v| v as f64)
 	}
 }
 
@@ -348,37 +750,267 @@ impl<K: TimeSeriesKey, I: ViewTimeSeries<K>> ViewTimeSeries<K> for TimeMap<I> {
 		let at = at + chrono::Duration::days(self.by);
 		self.inner.getf(k, at).or(self.pad)
 	}
+
+	fn get_range(&self, k: &K, start: NaiveDate, ndays: usize, out: &mut [f64]) {
+		self.inner
+			.get_range(k, start + chrono::Duration::days(self.by), ndays, out);
+		for (v, at) in out[..ndays].iter_mut().zip(start.iter_days()) {
+			let clamped = self.start.map(|start| at < start).unwrap_or(false)
+				|| self.end.map(|end| at >= end).unwrap_or(false);
+			if clamped {
+				*v = f64::NAN;
+			} else if v.is_nan() {
+				*v = self.pad.unwrap_or(f64::NAN);
+			}
+		}
+	}
+}
+
+// Hides the values of each key before its own start date, shifted by `offset` days. Keys without a start date are hidden entirely. With `starts` set to None, all values are passed through.
+pub struct KeyedClamp<K: TimeSeriesKey, I> {
+	inner: I,
+	starts: Option<Arc<HashMap<K, NaiveDate>>>,
+	offset: i64,
+}
+
+impl<K: TimeSeriesKey, I> KeyedClamp<K, I> {
+	pub fn new(inner: I, starts: Option<Arc<HashMap<K, NaiveDate>>>, offset: i64) -> Self {
+		Self {
+			inner,
+			starts,
+			offset,
+		}
+	}
+
+	// None if not clamping at all, Some(None) if the key has no start date
+	fn start(&self, k: &K) -> Option<Option<NaiveDate>> {
+		self.starts.as_ref().map(|starts| {
+			starts
+				.get(k)
+				.map(|start| *start + chrono::Duration::days(self.offset))
+		})
+	}
+}
+
+impl<K: TimeSeriesKey, I: ViewTimeSeries<K>> ViewTimeSeries<K> for KeyedClamp<K, I> {
+	fn getf(&self, k: &K, at: NaiveDate) -> Option<f64> {
+		match self.start(k) {
+			Some(None) => return None,
+			Some(Some(start)) if at < start => return None,
+			_ => (),
+		}
+		self.inner.getf(k, at)
+	}
+
+	fn get_range(&self, k: &K, start: NaiveDate, ndays: usize, out: &mut [f64]) {
+		self.inner.get_range(k, start, ndays, out);
+		match self.start(k) {
+			None => (),
+			Some(None) => out[..ndays].fill(f64::NAN),
+			Some(Some(key_start)) => {
+				for (v, at) in out[..ndays].iter_mut().zip(start.iter_days()) {
+					if at >= key_start {
+						break;
+					}
+					*v = f64::NAN;
+				}
+			}
+		}
+	}
 }
 
+// Holds the values of `inner` at fixed dates: each day gets the value of the latest of the dates on or before it, and the days before the first date that of the first one. With a single date, the series is constant.
 pub struct Filled<I> {
 	inner: I,
-	from: NaiveDate,
+	// ascending, never empty
+	dates: Arc<Vec<NaiveDate>>,
 }
 
 impl<I> Filled<I> {
 	pub fn new(inner: I, from: NaiveDate) -> Self {
-		Self { inner, from }
+		Self {
+			inner,
+			dates: Arc::new(vec![from]),
+		}
+	}
+
+	// `dates` must be sorted and must not be empty
+	pub fn stepwise(inner: I, dates: Arc<Vec<NaiveDate>>) -> Self {
+		assert!(!dates.is_empty());
+		Self { inner, dates }
+	}
+
+	fn date_for(&self, at: NaiveDate) -> NaiveDate {
+		let i = self.dates.partition_point(|date| *date <= at);
+		self.dates[i.saturating_sub(1)]
 	}
 }
 
 impl<K: TimeSeriesKey, I: ViewTimeSeries<K>> ViewTimeSeries<K> for Filled<I> {
-	fn getf(&self, k: &K, _at: NaiveDate) -> Option<f64> {
-		self.inner.getf(k, self.from)
+	fn getf(&self, k: &K, at: NaiveDate) -> Option<f64> {
+		self.inner.getf(k, self.date_for(at))
+	}
+}
+
+// How Interpolate fills the days without a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FillStrategy {
+	// leave them empty
+	None,
+	// the latest earlier value, if it is at most `max_age` days old
+	Forward { max_age: u32 },
+	// on a straight line between the neighbouring values, for gaps of at most `max_gap` days
+	Linear { max_gap: u32 },
+}
+
+// Fills the gaps of a gauge. A day is a gap if `inner` has no value for it or if `mask` has no value or zero, e.g. a count of reporting sites; this is how gaps in Counters (which read as zero) are found.
+pub struct Interpolate<I, M> {
+	inner: I,
+	mask: M,
+	strategy: FillStrategy,
+}
+
+impl<I> Interpolate<I, Constant> {
+	pub fn new(inner: I, strategy: FillStrategy) -> Self {
+		Self {
+			inner,
+			mask: Constant(1.),
+			strategy,
+		}
 	}
 }
 
+impl<I, M> Interpolate<I, M> {
+	pub fn masked(inner: I, mask: M, strategy: FillStrategy) -> Self {
+		Self {
+			inner,
+			mask,
+			strategy,
+		}
+	}
+
+	// days before and after the requested range which may contribute
+	fn margins(&self) -> (usize, usize) {
+		match self.strategy {
+			FillStrategy::None => (0, 0),
+			FillStrategy::Forward { max_age } => (max_age as usize, 0),
+			FillStrategy::Linear { max_gap } => (max_gap as usize, max_gap as usize),
+		}
+	}
+}
+
+impl<K: TimeSeriesKey, I: ViewTimeSeries<K>, M: ViewTimeSeries<K>> ViewTimeSeries<K>
+	for Interpolate<I, M>
+{
+	fn getf(&self, k: &K, at: NaiveDate) -> Option<f64> {
+		let mut out = [f64::NAN];
+		self.get_range(k, at, 1, &mut out[..]);
+		Some(out[0]).filter(|v| !v.is_nan())
+	}
+
+	fn get_range(&self, k: &K, start: NaiveDate, ndays: usize, out: &mut [f64]) {
+		let (before, after) = self.margins();
+		let total = before + ndays + after;
+		let first = start - chrono::Duration::days(before as i64);
+		let mut values = vec![f64::NAN; total];
+		let mut mask = vec![f64::NAN; total];
+		self.inner.get_range(k, first, total, &mut values[..]);
+		self.mask.get_range(k, first, total, &mut mask[..]);
+		for (v, m) in values.iter_mut().zip(mask.iter()) {
+			if m.is_nan() || *m == 0. {
+				*v = f64::NAN;
+			}
+		}
+		for (i, v) in out[..ndays].iter_mut().enumerate() {
+			let j = before + i;
+			*v = values[j];
+			if !v.is_nan() {
+				continue;
+			}
+			match self.strategy {
+				FillStrategy::None => (),
+				FillStrategy::Forward { max_age } => {
+					if let Some(prev) = (1..=max_age as usize)
+						.map(|d| values[j - d])
+						.find(|v| !v.is_nan())
+					{
+						*v = prev;
+					}
+				}
+				FillStrategy::Linear { max_gap } => {
+					let max_gap = max_gap as usize;
+					let prev = (1..=max_gap).find(|d| !values[j - d].is_nan());
+					let next = (1..=max_gap).find(|d| !values[j + d].is_nan());
+					if let (Some(d0), Some(d1)) = (prev, next) {
+						// the number of missing days between the two values
+						if d0 + d1 - 1 <= max_gap {
+							let (v0, v1) = (values[j - d0], values[j + d1]);
+							*v = v0 + (v1 - v0) * d0 as f64 / (d0 + d1) as f64;
+						}
+					}
+				}
+			}
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffPolicy {
+	// panic if the difference becomes negative, i.e. if the input is not monotonic
+	Strict,
+	// negative differences are clamped to zero
+	Saturating,
+	// negative differences are passed through; this happens with corrected upstream data
+	Signed,
+}
+
 pub struct Diff<I> {
 	inner: I,
 	window: u32,
 	pad: Option<f64>,
+	policy: DiffPolicy,
 }
 
 impl<I> Diff<I> {
+	// without padding, there is no value for the first `window` days of the inner series
+	pub fn new(inner: I, window: u32) -> Self {
+		Self {
+			inner,
+			window,
+			pad: None,
+			policy: DiffPolicy::Signed,
+		}
+	}
+
 	pub fn padded(inner: I, window: u32, pad: f64) -> Self {
 		Self {
 			inner,
 			window,
 			pad: Some(pad),
+			policy: DiffPolicy::Signed,
+		}
+	}
+
+	pub fn with_policy(mut self, policy: DiffPolicy) -> Self {
+		self.policy = policy;
+		self
+	}
+}
+
+impl<I> Diff<I> {
+	fn apply_policy<K: TimeSeriesKey>(&self, k: &K, at: NaiveDate, v: f64) -> f64 {
+		match self.policy {
+			DiffPolicy::Signed => v,
+			DiffPolicy::Saturating => v.max(0.),
+			DiffPolicy::Strict => {
+				if v < 0. {
+					panic!(
+						"diff needs monotonic input, got {} at {} for {:?}",
+						v, at, k
+					);
+				}
+				v
+			}
 		}
 	}
 }
@@ -390,7 +1022,33 @@ impl<K: TimeSeriesKey, I: ViewTimeSeries<K>> ViewTimeSeries<K> for Diff<I> {
 			.inner
 			.getf(k, at - chrono::Duration::days(self.window as i64))
 			.or(self.pad)?;
-		Some(vr - vl)
+		Some(self.apply_policy(k, at, vr - vl))
+	}
+
+	fn get_range(&self, k: &K, start: NaiveDate, ndays: usize, out: &mut [f64]) {
+		// a single evaluation of the inner view covers both operands: the left one is the same range, shifted back by the window
+		let window = self.window as usize;
+		let mut buf = vec![0.; ndays + window];
+		self.inner.get_range(
+			k,
+			start - chrono::Duration::days(self.window as i64),
+			ndays + window,
+			&mut buf[..],
+		);
+		for (i, (v, at)) in out[..ndays].iter_mut().zip(start.iter_days()).enumerate() {
+			let vr = buf[i + window];
+			let vl = buf[i];
+			let vl = if vl.is_nan() {
+				self.pad.unwrap_or(f64::NAN)
+			} else {
+				vl
+			};
+			*v = if vr.is_nan() || vl.is_nan() {
+				f64::NAN
+			} else {
+				self.apply_policy(k, at, vr - vl)
+			};
+		}
 	}
 }
 
@@ -418,22 +1076,646 @@ impl<K: TimeSeriesKey, I: ViewTimeSeries<K>> ViewTimeSeries<K> for MovingSum<I>
 	}
 }
 
-impl<K: TimeSeriesKey, T: ViewTimeSeries<K>> ViewTimeSeries<K> for &T {
-	fn getf(&self, k: &K, at: NaiveDate) -> Option<f64> {
-		(**self).getf(k, at)
-	}
+// Removes the weekday seasonality of a daily series. Each weekday gets a multiplier: the mean ratio of its days to the centered 7 day mean, over `window` days (whole weeks) ending three days before the day, so that the centered mean is available for all of them; the multipliers are normalized to a mean of one. The value of the day is divided by the multiplier of its weekday. There is no value unless all days the multipliers are based on are available.
+pub struct WeekdayAdjusted<I> {
+	inner: I,
+	window: u32,
 }
 
-impl<K: TimeSeriesKey, T: ViewTimeSeries<K>> ViewTimeSeries<K> for Arc<T> {
-	fn getf(&self, k: &K, at: NaiveDate) -> Option<f64> {
-		(**self).getf(k, at)
+impl<I> WeekdayAdjusted<I> {
+	pub fn new(inner: I, window: u32) -> Self {
+		assert!(window >= 7 && window.is_multiple_of(7));
+		Self { inner, window }
 	}
 }
 
-pub struct Yearly<I> {
-	inner: I,
-	base: i32,
-}
+impl<K: TimeSeriesKey, I: ViewTimeSeries<K>> ViewTimeSeries<K> for WeekdayAdjusted<I> {
+	fn getf(&self, k: &K, at: NaiveDate) -> Option<f64> {
+		let mut out = [f64::NAN];
+		self.get_range(k, at, 1, &mut out[..]);
+		Some(out[0]).filter(|v| !v.is_nan())
+	}
+
+	fn get_range(&self, k: &K, start: NaiveDate, ndays: usize, out: &mut [f64]) {
+		let window = self.window as usize;
+		// the window, the three days of the centered mean on either side of it and the three days between its end and the day itself
+		let lookback = window + 5;
+		let mut values = vec![f64::NAN; lookback + ndays];
+		self.inner.get_range(
+			k,
+			start - chrono::Duration::days(lookback as i64),
+			values.len(),
+			&mut values[..],
+		);
+		for (i, v) in out[..ndays].iter_mut().enumerate() {
+			let at = i + lookback;
+			let used = &values[i..=at];
+			if used.iter().any(|v| v.is_nan()) {
+				*v = f64::NAN;
+				continue;
+			}
+			let curr = values[at];
+			// indexed by the distance to the day modulo 7, so that 0 is the weekday of the day
+			let mut ratios = [(0f64, 0usize); 7];
+			for day in at - 3 - (window - 1)..=at - 3 {
+				let mean = values[day - 3..=day + 3].iter().sum::<f64>() / 7.;
+				if mean > 0. {
+					let slot = &mut ratios[(at - day) % 7];
+					slot.0 += values[day] / mean;
+					slot.1 += 1;
+				}
+			}
+			if ratios.iter().any(|(_, n)| *n == 0) {
+				// without cases in the window, there is nothing to adjust
+				*v = curr;
+				continue;
+			}
+			let multipliers: Vec<f64> = ratios.iter().map(|(sum, n)| sum / *n as f64).collect();
+			let norm = multipliers.iter().sum::<f64>() / 7.;
+			*v = if multipliers[0] > 0. {
+				curr / (multipliers[0] / norm)
+			} else {
+				curr
+			};
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bucket {
+	// ISO week, monday to sunday
+	Week,
+	Month,
+}
+
+impl Bucket {
+	// first and last day of the bucket containing `at`
+	pub fn range(&self, at: NaiveDate) -> (NaiveDate, NaiveDate) {
+		match self {
+			Self::Week => {
+				let first = at - chrono::Duration::days(at.weekday().num_days_from_monday() as i64);
+				(first, first + chrono::Duration::days(6))
+			}
+			Self::Month => {
+				let first = NaiveDate::from_ymd_opt(at.year(), at.month(), 1).unwrap();
+				let next = if at.month() == 12 {
+					NaiveDate::from_ymd_opt(at.year() + 1, 1, 1)
+				} else {
+					NaiveDate::from_ymd_opt(at.year(), at.month() + 1, 1)
+				};
+				(first, next.unwrap().pred_opt().unwrap())
+			}
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregation {
+	// for daily counts
+	Sum,
+	// for gauges
+	Mean,
+	// for cumulative counts and values which are windowed already, like 7 day sums
+	Last,
+}
+
+// Aggregates a daily series to weeks or months. The value of a bucket is written on its last day, like the weekly sums elsewhere; all other days have no value. Days without a value are skipped; there is no value if the bucket has none at all.
+pub struct Resample<I> {
+	inner: I,
+	bucket: Bucket,
+	aggregation: Aggregation,
+}
+
+impl<I> Resample<I> {
+	pub fn new(inner: I, bucket: Bucket, aggregation: Aggregation) -> Self {
+		Self {
+			inner,
+			bucket,
+			aggregation,
+		}
+	}
+}
+
+impl<K: TimeSeriesKey, I: ViewTimeSeries<K>> ViewTimeSeries<K> for Resample<I> {
+	fn getf(&self, k: &K, at: NaiveDate) -> Option<f64> {
+		let (first, last) = self.bucket.range(at);
+		if at != last {
+			return None;
+		}
+		let ndays = (last - first).num_days() as usize + 1;
+		let mut buf = vec![f64::NAN; ndays];
+		self.inner.get_range(k, first, ndays, &mut buf[..]);
+		let mut values = buf.iter().copied().filter(|v| !v.is_nan());
+		match self.aggregation {
+			Aggregation::Sum => values.fold(None, |acc, v| Some(acc.unwrap_or(0.) + v)),
+			Aggregation::Mean => {
+				let (sum, n) = values.fold((0., 0usize), |(sum, n), v| (sum + v, n + 1));
+				if n > 0 {
+					Some(sum / n as f64)
+				} else {
+					None
+				}
+			}
+			Aggregation::Last => values.next_back(),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowEdge {
+	// average over the values which are available in the window
+	Partial,
+	// no value unless the entire window is available
+	Full,
+}
+
+pub struct MovingAverage<I> {
+	inner: I,
+	window: u32,
+	edge: WindowEdge,
+}
+
+impl<I> MovingAverage<I> {
+	pub fn new(inner: I, window: u32, edge: WindowEdge) -> Self {
+		assert!(window >= 1);
+		Self {
+			inner,
+			window,
+			edge,
+		}
+	}
+}
+
+impl<K: TimeSeriesKey, I: ViewTimeSeries<K>> ViewTimeSeries<K> for MovingAverage<I> {
+	fn getf(&self, k: &K, at: NaiveDate) -> Option<f64> {
+		let mut accum = self.inner.getf(k, at)?;
+		let mut n = 1;
+		for i in 1..self.window {
+			match self.inner.getf(k, at - chrono::Duration::days(i as i64)) {
+				Some(v) => {
+					accum += v;
+					n += 1;
+				}
+				None => match self.edge {
+					WindowEdge::Partial => (),
+					WindowEdge::Full => return None,
+				},
+			}
+		}
+		Some(accum / (n as f64))
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mean {
+	Arithmetic,
+	// for quantities which span orders of magnitude, like viral loads; non-positive values are ignored
+	Geometric,
+}
+
+// Average over all values present in the trailing window. Unlike MovingAverage, this does not need a value on the day itself, which makes it suitable for irregularly sampled measurements.
+pub struct SparseMovingAverage<I> {
+	inner: I,
+	window: u32,
+	mean: Mean,
+}
+
+impl<I> SparseMovingAverage<I> {
+	pub fn new(inner: I, window: u32, mean: Mean) -> Self {
+		assert!(window >= 1);
+		Self {
+			inner,
+			window,
+			mean,
+		}
+	}
+}
+
+impl<K: TimeSeriesKey, I: ViewTimeSeries<K>> ViewTimeSeries<K> for SparseMovingAverage<I> {
+	fn getf(&self, k: &K, at: NaiveDate) -> Option<f64> {
+		let mut accum = 0.;
+		let mut n = 0;
+		for i in 0..self.window {
+			let v = match self.inner.getf(k, at - chrono::Duration::days(i as i64)) {
+				Some(v) => v,
+				None => continue,
+			};
+			match self.mean {
+				Mean::Arithmetic => accum += v,
+				Mean::Geometric => {
+					if v <= 0. {
+						continue;
+					}
+					accum += v.ln();
+				}
+			}
+			n += 1;
+		}
+		if n == 0 {
+			return None;
+		}
+		let v = accum / (n as f64);
+		match self.mean {
+			Mean::Arithmetic => Some(v),
+			Mean::Geometric => Some(v.exp()),
+		}
+	}
+}
+
+pub struct Ratio<A, B> {
+	numerator: A,
+	denominator: B,
+	scale: f64,
+}
+
+impl<A, B> Ratio<A, B> {
+	pub fn new(numerator: A, denominator: B) -> Self {
+		Self::scaled(numerator, denominator, 1.)
+	}
+
+	pub fn scaled(numerator: A, denominator: B, scale: f64) -> Self {
+		Self {
+			numerator,
+			denominator,
+			scale,
+		}
+	}
+}
+
+impl<K: TimeSeriesKey, A: ViewTimeSeries<K>, B: ViewTimeSeries<K>> ViewTimeSeries<K>
+	for Ratio<A, B>
+{
+	fn getf(&self, k: &K, at: NaiveDate) -> Option<f64> {
+		let denominator = self.denominator.getf(k, at)?;
+		if denominator == 0. {
+			return None;
+		}
+		Some(self.numerator.getf(k, at)? / denominator * self.scale)
+	}
+}
+
+// Source of the per-key factor of ScaleByKey: a closure or a map. Keys without a factor have no values.
+pub trait KeyFactor<K> {
+	fn factor(&self, k: &K) -> Option<f64>;
+}
+
+impl<K, F: Fn(&K) -> Option<f64>> KeyFactor<K> for F {
+	fn factor(&self, k: &K) -> Option<f64> {
+		self(k)
+	}
+}
+
+impl<K: TimeSeriesKey> KeyFactor<K> for HashMap<K, f64> {
+	fn factor(&self, k: &K) -> Option<f64> {
+		self.get(k).copied()
+	}
+}
+
+impl<K: TimeSeriesKey> KeyFactor<K> for Arc<HashMap<K, f64>> {
+	fn factor(&self, k: &K) -> Option<f64> {
+		self.get(k).copied()
+	}
+}
+
+// Multiplies or divides each series by a constant per key, e.g. to normalize by population or ICU bed capacity. Division by zero yields no value, like in Ratio.
+pub struct ScaleByKey<I, F> {
+	inner: I,
+	factor: F,
+	divide: bool,
+}
+
+impl<I, F> ScaleByKey<I, F> {
+	pub fn multiply(inner: I, factor: F) -> Self {
+		Self {
+			inner,
+			factor,
+			divide: false,
+		}
+	}
+
+	pub fn divide(inner: I, divisor: F) -> Self {
+		Self {
+			inner,
+			factor: divisor,
+			divide: true,
+		}
+	}
+
+	fn apply<K>(&self, k: &K) -> Option<f64>
+	where
+		F: KeyFactor<K>,
+	{
+		let factor = self.factor.factor(k)?;
+		if !self.divide {
+			Some(factor)
+		} else if factor == 0. {
+			None
+		} else {
+			Some(1. / factor)
+		}
+	}
+}
+
+impl<K: TimeSeriesKey, I: ViewTimeSeries<K>, F: KeyFactor<K>> ViewTimeSeries<K>
+	for ScaleByKey<I, F>
+{
+	fn getf(&self, k: &K, at: NaiveDate) -> Option<f64> {
+		let factor = self.apply(k)?;
+		Some(self.inner.getf(k, at)? * factor)
+	}
+
+	fn get_range(&self, k: &K, start: NaiveDate, ndays: usize, out: &mut [f64]) {
+		match self.apply(k) {
+			Some(factor) => {
+				self.inner.get_range(k, start, ndays, out);
+				// NaN stays NaN
+				for v in out[..ndays].iter_mut() {
+					*v *= factor;
+				}
+			}
+			None => out[..ndays].fill(f64::NAN),
+		}
+	}
+}
+
+// Aggregates a ratio-type series (per-capita values, positivity, ...) onto coarser keys as the weighted mean over the old keys, e.g. weighted by population; summing, as rekeyed does, is only correct for counts. Old keys without a weight are left out, as are those without a value on a given day, in which case the weights of the others are renormalized.
+pub struct WeightedMean<K, U, I> {
+	inner: I,
+	members: HashMap<U, Vec<(K, f64)>>,
+}
+
+impl<K: TimeSeriesKey, U: TimeSeriesKey, I> WeightedMean<K, U, I> {
+	pub fn new<'x, KI: IntoIterator<Item = &'x K>, F: Fn(&K) -> Option<U>, W: KeyFactor<K>>(
+		inner: I,
+		keys: KI,
+		f: F,
+		weights: W,
+	) -> Self {
+		let mut members: HashMap<U, Vec<(K, f64)>> = HashMap::new();
+		for k in keys {
+			let (k_new, weight) = match (f(k), weights.factor(k)) {
+				(Some(k_new), Some(weight)) => (k_new, weight),
+				_ => continue,
+			};
+			members.entry(k_new).or_default().push((k.clone(), weight));
+		}
+		Self { inner, members }
+	}
+
+	pub fn keys(&self) -> impl Iterator<Item = &U> {
+		self.members.keys()
+	}
+}
+
+impl<K: TimeSeriesKey, U: TimeSeriesKey, I: ViewTimeSeries<K>> ViewTimeSeries<U>
+	for WeightedMean<K, U, I>
+{
+	fn getf(&self, k: &U, at: NaiveDate) -> Option<f64> {
+		let mut accum = 0.;
+		let mut total_weight = 0.;
+		for (k_old, weight) in self.members.get(k)?.iter() {
+			if let Some(v) = self.inner.getf(k_old, at) {
+				accum += weight * v;
+				total_weight += weight;
+			}
+		}
+		if total_weight == 0. {
+			return None;
+		}
+		Some(accum / total_weight)
+	}
+
+	fn get_range(&self, k: &U, start: NaiveDate, ndays: usize, out: &mut [f64]) {
+		let out = &mut out[..ndays];
+		let members = match self.members.get(k) {
+			Some(v) => v,
+			None => {
+				out.fill(f64::NAN);
+				return;
+			}
+		};
+		out.fill(0.);
+		let mut total_weight = vec![0.; ndays];
+		let mut buf = vec![f64::NAN; ndays];
+		for (k_old, weight) in members.iter() {
+			self.inner.get_range(k_old, start, ndays, &mut buf[..]);
+			for ((acc, total), v) in out.iter_mut().zip(total_weight.iter_mut()).zip(buf.iter()) {
+				if !v.is_nan() {
+					*acc += weight * v;
+					*total += weight;
+				}
+			}
+		}
+		for (acc, total) in out.iter_mut().zip(total_weight.iter()) {
+			if *total == 0. {
+				*acc = f64::NAN;
+			} else {
+				*acc /= total;
+			}
+		}
+	}
+}
+
+// Serves the keys matching `select` from one series and all other keys from another, e.g. for an aggregate key whose values are computed separately from the regular keys.
+pub struct SelectByKey<K, A, B> {
+	select: fn(&K) -> bool,
+	matching: A,
+	other: B,
+}
+
+impl<K, A, B> SelectByKey<K, A, B> {
+	pub fn new(select: fn(&K) -> bool, matching: A, other: B) -> Self {
+		Self {
+			select,
+			matching,
+			other,
+		}
+	}
+}
+
+impl<K: TimeSeriesKey, A: ViewTimeSeries<K>, B: ViewTimeSeries<K>> ViewTimeSeries<K>
+	for SelectByKey<K, A, B>
+{
+	fn getf(&self, k: &K, at: NaiveDate) -> Option<f64> {
+		if (self.select)(k) {
+			self.matching.getf(k, at)
+		} else {
+			self.other.getf(k, at)
+		}
+	}
+
+	fn get_range(&self, k: &K, start: NaiveDate, ndays: usize, out: &mut [f64]) {
+		if (self.select)(k) {
+			self.matching.get_range(k, start, ndays, out)
+		} else {
+			self.other.get_range(k, start, ndays, out)
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissingPolicy {
+	// no value if any of the terms has none
+	Strict,
+	// missing terms count as zero; no value only if all terms are missing
+	Zero,
+}
+
+// Lazy sum of weighted series, e.g. active cases as cases - recovered - deaths.
+pub struct LinearCombination<K: TimeSeriesKey> {
+	terms: Vec<(f64, Arc<dyn ViewTimeSeries<K>>)>,
+	missing: MissingPolicy,
+}
+
+impl<K: TimeSeriesKey> LinearCombination<K> {
+	pub fn new(terms: Vec<(f64, Arc<dyn ViewTimeSeries<K>>)>, missing: MissingPolicy) -> Self {
+		Self { terms, missing }
+	}
+}
+
+impl<K: TimeSeriesKey> ViewTimeSeries<K> for LinearCombination<K> {
+	fn getf(&self, k: &K, at: NaiveDate) -> Option<f64> {
+		let mut accum = 0.;
+		let mut any = false;
+		for (coeff, term) in self.terms.iter() {
+			match term.getf(k, at) {
+				Some(v) => {
+					accum += coeff * v;
+					any = true;
+				}
+				None => match self.missing {
+					MissingPolicy::Strict => return None,
+					MissingPolicy::Zero => (),
+				},
+			}
+		}
+		if any {
+			Some(accum)
+		} else {
+			None
+		}
+	}
+
+	fn get_range(&self, k: &K, start: NaiveDate, ndays: usize, out: &mut [f64]) {
+		let out = &mut out[..ndays];
+		out.fill(0.);
+		// number of terms with a value, per day
+		let mut present = vec![0usize; ndays];
+		let mut buf = vec![f64::NAN; ndays];
+		for (coeff, term) in self.terms.iter() {
+			term.get_range(k, start, ndays, &mut buf[..]);
+			for ((acc, n), v) in out.iter_mut().zip(present.iter_mut()).zip(buf.iter()) {
+				if !v.is_nan() {
+					*acc += coeff * v;
+					*n += 1;
+				}
+			}
+		}
+		let required = match self.missing {
+			MissingPolicy::Strict => self.terms.len().max(1),
+			MissingPolicy::Zero => 1,
+		};
+		for (acc, n) in out.iter_mut().zip(present.iter()) {
+			if *n < required {
+				*acc = f64::NAN;
+			}
+		}
+	}
+}
+
+// Reproduction number estimate following the RKI methodology: the sum of new cases over a window, divided by the same sum shifted back by the generation time. The RKI uses a window of four days for the plain and seven days for the smoothed estimate, with a generation time of four days in both cases.
+pub struct RValue<I> {
+	inner: I,
+	window: u32,
+	generation_time: u32,
+}
+
+impl<I> RValue<I> {
+	pub fn new(inner: I, window: u32, generation_time: u32) -> Self {
+		Self {
+			inner,
+			window,
+			generation_time,
+		}
+	}
+
+	pub fn plain(inner: I) -> Self {
+		Self::new(inner, 4, 4)
+	}
+
+	pub fn smoothed(inner: I) -> Self {
+		Self::new(inner, 7, 4)
+	}
+
+	fn window_sum<K: TimeSeriesKey>(&self, k: &K, at: NaiveDate) -> Option<f64>
+	where
+		I: ViewTimeSeries<K>,
+	{
+		let mut accum = self.inner.getf(k, at)?;
+		for i in 1..self.window {
+			accum += self
+				.inner
+				.getf(k, at - chrono::Duration::days(i as i64))
+				.unwrap_or(0.)
+		}
+		Some(accum)
+	}
+}
+
+impl<K: TimeSeriesKey, I: ViewTimeSeries<K>> ViewTimeSeries<K> for RValue<I> {
+	fn getf(&self, k: &K, at: NaiveDate) -> Option<f64> {
+		let current = self.window_sum(k, at)?;
+		let previous =
+			self.window_sum(k, at - chrono::Duration::days(self.generation_time as i64))?;
+		if previous == 0. {
+			return None;
+		}
+		Some(current / previous)
+	}
+}
+
+impl<K: TimeSeriesKey, T: ViewTimeSeries<K> + ?Sized> ViewTimeSeries<K> for &T {
+	fn getf(&self, k: &K, at: NaiveDate) -> Option<f64> {
+		(**self).getf(k, at)
+	}
+
+	fn get_range(&self, k: &K, start: NaiveDate, ndays: usize, out: &mut [f64]) {
+		(**self).get_range(k, start, ndays, out)
+	}
+}
+
+impl<K: TimeSeriesKey, T: ViewTimeSeries<K> + ?Sized> ViewTimeSeries<K> for Arc<T> {
+	fn getf(&self, k: &K, at: NaiveDate) -> Option<f64> {
+		(**self).getf(k, at)
+	}
+
+	fn get_range(&self, k: &K, start: NaiveDate, ndays: usize, out: &mut [f64]) {
+		(**self).get_range(k, start, ndays, out)
+	}
+}
+
+// The same value for all keys and dates; used to record configuration alongside the data.
+pub struct Constant(pub f64);
+
+impl<K: TimeSeriesKey> ViewTimeSeries<K> for Constant {
+	fn getf(&self, _k: &K, _at: NaiveDate) -> Option<f64> {
+		Some(self.0)
+	}
+}
+
+// The same value on all dates, per key; used for properties of the keys, e.g. the position of a district. Keys without a value have none.
+pub struct KeyConstant<F>(pub F);
+
+impl<K: TimeSeriesKey, F: KeyFactor<K>> ViewTimeSeries<K> for KeyConstant<F> {
+	fn getf(&self, k: &K, _at: NaiveDate) -> Option<f64> {
+		self.0.factor(k)
+	}
+}
+
+pub struct Yearly<I> {
+	inner: I,
+	base: i32,
+}
 
 impl<I> Yearly<I> {
 	pub fn new(inner: I, base: i32) -> Self {
@@ -461,6 +1743,17 @@ impl<K: Hash + Eq, V> SparseTimeSeries<K, V> {
 		}
 	}
 
+	pub fn keys(&self) -> std::collections::hash_map::Keys<'_, K, usize> {
+		self.keys.keys()
+	}
+
+	// All series with their (date, value) pairs in chronological order.
+	pub fn iter(&self) -> impl Iterator<Item = (&K, &[(NaiveDate, V)])> + '_ {
+		self.keys
+			.iter()
+			.map(move |(k, index)| (k, &self.time_series[*index][..]))
+	}
+
 	fn get_or_create_index(&mut self, k: &K) -> usize
 	where
 		K: Clone,
@@ -554,59 +1847,75 @@ macro_rules! joined_keyset_ref {
 	}
 }
 
-impl<T: TimeSeriesKey> From<TimeSeries<T, u64>> for TimeSeries<T, f64> {
-	fn from(mut other: TimeSeries<T, u64>) -> Self {
-		// the most evil thing.
-		for vec in other.time_series.iter_mut() {
-			for v in vec.iter_mut() {
-				unsafe {
-					*v = std::mem::transmute::<f64, u64>(*v as f64);
-				}
-			}
-		}
+impl<T: TimeSeriesKey, V: CounterValue> From<TimeSeries<T, V>> for TimeSeries<T, f64> {
+	fn from(other: TimeSeries<T, V>) -> Self {
 		Self {
 			start: other.start,
 			len: other.len,
 			keys: other.keys,
-			time_series: unsafe {
-				std::mem::transmute::<Vec<Vec<u64>>, Vec<Vec<f64>>>(other.time_series)
-			},
+			// for u64, the map-collect reuses the allocation of each series, as both types have the same size and alignment, so this is as cheap as converting in place
+			time_series: other
+				.time_series
+				.into_iter()
+				.map(|vec| vec.into_iter().map(|v| v.as_()).collect())
+				.collect(),
+			offsets: other.offsets,
+			storage: other.storage,
 		}
 	}
 }
 
-pub struct CounterGroup<T: TimeSeriesKey> {
-	pub cum: Arc<Counters<T>>,
-	pub d1: Arc<Diff<Arc<Counters<T>>>>,
-	pub d7: Arc<Diff<Arc<Counters<T>>>>,
-	pub d7s7: Arc<TimeMap<Arc<Diff<Arc<Counters<T>>>>>>,
+pub struct CounterGroup<T: TimeSeriesKey, V: CounterValue = u64> {
+	pub cum: Arc<Counters<T, V>>,
+	pub d1: Arc<CounterDiff<T, V>>,
+	pub d7: Arc<CounterDiff<T, V>>,
+	pub d7s7: Arc<TimeMap<Arc<CounterDiff<T, V>>>>,
 }
 
-impl<T: TimeSeriesKey> CounterGroup<T> {
-	pub fn from_cum(cum: Counters<T>) -> Self {
+impl<T: TimeSeriesKey, V: CounterValue> CounterGroup<T, V> {
+	pub fn from_cum(cum: Counters<T, V>) -> Self {
+		Self::from_cum_with_policy(cum, DiffPolicy::Signed)
+	}
+
+	pub fn from_cum_with_policy(cum: Counters<T, V>, policy: DiffPolicy) -> Self {
 		let cum = Arc::new(cum);
-		let d7 = Arc::new(Diff::padded(cum.clone(), 7, 0.));
+		let d7 = Arc::new(Diff::padded(cum.clone(), 7, 0.).with_policy(policy));
 		Self {
 			cum: cum.clone(),
-			d1: Arc::new(Diff::padded(cum.clone(), 1, 0.)),
+			d1: Arc::new(Diff::padded(cum.clone(), 1, 0.).with_policy(policy)),
 			d7: d7.clone(),
 			d7s7: Arc::new(TimeMap::shift(d7.clone(), -7)),
 		}
 	}
 
-	pub fn from_d1(d1: Counters<T>) -> Self {
+	pub fn from_d1(d1: Counters<T, V>) -> Self {
 		let mut cum = d1.clone();
 		cum.cumsum();
 		Self::from_cum(cum)
 	}
 
-	pub fn from_d7(d7: Counters<T>) -> Self {
+	pub fn from_d7(d7: Counters<T, V>) -> Self {
 		let d1 = d7.unrolled(7);
 		Self::from_d1(d1)
 	}
 
-	pub fn rekeyed<U: TimeSeriesKey, F: Fn(&T) -> Option<U>>(&self, f: F) -> CounterGroup<U> {
-		CounterGroup::<U>::from_cum(self.cum.rekeyed(&f))
+	pub fn rekeyed<U: TimeSeriesKey, F: Fn(&T) -> Option<U>>(&self, f: F) -> CounterGroup<U, V> {
+		CounterGroup::<U, V>::from_cum(self.cum.rekeyed(&f))
+	}
+
+	pub fn rekeyed_via<U: TimeSeriesKey>(
+		&self,
+		source: &KeyInterner<T>,
+		map: &KeyMap<U>,
+	) -> CounterGroup<U, V> {
+		CounterGroup::<U, V>::from_cum(self.cum.rekeyed_via(source, map))
+	}
+
+	pub fn aggregated<U: TimeSeriesKey, I: IntoIterator<Item = U>, F: Fn(&T) -> I>(
+		&self,
+		f: F,
+	) -> CounterGroup<U, V> {
+		CounterGroup::<U, V>::from_cum(self.cum.aggregated(&f))
 	}
 
 	pub fn cum(&self) -> Arc<dyn ViewTimeSeries<T>> {
@@ -626,6 +1935,56 @@ impl<T: TimeSeriesKey> CounterGroup<T> {
 	}
 }
 
-pub type Counters<T> = TimeSeries<T, u64>;
+// Counters which are only known as weekly totals. The weekly totals are spread evenly over the days of their week, so that the 7 day sum matches the reported number at the end of each week and is interpolated between them.
+pub struct WeeklyCounterGroup<T: TimeSeriesKey> {
+	pub d1: Arc<FGauge<T>>,
+	pub d7: Arc<MovingSum<Arc<FGauge<T>>>>,
+}
+
+impl<T: TimeSeriesKey> WeeklyCounterGroup<T> {
+	// `weekly` holds each weekly total on the last day of the respective week and zero on all other days.
+	pub fn from_weekly<V: CounterValue>(weekly: Counters<T, V>) -> Self {
+		let mut d1 = FGauge::<T> {
+			start: weekly.start,
+			len: weekly.len,
+			keys: weekly.keys.clone(),
+			time_series: Vec::with_capacity(weekly.time_series.len()),
+			offsets: vec![0; weekly.offsets.len()],
+			storage: Storage::Dense,
+		};
+		for index in 0..weekly.time_series.len() {
+			let src = weekly.series(index);
+			let mut dst = vec![0.; src.len()];
+			for (i, v) in src.iter().enumerate() {
+				if v.is_zero() {
+					continue;
+				}
+				let share = v.as_() / 7.;
+				for d in dst[i.saturating_sub(6)..=i].iter_mut() {
+					*d += share;
+				}
+			}
+			d1.time_series.push(dst);
+		}
+		d1.set_storage(weekly.storage);
+		let d1 = Arc::new(d1);
+		Self {
+			d1: d1.clone(),
+			d7: Arc::new(MovingSum::new(d1, 7)),
+		}
+	}
+
+	pub fn d1(&self) -> Arc<dyn ViewTimeSeries<T>> {
+		self.d1.clone() as _
+	}
+
+	pub fn d7(&self) -> Arc<dyn ViewTimeSeries<T>> {
+		self.d7.clone() as _
+	}
+}
+
+pub type Counters<T, V = u64> = TimeSeries<T, V>;
 pub type IGauge<T> = TimeSeries<T, u64>;
-pub type FGauge<T> = TimeSeries<T, f64>;
+pub type FGauge<T, V = f64> = TimeSeries<T, V>;
+// the daily or windowed differences of counters, as in CounterGroup
+pub type CounterDiff<T, V = u64> = Diff<Arc<Counters<T, V>>>;
@@ -0,0 +1,177 @@
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::NaiveDate;
+
+use super::timeseries::{TimeSeriesKey, ViewTimeSeries};
+
+// The protection of a vaccinated person by days since their latest dose: linear between the given (day, level) points, zero before the first and after the last one. The levels are the protected share, between zero and one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WaningModel {
+	// strictly ascending in the days
+	points: Vec<(u32, f64)>,
+}
+
+impl WaningModel {
+	pub fn new(points: Vec<(u32, f64)>) -> Result<Self, String> {
+		if points.is_empty() {
+			return Err("a waning model needs at least one point".into());
+		}
+		for w in points.windows(2) {
+			if w[0].0 >= w[1].0 {
+				return Err(format!(
+					"the days of a waning model must be ascending, got {} after {}",
+					w[1].0, w[0].0
+				));
+			}
+		}
+		if let Some((day, level)) = points.iter().find(|(_, level)| !(0. ..=1.).contains(level)) {
+			return Err(format!(
+				"protection level {} on day {} is not between 0 and 1",
+				level, day
+			));
+		}
+		Ok(Self { points })
+	}
+
+	// COVID_WANING_MODEL in the format of from_str, or the default
+	pub fn from_env() -> Result<Self, String> {
+		match std::env::var("COVID_WANING_MODEL") {
+			Ok(v) => v.parse(),
+			Err(_) => Ok(Self::default()),
+		}
+	}
+
+	pub fn level(&self, days: u32) -> f64 {
+		let first = self.points[0];
+		let last = self.points[self.points.len() - 1];
+		if days < first.0 || days > last.0 {
+			return 0.;
+		}
+		let i = self.points.partition_point(|(d, _)| *d <= days);
+		let (d0, l0) = self.points[i - 1];
+		if d0 == days || i == self.points.len() {
+			return l0;
+		}
+		let (d1, l1) = self.points[i];
+		l0 + (l1 - l0) * (days - d0) as f64 / (d1 - d0) as f64
+	}
+
+	// The level for each day since the dose, up to the last day with any protection.
+	pub fn kernel(&self) -> Vec<f64> {
+		let ndays = self.points[self.points.len() - 1].0 + 1;
+		(0..ndays).map(|i| self.level(i)).collect()
+	}
+}
+
+// A rough assumption, not a fit: full protection two weeks after the dose, down to half after six months and gone after a year.
+impl Default for WaningModel {
+	fn default() -> Self {
+		Self {
+			points: vec![(0, 0.), (14, 1.), (180, 0.5), (365, 0.)],
+		}
+	}
+}
+
+impl FromStr for WaningModel {
+	type Err = String;
+
+	// comma separated DAYS:LEVEL points, e.g. 0:0,14:1,180:0.5,365:0
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let mut points = Vec::new();
+		for point in s.split(',') {
+			let (days, level) = point
+				.split_once(':')
+				.ok_or_else(|| format!("expected days:level, got {:?}", point))?;
+			let days = days
+				.trim()
+				.parse()
+				.map_err(|e| format!("invalid number of days {:?}: {}", days, e))?;
+			let level = level
+				.trim()
+				.parse()
+				.map_err(|e| format!("invalid protection level {:?}: {}", level, e))?;
+			points.push((days, level));
+		}
+		Self::new(points)
+	}
+}
+
+impl fmt::Display for WaningModel {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		for (i, (days, level)) in self.points.iter().enumerate() {
+			if i > 0 {
+				f.write_str(",")?;
+			}
+			write!(f, "{}:{}", days, level)?;
+		}
+		Ok(())
+	}
+}
+
+// The number of people protected on each day under a waning model. `levels` are the cumulative doses of successive vaccination levels (e.g. the basic immunization and the boosters); a dose of one level renews the protection of someone who got the previous one, first in first out. Everyone is counted with the protection of their latest dose: the doses of each level are convolved with the kernel of the model, less those which the next level has superseded by the day. There is no value on days where any of the levels has none.
+pub struct Waning<I> {
+	levels: Vec<I>,
+	kernel: Vec<f64>,
+}
+
+impl<I> Waning<I> {
+	pub fn new(levels: Vec<I>, model: &WaningModel) -> Self {
+		Self {
+			levels,
+			kernel: model.kernel(),
+		}
+	}
+}
+
+impl<K: TimeSeriesKey, I: ViewTimeSeries<K>> ViewTimeSeries<K> for Waning<I> {
+	fn getf(&self, k: &K, at: NaiveDate) -> Option<f64> {
+		let mut out = [f64::NAN];
+		self.get_range(k, at, 1, &mut out[..]);
+		Some(out[0]).filter(|v| !v.is_nan())
+	}
+
+	fn get_range(&self, k: &K, start: NaiveDate, ndays: usize, out: &mut [f64]) {
+		// the kernel and the day before its first dose
+		let lookback = self.kernel.len();
+		let cums: Vec<Vec<f64>> = self
+			.levels
+			.iter()
+			.map(|level| {
+				let mut values = vec![f64::NAN; lookback + ndays];
+				level.get_range(
+					k,
+					start - chrono::Duration::days(lookback as i64),
+					values.len(),
+					&mut values[..],
+				);
+				values
+			})
+			.collect();
+		// nobody was vaccinated before the data starts
+		let cum_at = |cum: &[f64], day: usize| Some(cum[day]).filter(|v| !v.is_nan()).unwrap_or(0.);
+		for (i, v) in out[..ndays].iter_mut().enumerate() {
+			let at = i + lookback;
+			if cums.iter().any(|cum| cum[at].is_nan()) {
+				*v = f64::NAN;
+				continue;
+			}
+			let mut protected = 0.;
+			for (l, cum) in cums.iter().enumerate() {
+				// the doses of this level up to here have been superseded by the next one
+				let superseded = cums.get(l + 1).map(|next| next[at]).unwrap_or(0.);
+				for (age, weight) in self.kernel.iter().enumerate() {
+					if *weight == 0. {
+						continue;
+					}
+					let day = at - age;
+					let remaining = cum_at(cum, day) - cum_at(cum, day - 1).max(superseded);
+					if remaining > 0. {
+						protected += weight * remaining;
+					}
+				}
+			}
+			*v = protected;
+		}
+	}
+}
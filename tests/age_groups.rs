@@ -0,0 +1,136 @@
+// Tests of the age band schemes and the conversions between them.
+use covid::{age_rekey, load_age_band_config, AgeBands, AgeGroup, AgeScheme};
+
+fn ag(s: &str) -> AgeGroup {
+	s.parse().unwrap()
+}
+
+fn single(age: u16) -> AgeGroup {
+	AgeGroup {
+		low: age,
+		high: Some(age),
+	}
+}
+
+#[test]
+fn bands_are_exhaustive_and_non_overlapping() {
+	for scheme in AgeScheme::all().iter() {
+		let bands = scheme.bands().bands();
+		assert_eq!(bands[0].low, 0, "{:?}", scheme);
+		assert_eq!(bands[bands.len() - 1].high, None, "{:?}", scheme);
+		for w in bands.windows(2) {
+			assert_eq!(w[0].high, Some(w[1].low - 1), "{:?}", scheme);
+		}
+		for age in 0..=120 {
+			let containing: Vec<_> = bands
+				.iter()
+				.filter(|band| band.low <= age && band.high.map(|h| age <= h).unwrap_or(true))
+				.collect();
+			assert_eq!(containing.len(), 1, "{:?}: age {}", scheme, age);
+			assert_eq!(scheme.bands().band_of_age(age), *containing[0]);
+		}
+	}
+}
+
+#[test]
+fn rekeys_single_ages_into_bands() {
+	let to_vacc = age_rekey(AgeScheme::Vaccination);
+	assert_eq!(to_vacc(&single(0)), Some(ag("A00-A04")));
+	assert_eq!(to_vacc(&single(11)), Some(ag("A05-A11")));
+	assert_eq!(to_vacc(&single(12)), Some(ag("A12-A17")));
+	assert_eq!(to_vacc(&single(59)), Some(ag("A18-A59")));
+	// destatis ends with an open-ended group
+	assert_eq!(to_vacc(&ag("A90+")), Some(ag("A60+")));
+
+	let to_cases = age_rekey(AgeScheme::Cases);
+	assert_eq!(to_cases(&single(14)), Some(ag("A05-A14")));
+	assert_eq!(to_cases(&single(80)), Some(ag("A80+")));
+	assert_eq!(to_cases(&ag("A60-A79")), Some(ag("A60-A79")));
+
+	// the hospitalization total spans all bands
+	let to_hosp = age_rekey(AgeScheme::Hospitalization);
+	assert_eq!(to_hosp(&ag("A00+")), None);
+	assert_eq!(to_hosp(&ag("A35-A59")), Some(ag("A35-A59")));
+}
+
+#[test]
+fn converts_between_schemes() {
+	let cases = AgeScheme::Cases.bands();
+	let vacc = AgeScheme::Vaccination.bands();
+	assert_eq!(
+		vacc.conversion_from(&cases),
+		vec![
+			(ag("A00-A04"), Some(ag("A00-A04"))),
+			(ag("A05-A14"), None),
+			(ag("A15-A34"), None),
+			(ag("A35-A59"), Some(ag("A18-A59"))),
+			(ag("A60-A79"), Some(ag("A60+"))),
+			(ag("A80+"), Some(ag("A60+"))),
+		]
+	);
+	// the hospitalization data shares the bands of the cases
+	for (from, to) in AgeScheme::Hospitalization.bands().conversion_from(&cases) {
+		assert_eq!(Some(from), to);
+	}
+
+	let coarse = AgeBands::new(vec![0, 60]).unwrap();
+	assert!(coarse
+		.conversion_from(&vacc)
+		.iter()
+		.all(|(_, to)| to.is_some()));
+	assert_eq!(coarse.to_string(), "A00-A59,A60+");
+}
+
+#[test]
+fn rejects_invalid_bands() {
+	assert!(AgeBands::new(vec![]).is_err());
+	assert!(AgeBands::new(vec![5, 18]).is_err());
+	assert!(AgeBands::new(vec![0, 18, 18]).is_err());
+	assert!(AgeBands::new(vec![0, 60, 18]).is_err());
+	assert_eq!("vacc".parse::<AgeScheme>().unwrap(), AgeScheme::Vaccination);
+	assert!("vaccination".parse::<AgeScheme>().is_err());
+}
+
+static SUPPORTED: [(&str, AgeScheme); 3] = [
+	("data_v2_demo", AgeScheme::Cases),
+	("data_v2_vacc", AgeScheme::Vaccination),
+	("data_v2_hosp", AgeScheme::Hospitalization),
+];
+
+#[test]
+fn loads_custom_bands() {
+	let config =
+		load_age_band_config(&br#"{"data_v2_demo": [0, 15, 60], "data_v2_vacc": [0, 18, 60]}"#[..])
+			.unwrap();
+	config.validate(&SUPPORTED).unwrap();
+	assert_eq!(
+		config.bands("data_v2_demo").unwrap().to_string(),
+		"A00-A14,A15-A59,A60+"
+	);
+	assert!(config.bands("data_v2_hosp").is_none());
+
+	let demo = config.rekey("data_v2_demo");
+	assert_eq!(demo(&ag("A35-A59")), Some(ag("A15-A59")));
+	assert_eq!(demo(&ag("A80+")), Some(ag("A60+")));
+	let vacc = config.rekey("data_v2_vacc");
+	assert_eq!(vacc(&ag("A12-A17")), Some(ag("A00-A17")));
+	// without bands, the age groups are kept
+	let hosp = config.rekey("data_v2_hosp");
+	assert_eq!(hosp(&ag("A00+")), Some(ag("A00+")));
+}
+
+#[test]
+fn rejects_incompatible_custom_bands() {
+	// 18 cuts through the A15-A34 band of the cases
+	let config = load_age_band_config(&br#"{"data_v2_demo": [0, 18, 50, 65]}"#[..]).unwrap();
+	let err = config.validate(&SUPPORTED).unwrap_err().to_string();
+	assert!(err.contains("A15-A34"), "{}", err);
+	// but fits the vaccination bands up to 18
+	let config = load_age_band_config(&br#"{"data_v2_vacc": [0, 18]}"#[..]).unwrap();
+	config.validate(&SUPPORTED).unwrap();
+
+	let config = load_age_band_config(&br#"{"data_v2_geo": [0, 60]}"#[..]).unwrap();
+	assert!(config.validate(&SUPPORTED).is_err());
+	assert!(load_age_band_config(&br#"{"data_v2_demo": [5, 60]}"#[..]).is_err());
+	assert!(load_age_band_config(&br#"[0, 60]"#[..]).is_err());
+}
@@ -0,0 +1,308 @@
+// Tests of influxdb::Client against a mock transport, without any server (requires the `testing` feature).
+use std::io::Read;
+
+use chrono::{TimeZone, Utc};
+
+use covid::influxdb::readout::FieldValue;
+use covid::influxdb::{self, Auth, Method, Precision, Readout, Sample};
+use covid::testing::MockTransport;
+
+fn post(client: &influxdb::Client, auth: Option<&Auth>) -> Result<(), influxdb::Error> {
+	client.post_raw(
+		"covid",
+		None,
+		auth,
+		Precision::Seconds,
+		&b"m n=1.0 1609459200\n"[..],
+	)
+}
+
+#[test]
+fn builds_the_write_query() {
+	let mock = MockTransport::new();
+	let client = mock.client(Auth::None);
+	client
+		.post_raw(
+			"covid",
+			Some("long term"),
+			None,
+			Precision::Milliseconds,
+			&b"m n=1.0 1609459200000\n"[..],
+		)
+		.unwrap();
+	post(&client, None).unwrap();
+
+	let requests = mock.requests();
+	assert_eq!(requests.len(), 2);
+	let req = &requests[0];
+	assert_eq!(req.method, Method::Post);
+	assert_eq!(req.url, "http://influxdb.invalid/write");
+	assert_eq!(
+		req.query,
+		vec![
+			("db".to_string(), "covid".to_string()),
+			("precision".to_string(), "ms".to_string()),
+			("rp".to_string(), "long term".to_string()),
+		]
+	);
+	assert_eq!(&req.body[..], &b"m n=1.0 1609459200000\n"[..]);
+	assert!(req.headers.is_empty());
+	assert_eq!(requests[1].param("precision"), Some("s"));
+	assert_eq!(requests[1].param("rp"), None);
+}
+
+#[test]
+fn serializes_readouts() {
+	let mock = MockTransport::new();
+	let readout = Readout {
+		ts: Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap(),
+		measurement: "m".into(),
+		precision: Precision::Seconds,
+		tags: vec!["state".into()],
+		fields: vec!["n".into(), "i".into()],
+		samples: vec![Sample {
+			tagv: vec!["Hamburg".into()],
+			fieldv: vec![FieldValue::Numeric(1.5), FieldValue::Integer(2)],
+		}],
+	};
+	mock.client(Auth::None)
+		.post("covid", None, None, Precision::Seconds, &[readout])
+		.unwrap();
+	assert_eq!(
+		std::str::from_utf8(&mock.requests()[0].body).unwrap(),
+		"m,state=Hamburg n=1.5,i=2i 1609459200\n"
+	);
+}
+
+#[test]
+fn encodes_auth() {
+	let mock = MockTransport::new();
+	let http = Auth::HTTP {
+		username: "user".into(),
+		password: "pass:word".into(),
+	};
+	let query = Auth::Query {
+		username: "user".into(),
+		password: "pass&word".into(),
+	};
+	post(&mock.client(http.clone()), None).unwrap();
+	post(&mock.client(query.clone()), None).unwrap();
+	// per request, instead of the client default
+	post(&mock.client(Auth::None), Some(&http)).unwrap();
+
+	let requests = mock.requests();
+	assert_eq!(
+		requests[0].header("authorization"),
+		Some("Basic dXNlcjpwYXNzOndvcmQ=")
+	);
+	assert_eq!(requests[0].param("u"), None);
+	// unencoded; the transport encodes the query string
+	assert_eq!(requests[1].param("u"), Some("user"));
+	assert_eq!(requests[1].param("p"), Some("pass&word"));
+	assert_eq!(requests[1].header("Authorization"), None);
+	assert_eq!(
+		requests[2].header("Authorization"),
+		requests[0].header("Authorization")
+	);
+}
+
+#[test]
+fn maps_status_codes() {
+	for (status, check) in [
+		(
+			200u16,
+			(|e: &influxdb::Error| matches!(e, influxdb::Error::UnexpectedSuccessStatus))
+				as fn(&_) -> bool,
+		),
+		(401, |e| matches!(e, influxdb::Error::PermissionError)),
+		(403, |e| matches!(e, influxdb::Error::PermissionError)),
+		(400, |e| matches!(e, influxdb::Error::DataError)),
+		(413, |e| matches!(e, influxdb::Error::DataError)),
+		(404, |e| matches!(e, influxdb::Error::DatabaseNotFound)),
+		(500, |e| matches!(e, influxdb::Error::Status(500))),
+		(503, |e| matches!(e, influxdb::Error::Status(503))),
+	]
+	.iter()
+	{
+		let mock = MockTransport::new();
+		mock.respond(*status, "{\"error\":\"injected\"}");
+		let err = post(&mock.client(Auth::None), None).unwrap_err();
+		assert!(check(&err), "status {}: unexpected error {:?}", status, err);
+	}
+	let mock = MockTransport::new();
+	mock.respond(204, "");
+	post(&mock.client(Auth::None), None).unwrap();
+}
+
+#[test]
+fn compresses_above_threshold() {
+	let mock = MockTransport::new();
+	let client = mock.client(Auth::None).with_gzip(Some(32));
+	let long = "m n=1.0 1609459200\n".repeat(4);
+	client
+		.post_raw("covid", None, None, Precision::Seconds, long.clone())
+		.unwrap();
+	post(&client, None).unwrap();
+
+	let requests = mock.requests();
+	assert_eq!(requests[0].header("content-encoding"), Some("gzip"));
+	let mut decoded = String::new();
+	flate2::read::GzDecoder::new(&requests[0].body[..])
+		.read_to_string(&mut decoded)
+		.unwrap();
+	assert_eq!(decoded, long);
+	assert_eq!(requests[1].header("content-encoding"), None);
+	assert_eq!(&requests[1].body[..], &b"m n=1.0 1609459200\n"[..]);
+}
+
+#[test]
+fn pings() {
+	let mock = MockTransport::new();
+	let client = mock.client(Auth::None);
+	client.ping().unwrap();
+	mock.respond(401, "");
+	assert!(matches!(
+		client.ping().unwrap_err(),
+		influxdb::Error::PermissionError
+	));
+	mock.respond(502, "");
+	assert!(matches!(
+		client.ping().unwrap_err(),
+		influxdb::Error::Status(502)
+	));
+
+	let requests = mock.requests();
+	assert_eq!(requests[0].method, Method::Get);
+	assert_eq!(requests[0].url, "http://influxdb.invalid/ping");
+	assert!(requests[0].body.is_empty());
+}
+
+static DATABASES: &str = r#"{"results":[{"statement_id":0,"series":[{"name":"databases","columns":["name"],"values":[["_internal"],["covid"]]}]}]}"#;
+
+#[test]
+fn creates_database() {
+	let mock = MockTransport::new();
+	let client = mock.client(Auth::Query {
+		username: "user".into(),
+		password: "pass".into(),
+	});
+	mock.respond(200, r#"{"results":[{"statement_id":0}]}"#);
+	client.ensure_database("co\"vid").unwrap();
+
+	let requests = mock.requests();
+	assert_eq!(requests.len(), 1);
+	assert_eq!(requests[0].method, Method::Post);
+	assert_eq!(requests[0].url, "http://influxdb.invalid/query");
+	assert_eq!(
+		requests[0].param("q"),
+		Some("CREATE DATABASE \"co\\\"vid\"")
+	);
+	assert_eq!(requests[0].param("u"), Some("user"));
+
+	mock.respond(
+		200,
+		r#"{"results":[{"statement_id":0,"error":"database name required"}]}"#,
+	);
+	match client.ensure_database("").unwrap_err() {
+		influxdb::Error::Query(msg) => assert_eq!(msg, "database name required"),
+		other => panic!("unexpected error {:?}", other),
+	}
+	mock.respond(200, "<html>");
+	assert!(matches!(
+		client.ensure_database("covid").unwrap_err(),
+		influxdb::Error::Query(_)
+	));
+}
+
+#[test]
+fn finds_database_without_admin_privileges() {
+	let mock = MockTransport::new();
+	let client = mock.client(Auth::None);
+	let denied = r#"{"error":"error authorizing query: user not authorized to execute statement 'CREATE DATABASE covid', requires admin privilege"}"#;
+	mock.respond(403, denied);
+	mock.respond(200, DATABASES);
+	client.ensure_database("covid").unwrap();
+	let requests = mock.requests();
+	assert_eq!(requests.len(), 2);
+	assert_eq!(requests[1].param("q"), Some("SHOW DATABASES"));
+
+	mock.respond(403, denied);
+	mock.respond(200, DATABASES);
+	assert!(matches!(
+		client.ensure_database("other").unwrap_err(),
+		influxdb::Error::DatabaseNotFound
+	));
+}
+
+#[test]
+fn queries_last_timestamp() {
+	let mock = MockTransport::new();
+	let client = mock.client(Auth::None);
+	mock.respond(
+		200,
+		r#"{"results":[{"statement_id":0,"series":[{"name":"data_v2_geo","columns":["time","cases_d1","state"],"values":[[1609459200,12.0,"Hamburg"]]}]}]}"#,
+	);
+	assert_eq!(
+		client
+			.last_timestamp("covid", Some("long term"), "data_v2_geo")
+			.unwrap(),
+		Some(Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap())
+	);
+	mock.respond(200, r#"{"results":[{"statement_id":0}]}"#);
+	assert_eq!(client.last_timestamp("covid", None, "m").unwrap(), None);
+	mock.respond(
+		200,
+		r#"{"results":[{"statement_id":0,"series":[{"name":"m","columns":["time"],"values":[["2021-01-01T00:00:00Z"]]}]}]}"#,
+	);
+	assert!(matches!(
+		client.last_timestamp("covid", None, "m").unwrap_err(),
+		influxdb::Error::Query(_)
+	));
+
+	let requests = mock.requests();
+	assert_eq!(requests[0].method, Method::Get);
+	assert_eq!(requests[0].url, "http://influxdb.invalid/query");
+	assert_eq!(requests[0].param("db"), Some("covid"));
+	assert_eq!(requests[0].param("epoch"), Some("s"));
+	assert_eq!(
+		requests[0].param("q"),
+		Some("SELECT * FROM \"long term\".\"data_v2_geo\" ORDER BY time DESC LIMIT 1")
+	);
+	assert_eq!(
+		requests[1].param("q"),
+		Some("SELECT * FROM \"m\" ORDER BY time DESC LIMIT 1")
+	);
+}
+
+#[test]
+fn returns_query_series() {
+	let mock = MockTransport::new();
+	mock.respond(
+		200,
+		r#"{"results":[{"statement_id":0,"series":[{"name":"m","tags":{"state":"Hamburg"},"columns":["time","n"],"values":[[1609459200000,1],[1609545600000,2]]}]}]}"#,
+	);
+	let series = mock
+		.client(Auth::None)
+		.query(
+			"covid",
+			Precision::Milliseconds,
+			"SELECT n FROM m GROUP BY state",
+		)
+		.unwrap();
+	assert_eq!(series.len(), 1);
+	assert_eq!(series[0].name, "m");
+	assert_eq!(series[0].columns, vec!["time", "n"]);
+	assert_eq!(series[0].values.len(), 2);
+	assert_eq!(series[0].values[1][1].as_i64(), Some(2));
+	assert_eq!(mock.requests()[0].param("epoch"), Some("ms"));
+
+	mock.respond(400, r#"{"error":"error parsing query: found EOF"}"#);
+	match mock
+		.client(Auth::None)
+		.query("covid", Precision::Seconds, "SELECT")
+		.unwrap_err()
+	{
+		influxdb::Error::Query(msg) => assert_eq!(msg, "error parsing query: found EOF"),
+		other => panic!("unexpected error {:?}", other),
+	}
+}
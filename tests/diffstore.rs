@@ -0,0 +1,352 @@
+// Tests of the inputs and outputs of rki_diff: the dates and checksums of the case file dumps, and the partitioned diff store with its migration from diff files of the first version.
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use covid::pipeline::PipelineBuilder;
+use covid::{
+	diff_partition_of, dump_publication_date, load_sha256sums, sha256_file, DiffRecord, DiffStore,
+	NaiveDate, RunLog, Sex, SubmitPolicy, ViewTimeSeries,
+};
+
+fn fixture(name: &str) -> String {
+	Path::new(env!("CARGO_MANIFEST_DIR"))
+		.join("tests/fixtures")
+		.join(name)
+		.to_str()
+		.unwrap()
+		.to_string()
+}
+
+fn temp_path(name: &str) -> PathBuf {
+	let path = std::env::temp_dir().join(format!("covid-{}-{}", name, std::process::id()));
+	let _ = fs::remove_dir_all(&path);
+	let _ = fs::remove_file(&path);
+	path
+}
+
+fn record(date: NaiveDate, cases: u64) -> DiffRecord {
+	DiffRecord {
+		date,
+		district_id: 1001,
+		age_group: Some("A15-A34".parse().unwrap()).into(),
+		sex: Sex::Male,
+		delay_total: 0,
+		cases_delayed: 0,
+		late_cases: 0,
+		cases,
+		deaths: 0,
+		recovered: 0,
+		cases_rep_d7: cases,
+		cases_retracted: 0,
+	}
+}
+
+fn read_all(store: &DiffStore) -> Vec<DiffRecord> {
+	let mut out = Vec::new();
+	for partition in store.partitions() {
+		let mut r = store.read_partition(partition).unwrap();
+		out.extend(r.deserialize::<DiffRecord>().map(|row| row.unwrap()));
+	}
+	out
+}
+
+#[test]
+fn migrates_and_partitions_by_month() {
+	let records: Vec<DiffRecord> = [(2, 27), (2, 28), (3, 1), (3, 2), (5, 31)]
+		.iter()
+		.enumerate()
+		.map(|(i, (m, d))| record(NaiveDate::from_ymd_opt(2021, *m, *d).unwrap(), i as u64 + 1))
+		.collect();
+	let v1 = temp_path("diff-v1.csv");
+	{
+		let mut w = DiffRecord::writer(fs::File::create(&v1).unwrap()).unwrap();
+		for rec in records.iter() {
+			w.serialize(rec).unwrap();
+		}
+	}
+
+	let dir = temp_path("diff-store");
+	let mut store = DiffStore::create(&dir).unwrap();
+	assert_eq!(store.migrate(&v1).unwrap(), records.len());
+	let months: Vec<(NaiveDate, usize)> = store.partitions().map(|p| (p.month, p.rows)).collect();
+	assert_eq!(
+		months,
+		vec![
+			(NaiveDate::from_ymd_opt(2021, 2, 1).unwrap(), 2),
+			(NaiveDate::from_ymd_opt(2021, 3, 1).unwrap(), 2),
+			(NaiveDate::from_ymd_opt(2021, 5, 1).unwrap(), 1),
+		]
+	);
+	assert!(dir.join("2021-03.csv.zst").exists());
+
+	// the index survives a reopen, and rewriting a month leaves the others alone
+	let mut store = DiffStore::open(&dir).unwrap();
+	assert_eq!(read_all(&store), records);
+	let march = NaiveDate::from_ymd_opt(2021, 3, 1).unwrap();
+	let rewritten = [record(march, 10)];
+	store.write_partition(march, rewritten.iter()).unwrap();
+	let store = DiffStore::open(&dir).unwrap();
+	assert_eq!(store.rows(), 4);
+	assert_eq!(
+		read_all(&store),
+		vec![
+			records[0].clone(),
+			records[1].clone(),
+			rewritten[0].clone(),
+			records[4].clone()
+		]
+	);
+	assert_eq!(
+		diff_partition_of(NaiveDate::from_ymd_opt(2021, 3, 31).unwrap()),
+		march
+	);
+
+	fs::remove_dir_all(&dir).unwrap();
+	fs::remove_file(&v1).unwrap();
+}
+
+#[test]
+fn rejects_misplaced_and_unsorted_records() {
+	let dir = temp_path("diff-store-invalid");
+	let mut store = DiffStore::create(&dir).unwrap();
+	let feb = NaiveDate::from_ymd_opt(2021, 2, 1).unwrap();
+	assert!(matches!(
+		store.write_partition(
+			feb,
+			[record(NaiveDate::from_ymd_opt(2021, 3, 1).unwrap(), 1)].iter()
+		),
+		Err(covid::Error::Schema(_))
+	));
+	assert_eq!(store.partitions().count(), 0);
+
+	let v1 = temp_path("diff-v1-unsorted.csv");
+	{
+		let mut w = DiffRecord::writer(fs::File::create(&v1).unwrap()).unwrap();
+		w.serialize(record(NaiveDate::from_ymd_opt(2021, 3, 1).unwrap(), 1))
+			.unwrap();
+		w.serialize(record(feb, 1)).unwrap();
+	}
+	assert!(matches!(store.migrate(&v1), Err(covid::Error::Schema(_))));
+
+	fs::write(dir.join("index"), "something else\n").unwrap();
+	assert!(DiffStore::open(&dir).is_err());
+	fs::remove_dir_all(&dir).unwrap();
+	fs::remove_file(&v1).unwrap();
+}
+
+#[test]
+fn loads_cases_from_a_store() {
+	let dir = temp_path("diff-store-cases");
+	DiffStore::create(&dir)
+		.unwrap()
+		.migrate(fixture("diff.csv"))
+		.unwrap();
+
+	let (_, districts) = covid::load_districts(fixture("districts.csv")).unwrap();
+	let start = NaiveDate::from_ymd_opt(2021, 2, 22).unwrap();
+	let end = NaiveDate::from_ymd_opt(2021, 3, 22).unwrap();
+	let load = |diff: &str| {
+		PipelineBuilder::new(
+			fixture("cases.csv").as_str(),
+			diff,
+			start,
+			"divi",
+			"vacc",
+			"hosp",
+			"destatis",
+		)
+		.policy(SubmitPolicy::Fail)
+		.range(start, end)
+		.load_cases(&mut RunLog::new(), &districts)
+		.unwrap()
+	};
+	let from_file = load(&fixture("diff.csv"));
+	let from_store = load(dir.to_str().unwrap());
+	let keys: Vec<_> = from_file.cases_by_pub.cum.keys().cloned().collect();
+	assert!(!keys.is_empty());
+	for k in keys.iter() {
+		for day in 0..(end - start).num_days() {
+			let at = start + chrono::Duration::days(day);
+			assert_eq!(
+				from_store.cases_by_pub.cum.getf(k, at),
+				from_file.cases_by_pub.cum.getf(k, at),
+				"{:?} at {}",
+				k,
+				at
+			);
+		}
+	}
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn loads_diff_data_from_a_later_start() {
+	let dir = temp_path("diff-store-since");
+	DiffStore::create(&dir)
+		.unwrap()
+		.migrate(fixture("diff.csv"))
+		.unwrap();
+
+	let (_, districts) = covid::load_districts(fixture("districts.csv")).unwrap();
+	let start = NaiveDate::from_ymd_opt(2021, 2, 22).unwrap();
+	let end = NaiveDate::from_ymd_opt(2021, 3, 22).unwrap();
+	let builder = |diff: &str, diffstart: NaiveDate| {
+		PipelineBuilder::new(
+			fixture("cases.csv").as_str(),
+			diff,
+			diffstart,
+			"divi",
+			"vacc",
+			"hosp",
+			"destatis",
+		)
+		.policy(SubmitPolicy::Fail)
+		.range(start, end)
+	};
+	let full = builder(&fixture("diff.csv"), start)
+		.load_cases(&mut RunLog::new(), &districts)
+		.unwrap();
+	// the diff data is then loaded from `later`, and the records before it are skipped
+	let later = NaiveDate::from_ymd_opt(2021, 3, 10).unwrap();
+	let since = later + chrono::Duration::days(35);
+	for diff in [fixture("diff.csv"), dir.to_str().unwrap().to_string()].iter() {
+		let partial = builder(diff, start)
+			.since(since)
+			.load_cases(&mut RunLog::new(), &districts)
+			.unwrap();
+		// while the records before the diff start itself are still out of range
+		assert!(builder(diff, later)
+			.load_cases(&mut RunLog::new(), &districts)
+			.is_err());
+		for k in full.cases_by_pub.cum.keys() {
+			for day in 0..(end - later).num_days() {
+				let at = later + chrono::Duration::days(day);
+				assert_eq!(
+					partial.cases_by_pub.d1.getf(k, at),
+					full.cases_by_pub.d1.getf(k, at),
+					"{:?} at {}",
+					k,
+					at
+				);
+			}
+			assert_eq!(
+				partial
+					.cases_by_pub
+					.d1
+					.getf(k, later - chrono::Duration::days(1)),
+				None
+			);
+		}
+	}
+	fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn snapshots_diff_stores_until_a_partition_changes() {
+	let dir = temp_path("diff-store-snapshotted");
+	let snapshots = temp_path("diff-store-snapshots");
+	let mut store = DiffStore::create(&dir).unwrap();
+	store.migrate(fixture("diff.csv")).unwrap();
+
+	let (_, districts) = covid::load_districts(fixture("districts.csv")).unwrap();
+	let start = NaiveDate::from_ymd_opt(2021, 2, 22).unwrap();
+	let builder = PipelineBuilder::new(
+		fixture("cases.csv").as_str(),
+		dir.to_str().unwrap(),
+		start,
+		"divi",
+		"vacc",
+		"hosp",
+		"destatis",
+	)
+	.range(start, NaiveDate::from_ymd_opt(2021, 3, 22).unwrap())
+	.snapshots(&snapshots);
+	let load = || {
+		let mut runs = RunLog::new();
+		builder.load_cases(&mut runs, &districts).unwrap();
+		runs.phases()
+			.map(|(name, _)| name.to_string())
+			.collect::<Vec<_>>()
+	};
+	assert_eq!(load(), ["load_cases", "load_diff"]);
+	assert_eq!(load(), ["load_cases_snapshot", "load_diff_snapshot"]);
+
+	// as a merge which rewrites the last month
+	let last = store.partitions().last().unwrap().clone();
+	let records: Vec<DiffRecord> = store
+		.read_partition(&last)
+		.unwrap()
+		.deserialize()
+		.map(|rec| rec.unwrap())
+		.collect();
+	store
+		.write_partition(last.month, &records[..records.len() - 1])
+		.unwrap();
+	assert_eq!(load(), ["load_cases_snapshot", "load_diff"]);
+	fs::remove_dir_all(&dir).unwrap();
+	fs::remove_dir_all(&snapshots).unwrap();
+}
+
+#[test]
+fn since_restricts_the_diff_load() {
+	let diffstart = NaiveDate::from_ymd_opt(2021, 1, 1).unwrap();
+	let builder = || PipelineBuilder::new("c", "d", diffstart, "divi", "vacc", "hosp", "destatis");
+	assert_eq!(builder().diff_load_start(), diffstart);
+	assert_eq!(
+		builder()
+			.since(NaiveDate::from_ymd_opt(2021, 6, 1).unwrap())
+			.diff_load_start(),
+		NaiveDate::from_ymd_opt(2021, 4, 27).unwrap()
+	);
+	// never before the diff start
+	assert_eq!(
+		builder()
+			.since(NaiveDate::from_ymd_opt(2021, 1, 10).unwrap())
+			.diff_load_start(),
+		diffstart
+	);
+}
+
+#[test]
+fn infers_dump_dates_from_file_names() {
+	let date = NaiveDate::from_ymd_opt(2023, 5, 12).unwrap();
+	for name in [
+		"RKI_COVID19_2023-05-12.csv.gz",
+		"dumps/2021-01-01/RKI_COVID19_2023-05-12.csv",
+		"2023-05-12_Deutschland_SarsCov2_Infektionen.csv.xz",
+		"covid-2021-01-01-2023-05-12.csv",
+	]
+	.iter()
+	{
+		assert_eq!(dump_publication_date(name), Some(date), "{}", name);
+	}
+	for name in [
+		"RKI_COVID19.csv",
+		"RKI_COVID19_2023-13-12.csv",
+		"2023-05-12/cases.csv",
+	]
+	.iter()
+	{
+		assert_eq!(dump_publication_date(name), None, "{}", name);
+	}
+}
+
+#[test]
+fn verifies_dump_checksums() {
+	let path = temp_path("RKI_COVID19_2023-05-12.csv");
+	fs::write(&path, "abc").unwrap();
+	let abc = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+	assert_eq!(sha256_file(&path).unwrap(), abc);
+	fs::remove_file(&path).unwrap();
+
+	let sums = format!(
+		"{}  RKI_COVID19_2023-05-12.csv\n{} *RKI_COVID19_2023-05-13.csv.gz\n\n",
+		abc,
+		abc.to_uppercase()
+	);
+	let checksums = load_sha256sums(sums.as_bytes()).unwrap();
+	assert_eq!(checksums.len(), 2);
+	assert_eq!(checksums["RKI_COVID19_2023-05-12.csv"], abc);
+	assert_eq!(checksums["RKI_COVID19_2023-05-13.csv.gz"], abc);
+	assert!(load_sha256sums(&b"abc  RKI_COVID19_2023-05-12.csv\n"[..]).is_err());
+}
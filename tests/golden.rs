@@ -0,0 +1,247 @@
+// Golden-file tests of the line protocol: the fixture CSVs in tests/fixtures are loaded, cooked and streamed into the mock InfluxDB server, and the sorted lines are compared against tests/golden/<measurement>.lp (requires the `testing` feature). After an intended change of the output, rerun with COVID_UPDATE_GOLDEN=1 to rewrite the golden files, and review their diff.
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use covid::pipeline::{load_cooked_testing_data, resampled, PipelineBuilder};
+use covid::testing::{stream_to_mock, MockInflux};
+use covid::{
+	Bucket, Downsampling, FieldDescriptor, GeoCaseKey, NaiveDate, RunLog, Sex, StateId,
+	StreamOptions, SubmitPolicy, ViewTimeSeries,
+};
+
+fn start() -> NaiveDate {
+	NaiveDate::from_ymd_opt(2021, 2, 22).unwrap()
+}
+
+fn end() -> NaiveDate {
+	NaiveDate::from_ymd_opt(2021, 3, 22).unwrap()
+}
+
+fn ndays() -> usize {
+	(end() - start()).num_days() as usize
+}
+
+fn fixture(name: &str) -> String {
+	Path::new(env!("CARGO_MANIFEST_DIR"))
+		.join("tests/fixtures")
+		.join(name)
+		.to_str()
+		.unwrap()
+		.to_string()
+}
+
+fn golden_path(name: &str) -> PathBuf {
+	Path::new(env!("CARGO_MANIFEST_DIR"))
+		.join("tests/golden")
+		.join(format!("{}.lp", name))
+}
+
+fn assert_golden(name: &str, mock: &MockInflux) {
+	let mut lines = mock.lines();
+	assert!(!lines.is_empty(), "{}: nothing written", name);
+	// the order of the lines depends on the keyset and the serializer threads
+	lines.sort();
+	let mut actual = lines.join("\n");
+	actual.push('\n');
+
+	let path = golden_path(name);
+	if std::env::var_os("COVID_UPDATE_GOLDEN").is_some() {
+		fs::write(&path, &actual).unwrap();
+		return;
+	}
+	let expected = fs::read_to_string(&path).unwrap_or_else(|e| {
+		panic!(
+			"{}: {} (rerun with COVID_UPDATE_GOLDEN=1 to create it)",
+			path.display(),
+			e
+		)
+	});
+	if actual == expected {
+		return;
+	}
+	// the lines are too long to make sense of a full assert_eq! diff
+	for (i, (a, e)) in actual.lines().zip(expected.lines()).enumerate() {
+		assert_eq!(a, e, "{}:{} differs", path.display(), i + 1);
+	}
+	panic!(
+		"{}: {} lines written, {} expected",
+		path.display(),
+		actual.lines().count(),
+		expected.lines().count()
+	);
+}
+
+#[test]
+fn case_measurements_match_golden_files() {
+	let (states, districts) = covid::load_districts(fixture("districts.csv")).unwrap();
+	let mut runs = RunLog::new();
+	let cases = PipelineBuilder::new(
+		fixture("cases.csv").as_str(),
+		&fixture("diff.csv"),
+		start(),
+		"divi",
+		"vacc",
+		"hosp",
+		"destatis",
+	)
+	.policy(SubmitPolicy::Fail)
+	.range(start(), end())
+	.load_cases(&mut runs, &districts)
+	.unwrap();
+
+	let geo = cases.rekeyed(|(state_id, district_id, _, _)| Some((*state_id, *district_id)));
+	let mut fields: Vec<FieldDescriptor<Arc<dyn ViewTimeSeries<GeoCaseKey>>>> = Vec::new();
+	geo.write_field_descriptors(&mut fields);
+	geo.write_r_value_field_descriptors(&mut fields);
+	let mut key_list: Vec<GeoCaseKey> = districts.values().map(|d| (d.state.id, d.id)).collect();
+	key_list.sort();
+	let keys = covid::KeySet::new(&["state", "district"][..], key_list.iter(), |k, out| {
+		out.push(states[&k.0].name.as_str().into());
+		out.push(districts[&k.1].name.as_str().into());
+	})
+	.unwrap();
+
+	let mock = MockInflux::start().unwrap();
+	let options = StreamOptions::default();
+	stream_to_mock(
+		&mock,
+		"data_v2_geo",
+		start(),
+		ndays(),
+		&keys,
+		&fields,
+		&options,
+	)
+	.unwrap();
+	assert_golden("data_v2_geo", &mock);
+
+	mock.clear();
+	stream_to_mock(
+		&mock,
+		"data_v2_geo_weekly",
+		start(),
+		ndays(),
+		&keys,
+		&resampled(&fields, Bucket::Week),
+		&options,
+	)
+	.unwrap();
+	assert_golden("data_v2_geo_weekly", &mock);
+
+	// by state, with everything but the last ten days as weekly rollups
+	let light = cases.rekeyed(|(state_id, _, _, _)| Some(*state_id));
+	let mut fields: Vec<FieldDescriptor<Arc<dyn ViewTimeSeries<StateId>>>> = Vec::new();
+	light.write_field_descriptors(&mut fields);
+	let mut key_list: Vec<StateId> = states.keys().copied().collect();
+	key_list.sort();
+	let keys = covid::KeySet::new(&["state"][..], key_list.iter(), |k, out| {
+		out.push(states[k].name.as_str().into());
+	})
+	.unwrap();
+	let mut options = StreamOptions::default();
+	options.downsampling.insert(
+		"data_v2_geo_light".into(),
+		"10:data_v2_geo_light_long".parse::<Downsampling>().unwrap(),
+	);
+	mock.clear();
+	stream_to_mock(
+		&mock,
+		"data_v2_geo_light",
+		start(),
+		ndays(),
+		&keys,
+		&fields,
+		&options,
+	)
+	.unwrap();
+	assert_golden("data_v2_geo_light", &mock);
+
+	let mut fields: Vec<FieldDescriptor<Arc<dyn ViewTimeSeries<StateId>>>> = Vec::new();
+	light.write_data_quality_field_descriptors(&mut fields);
+	mock.clear();
+	stream_to_mock(
+		&mock,
+		"data_v2_quality",
+		start(),
+		ndays(),
+		&keys,
+		&fields,
+		&StreamOptions::default(),
+	)
+	.unwrap();
+	assert_golden("data_v2_quality", &mock);
+}
+
+#[test]
+fn testing_measurement_matches_golden_file() {
+	let mut runs = RunLog::new();
+	let testing =
+		load_cooked_testing_data(&mut runs, start(), end(), fixture("testing.csv")).unwrap();
+	let mut fields = Vec::new();
+	testing.write_field_descriptors(&mut fields);
+	let keys = covid::KeySet::new(&[][..], [()].iter(), |_, _| ()).unwrap();
+
+	let mock = MockInflux::start().unwrap();
+	stream_to_mock(
+		&mock,
+		"data_v2_testing",
+		start(),
+		ndays(),
+		&keys,
+		&fields,
+		&StreamOptions::default(),
+	)
+	.unwrap();
+	assert_golden("data_v2_testing", &mock);
+}
+
+#[test]
+fn unknown_sexes_load_and_collapse() {
+	// the first record (W) as if it were published with another sex
+	let original = fs::read_to_string(fixture("cases.csv")).unwrap();
+	let mut lines: Vec<&str> = original.lines().collect();
+	let divers = lines[1].replacen(",W,", ",divers,", 1);
+	lines[1] = &divers;
+	let casefile = std::env::temp_dir().join(format!("covid-sexes-{}.csv", std::process::id()));
+	fs::write(&casefile, lines.join("\n")).unwrap();
+
+	let (_, districts) = covid::load_districts(fixture("districts.csv")).unwrap();
+	let load = |collapse_sex| {
+		PipelineBuilder::new(
+			casefile.to_str().unwrap(),
+			&fixture("diff.csv"),
+			start(),
+			"divi",
+			"vacc",
+			"hosp",
+			"destatis",
+		)
+		.policy(SubmitPolicy::Fail)
+		.range(start(), end())
+		.collapse_sex(collapse_sex)
+		.load_cases(&mut RunLog::new(), &districts)
+		.unwrap()
+	};
+	let total = |cum: &dyn ViewTimeSeries<_>, keys: &[_]| -> f64 {
+		keys.iter()
+			.map(|k| cum.getf(k, end() - chrono::Duration::days(1)).unwrap())
+			.sum()
+	};
+
+	let cases = load(false);
+	let keys: Vec<_> = cases.cases_by_ref.cum.keys().cloned().collect();
+	assert!(keys.iter().any(|k| k.1 == 1001 && k.3 == Sex::Unknown));
+	assert!(keys.iter().all(|k| k.3 != Sex::All));
+	let expected = total(&*cases.cases_by_ref.cum, &keys);
+
+	let collapsed = load(true);
+	let collapsed_keys: Vec<_> = collapsed.cases_by_ref.cum.keys().cloned().collect();
+	assert!(collapsed_keys.iter().all(|k| k.3 == Sex::All));
+	assert!(collapsed_keys.len() < keys.len());
+	assert_eq!(
+		total(&*collapsed.cases_by_ref.cum, &collapsed_keys),
+		expected
+	);
+	fs::remove_file(&casefile).unwrap();
+}
@@ -0,0 +1,125 @@
+// Tests of the snapshot cache of loaded datasets (requires the `testing` feature).
+use std::fs;
+use std::path::Path;
+
+use covid::pipeline::PipelineBuilder;
+use covid::testing::MockFiles;
+use covid::{
+	AgeGroup, Counters, FullCaseKey, InputHash, NaiveDate, RunLog, Sex, SnapshotCache,
+	ViewTimeSeries,
+};
+
+fn counters() -> Counters<FullCaseKey> {
+	let start = NaiveDate::from_ymd_opt(2021, 1, 1).unwrap();
+	let mut ts = Counters::<FullCaseKey>::new(start, start + chrono::Duration::days(3));
+	let ag = "A15-A34".parse::<AgeGroup>().unwrap();
+	ts.get_or_create((1, 1001, Some(ag).into(), Sex::Female))
+		.copy_from_slice(&[1, 2, 3]);
+	ts.get_or_create((1, 1001, None.into(), Sex::Unknown))
+		.copy_from_slice(&[0, 0, 7]);
+	ts
+}
+
+#[test]
+fn snapshot_round_trip_requires_same_inputs() {
+	let dir = std::env::temp_dir().join(format!("covid-snapshots-{}", std::process::id()));
+	let input = dir.with_extension("csv");
+	fs::write(&input, "a,b\n1,2\n").unwrap();
+	let cache = SnapshotCache::new(&dir);
+	let hash = |path: &std::path::Path| {
+		let mut h = InputHash::new();
+		h.file(path).unwrap();
+		h.param(&3u32);
+		h.finish()
+	};
+
+	let ts = counters();
+	assert!(cache
+		.load::<Counters<FullCaseKey>>("cases", hash(&input))
+		.unwrap()
+		.is_none());
+	cache.store("cases", hash(&input), &ts).unwrap();
+
+	let loaded: Counters<FullCaseKey> = cache.load("cases", hash(&input)).unwrap().unwrap();
+	assert_eq!(loaded.start(), ts.start());
+	assert_eq!(loaded.len(), ts.len());
+	let mut keys: Vec<_> = loaded.keys().cloned().collect();
+	keys.sort_by_key(|k| format!("{:?}", k));
+	for k in keys.iter() {
+		assert_eq!(loaded.get(k), ts.get(k));
+	}
+	assert_eq!(keys.len(), 2);
+
+	fs::write(&input, "a,b\n1,3\n").unwrap();
+	assert!(cache
+		.load::<Counters<FullCaseKey>>("cases", hash(&input))
+		.unwrap()
+		.is_none());
+
+	fs::remove_dir_all(&dir).unwrap();
+	fs::remove_file(&input).unwrap();
+}
+
+fn fixture(name: &str) -> Vec<u8> {
+	fs::read(
+		Path::new(env!("CARGO_MANIFEST_DIR"))
+			.join("tests/fixtures")
+			.join(name),
+	)
+	.unwrap()
+}
+
+#[test]
+fn snapshots_of_url_inputs_follow_their_etag() {
+	let server = MockFiles::start().unwrap();
+	server.put("/cases.csv", &fixture("cases.csv"), "\"c1\"");
+	server.put("/diff.csv", &fixture("diff.csv"), "\"d1\"");
+	let dir = std::env::temp_dir().join(format!("covid-url-snapshots-{}", std::process::id()));
+	let (_, districts) = covid::load_districts(
+		Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/districts.csv"),
+	)
+	.unwrap();
+	let start = NaiveDate::from_ymd_opt(2021, 2, 22).unwrap();
+	let builder = PipelineBuilder::new(
+		server.url("/cases.csv").as_str(),
+		&server.url("/diff.csv"),
+		start,
+		"divi",
+		"vacc",
+		"hosp",
+		"destatis",
+	)
+	.range(start, NaiveDate::from_ymd_opt(2021, 3, 22).unwrap())
+	.snapshots(&dir);
+	// the phases of a load, and the number of downloads it made
+	let load = || {
+		let before = server.requests().len();
+		let mut runs = RunLog::new();
+		let cases = builder.load_cases(&mut runs, &districts).unwrap();
+		let phases: Vec<String> = runs.phases().map(|(name, _)| name.into()).collect();
+		let gets = server.requests()[before..]
+			.iter()
+			.filter(|(method, _)| method == "GET")
+			.count();
+		(cases, phases, gets)
+	};
+
+	let (loaded, phases, gets) = load();
+	assert_eq!(phases, ["load_cases", "load_diff"]);
+	assert_eq!(gets, 2);
+	let (from_snapshot, phases, gets) = load();
+	assert_eq!(phases, ["load_cases_snapshot", "load_diff_snapshot"]);
+	assert_eq!(gets, 0);
+	for k in loaded.cases_by_pub.cum.keys() {
+		assert_eq!(
+			from_snapshot.cases_by_pub.cum.getf(k, start),
+			loaded.cases_by_pub.cum.getf(k, start)
+		);
+	}
+
+	server.put("/cases.csv", &fixture("cases.csv"), "\"c2\"");
+	let (_, phases, gets) = load();
+	assert_eq!(phases, ["load_cases", "load_diff_snapshot"]);
+	assert_eq!(gets, 1);
+	fs::remove_dir_all(&dir).unwrap();
+}
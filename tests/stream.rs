@@ -0,0 +1,1056 @@
+// Integration tests for stream_dynamic against the mock InfluxDB server (requires the `testing` feature).
+use std::sync::Arc;
+
+use smartstring::alias::String as SmartString;
+
+use covid::influxdb;
+use covid::pipeline::{resampled, write_centroid_field_descriptors};
+use covid::testing::{stream_to_mock, MockInflux};
+use covid::{
+	Aggregation, Bucket, Checkpoint, CsvSink, DiffRecord, DiffSchema, DistrictCentroids, FanOut,
+	FieldDescriptor, GeoCaseKey, NaiveDate, NullPolicy, NullSink, Output, ParquetSink, Sex,
+	SinkErrorPolicy, SqliteSink, StreamOptions, TimeSeries, ViewTimeSeries,
+};
+
+type Fields = Vec<FieldDescriptor<Arc<dyn ViewTimeSeries<SmartString>>>>;
+
+fn start() -> NaiveDate {
+	NaiveDate::from_ymd_opt(2021, 1, 1).unwrap()
+}
+
+fn series(keys: &[&str], ndays: usize) -> Arc<TimeSeries<SmartString, u64>> {
+	let end = start() + chrono::Duration::days(ndays as i64);
+	let mut ts = TimeSeries::<SmartString, u64>::new(start(), end);
+	for (i, k) in keys.iter().enumerate() {
+		let values = ts.get_or_create((*k).into());
+		for (j, v) in values.iter_mut().enumerate() {
+			*v = (i * 100 + j) as u64;
+		}
+	}
+	Arc::new(ts)
+}
+
+#[test]
+fn writes_one_line_per_key_and_day() {
+	let mock = MockInflux::start().unwrap();
+	let ts = series(&["a", "b"], 3);
+	let fields: Fields = vec![FieldDescriptor::new(ts.clone(), "n")];
+	let keyset = covid::KeySet::new(&["k"], ts.keys(), |k, out| out.push(k.clone())).unwrap();
+
+	let stats = stream_to_mock(
+		&mock,
+		"m",
+		start(),
+		3,
+		&keyset,
+		&fields,
+		&StreamOptions::default(),
+	)
+	.unwrap();
+
+	let mut lines = mock.lines();
+	lines.sort();
+	assert_eq!(
+		lines,
+		vec![
+			"m,k=a n=0.0 1609459200",
+			"m,k=a n=1.0 1609545600",
+			"m,k=a n=2.0 1609632000",
+			"m,k=b n=100.0 1609459200",
+			"m,k=b n=101.0 1609545600",
+			"m,k=b n=102.0 1609632000",
+		]
+	);
+	assert_eq!(stats.rows, 6);
+	assert_eq!(stats.last_day, Some(start() + chrono::Duration::days(2)));
+	let writes = mock.writes();
+	assert_eq!(
+		stats.bytes,
+		writes.iter().map(|w| w.body.len()).sum::<usize>()
+	);
+	for w in writes.iter() {
+		assert_eq!(w.path, "/write");
+		assert_eq!(w.param("db"), Some("covid"));
+		assert_eq!(w.param("precision"), Some("s"));
+	}
+}
+
+#[test]
+fn escapes_measurement_tags_and_fields() {
+	let mock = MockInflux::start().unwrap();
+	let ts = series(&["Stadt, Kreis=1"], 1);
+	let fields: Fields = vec![FieldDescriptor::new(ts.clone(), "a b")];
+	let keyset =
+		covid::KeySet::new(&["tag name"], ts.keys(), |k, out| out.push(k.clone())).unwrap();
+
+	stream_to_mock(
+		&mock,
+		"my measurement,x",
+		start(),
+		1,
+		&keyset,
+		&fields,
+		&StreamOptions::default(),
+	)
+	.unwrap();
+
+	assert_eq!(
+		mock.lines(),
+		vec!["my\\ measurement\\,x,tag\\ name=Stadt\\,\\ Kreis\\=1 a\\ b=0.0 1609459200"]
+	);
+}
+
+#[test]
+fn splits_large_keysets_into_chunks() {
+	let mock = MockInflux::start().unwrap();
+	let names: Vec<String> = (0..2500).map(|i| format!("k{}", i)).collect();
+	let names: Vec<&str> = names.iter().map(|x| x.as_str()).collect();
+	let ndays = 7;
+	let ts = series(&names, ndays);
+	let fields: Fields = vec![FieldDescriptor::new(ts.clone(), "n")];
+	let keyset = covid::KeySet::new(&["k"], ts.keys(), |k, out| out.push(k.clone())).unwrap();
+
+	let stats = stream_to_mock(
+		&mock,
+		"m",
+		start(),
+		ndays,
+		&keyset,
+		&fields,
+		&StreamOptions::default(),
+	)
+	.unwrap();
+
+	let writes = mock.writes();
+	assert!(writes.len() > 1);
+	for w in writes.iter() {
+		assert!(w.lines().count() <= StreamOptions::default().chunk_lines);
+	}
+	let lines = mock.lines();
+	assert_eq!(lines.len(), names.len() * ndays);
+	assert_eq!(stats.rows, lines.len());
+	let mut unique = lines.clone();
+	unique.sort();
+	unique.dedup();
+	assert_eq!(unique.len(), lines.len());
+}
+
+#[test]
+fn skips_samples_according_to_filter() {
+	let mock = MockInflux::start().unwrap();
+	let end = start() + chrono::Duration::days(4);
+	let mut ts = TimeSeries::<SmartString, u64>::new(start(), end);
+	ts.get_or_create("a".into())
+		.copy_from_slice(&[0, 1, 1, 0][..]);
+	let ts = Arc::new(ts);
+	let fields: Fields = vec![FieldDescriptor::new(ts.clone(), "n")];
+	let keyset = covid::KeySet::new(&["k"], ts.keys(), |k, out| out.push(k.clone())).unwrap();
+
+	let stats = stream_to_mock(
+		&mock,
+		"m",
+		start(),
+		4,
+		&keyset,
+		&fields,
+		&StreamOptions {
+			sample_filter: "default,unchanged".parse().unwrap(),
+			..StreamOptions::default()
+		},
+	)
+	.unwrap();
+
+	assert_eq!(mock.lines(), vec!["m,k=a n=1.0 1609545600"]);
+	assert_eq!(stats.rows, 1);
+}
+
+#[test]
+fn maps_error_statuses() {
+	let ts = series(&["a"], 1);
+	let fields: Fields = vec![FieldDescriptor::new(ts.clone(), "n")];
+	let keyset = covid::KeySet::new(&["k"], ts.keys(), |k, out| out.push(k.clone())).unwrap();
+
+	for (status, check) in [
+		(
+			401u16,
+			(|e: &influxdb::Error| matches!(e, influxdb::Error::PermissionError)) as fn(&_) -> bool,
+		),
+		(403, |e| matches!(e, influxdb::Error::PermissionError)),
+		(400, |e| matches!(e, influxdb::Error::DataError)),
+		(413, |e| matches!(e, influxdb::Error::DataError)),
+		(404, |e| matches!(e, influxdb::Error::DatabaseNotFound)),
+		(500, |e| matches!(e, influxdb::Error::Status(500))),
+	]
+	.iter()
+	{
+		let mock = MockInflux::start().unwrap();
+		mock.fail_next(*status);
+		let err = stream_to_mock(
+			&mock,
+			"m",
+			start(),
+			1,
+			&keyset,
+			&fields,
+			&StreamOptions::default(),
+		)
+		.unwrap_err();
+		let err = match err {
+			covid::Error::Sink(e) => e,
+			other => panic!("status {}: not a sink error: {:?}", status, other),
+		};
+		assert!(check(&err), "status {}: unexpected error {:?}", status, err);
+		assert!(mock.lines().is_empty());
+		assert_eq!(mock.writes().len(), 1);
+	}
+}
+
+#[test]
+fn recovers_after_injected_failure() {
+	let mock = MockInflux::start().unwrap();
+	let ts = series(&["a"], 1);
+	let fields: Fields = vec![FieldDescriptor::new(ts.clone(), "n")];
+	let keyset = covid::KeySet::new(&["k"], ts.keys(), |k, out| out.push(k.clone())).unwrap();
+	let options = StreamOptions::default();
+
+	mock.fail_next(503);
+	assert!(stream_to_mock(&mock, "m", start(), 1, &keyset, &fields, &options).is_err());
+	stream_to_mock(&mock, "m", start(), 1, &keyset, &fields, &options).unwrap();
+
+	let writes = mock.writes();
+	assert_eq!(writes.len(), 2);
+	assert_eq!(writes[0].status, 503);
+	assert_eq!(writes[1].status, 204);
+	assert_eq!(mock.lines(), vec!["m,k=a n=0.0 1609459200"]);
+}
+
+#[test]
+fn applies_database_and_measurement_options() {
+	let mock = MockInflux::start().unwrap();
+	let ts = series(&["a"], 1);
+	let fields: Fields = vec![FieldDescriptor::new(ts.clone(), "n")];
+	let keyset = covid::KeySet::new(&["k"], ts.keys(), |k, out| out.push(k.clone())).unwrap();
+	let mut options = StreamOptions {
+		database: "other".into(),
+		retention_policy: Some("short".into()),
+		..StreamOptions::default()
+	};
+	options
+		.measurement_names
+		.insert("m".into(), "renamed".into());
+	options.disabled_measurements.insert("off".into());
+
+	stream_to_mock(&mock, "m", start(), 1, &keyset, &fields, &options).unwrap();
+	let stats = stream_to_mock(&mock, "off", start(), 1, &keyset, &fields, &options).unwrap();
+
+	assert_eq!(stats.rows, 0);
+	let writes = mock.writes();
+	assert_eq!(writes.len(), 1);
+	assert_eq!(writes[0].param("db"), Some("other"));
+	assert_eq!(writes[0].param("rp"), Some("short"));
+	assert_eq!(mock.lines(), vec!["renamed,k=a n=0.0 1609459200"]);
+}
+
+#[test]
+fn compresses_bodies_above_threshold() {
+	let mock = MockInflux::start().unwrap();
+	let ts = series(&["a", "b"], 3);
+	let fields: Fields = vec![FieldDescriptor::new(ts.clone(), "n")];
+	let keyset = covid::KeySet::new(&["k"], ts.keys(), |k, out| out.push(k.clone())).unwrap();
+
+	let stats = covid::stream_dynamic(
+		&mock.client().with_gzip(Some(64)),
+		&mut covid::NullSink(),
+		"m",
+		start(),
+		3,
+		&keyset,
+		&fields,
+		&StreamOptions {
+			chunk_lines: 4,
+			..StreamOptions::default()
+		},
+	)
+	.unwrap();
+
+	// four lines exceed the threshold, the remaining two do not
+	let writes = mock.writes();
+	assert_eq!(writes.len(), 2);
+	assert!(writes[0].gzip);
+	assert!(!writes[1].gzip);
+	assert_eq!(mock.lines().len(), 6);
+	assert_eq!(
+		stats.bytes,
+		writes.iter().map(|w| w.body.len()).sum::<usize>()
+	);
+}
+
+#[test]
+fn flushes_on_line_and_byte_limits() {
+	let ts = series(&["a", "b", "c"], 4);
+	let fields: Fields = vec![FieldDescriptor::new(ts.clone(), "n")];
+	let keyset = covid::KeySet::new(&["k"], ts.keys(), |k, out| out.push(k.clone())).unwrap();
+
+	let mock = MockInflux::start().unwrap();
+	let options = StreamOptions {
+		chunk_lines: 5,
+		chunk_bytes: 0,
+		..StreamOptions::default()
+	};
+	stream_to_mock(&mock, "m", start(), 4, &keyset, &fields, &options).unwrap();
+	let counts: Vec<usize> = mock.writes().iter().map(|w| w.lines().count()).collect();
+	assert_eq!(counts, vec![5, 5, 2]);
+
+	// every line is longer than 20 bytes, so each one is sent on its own
+	let mock = MockInflux::start().unwrap();
+	let options = StreamOptions {
+		chunk_lines: 0,
+		chunk_bytes: 20,
+		..StreamOptions::default()
+	};
+	stream_to_mock(&mock, "m", start(), 4, &keyset, &fields, &options).unwrap();
+	assert_eq!(mock.writes().len(), 12);
+	assert_eq!(mock.lines().len(), 12);
+}
+
+#[test]
+fn resumes_after_checkpoint() {
+	let path = std::env::temp_dir().join(format!("covid-checkpoint-{}", std::process::id()));
+	let _ = std::fs::remove_file(&path);
+	let ts = series(&["a", "b"], 3);
+	let fields: Fields = vec![FieldDescriptor::new(ts.clone(), "n")];
+	let keyset = covid::KeySet::new(&["k"], ts.keys(), |k, out| out.push(k.clone())).unwrap();
+	let mut options = StreamOptions {
+		chunk_lines: 2,
+		checkpoint: Some(path.clone()),
+		..StreamOptions::default()
+	};
+
+	// the first request fails, so nothing is recorded as written
+	let mock = MockInflux::start().unwrap();
+	mock.fail_next(500);
+	assert!(stream_to_mock(&mock, "m", start(), 3, &keyset, &fields, &options).is_err());
+	assert_eq!(Checkpoint::load(&path).unwrap().get("m"), None);
+
+	// pretend that an earlier run got through the first day
+	Checkpoint::load(&path)
+		.unwrap()
+		.set("m", Some(start()))
+		.unwrap();
+	options.resume = true;
+	let mock = MockInflux::start().unwrap();
+	let stats = stream_to_mock(&mock, "m", start(), 3, &keyset, &fields, &options).unwrap();
+	assert_eq!(stats.rows, 4);
+	let mut lines = mock.lines();
+	lines.sort();
+	assert_eq!(
+		lines,
+		vec![
+			"m,k=a n=1.0 1609545600",
+			"m,k=a n=2.0 1609632000",
+			"m,k=b n=101.0 1609545600",
+			"m,k=b n=102.0 1609632000",
+		]
+	);
+	assert_eq!(
+		Checkpoint::load(&path).unwrap().get("m"),
+		Some(NaiveDate::from_ymd_opt(2021, 1, 3).unwrap())
+	);
+
+	// everything is written, nothing left to resume
+	let mock = MockInflux::start().unwrap();
+	stream_to_mock(&mock, "m", start(), 3, &keyset, &fields, &options).unwrap();
+	assert!(mock.writes().is_empty());
+
+	std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn writes_only_changed_days_incrementally() {
+	let path = std::env::temp_dir().join(format!("covid-dayhashes-{}", std::process::id()));
+	let _ = std::fs::remove_file(&path);
+	let keys = ["a", "b"];
+	let ts = series(&keys, 3);
+	let fields: Fields = vec![FieldDescriptor::new(ts.clone(), "n")];
+	let keyset = covid::KeySet::new(&["k"], ts.keys(), |k, out| out.push(k.clone())).unwrap();
+	let options = StreamOptions {
+		incremental_state: Some(path.clone()),
+		..StreamOptions::default()
+	};
+
+	let mock = MockInflux::start().unwrap();
+	stream_to_mock(&mock, "m", start(), 3, &keyset, &fields, &options).unwrap();
+	assert_eq!(mock.lines().len(), 6);
+
+	// nothing changed
+	let mock = MockInflux::start().unwrap();
+	let stats = stream_to_mock(&mock, "m", start(), 3, &keyset, &fields, &options).unwrap();
+	assert!(mock.writes().is_empty());
+	assert_eq!(stats.rows, 0);
+	assert_eq!(stats.last_day, None);
+
+	// a changed value resends all lines of its day, and only those
+	let mut changed = (*series(&keys, 3)).clone();
+	changed.get_or_create("b".into())[1] = 42;
+	let changed = Arc::new(changed);
+	let fields: Fields = vec![FieldDescriptor::new(changed.clone(), "n")];
+	let keyset = covid::KeySet::new(&["k"], changed.keys(), |k, out| out.push(k.clone())).unwrap();
+	let mock = MockInflux::start().unwrap();
+	stream_to_mock(&mock, "m", start(), 3, &keyset, &fields, &options).unwrap();
+	let mut lines = mock.lines();
+	lines.sort();
+	assert_eq!(
+		lines,
+		vec!["m,k=a n=1.0 1609545600", "m,k=b n=42.0 1609545600"]
+	);
+
+	std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn output_does_not_depend_on_thread_count() {
+	let names: Vec<String> = (0..300).map(|i| format!("k{}", i)).collect();
+	let names: Vec<&str> = names.iter().map(|x| x.as_str()).collect();
+	let ts = series(&names, 40);
+	let fields: Fields = vec![FieldDescriptor::new(ts.clone(), "n")];
+	let keyset = covid::KeySet::new(&["k"], ts.keys(), |k, out| out.push(k.clone())).unwrap();
+
+	let mut outputs = Vec::new();
+	for threads in [1, 4].iter() {
+		let mock = MockInflux::start().unwrap();
+		let options = StreamOptions {
+			serialize_threads: *threads,
+			sample_filter: "unchanged".parse().unwrap(),
+			chunk_lines: 1000,
+			..StreamOptions::default()
+		};
+		stream_to_mock(&mock, "m", start(), 40, &keyset, &fields, &options).unwrap();
+		let bodies: Vec<Vec<u8>> = mock.writes().into_iter().map(|w| w.body).collect();
+		outputs.push(bodies);
+	}
+	assert_eq!(outputs[0], outputs[1]);
+	assert_eq!(outputs[0].len(), 12);
+}
+
+#[test]
+fn downsamples_older_days_into_weekly_measurement() {
+	let mock = MockInflux::start().unwrap();
+	// 2021-01-01 is a friday, the first complete week ends on 2021-01-10
+	let ts = series(&["a"], 24);
+	let view = ts.clone() as Arc<dyn ViewTimeSeries<SmartString>>;
+	let fields: Fields =
+		vec![FieldDescriptor::new(view, "n_d1").with_aggregation(Aggregation::Sum)];
+	let keyset = covid::KeySet::new(&["k"], ts.keys(), |k, out| out.push(k.clone())).unwrap();
+	let mut options = StreamOptions::default();
+	options.downsampling.insert(
+		"m".into(),
+		"5:m_weekly@long".parse::<covid::Downsampling>().unwrap(),
+	);
+
+	let stats = stream_to_mock(&mock, "m", start(), 24, &keyset, &fields, &options).unwrap();
+
+	let writes = mock.writes();
+	assert_eq!(writes.len(), 2);
+	assert_eq!(writes[0].param("rp"), Some("long"));
+	assert_eq!(writes[1].param("rp"), None);
+	assert_eq!(
+		mock.lines(),
+		vec![
+			// days 0 to 2 (friday to sunday), 3 to 9 and 10 to 16
+			"m_weekly,k=a n_d1=3.0 1609632000",
+			"m_weekly,k=a n_d1=42.0 1610236800",
+			"m_weekly,k=a n_d1=91.0 1610841600",
+			// the week from day 17 straddles the split at day 19 and is written daily only
+			"m,k=a n_d1=19.0 1611100800",
+			"m,k=a n_d1=20.0 1611187200",
+			"m,k=a n_d1=21.0 1611273600",
+			"m,k=a n_d1=22.0 1611360000",
+			"m,k=a n_d1=23.0 1611446400",
+		]
+	);
+	assert_eq!(stats.rows, 8);
+	assert_eq!(stats.last_day, Some(start() + chrono::Duration::days(23)));
+}
+
+#[test]
+fn streams_only_keys_matching_the_filter() {
+	let mock = MockInflux::start().unwrap();
+	let ts = series(&["a", "b", "c"], 1);
+	let fields: Fields = vec![FieldDescriptor::new(ts.clone(), "n")];
+	let mut keys: Vec<&SmartString> = ts.keys().collect();
+	keys.sort();
+	let keyset = covid::KeySet::new(&["k", "group"], keys, |k, out| {
+		out.push(k.clone());
+		out.push(if k == "c" { "y" } else { "x" }.into());
+	})
+	.unwrap();
+
+	let filter: covid::KeyFilter = "k=a, k = c,group=x".parse().unwrap();
+	assert_eq!(
+		filter,
+		covid::KeyFilter::new()
+			.with_condition("k", "a")
+			.with_condition("k", "c")
+			.with_condition("group", "x")
+	);
+	let options = StreamOptions {
+		key_filter: Some(filter),
+		..StreamOptions::default()
+	};
+	let stats = stream_to_mock(&mock, "m", start(), 1, &keyset, &fields, &options).unwrap();
+	assert_eq!(mock.lines(), vec!["m,k=a,group=x n=0.0 1609459200"]);
+	assert_eq!(stats.rows, 1);
+
+	// conditions on tags which a measurement does not have are ignored
+	let options = StreamOptions {
+		key_filter: Some("k=b,district=SK Dresden".parse().unwrap()),
+		..StreamOptions::default()
+	};
+	mock.clear();
+	stream_to_mock(&mock, "m", start(), 1, &keyset, &fields, &options).unwrap();
+	assert_eq!(mock.lines(), vec!["m,k=b,group=x n=100.0 1609459200"]);
+
+	for invalid in ["", "k", "=a", "k=a,"].iter() {
+		assert!(
+			invalid.parse::<covid::KeyFilter>().is_err(),
+			"{:?}",
+			invalid
+		);
+	}
+}
+
+#[test]
+fn writes_integer_fields_if_enabled() {
+	let ts = series(&["a"], 3);
+	let fields: Fields = vec![
+		FieldDescriptor::integer(ts.clone(), "n_d1"),
+		FieldDescriptor::new(ts.clone(), "n"),
+	];
+	let keyset = covid::KeySet::new(&["k"], ts.keys(), |k, out| out.push(k.clone())).unwrap();
+	let options = StreamOptions {
+		integer_fields: true,
+		..StreamOptions::default()
+	};
+
+	let mock = MockInflux::start().unwrap();
+	stream_to_mock(&mock, "m", start(), 2, &keyset, &fields, &options).unwrap();
+	assert_eq!(
+		mock.lines(),
+		vec![
+			"m,k=a n_d1=0i,n=0.0 1609459200",
+			"m,k=a n_d1=1i,n=1.0 1609545600",
+		]
+	);
+
+	// off by default, as InfluxDB refuses to change the type of a field
+	let mock = MockInflux::start().unwrap();
+	let default_options = StreamOptions::default();
+	stream_to_mock(&mock, "m", start(), 1, &keyset, &fields, &default_options).unwrap();
+	assert_eq!(mock.lines(), vec!["m,k=a n_d1=0.0,n=0.0 1609459200"]);
+
+	// sums and last values of counts stay integers, means do not
+	let mock = MockInflux::start().unwrap();
+	let view = ts.clone() as Arc<dyn ViewTimeSeries<SmartString>>;
+	let weekly = resampled(
+		&[
+			FieldDescriptor::integer(view.clone(), "n_d1").with_aggregation(Aggregation::Sum),
+			FieldDescriptor::integer(view.clone(), "n"),
+			FieldDescriptor::integer(view, "c").with_aggregation(Aggregation::Last),
+		],
+		Bucket::Week,
+	);
+	stream_to_mock(&mock, "m", start(), 3, &keyset, &weekly, &options).unwrap();
+	assert_eq!(mock.lines(), vec!["m,k=a n_d1=3i,n=1.0,c=2i 1609632000"]);
+}
+
+// values on even days only
+struct EvenDays;
+
+impl ViewTimeSeries<SmartString> for EvenDays {
+	fn getf(&self, _k: &SmartString, at: NaiveDate) -> Option<f64> {
+		let i = (at - start()).num_days();
+		if i % 2 == 0 {
+			Some(i as f64)
+		} else {
+			None
+		}
+	}
+}
+
+#[test]
+fn applies_null_policies() {
+	let ts = series(&["a"], 3);
+	let even: Arc<dyn ViewTimeSeries<SmartString>> = Arc::new(EvenDays);
+	let fields: Fields = vec![
+		FieldDescriptor::new(even.clone(), "skip"),
+		FieldDescriptor::new(even.clone(), "zero").with_null_policy(NullPolicy::Zero),
+		FieldDescriptor::new(even, "pad").with_null_policy(NullPolicy::Pad(-1.)),
+	];
+	let keyset = covid::KeySet::new(&["k"], ts.keys(), |k, out| out.push(k.clone())).unwrap();
+
+	let mock = MockInflux::start().unwrap();
+	stream_to_mock(
+		&mock,
+		"m",
+		start(),
+		3,
+		&keyset,
+		&fields,
+		&StreamOptions::default(),
+	)
+	.unwrap();
+	assert_eq!(
+		mock.lines(),
+		vec![
+			"m,k=a skip=0.0,zero=0.0,pad=0.0 1609459200",
+			"m,k=a zero=0.0,pad=-1.0 1609545600",
+			"m,k=a skip=2.0,zero=2.0,pad=2.0 1609632000",
+		]
+	);
+
+	// the other outputs go through the same FieldDescriptor::get_range
+	let dir = std::env::temp_dir().join(format!("covid-nullpolicy-{}", std::process::id()));
+	let mut output = Output::Csv(CsvSink::new(&dir).unwrap());
+	output
+		.stream_dynamic(
+			&mut NullSink(),
+			"m",
+			start(),
+			3,
+			&keyset,
+			&fields,
+			&StreamOptions::default(),
+		)
+		.unwrap();
+	let csv = std::fs::read_to_string(dir.join("m.csv")).unwrap();
+	std::fs::remove_dir_all(&dir).unwrap();
+	assert_eq!(
+		csv,
+		"date,k,skip,zero,pad\n2021-01-01,a,0,0,0\n2021-01-02,a,,0,-1\n2021-01-03,a,2,2,2\n"
+	);
+}
+
+#[test]
+fn validates_keysets() {
+	let keys: Vec<SmartString> = vec!["a".into(), "b c".into()];
+	let mut keyset =
+		covid::KeySet::new(&["k"], keys[..1].iter(), |k, out| out.push(k.clone())).unwrap();
+	let other = covid::KeySet::from_tagged(
+		&["k", "extra"],
+		keys[1..].iter().map(|k| (k, vec![k.clone(), "x".into()])),
+	)
+	.unwrap();
+	match keyset.append(other).unwrap_err() {
+		covid::Error::Schema(msg) => assert_eq!(
+			msg,
+			"cannot append a keyset with the tags [\"k\", \"extra\"] to one with the tags [\"k\"]"
+		),
+		other => panic!("unexpected error {:?}", other),
+	}
+	keyset
+		.append(covid::KeySet::new(&["k"], keys[1..].iter(), |k, out| out.push(k.clone())).unwrap())
+		.unwrap();
+	assert_eq!(keyset.len(), 2);
+	assert_eq!(keyset.tag_names(), &["k"]);
+	assert_eq!(keyset.entries()[1].key, &keys[1]);
+	assert_eq!(keyset.entries()[1].tag_values, vec!["b c"]);
+	assert_eq!(&keyset.entries()[1].tagset[..], &b",k=b\\ c"[..]);
+
+	// a closure which renders the wrong number of tag values is an error, not a panic
+	let err =
+		covid::KeySet::new(&["k", "extra"], keys.iter(), |k, out| out.push(k.clone())).unwrap_err();
+	assert!(matches!(err, covid::Error::Schema(_)), "{:?}", err);
+	assert!(keyset.push(&keys[0], Vec::new()).is_err());
+	assert_eq!(keyset.len(), 2);
+}
+
+#[test]
+fn extends_keysets_with_tags() {
+	let keys: Vec<(u32, u32)> = vec![(1, 1001), (11, 11000)];
+	let keyset = covid::KeySet::new(&["state"], keys.iter(), |k, out| {
+		out.push(format!("s{}", k.0).into())
+	})
+	.unwrap()
+	.extended(&["state_id", "district_id"], |k, out| {
+		out.push(format!("{:02}", k.0).into());
+		out.push(format!("{:05}", k.1).into());
+	})
+	.unwrap();
+	assert_eq!(keyset.tag_names(), &["state", "state_id", "district_id"]);
+	assert_eq!(keyset.entries()[0].tag_values, vec!["s1", "01", "01001"]);
+	assert_eq!(
+		&keyset.entries()[1].tagset[..],
+		&b",state=s11,state_id=11,district_id=11000"[..]
+	);
+
+	let err = covid::KeySet::new(&["state"], keys.iter(), |_, out| out.push("s".into()))
+		.unwrap()
+		.extended(&["state_id"], |_, _| ())
+		.unwrap_err();
+	assert!(matches!(err, covid::Error::Schema(_)), "{:?}", err);
+}
+
+#[test]
+fn writes_district_centroids() {
+	let centroids =
+		DistrictCentroids::load(&b"district_id,lat,lon\n1001,54.78,9.43\n11000,52.5,13.4\n"[..])
+			.unwrap();
+	let mut fields = Vec::new();
+	write_centroid_field_descriptors(&Arc::new(centroids), &mut fields);
+	// the national key has no position
+	let keys: Vec<GeoCaseKey> = vec![(1, 1001), (11, 11000), (0, 0)];
+	let keyset = covid::KeySet::new(&["district"], keys.iter(), |k, out| {
+		out.push(k.1.to_string().into())
+	})
+	.unwrap();
+
+	let mock = MockInflux::start().unwrap();
+	stream_to_mock(
+		&mock,
+		"geo",
+		start(),
+		1,
+		&keyset,
+		&fields,
+		&StreamOptions::default(),
+	)
+	.unwrap();
+	let mut lines = mock.lines();
+	lines.sort();
+	assert_eq!(
+		lines,
+		vec![
+			"geo,district=1001 lat=54.78,lon=9.43 1609459200",
+			"geo,district=11000 lat=52.5,lon=13.4 1609459200",
+		]
+	);
+
+	for invalid in [
+		&b"district_id,lat,lon\n1001,9.43,540.78\n"[..],
+		&b"district_id,lat,lon\n1001,54.78,9.43\n1001,54.78,9.43\n"[..],
+		&b"district_id,lat\n1001,54.78\n"[..],
+	]
+	.iter()
+	{
+		assert!(DistrictCentroids::load(*invalid).is_err());
+	}
+}
+
+#[test]
+fn diff_records_round_trip() {
+	let rec = DiffRecord {
+		date: start(),
+		district_id: 1001,
+		age_group: Some("A15-A34".parse().unwrap()).into(),
+		sex: Sex::Female,
+		delay_total: 6,
+		cases_delayed: 3,
+		late_cases: 1,
+		cases: 6,
+		deaths: 0,
+		recovered: 2,
+		cases_rep_d7: 36,
+		cases_retracted: 1,
+	};
+	let mut w = DiffRecord::writer(Vec::new()).unwrap();
+	w.serialize(&rec).unwrap();
+	let written = w.into_inner().unwrap();
+	assert!(written.ends_with(b"\n2021-01-01,1001,A15-A34,W,6,3,1,6,0,2,36,1\n"));
+
+	let mut r = csv::Reader::from_reader(&written[..]);
+	assert_eq!(
+		DiffSchema::detect(r.headers().unwrap()).unwrap(),
+		DiffSchema::V2
+	);
+	let read: Vec<DiffRecord> = r.deserialize().map(|row| row.unwrap()).collect();
+	assert_eq!(read, vec![rec.clone()]);
+
+	// files written before AnzahlFaelle7Tage and AnzahlZurueckgezogen
+	let v1 = b"Datum,LandkreisId,Altersgruppe,Geschlecht,VerzugGesamt,AnzahlFallVerzoegert,AnzahlFallVerspaetet,AnzahlFall,AnzahlTodesfall,AnzahlGenesen\n2021-01-01,1001,A15-A34,W,6,3,1,6,0,2\n";
+	let mut r = csv::Reader::from_reader(&v1[..]);
+	assert_eq!(
+		DiffSchema::detect(r.headers().unwrap()).unwrap(),
+		DiffSchema::V1
+	);
+	let read: Vec<DiffRecord> = r.deserialize().map(|row| row.unwrap()).collect();
+	assert_eq!(
+		read,
+		vec![DiffRecord {
+			cases_rep_d7: 0,
+			cases_retracted: 0,
+			..rec
+		}]
+	);
+
+	let r = csv::Reader::from_reader(&b"Datum,LandkreisId,AnzahlFall\n"[..])
+		.headers()
+		.cloned()
+		.unwrap();
+	assert!(matches!(
+		DiffSchema::detect(&r),
+		Err(covid::Error::Schema(_))
+	));
+}
+
+#[test]
+fn fans_out_according_to_error_policies() {
+	let prod = MockInflux::start().unwrap();
+	let staging = MockInflux::start().unwrap();
+	let path = std::env::temp_dir().join(format!("covid-fanout-{}.lp", std::process::id()));
+	let ts = series(&["a"], 2);
+	let fields: Fields = vec![FieldDescriptor::new(ts.clone(), "n")];
+	let keyset = covid::KeySet::new(&["k"], ts.keys(), |k, out| out.push(k.clone())).unwrap();
+	let expected = vec!["m,k=a n=0.0 1609459200", "m,k=a n=1.0 1609545600"];
+
+	let mut output = Output::FanOut(
+		FanOut::new()
+			.with_target(
+				"prod",
+				Output::Influx(prod.client()),
+				SinkErrorPolicy::FailFast,
+			)
+			.with_target(
+				"staging",
+				Output::Influx(staging.client()),
+				SinkErrorPolicy::LogAndContinue,
+			)
+			.with_target(
+				"file",
+				Output::line_protocol_file(&path).unwrap(),
+				SinkErrorPolicy::FailFast,
+			),
+	);
+	assert_eq!(output.influx_clients().len(), 2);
+	assert_eq!(
+		output.primary_influx().unwrap().api_url(),
+		prod.client().api_url()
+	);
+
+	// a failing staging server does not keep the run from the others
+	staging.fail_next(500);
+	let stats = output
+		.stream_dynamic(
+			&mut NullSink(),
+			"m",
+			start(),
+			2,
+			&keyset,
+			&fields,
+			&StreamOptions::default(),
+		)
+		.unwrap();
+	assert_eq!(stats.rows, 2);
+	assert_eq!(prod.lines(), expected);
+	assert!(staging.lines().is_empty());
+	let written = std::fs::read_to_string(&path).unwrap();
+	assert_eq!(written.lines().collect::<Vec<_>>(), expected);
+	match &output {
+		Output::FanOut(fanout) => assert_eq!(
+			fanout
+				.targets()
+				.iter()
+				.map(|t| t.failures())
+				.collect::<Vec<_>>(),
+			vec![0, 1, 0]
+		),
+		_ => unreachable!(),
+	}
+
+	// but a failing prod server aborts it
+	prod.fail_next(500);
+	assert!(matches!(
+		output.stream_dynamic(
+			&mut NullSink(),
+			"m",
+			start(),
+			2,
+			&keyset,
+			&fields,
+			&StreamOptions::default(),
+		),
+		Err(covid::Error::Sink(influxdb::Error::Status(500)))
+	));
+	assert_eq!(staging.lines(), Vec::<String>::new());
+	std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn writes_parquet_files_per_measurement() {
+	use arrow_array::cast::AsArray;
+	use arrow_array::types::{Date32Type, Float64Type, Int64Type};
+	use arrow_array::Array;
+	use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+	let dir = std::env::temp_dir().join(format!("covid-parquet-{}", std::process::id()));
+	let ts = series(&["a", "b"], 3);
+	let even: Arc<dyn ViewTimeSeries<SmartString>> = Arc::new(EvenDays);
+	let fields: Fields = vec![
+		FieldDescriptor::integer(ts.clone(), "n"),
+		FieldDescriptor::new(even, "even"),
+	];
+	let keyset = covid::KeySet::new(&["k"], ts.keys(), |k, out| out.push(k.clone())).unwrap();
+	let options = StreamOptions {
+		integer_fields: true,
+		..Default::default()
+	};
+
+	let mut output = Output::Parquet(ParquetSink::new(&dir).unwrap());
+	let stats = output
+		.stream_dynamic(&mut NullSink(), "m", start(), 3, &keyset, &fields, &options)
+		.unwrap();
+	assert_eq!(stats.rows, 6);
+
+	let read = |name: &str| {
+		let f = std::fs::File::open(dir.join(name)).unwrap();
+		let reader = ParquetRecordBatchReaderBuilder::try_new(f)
+			.unwrap()
+			.build()
+			.unwrap();
+		reader.map(|batch| batch.unwrap()).collect::<Vec<_>>()
+	};
+	let batches = read("m.parquet");
+	assert_eq!(batches.len(), 1);
+	let batch = &batches[0];
+	let schema = batch.schema();
+	let columns: Vec<_> = schema
+		.fields()
+		.iter()
+		.map(|f| (f.name().as_str(), f.data_type().to_string()))
+		.collect();
+	assert_eq!(
+		columns,
+		vec![
+			("date", "Date32".into()),
+			("k", "Utf8".into()),
+			("n", "Int64".into()),
+			("even", "Float64".into()),
+		]
+	);
+	let dates = batch.column(0).as_primitive::<Date32Type>();
+	let keys = batch.column(1).as_string::<i32>();
+	let n = batch.column(2).as_primitive::<Int64Type>();
+	let even = batch.column(3).as_primitive::<Float64Type>();
+	let mut rows: Vec<_> = (0..batch.num_rows())
+		.map(|i| {
+			(
+				keys.value(i),
+				dates.value(i),
+				n.value(i),
+				// absent values are null
+				Some(even.value(i)).filter(|_| even.is_valid(i)),
+			)
+		})
+		.collect();
+	rows.sort_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)));
+	// 2021-01-01 is day 18628 since the epoch
+	assert_eq!(
+		rows,
+		vec![
+			("a", 18628, 0, Some(0.)),
+			("a", 18629, 1, None),
+			("a", 18630, 2, Some(2.)),
+			("b", 18628, 100, Some(0.)),
+			("b", 18629, 101, None),
+			("b", 18630, 102, Some(2.)),
+		]
+	);
+
+	// the run statistics are appended
+	let mut runs = covid::RunLog::new();
+	runs.record("stream_m", std::time::Instant::now(), stats);
+	output.submit_runs(&runs, &options).unwrap();
+	output.submit_runs(&runs, &options).unwrap();
+	let batches = read("meta_v1_runs.parquet");
+	assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 2);
+	for batch in batches.iter() {
+		assert_eq!(batch.column(1).as_string::<i32>().value(0), "stream_m");
+		assert_eq!(batch.column(3).as_primitive::<Int64Type>().value(0), 6);
+	}
+	std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn records_the_phase_a_run_failed_in() {
+	let mut runs = covid::RunLog::new();
+	drop(runs.begin_phase("loading case data"));
+	runs.record("load_cases", std::time::Instant::now(), Default::default());
+	drop(runs.begin_phase("streaming m"));
+	runs.record_failure();
+	// nothing begun: a failure between the phases
+	runs.record_failure();
+	let phases: Vec<_> = runs
+		.phases()
+		.map(|(phase, stats)| (phase, stats.errors))
+		.collect();
+	assert_eq!(
+		phases,
+		vec![("load_cases", 0), ("streaming m", 1), ("run", 1)]
+	);
+}
+
+#[test]
+fn upserts_into_sqlite_and_adds_new_fields() {
+	let path = std::env::temp_dir().join(format!("covid-sqlite-{}.db", std::process::id()));
+	let ts = series(&["a"], 3);
+	let keyset = covid::KeySet::new(&["k"], ts.keys(), |k, out| out.push(k.clone())).unwrap();
+	let options = StreamOptions::default();
+	let mut output = Output::Sqlite(SqliteSink::open(&path).unwrap());
+	let fields: Fields = vec![FieldDescriptor::new(ts.clone(), "n")];
+	output
+		.stream_dynamic(&mut NullSink(), "m", start(), 3, &keyset, &fields, &options)
+		.unwrap();
+
+	// the last two days again, corrected and with another field
+	let mut corrected =
+		TimeSeries::<SmartString, u64>::new(start(), start() + chrono::Duration::days(3));
+	corrected
+		.get_or_create("a".into())
+		.copy_from_slice(&[10, 11, 12]);
+	let even: Arc<dyn ViewTimeSeries<SmartString>> = Arc::new(EvenDays);
+	let fields: Fields = vec![
+		FieldDescriptor::new(Arc::new(corrected), "n"),
+		FieldDescriptor::new(even, "even"),
+	];
+	let stats = output
+		.stream_dynamic(
+			&mut NullSink(),
+			"m",
+			start() + chrono::Duration::days(1),
+			2,
+			&keyset,
+			&fields,
+			&options,
+		)
+		.unwrap();
+	assert_eq!(stats.rows, 2);
+	drop(output);
+
+	let conn = rusqlite::Connection::open(&path).unwrap();
+	let columns: Vec<String> = conn
+		.prepare("PRAGMA table_info(m)")
+		.unwrap()
+		.query_map([], |row| row.get(1))
+		.unwrap()
+		.map(|v| v.unwrap())
+		.collect();
+	assert_eq!(columns, ["k", "date", "n", "even"]);
+	let rows: Vec<(String, String, f64, Option<f64>)> = conn
+		.prepare("SELECT k, date, n, even FROM m ORDER BY k, date")
+		.unwrap()
+		.query_map([], |row| {
+			Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+		})
+		.unwrap()
+		.map(|v| v.unwrap())
+		.collect();
+	drop(conn);
+	std::fs::remove_file(&path).unwrap();
+	assert_eq!(
+		rows,
+		vec![
+			("a".into(), "2021-01-01".into(), 0., None),
+			("a".into(), "2021-01-02".into(), 11., None),
+			("a".into(), "2021-01-03".into(), 12., Some(2.)),
+		]
+	);
+}
@@ -0,0 +1,702 @@
+// Tests of the time series combinators against synthetic series.
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use chrono::{Datelike, NaiveDate};
+
+use covid::{
+	estimate_delay, Aggregation, Bucket, CounterGroup, Counters, Diff, DiffPolicy, FGauge,
+	FillStrategy, Interpolate, KeyInterner, LinearCombination, MissingPolicy, Nowcast, Resample,
+	SparseTimeSeries, Storage, ViewTimeSeries, Waning, WaningModel, WeekdayAdjusted, WeightedMean,
+	NOWCAST_DAYS, NOWCAST_ESTIMATION_DAYS,
+};
+
+// a monday
+fn start() -> NaiveDate {
+	NaiveDate::from_ymd_opt(2021, 1, 4).unwrap()
+}
+
+// reporting multipliers from monday to sunday, with a mean of one
+static SEASONALITY: [f64; 7] = [1.3, 1.2, 1.1, 1.0, 0.9, 0.8, 0.7];
+
+static WINDOW: u32 = 28;
+// the window and the three days on either side of it for the centered mean, the last of which is the adjusted day
+static FIRST_DAY: usize = WINDOW as usize + 5;
+
+fn seasonal(ndays: usize, base: impl Fn(usize) -> f64) -> FGauge<()> {
+	let end = start() + chrono::Duration::days(ndays as i64);
+	let mut ts = FGauge::<()>::new(start(), end);
+	for (i, v) in ts.get_or_create(()).iter_mut().enumerate() {
+		let weekday = (start() + chrono::Duration::days(i as i64))
+			.weekday()
+			.num_days_from_monday() as usize;
+		*v = base(i) * SEASONALITY[weekday];
+	}
+	ts
+}
+
+#[test]
+fn weekday_adjustment_removes_known_seasonality() {
+	let ts = seasonal(70, |_| 100.);
+	let adj = WeekdayAdjusted::new(&ts, WINDOW);
+
+	for i in FIRST_DAY..70 {
+		let at = start() + chrono::Duration::days(i as i64);
+		let v = adj.getf(&(), at).unwrap();
+		assert!((v - 100.).abs() < 1e-9, "day {}: {}", i, v);
+	}
+}
+
+#[test]
+fn weekday_adjustment_needs_the_entire_window() {
+	let ts = seasonal(70, |_| 100.);
+	let adj = WeekdayAdjusted::new(&ts, WINDOW);
+
+	for i in 0..FIRST_DAY {
+		assert_eq!(
+			adj.getf(&(), start() + chrono::Duration::days(i as i64)),
+			None
+		);
+	}
+	let mut out = vec![0.; 40];
+	adj.get_range(&(), start(), 40, &mut out[..]);
+	assert!(out[..FIRST_DAY].iter().all(|v| v.is_nan()));
+	assert!(out[FIRST_DAY..].iter().all(|v| (v - 100.).abs() < 1e-9));
+}
+
+#[test]
+fn weekday_adjustment_follows_growth() {
+	// doubling roughly every ten days
+	let base = |i: usize| 100. * 1.07f64.powi(i as i32);
+	let ts = seasonal(70, base);
+	let adj = WeekdayAdjusted::new(&ts, WINDOW);
+
+	for i in FIRST_DAY..70 {
+		let v = adj
+			.getf(&(), start() + chrono::Duration::days(i as i64))
+			.unwrap();
+		let rel = v / base(i);
+		// the centered mean does not follow exponential growth exactly, hence a bias of a few percent, compared to the ±30% of the seasonality
+		assert!(rel > 0.95 && rel < 1.05, "day {}: {}", i, rel);
+	}
+}
+
+#[test]
+fn weekday_adjustment_passes_zero_through() {
+	let ts = seasonal(35, |_| 0.);
+	let adj = WeekdayAdjusted::new(&ts, 7);
+
+	assert_eq!(
+		adj.getf(&(), start() + chrono::Duration::days(20)),
+		Some(0.)
+	);
+}
+
+fn day(i: i64) -> NaiveDate {
+	start() + chrono::Duration::days(i)
+}
+
+#[test]
+fn add_aligns_dates_and_unions_keys() {
+	let mut a = Counters::<u8>::new(day(0), day(4));
+	a.get_or_create(1).copy_from_slice(&[1, 2, 3, 4]);
+	let mut b = Counters::<u8>::new(day(2), day(6));
+	b.get_or_create(1).copy_from_slice(&[10, 20, 30, 40]);
+	b.get_or_create(2).copy_from_slice(&[5, 5, 5, 5]);
+
+	let sum = a.add(&b);
+	assert_eq!(sum.start(), day(0));
+	assert_eq!(sum.end(), day(6));
+	assert_eq!(&sum.get(&1).unwrap()[..], &[1, 2, 13, 24, 30, 40]);
+	assert_eq!(&sum.get(&2).unwrap()[..], &[0, 0, 5, 5, 5, 5]);
+}
+
+#[test]
+fn saturating_sub_clamps_at_zero() {
+	let mut a = Counters::<()>::new(day(0), day(3));
+	a.get_or_create(()).copy_from_slice(&[5, 5, 5]);
+	let mut b = Counters::<()>::new(day(1), day(3));
+	b.get_or_create(()).copy_from_slice(&[3, 7]);
+
+	assert_eq!(&a.saturating_sub(&b).get(&()).unwrap()[..], &[5, 2, 0]);
+}
+
+#[test]
+fn slice_copies_and_zero_fills() {
+	let mut ts = Counters::<u8>::new(day(0), day(4));
+	ts.get_or_create(1).copy_from_slice(&[1, 2, 3, 4]);
+	ts.get_or_create(2);
+
+	let sl = ts.slice(day(2), day(6));
+	assert_eq!(sl.start(), day(2));
+	assert_eq!(sl.len(), 4);
+	assert_eq!(&sl.get(&1).unwrap()[..], &[3, 4, 0, 0]);
+	assert_eq!(&sl.get(&2).unwrap()[..], &[0, 0, 0, 0]);
+
+	let sl = ts.slice(day(-1), day(1));
+	assert_eq!(&sl.get(&1).unwrap()[..], &[0, 1]);
+}
+
+#[test]
+fn extend_to_and_truncate_keep_keys() {
+	let mut ts = Counters::<u8>::new(day(0), day(3));
+	ts.get_or_create(1).copy_from_slice(&[1, 2, 3]);
+
+	ts.extend_to(day(5));
+	assert_eq!(ts.end(), day(5));
+	assert_eq!(&ts.get(&1).unwrap()[..], &[1, 2, 3, 0, 0]);
+	ts.get_or_create(2)[4] = 7;
+
+	ts.truncate(day(2));
+	assert_eq!(ts.end(), day(2));
+	assert_eq!(&ts.get(&1).unwrap()[..], &[1, 2]);
+	assert_eq!(&ts.get(&2).unwrap()[..], &[0, 0]);
+
+	// neither shrinks nor grows in the other direction
+	ts.extend_to(day(1));
+	ts.truncate(day(4));
+	assert_eq!(ts.len(), 2);
+}
+
+#[test]
+fn pruning_drops_zero_keys_only() {
+	for storage in [Storage::Dense, Storage::Compact].iter() {
+		let mut ts = Counters::<u8>::new(day(0), day(4));
+		ts.set_storage(*storage);
+		ts.get_or_create(1)[3] = 1;
+		ts.get_or_create(2);
+		ts.get_or_create(3).copy_from_slice(&[2, 0, 0, 2]);
+		ts.get_or_create(4);
+		assert_eq!(ts.key_count(), 4);
+		let mut zero: Vec<u8> = ts.zero_keys().copied().collect();
+		zero.sort_unstable();
+		assert_eq!(zero, vec![2, 4]);
+
+		assert_eq!(ts.prune_zero_keys(), 2);
+		assert_eq!(ts.prune_zero_keys(), 0);
+		assert_eq!(ts.key_count(), 2);
+		assert_eq!(&ts.get(&1).unwrap()[..], &[0, 0, 0, 1]);
+		assert_eq!(&ts.get(&3).unwrap()[..], &[2, 0, 0, 2]);
+		assert_eq!(ts.get_value(&2, 0), None);
+
+		assert_eq!(ts.retain_keys(|k| *k != 3), 1);
+		assert_eq!(ts.get_value(&1, 3), Some(1));
+		// keys can be created again after dropping others
+		ts.get_or_create(5)[0] = 7;
+		assert_eq!(ts.get_value(&5, 0), Some(7));
+		assert_eq!(ts.get_value(&1, 3), Some(1));
+
+		ts.clear();
+		assert_eq!(ts.key_count(), 0);
+		assert_eq!(ts.stored_values(), 0);
+		assert_eq!(ts.end(), day(4));
+	}
+}
+
+#[test]
+fn resample_writes_buckets_on_their_last_day() {
+	let mut ts = FGauge::<()>::new(day(0), day(21));
+	for (i, v) in ts.get_or_create(()).iter_mut().enumerate() {
+		*v = i as f64;
+	}
+	let weekly = Resample::new(&ts, Bucket::Week, Aggregation::Sum);
+
+	// start() is a monday
+	for i in 0..21 {
+		let v = weekly.getf(&(), day(i));
+		if i % 7 == 6 {
+			let first = (i - 6) as f64;
+			assert_eq!(v, Some(7. * first + 21.));
+		} else {
+			assert_eq!(v, None);
+		}
+	}
+	assert_eq!(
+		Resample::new(&ts, Bucket::Week, Aggregation::Last).getf(&(), day(13)),
+		Some(13.)
+	);
+}
+
+#[test]
+fn resample_by_month_skips_missing_days() {
+	let mut ts = FGauge::<()>::new(day(-3), day(40));
+	ts.get_or_create(()).fill(2.);
+	let monthly = Resample::new(&ts, Bucket::Month, Aggregation::Mean);
+	let sum = Resample::new(&ts, Bucket::Month, Aggregation::Sum);
+
+	let end_of_january = NaiveDate::from_ymd_opt(2021, 1, 31).unwrap();
+	assert_eq!(monthly.getf(&(), end_of_january), Some(2.));
+	// the series starts on the 1st of january
+	assert_eq!(sum.getf(&(), end_of_january), Some(62.));
+	assert_eq!(
+		monthly.getf(&(), NaiveDate::from_ymd_opt(2021, 1, 30).unwrap()),
+		None
+	);
+	// entirely outside of the series
+	assert_eq!(
+		monthly.getf(&(), NaiveDate::from_ymd_opt(2021, 3, 31).unwrap()),
+		None
+	);
+	assert_eq!(
+		Bucket::Month.range(NaiveDate::from_ymd_opt(2020, 12, 5).unwrap()),
+		(
+			NaiveDate::from_ymd_opt(2020, 12, 1).unwrap(),
+			NaiveDate::from_ymd_opt(2020, 12, 31).unwrap()
+		)
+	);
+}
+
+#[test]
+fn compact_storage_reads_like_dense() {
+	let mut dense = Counters::<u8>::new(day(0), day(100));
+	dense.get_or_create(1)[90] = 3;
+	dense.get_or_create(1)[95] = 4;
+	dense.get_or_create(2)[0] = 1;
+	dense.get_or_create(3);
+	let mut compact = dense.clone();
+	compact.set_storage(Storage::Compact);
+	assert!(compact.memory_usage() < dense.memory_usage() / 2);
+
+	for k in 1..=3 {
+		for i in -1..101 {
+			assert_eq!(compact.getf(&k, day(i)), dense.getf(&k, day(i)));
+		}
+		let mut a = vec![0.; 102];
+		let mut b = vec![0.; 102];
+		compact.get_range(&k, day(-1), 102, &mut a[..]);
+		dense.get_range(&k, day(-1), 102, &mut b[..]);
+		assert_eq!(format!("{:?}", a), format!("{:?}", b));
+		assert_eq!(compact.get(&k), dense.get(&k));
+	}
+	assert_eq!(compact.get_value(&1, 95), Some(4));
+	assert_eq!(compact.get_value(&1, 10), Some(0));
+}
+
+#[test]
+fn compact_storage_survives_mutation() {
+	let mut ts = Counters::<u8>::new(day(0), day(10));
+	ts.set_storage(Storage::Compact);
+	ts.get_or_create(1)[6] = 2;
+	ts.get_or_create(1)[8] = 1;
+	ts.cumsum();
+	assert_eq!(ts.storage(), Storage::Compact);
+	assert_eq!(&ts.get(&1).unwrap()[..], &[0, 0, 0, 0, 0, 0, 2, 2, 3, 3]);
+
+	let mut d1 = ts.clone();
+	d1.diff(1);
+	assert_eq!(&d1.get(&1).unwrap()[..], &[0, 0, 0, 0, 0, 0, 2, 0, 1, 0]);
+
+	let national = ts.rekeyed(|_| Some(0u8));
+	assert_eq!(national.storage(), Storage::Compact);
+	assert_eq!(national.get_value(&0, 9), Some(3));
+
+	ts.truncate(day(5));
+	assert_eq!(ts.get_value(&1, 4), Some(0));
+	ts.extend_to(day(7));
+	assert_eq!(&ts.get(&1).unwrap()[..], &[0; 7]);
+}
+
+#[test]
+fn narrow_value_types_match_the_defaults() {
+	let mut wide = Counters::<u8>::new(day(0), day(100));
+	let mut narrow = Counters::<u8, u32>::new(day(0), day(100));
+	for i in 0..100 {
+		wide.get_or_create(1)[i] = (i * i) as u64;
+		narrow.get_or_create(1)[i] = (i * i) as u32;
+	}
+	let wide = CounterGroup::from_d1(wide).rekeyed(|_| Some(0u8));
+	let narrow = CounterGroup::from_d1(narrow).rekeyed(|_| Some(0u8));
+	for i in 0..100 {
+		assert_eq!(narrow.cum.getf(&0, day(i)), wide.cum.getf(&0, day(i)));
+		assert_eq!(narrow.d7.getf(&0, day(i)), wide.d7.getf(&0, day(i)));
+	}
+	// the offsets of the keys come on top of the values
+	assert!(narrow.cum.memory_usage() * 5 < wide.cum.memory_usage() * 3);
+
+	let mut gauge = FGauge::<(), f32>::new(day(0), day(3));
+	gauge.get_or_create(()).copy_from_slice(&[0.5, 1.5, 2.5]);
+	let twice = gauge.add(&gauge);
+	assert_eq!(twice.getf(&(), day(2)), Some(5.));
+	assert_eq!(twice.getf(&(), day(3)), None);
+}
+
+#[test]
+fn rekeyed_via_interner_matches_rekeyed() {
+	let mut ts = Counters::<(u8, u8)>::new(day(0), day(20));
+	for (i, k) in [(1, 1), (1, 2), (2, 1), (3, 3)].iter().enumerate() {
+		for (j, v) in ts.get_or_create(*k).iter_mut().enumerate() {
+			*v = (i * 100 + j) as u64;
+		}
+	}
+	ts.set_storage(Storage::Compact);
+	let f = |k: &(u8, u8)| if k.0 == 3 { None } else { Some(k.0) };
+
+	let mut source = KeyInterner::new();
+	for k in ts.keys() {
+		source.intern(k);
+	}
+	assert_eq!(source.intern(&(1, 1)), source.get(&(1, 1)).unwrap());
+	assert_eq!(source.len(), 4);
+	let map = source.map(f);
+	assert_eq!(map.targets().len(), 2);
+
+	let expected = ts.rekeyed(f);
+	let actual = ts.rekeyed_via(&source, &map);
+	let mut keys: Vec<_> = actual.keys().copied().collect();
+	keys.sort();
+	assert_eq!(keys, vec![1, 2]);
+	for k in keys.iter() {
+		assert_eq!(actual.get(k), expected.get(k));
+	}
+}
+
+#[test]
+fn counters_convert_to_gauges_by_value() {
+	let big = (1u64 << 53) + 1;
+	let mut ts = Counters::<u8>::new(day(0), day(4));
+	ts.get_or_create(1).copy_from_slice(&[0, 0, 3, big]);
+	ts.get_or_create(2).copy_from_slice(&[1, 2, 3, 4]);
+	ts.set_storage(Storage::Compact);
+	let gauge: FGauge<u8> = ts.clone().into();
+	assert_eq!(gauge.storage(), Storage::Compact);
+	let mut keys: Vec<_> = gauge.keys().copied().collect();
+	keys.sort();
+	assert_eq!(keys, vec![1, 2]);
+	assert_eq!(
+		gauge.get(&1).unwrap().as_ref(),
+		&[0., 0., 3., big as f64][..]
+	);
+	// beyond 2^53, values are rounded like `as f64`
+	assert_eq!(gauge.getf(&1, day(3)), Some((1u64 << 53) as f64));
+	assert_eq!(gauge.getf(&2, day(0)), Some(1.));
+
+	let mut narrow = Counters::<(), u32>::new(day(0), day(2));
+	narrow.get_or_create(()).copy_from_slice(&[u32::MAX, 7]);
+	let gauge: FGauge<()> = narrow.into();
+	assert_eq!(gauge.get(&()).unwrap(), &[u32::MAX as f64, 7.][..]);
+}
+
+#[test]
+fn waning_model_interpolates_between_points() {
+	let model: WaningModel = "0:0, 14:1, 180:0.5, 365:0".parse().unwrap();
+	assert_eq!(model, WaningModel::default());
+	assert_eq!(model.to_string(), "0:0,14:1,180:0.5,365:0");
+	assert_eq!(model.level(7), 0.5);
+	assert_eq!(model.level(14), 1.);
+	assert_eq!(model.level(97), 0.75);
+	assert_eq!(model.level(366), 0.);
+	assert_eq!(model.kernel().len(), 366);
+
+	let late: WaningModel = "7:1,9:1".parse().unwrap();
+	assert_eq!(late.level(6), 0.);
+	assert_eq!(late.kernel(), vec![0., 0., 0., 0., 0., 0., 0., 1., 1., 1.]);
+
+	for invalid in ["", "14:1,7:1", "0:1,0:1", "0:1.5", "0:-1", "x:1", "7"].iter() {
+		assert!(invalid.parse::<WaningModel>().is_err(), "{:?}", invalid);
+	}
+}
+
+#[test]
+fn waning_counts_the_latest_dose() {
+	let mut basic = Counters::<()>::new(day(0), day(10));
+	basic.get_or_create(()).copy_from_slice(&[10; 10]);
+	let mut booster = Counters::<()>::new(day(0), day(10));
+	booster
+		.get_or_create(())
+		.copy_from_slice(&[0, 0, 0, 4, 4, 4, 4, 4, 4, 4]);
+	let model: WaningModel = "0:1,4:1".parse().unwrap();
+	let protected = Waning::new(vec![basic, booster], &model);
+
+	let mut out = vec![0.; 10];
+	protected.get_range(&(), day(0), 10, &mut out[..]);
+	// the boosted four drop out of the basic immunization, which wanes after day 4; the booster after day 7
+	assert_eq!(out, vec![10., 10., 10., 10., 10., 4., 4., 4., 0., 0.]);
+	assert_eq!(protected.getf(&(), day(4)), Some(10.));
+	assert_eq!(protected.getf(&(), day(10)), None);
+	assert_eq!(protected.getf(&(), day(-1)), None);
+}
+
+#[test]
+fn waning_generalizes_fixed_windows() {
+	let mut cum = Counters::<()>::new(day(0), day(400));
+	for (i, v) in cum.get_or_create(()).iter_mut().enumerate() {
+		*v = (i as u64 * i as u64) / 3;
+	}
+	let d180 = Diff::padded(cum.clone(), 180, 0.);
+	let protected = Waning::new(vec![cum], &"0:1,179:1".parse().unwrap());
+	for i in 0..400 {
+		assert_eq!(
+			protected.getf(&(), day(i)),
+			d180.getf(&(), day(i)),
+			"day {}",
+			i
+		);
+	}
+}
+
+// a cumulative series with a retraction on day 3
+fn retracted() -> Counters<()> {
+	let mut cum = Counters::<()>::new(day(0), day(5));
+	cum.get_or_create(()).copy_from_slice(&[1, 3, 6, 4, 5]);
+	cum
+}
+
+#[test]
+fn diff_policies_handle_retractions() {
+	let cum = retracted();
+	let d1 = |policy| {
+		let diff = Diff::padded(&cum, 1, 0.).with_policy(policy);
+		(0..5).map(|i| diff.getf(&(), day(i))).collect::<Vec<_>>()
+	};
+	assert_eq!(
+		d1(DiffPolicy::Signed),
+		vec![Some(1.), Some(2.), Some(3.), Some(-2.), Some(1.)]
+	);
+	assert_eq!(
+		d1(DiffPolicy::Saturating),
+		vec![Some(1.), Some(2.), Some(3.), Some(0.), Some(1.)]
+	);
+
+	// get_range applies the same policy
+	let diff = Diff::new(&cum, 1).with_policy(DiffPolicy::Saturating);
+	let mut out = [0.; 5];
+	diff.get_range(&(), day(0), 5, &mut out);
+	assert!(out[0].is_nan());
+	assert_eq!(out[1..], [2., 3., 0., 1.]);
+}
+
+#[test]
+#[should_panic(expected = "diff needs monotonic input")]
+fn strict_diff_policy_rejects_retractions() {
+	let cum = retracted();
+	Diff::padded(&cum, 1, 0.)
+		.with_policy(DiffPolicy::Strict)
+		.getf(&(), day(3));
+}
+
+#[test]
+fn in_place_diffs_handle_retractions() {
+	let mut saturating = retracted();
+	saturating.saturating_diff(1);
+	assert_eq!(&saturating.get(&()).unwrap()[..], &[0, 2, 3, 0, 1]);
+
+	assert_eq!(
+		&retracted().signed_diff(1).get(&()).unwrap()[..],
+		&[0, 2, 3, -2, 1]
+	);
+}
+
+#[test]
+#[should_panic(expected = "diff needs cumsum as input")]
+fn in_place_diff_rejects_retractions() {
+	retracted().diff(1);
+}
+
+#[test]
+fn signed_diff_saturates_on_overflow() {
+	let mut cum = Counters::<()>::new(day(0), day(4));
+	cum.get_or_create(())
+		.copy_from_slice(&[0, u64::MAX, u64::MAX - 1, 0]);
+	assert_eq!(
+		&cum.signed_diff(1).get(&()).unwrap()[..],
+		&[0, i64::MAX, -1, i64::MIN]
+	);
+}
+
+fn sparse(values: &[(i64, f64)]) -> SparseTimeSeries<(), f64> {
+	let mut ts = SparseTimeSeries::new();
+	for (i, v) in values.iter() {
+		*ts.insert_default(&(), &day(*i), &0.) = *v;
+	}
+	ts
+}
+
+// the days from `from` as read through get_range, with None for the gaps
+fn range(ts: &dyn ViewTimeSeries<()>, from: i64, ndays: usize) -> Vec<Option<f64>> {
+	let mut out = vec![f64::NAN; ndays];
+	ts.get_range(&(), day(from), ndays, &mut out[..]);
+	out.into_iter()
+		.map(|v| Some(v).filter(|v| !v.is_nan()))
+		.collect()
+}
+
+#[test]
+fn forward_fill_stops_after_max_age() {
+	let ts = Interpolate::new(
+		sparse(&[(0, 1.), (5, 2.)]),
+		FillStrategy::Forward { max_age: 3 },
+	);
+	assert_eq!(
+		range(&ts, 0, 6),
+		[Some(1.), Some(1.), Some(1.), Some(1.), None, Some(2.)]
+	);
+	assert_eq!(ts.getf(&(), day(3)), Some(1.));
+	assert_eq!(ts.getf(&(), day(4)), None);
+}
+
+#[test]
+fn linear_fill_bridges_gaps_up_to_max_gap() {
+	// a gap of two days, then one of three
+	let ts = Interpolate::new(
+		sparse(&[(0, 0.), (3, 3.), (7, 7.)]),
+		FillStrategy::Linear { max_gap: 2 },
+	);
+	assert_eq!(
+		range(&ts, 0, 8),
+		[
+			Some(0.),
+			Some(1.),
+			Some(2.),
+			Some(3.),
+			None,
+			None,
+			None,
+			Some(7.)
+		]
+	);
+	// nothing to interpolate towards after the last value
+	assert_eq!(ts.getf(&(), day(8)), None);
+}
+
+#[test]
+fn masked_zero_days_are_gaps() {
+	let mut values = FGauge::<()>::new(day(0), day(4));
+	values.get_or_create(()).copy_from_slice(&[1., 2., 3., 4.]);
+	let mut sites = Counters::<()>::new(day(0), day(4));
+	sites.get_or_create(()).copy_from_slice(&[1, 1, 0, 1]);
+
+	let none = Interpolate::masked(&values, &sites, FillStrategy::None);
+	assert_eq!(range(&none, 0, 4), [Some(1.), Some(2.), None, Some(4.)]);
+	let forward = Interpolate::masked(&values, &sites, FillStrategy::Forward { max_age: 1 });
+	assert_eq!(
+		range(&forward, 0, 4),
+		[Some(1.), Some(2.), Some(2.), Some(4.)]
+	);
+	let linear = Interpolate::masked(&values, &sites, FillStrategy::Linear { max_gap: 1 });
+	assert_eq!(
+		range(&linear, 0, 4),
+		[Some(1.), Some(2.), Some(3.), Some(4.)]
+	);
+}
+
+#[test]
+fn ranges_starting_inside_a_gap_see_the_values_around_it() {
+	let inner = sparse(&[(0, 0.), (4, 8.)]);
+	let forward = Interpolate::new(&inner, FillStrategy::Forward { max_age: 3 });
+	assert_eq!(range(&forward, 2, 3), [Some(0.), Some(0.), Some(8.)]);
+	let linear = Interpolate::new(&inner, FillStrategy::Linear { max_gap: 3 });
+	assert_eq!(range(&linear, 2, 2), [Some(4.), Some(6.)]);
+	// the same as day by day
+	for i in 0..6 {
+		assert_eq!(range(&linear, i, 1)[0], linear.getf(&(), day(i)));
+	}
+}
+
+#[test]
+fn nowcast_recovers_a_geometric_delay() {
+	// 900 cases per report date, published with a geometric delay of a mean of three days
+	let cases: f64 = 900.;
+	let mean: f64 = 3.;
+	let p = 1. / mean;
+	let ndays = 200;
+	let asof = day(ndays);
+	let mut delay_total = Counters::<()>::new(day(0), asof);
+	let mut cases_delayed = Counters::<()>::new(day(0), asof);
+	for publication in 0..ndays as usize {
+		let (mut total, mut delayed) = (0., 0.);
+		for delay in 1..=publication {
+			let n = cases * p * (1. - p).powi(delay as i32 - 1);
+			total += n * delay as f64;
+			delayed += n;
+		}
+		delay_total.get_or_create(())[publication] = total.round() as u64;
+		cases_delayed.get_or_create(())[publication] = delayed.round() as u64;
+	}
+
+	let estimate =
+		estimate_delay(&delay_total, &cases_delayed, asof, NOWCAST_ESTIMATION_DAYS).unwrap();
+	assert!(
+		(estimate.expected.mean - mean).abs() < 0.01,
+		"{:?}",
+		estimate
+	);
+
+	// the cases of each report date which are published by `asof`
+	let mut reported = FGauge::<()>::new(day(0), asof);
+	for (i, v) in reported.get_or_create(()).iter_mut().enumerate() {
+		let age = ndays - i as i64;
+		*v = cases * (1. - (1. - p).powi(age as i32));
+	}
+	let nowcast = Nowcast::new(&reported, estimate.expected, asof, NOWCAST_DAYS);
+	for age in 1..=NOWCAST_DAYS as i64 {
+		let at = asof - chrono::Duration::days(age);
+		let v = nowcast.getf(&(), at).unwrap();
+		assert!(v > reported.getf(&(), at).unwrap(), "{}", at);
+		assert!((v - cases).abs() < cases * 0.01, "{}: {}", at, v);
+	}
+	for age in NOWCAST_DAYS as i64 + 1..ndays {
+		let at = asof - chrono::Duration::days(age);
+		assert_eq!(nowcast.getf(&(), at), reported.getf(&(), at));
+	}
+}
+
+// active cases as cases - recovered - deaths, where the deaths are missing on day 1 and everything on day 2
+fn active(missing: MissingPolicy) -> LinearCombination<()> {
+	let cases = sparse(&[(0, 10.), (1, 12.)]);
+	let recovered = sparse(&[(0, 4.), (1, 5.)]);
+	let deaths = sparse(&[(0, 1.)]);
+	LinearCombination::new(
+		vec![
+			(1., Arc::new(cases) as Arc<dyn ViewTimeSeries<()>>),
+			(-1., Arc::new(recovered)),
+			(-1., Arc::new(deaths)),
+		],
+		missing,
+	)
+}
+
+#[test]
+fn linear_combination_follows_its_missing_policy() {
+	let strict = active(MissingPolicy::Strict);
+	assert_eq!(range(&strict, 0, 3), [Some(5.), None, None]);
+	let zero = active(MissingPolicy::Zero);
+	assert_eq!(range(&zero, 0, 3), [Some(5.), Some(7.), None]);
+	for i in 0..3 {
+		assert_eq!(range(&strict, i, 1)[0], strict.getf(&(), day(i)));
+		assert_eq!(range(&zero, i, 1)[0], zero.getf(&(), day(i)));
+	}
+}
+
+#[test]
+fn weighted_mean_weights_by_population() {
+	// the incidence of three districts in two states
+	let mut incidence = SparseTimeSeries::new();
+	for (k, i, v) in [
+		(1, 0, 100.),
+		(1, 1, 50.),
+		(2, 0, 200.),
+		(3, 0, 40.),
+		(3, 1, 40.),
+	]
+	.iter()
+	{
+		*incidence.insert_default(k, &day(*i), &0.) = *v;
+	}
+	let population: HashMap<u8, f64> = [(1, 300_000.), (2, 100_000.), (3, 50_000.)]
+		.iter()
+		.cloned()
+		.collect();
+	let state = |k: &u8| Some(if *k == 3 { 2u8 } else { 1u8 });
+	let keys = [1u8, 2, 3];
+	let mean = WeightedMean::new(&incidence, keys.iter(), state, population);
+
+	// (100 * 300k + 200 * 100k) / 400k
+	assert_eq!(mean.getf(&1, day(0)), Some(125.));
+	// district 2 has no value, its weight is left out
+	assert_eq!(mean.getf(&1, day(1)), Some(50.));
+	assert_eq!(mean.getf(&2, day(0)), Some(40.));
+	assert_eq!(mean.getf(&3, day(0)), None);
+	let mut out = [0.; 2];
+	mean.get_range(&1, day(0), 2, &mut out[..]);
+	assert_eq!(out, [125., 50.]);
+}
@@ -0,0 +1,160 @@
+// Property tests and fixtures of the transformations between cumulative, daily and 7 day counters.
+use chrono::NaiveDate;
+
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+use covid::{CounterGroup, Counters, Storage, ViewTimeSeries, WeeklyCounterGroup};
+
+fn day(i: usize) -> NaiveDate {
+	NaiveDate::from_ymd_opt(2021, 1, 4).unwrap() + chrono::Duration::days(i as i64)
+}
+
+fn counters(values: &[u64], storage: Storage) -> Counters<()> {
+	let mut ts = Counters::new(day(0), day(values.len()));
+	ts.get_or_create(()).copy_from_slice(values);
+	ts.set_storage(storage);
+	ts
+}
+
+fn values(ts: &Counters<()>) -> Vec<u64> {
+	ts.get(&()).unwrap().into_owned()
+}
+
+fn window_sums(values: &[u64], window: usize) -> Vec<u64> {
+	(0..values.len())
+		.map(|i| values[(i + 1).saturating_sub(window)..=i].iter().sum())
+		.collect()
+}
+
+// daily counts, with a run of leading zeros so that compact storage has something to drop
+fn daily() -> impl Strategy<Value = Vec<u64>> {
+	(0usize..20, vec(0u64..10_000, 1..100)).prop_map(|(zeros, values)| {
+		let mut result = vec![0; zeros];
+		result.extend(values);
+		result
+	})
+}
+
+fn storage() -> impl Strategy<Value = Storage> {
+	prop_oneof![Just(Storage::Dense), Just(Storage::Compact)]
+}
+
+proptest! {
+	#[test]
+	fn diff_undoes_cumsum(d in daily(), storage in storage(), offset in 1usize..10) {
+		let mut ts = counters(&d, storage);
+		ts.cumsum();
+		ts.diff(offset);
+		// for an offset of one, these are the daily values; the first `offset` days have nothing to diff against
+		let expected: Vec<u64> = window_sums(&d, offset)
+			.into_iter()
+			.enumerate()
+			.map(|(i, v)| if i < offset { 0 } else { v })
+			.collect();
+		prop_assert_eq!(values(&ts), expected);
+		prop_assert_eq!(ts.storage(), storage);
+	}
+
+	#[test]
+	fn cumsum_undoes_diff(d in daily(), storage in storage()) {
+		let cum = window_sums(&d, d.len());
+		let mut ts = counters(&cum, storage);
+		ts.diff(1);
+		ts.cumsum();
+		let expected: Vec<u64> = cum.iter().map(|v| v - cum[0]).collect();
+		prop_assert_eq!(values(&ts), expected);
+	}
+
+	#[test]
+	fn signed_diff_matches_diff(d in daily(), storage in storage(), offset in 1usize..150) {
+		let mut ts = counters(&d, storage);
+		ts.cumsum();
+		let signed = ts.signed_diff(offset);
+		ts.diff(offset);
+		let expected: Vec<i64> = values(&ts).into_iter().map(|v| v as i64).collect();
+		prop_assert_eq!(signed.get(&()).unwrap().into_owned(), expected);
+	}
+
+	#[test]
+	fn unrolled_inverts_d7(d in daily(), storage in storage()) {
+		let d7 = window_sums(&d, 7);
+		let ts = counters(&d7, storage);
+		prop_assert_eq!(values(&ts.unrolled(7)), d.clone());
+
+		let group = CounterGroup::from_d7(ts);
+		for (i, v) in d7.iter().enumerate() {
+			prop_assert_eq!(group.d7.getf(&(), day(i)), Some(*v as f64));
+			prop_assert_eq!(group.d1.getf(&(), day(i)), Some(d[i] as f64));
+		}
+	}
+
+	#[test]
+	fn unrolled_keeps_d7_totals_on_inconsistent_input(d7 in vec(0u64..1000, 1..100)) {
+		// arbitrary input is no valid 7 day sum; whatever cannot be unrolled is carried forward, so the 7 day sums of the result are at least the input
+		let unrolled = values(&counters(&d7, Storage::Dense).unrolled(7));
+		for (i, (total, v)) in window_sums(&unrolled, 7).into_iter().zip(d7).enumerate() {
+			prop_assert!(total >= v, "day {}: {} < {}", i, total, v);
+		}
+	}
+
+	#[test]
+	fn shift_fwd_moves_values(d in daily(), storage in storage(), n in 0usize..150) {
+		let mut ts = counters(&d, storage);
+		ts.shift_fwd(n);
+		let expected: Vec<u64> = (0..d.len()).map(|i| if i < n { 0 } else { d[i - n] }).collect();
+		prop_assert_eq!(values(&ts), expected);
+		prop_assert_eq!(ts.storage(), storage);
+	}
+
+	#[test]
+	fn shift_fwd_composes(d in daily(), n in 0usize..60, m in 0usize..60) {
+		let mut twice = counters(&d, Storage::Compact);
+		twice.shift_fwd(n);
+		twice.shift_fwd(m);
+		let mut once = counters(&d, Storage::Dense);
+		once.shift_fwd(n + m);
+		prop_assert_eq!(values(&twice), values(&once));
+	}
+}
+
+#[test]
+fn unrolled_carries_uncorrected_retractions() {
+	// a case is counted on day 2 and retracted on day 4 without correcting the 7 day sums before; a new case shows up on day 9
+	let d7 = [0, 0, 1, 1, 0, 0, 0, 0, 0, 2, 2];
+	let unrolled = values(&counters(&d7, Storage::Dense).unrolled(7));
+	assert_eq!(unrolled, vec![0, 0, 1, 0, 0, 0, 0, 0, 0, 2, 0]);
+	// the retraction is carried until the case drops out of the window again
+	assert_eq!(
+		window_sums(&unrolled, 7),
+		vec![0, 0, 1, 1, 1, 1, 1, 1, 1, 2, 2]
+	);
+}
+
+// positive tests per calendar week, 2020 weeks 11 to 30, from rki/tests.csv
+static POSITIVE_TESTS: [u64; 20] = [
+	7502, 25886, 33139, 37649, 30727, 22724, 18127, 12600, 10181, 7142, 5315, 4335, 3219, 2956,
+	5588, 3919, 3204, 3042, 3608, 4537,
+];
+
+#[test]
+fn weekly_tests_round_trip() {
+	let mut ts = counters(&POSITIVE_TESTS, Storage::Dense);
+	ts.cumsum();
+	assert_eq!(ts.get_value(&(), 19), Some(245400));
+	ts.diff(1);
+	assert_eq!(values(&ts)[1..], POSITIVE_TESTS[1..]);
+
+	let mut weekly = Counters::<()>::new(day(0), day(7 * POSITIVE_TESTS.len()));
+	let vec = weekly.get_or_create(());
+	for (week, v) in POSITIVE_TESTS.iter().enumerate() {
+		vec[week * 7 + 6] = *v;
+	}
+	let group = WeeklyCounterGroup::from_weekly(weekly);
+	for (week, v) in POSITIVE_TESTS.iter().enumerate() {
+		let d7 = group.d7.getf(&(), day(week * 7 + 6)).unwrap();
+		assert!((d7 - *v as f64).abs() < 1e-6, "week {}: {}", week, d7);
+		let d1 = group.d1.getf(&(), day(week * 7)).unwrap();
+		assert!((d1 - *v as f64 / 7.).abs() < 1e-9, "week {}: {}", week, d1);
+	}
+}